@@ -1,4 +1,4 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
 use cynda_core::{SensorPayload, contracts::ANOMALY_VECTOR_SIZE};
 use cynda_core::transmitter::Transmitter;
 
@@ -48,10 +48,42 @@ fn benchmark_ack_backoff(c: &mut Criterion) {
     });
 }
 
+fn benchmark_compression(c: &mut Criterion) {
+    use cynda_core::compression::{compress_frame, decompress_frame};
+    use cynda_core::framing::Compression;
+    
+    let payloads: Vec<SensorPayload> = (1..=5)
+        .map(|id| SensorPayload::new(id, 1000 * id as u64, 1, 50, 1000, id, [0.5; ANOMALY_VECTOR_SIZE]).unwrap())
+        .collect();
+    let frame = Transmitter::frame_packed(&payloads).unwrap();
+    
+    // Throughput counts the bytes each codec puts on the wire, and the
+    // benchmark id names both sizes, so the report sets size against CPU.
+    let mut group = c.benchmark_group("packed_5");
+    for codec in [Compression::None, Compression::Lz4, Compression::Zstd] {
+        if !codec.is_available() {
+            continue;
+        }
+        
+        let compressed = compress_frame(&frame, codec).unwrap();
+        let sizes = format!("{:?}/{}_to_{}_bytes", codec, frame.len(), compressed.len());
+        group.throughput(Throughput::Bytes(compressed.len() as u64));
+        
+        group.bench_function(BenchmarkId::new("compress", &sizes), |b| {
+            b.iter(|| compress_frame(black_box(&frame), codec))
+        });
+        group.bench_function(BenchmarkId::new("decompress", &sizes), |b| {
+            b.iter(|| decompress_frame(black_box(&compressed)))
+        });
+    }
+    group.finish();
+}
+
 criterion_group!(
     benches,
     benchmark_serialization,
     benchmark_batch_serialization,
-    benchmark_ack_backoff
+    benchmark_ack_backoff,
+    benchmark_compression
 );
 criterion_main!(benches);
@@ -0,0 +1,18 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+// The vendored protoc keeps the `grpc` feature buildable without a system
+// protobuf install.
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    if std::env::var_os("PROTOC").is_none() {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("no vendored protoc for this platform");
+        std::env::set_var("PROTOC", protoc);
+    }
+    
+    tonic_build::configure()
+        .compile_protos(&["proto/cynda.proto"], &["proto"])
+        .expect("failed to compile proto/cynda.proto");
+}
@@ -0,0 +1,159 @@
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use cynda_core::server::ShardedGateway;
+
+use crate::DaemonStats;
+
+const REQUEST_TIMEOUT_MS: u64 = 2_000;
+
+// Connections served at once; beyond this new ones are closed unanswered.
+const MAX_CONCURRENT_REQUESTS: usize = 16;
+
+// Minimal HTTP/1.0 responder for probes and scrapers: one request per
+// connection, each on its own thread so a client that stalls until the
+// read timeout holds up nobody else. Runs until the listener fails.
+pub fn serve(address: &str, gateway: &ShardedGateway, stats: &DaemonStats) -> std::io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    eprintln!("cynda-gateway: admin endpoint on http://{}", listener.local_addr()?);
+    
+    let in_flight = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            if in_flight.fetch_add(1, Ordering::AcqRel) >= MAX_CONCURRENT_REQUESTS {
+                in_flight.fetch_sub(1, Ordering::AcqRel);
+                continue;
+            }
+            
+            let in_flight = &in_flight;
+            scope.spawn(move || {
+                // A misbehaving client must not take the endpoint down.
+                let _ = respond(stream, gateway, stats);
+                in_flight.fetch_sub(1, Ordering::AcqRel);
+            });
+        }
+    });
+    
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream, gateway: &ShardedGateway, stats: &DaemonStats) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_millis(REQUEST_TIMEOUT_MS)))?;
+    
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    
+    let (status, content_type, body) = route(&request_line, gateway, stats);
+    write!(
+        stream,
+        "HTTP/1.0 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body,
+    )
+}
+
+fn route(request_line: &str, gateway: &ShardedGateway, stats: &DaemonStats) -> (&'static str, &'static str, String) {
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    
+    match (method, path) {
+        ("GET", "/health") => health(gateway),
+        ("GET", "/metrics") => ("200 OK", "text/plain; version=0.0.4", render_metrics(gateway, stats)),
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    }
+}
+
+// Unhealthy once any receive loop has exited: SO_REUSEPORT keeps sending
+// that socket its share of the traffic, which then goes unanswered.
+fn health(gateway: &ShardedGateway) -> (&'static str, &'static str, String) {
+    match gateway.dead_shards() {
+        0 => ("200 OK", "application/json", r#"{"status":"ok"}"#.to_string()),
+        dead => (
+            "503 Service Unavailable",
+            "application/json",
+            format!(r#"{{"status":"degraded","dead_shards":{}}}"#, dead),
+        ),
+    }
+}
+
+// Prometheus text exposition format.
+pub fn render_metrics(gateway: &ShardedGateway, stats: &DaemonStats) -> String {
+    let metrics = gateway.metrics();
+    let replay = gateway.replay_metrics();
+    
+    let counters = [
+        ("cynda_packets_received_total", "Sensor frames received", metrics.received),
+        ("cynda_packets_accepted_total", "Frames validated and handed to the handler", metrics.accepted),
+        ("cynda_packets_duplicate_total", "Retransmissions re-ACKed without handling", metrics.duplicates),
+        ("cynda_packets_rejected_total", "Frames NACKed", metrics.rejected),
+        ("cynda_packets_malformed_total", "Datagrams that were not valid frames", metrics.malformed),
+        ("cynda_packets_unauthenticated_total", "Frames dropped for a missing or forged envelope", metrics.unauthenticated),
+        ("cynda_handler_panics_total", "Payloads NACKed because the handler panicked", metrics.handler_panics),
+        ("cynda_shard_restarts_total", "Receive loops restarted after a panic", metrics.restarts),
+        ("cynda_replay_evictions_total", "Devices evicted from the replay guard", replay.evictions),
+        ("cynda_dlt_records_signed_total", "DLT records signed and written", stats.records_signed.load(Ordering::Relaxed)),
+        ("cynda_dlt_record_errors_total", "DLT records that failed to sign or write", stats.record_errors.load(Ordering::Relaxed)),
+    ];
+    
+    let mut body = String::new();
+    for (name, help, value) in counters {
+        let _ = writeln!(body, "# HELP {} {}\n# TYPE {} counter\n{} {}", name, help, name, name, value);
+    }
+    
+    let _ = writeln!(body, "# HELP cynda_shards Receive shards running\n# TYPE cynda_shards gauge");
+    let _ = writeln!(body, "cynda_shards {}", gateway.shard_count());
+    
+    if let Some(shedding) = gateway.shedding_metrics() {
+        let counters = [
+            ("cynda_shed_total", "Payloads NACKed while shedding load", shedding.shed),
+            ("cynda_shed_critical_admitted_total", "Critical payloads admitted while shedding", shedding.critical_admitted),
+            ("cynda_shed_critical_total", "Critical payloads shed once their allowance ran out", shedding.critical_shed),
+            ("cynda_shed_activations_total", "Times load shedding switched on", shedding.activations),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(body, "# HELP {} {}\n# TYPE {} counter\n{} {}", name, help, name, name, value);
+        }
+        
+        let _ = writeln!(body, "# HELP cynda_shedding Whether load shedding is active\n# TYPE cynda_shedding gauge");
+        let _ = writeln!(body, "cynda_shedding {}", u8::from(shedding.shedding));
+        let _ = writeln!(body, "# HELP cynda_shed_peak_queue_depth Deepest queue seen by the overload detector\n# TYPE cynda_shed_peak_queue_depth gauge");
+        let _ = writeln!(body, "cynda_shed_peak_queue_depth {}", shedding.peak_queue_depth);
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cynda_core::server::GatewayServer;
+    
+    #[test]
+    fn test_routes() {
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_poll_interval_ms(20)
+            .spawn()
+            .unwrap();
+        let stats = DaemonStats::default();
+        stats.records_signed.fetch_add(3, Ordering::Relaxed);
+        
+        let (status, _, body) = route("GET /health HTTP/1.1\r\n", &gateway, &stats);
+        assert_eq!(status, "200 OK");
+        assert!(body.contains("ok"));
+        
+        let (status, _, body) = route("GET /metrics HTTP/1.1\r\n", &gateway, &stats);
+        assert_eq!(status, "200 OK");
+        assert!(body.contains("cynda_dlt_records_signed_total 3\n"));
+        assert!(body.contains("cynda_shards 1\n"));
+        assert!(!body.contains("cynda_shedding"));
+        
+        let (status, _, _) = route("POST /metrics HTTP/1.1\r\n", &gateway, &stats);
+        assert_eq!(status, "404 Not Found");
+        gateway.shutdown();
+    }
+}
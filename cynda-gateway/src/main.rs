@@ -0,0 +1,189 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use cynda_core::config::CyDnAConfig;
+use cynda_core::contracts::DLTTransactionRecord;
+use cynda_core::framing::{FrameHeader, Priority, FRAME_HEADER_SIZE};
+use cynda_core::pool::PooledPacket;
+use ed25519_dalek::SigningKey;
+
+mod admin;
+
+const DEFAULT_CONFIG_PATH: &str = "cynda-gateway.toml";
+
+#[derive(Default)]
+pub struct DaemonStats {
+    pub records_signed: AtomicU64,
+    
+    pub record_errors: AtomicU64,
+}
+
+fn load_config(path: &str) -> Result<CyDnAConfig, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let config: CyDnAConfig = toml::from_str(&text)
+        .map_err(|e| format!("invalid config {}: {}", path, e))?;
+    
+    config.validate()
+        .map_err(|e| format!("invalid config {}: {}", path, e))?;
+    Ok(config)
+}
+
+// Without a key file every restart signs with a fresh key, which is only
+// useful for trying the daemon out.
+fn load_signing_key(config: &CyDnAConfig) -> Result<SigningKey, String> {
+    let Some(path) = &config.signing_key_path else {
+        eprintln!("cynda-gateway: no signing_key_path configured, using an ephemeral key");
+        return Ok(SigningKey::from_bytes(&rand::random()));
+    };
+    
+    let bytes = std::fs::read(path)
+        .map_err(|e| format!("cannot read signing key {}: {}", path, e))?;
+    let secret: [u8; 32] = bytes.as_slice().try_into()
+        .map_err(|_| format!("signing key {} must be exactly 32 bytes, got {}", path, bytes.len()))?;
+    
+    Ok(SigningKey::from_bytes(&secret))
+}
+
+fn open_dlt_output(config: &CyDnAConfig) -> Result<Box<dyn Write + Send>, String> {
+    match &config.dlt_output_path {
+        Some(path) => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map(|file| Box::new(file) as Box<dyn Write + Send>)
+            .map_err(|e| format!("cannot open {}: {}", path, e)),
+        None => Ok(Box::new(std::io::stdout())),
+    }
+}
+
+// Root-mean-square of the anomaly vector: 0 for a quiet sensor, about 1 when
+// the on-device model saturates every dimension.
+pub fn anomaly_score(vector: &[f32]) -> f32 {
+    if vector.is_empty() {
+        return 0.0;
+    }
+    
+    let sum_of_squares: f32 = vector.iter().map(|value| value * value).sum();
+    (sum_of_squares / vector.len() as f32).sqrt()
+}
+
+pub fn sign_record(
+    config: &CyDnAConfig,
+    signing_key: &SigningKey,
+    packet: &PooledPacket,
+) -> cynda_core::Result<DLTTransactionRecord> {
+    let score = anomaly_score(&packet.anomaly_ai_vector);
+    let sent_critical = FrameHeader::decode(packet.frame())?.priority() == Priority::Critical;
+    
+    DLTTransactionRecord::builder()
+        .with_payload_bytes(&packet.frame()[FRAME_HEADER_SIZE..])
+        .with_gateway_id(config.gateway_id)
+        .with_anomaly_score(score)
+        .with_critical_alert(sent_critical || score >= config.critical_score_threshold)
+        .with_consensus_mode(config.consensus_mode)
+        .build(signing_key)
+}
+
+fn run(config_path: &str) -> Result<(), String> {
+    let config = load_config(config_path)?;
+    let signing_key = load_signing_key(&config)?;
+    let output = Mutex::new(open_dlt_output(&config)?);
+    let stats = Arc::new(DaemonStats::default());
+    
+    let handler_config = config.clone();
+    let handler_stats = Arc::clone(&stats);
+    let handler = move |packet: &PooledPacket| {
+        let line = sign_record(&handler_config, &signing_key, packet)
+            .and_then(|record| record.to_json());
+        
+        let written = match line {
+            Ok(line) => writeln!(output.lock().unwrap(), "{}", line).is_ok(),
+            Err(_) => false,
+        };
+        
+        let counter = if written { &handler_stats.records_signed } else { &handler_stats.record_errors };
+        counter.fetch_add(1, Ordering::Relaxed);
+    };
+    
+    let gateway = config.gateway_server()
+        .and_then(|server| server.with_handler(handler).spawn_sharded(config.shards))
+        .map_err(|e| format!("cannot start gateway on {}: {}", config.bind_address, e))?;
+    eprintln!(
+        "cynda-gateway: gateway {} listening on {} with {} shard(s)",
+        config.gateway_id,
+        gateway.local_address(),
+        gateway.shard_count(),
+    );
+    if let Some(grpc_address) = gateway.grpc_address() {
+        eprintln!("cynda-gateway: gRPC ingestion on {}", grpc_address);
+    }
+    if !config.require_authentication && !config.require_encryption {
+        eprintln!("cynda-gateway: no envelope required, so any host can report as any device");
+    }
+    
+    match &config.admin_address {
+        Some(admin_address) => admin::serve(admin_address, &gateway, &stats)
+            .map_err(|e| format!("admin endpoint {} failed: {}", admin_address, e)),
+        None => loop {
+            std::thread::park();
+        },
+    }
+}
+
+fn main() -> ExitCode {
+    let config_path = std::env::args().nth(1).unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_string());
+    
+    match run(&config_path) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("cynda-gateway: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_anomaly_score() {
+        assert_eq!(anomaly_score(&[]), 0.0);
+        assert_eq!(anomaly_score(&[0.0; 32]), 0.0);
+        assert!((anomaly_score(&[1.0; 32]) - 1.0).abs() < 1e-6);
+        assert!((anomaly_score(&[3.0, 4.0]) - 12.5f32.sqrt()).abs() < 1e-6);
+    }
+    
+    #[test]
+    fn test_config_file_parses() {
+        let config: CyDnAConfig = toml::from_str(r#"
+            gateway_id = 42
+            bind_address = "127.0.0.1:9000"
+            shards = 4
+            tcp_fallback = true
+            admin_address = "127.0.0.1:9100"
+        "#).unwrap();
+        
+        assert_eq!(config.gateway_id, 42);
+        assert_eq!(config.shards, 4);
+        assert!(config.tcp_fallback);
+        assert_eq!(config.replay_max_age_ms, CyDnAConfig::default().replay_max_age_ms);
+        assert!(config.validate().is_ok());
+        
+        let config: CyDnAConfig = toml::from_str(r#"
+            require_authentication = true
+            device_keys_path = "/etc/cynda/device-keys"
+            allowed_devices = [[1, 100], [500, 500]]
+            overload_queue_depth = 2048
+        "#).unwrap();
+        
+        assert!(config.require_authentication);
+        assert_eq!(config.allowed_devices, Some(vec![[1, 100], [500, 500]]));
+        assert_eq!(config.overload_queue_depth, Some(2048));
+        assert!(config.validate().is_ok());
+    }
+}
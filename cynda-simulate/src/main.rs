@@ -0,0 +1,377 @@
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use cynda_core::ack_manager::RetransmissionEvent;
+use cynda_core::SensorClient;
+
+mod sensor;
+
+use sensor::{DrainCurve, VirtualSensor};
+
+const USAGE: &str = "usage: cynda-simulate <gateway-address> [--sensors N] [--first-device-id N] \
+[--rate HZ] [--anomaly-probability P] [--loss P] [--battery PERCENT] \
+[--drain none|linear:PCT_PER_HOUR|knee:PCT_PER_HOUR] [--duration SECS] [--seed N]";
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+// Upper bound on the loop's sleep so retransmission timers are serviced
+// between sends.
+const MAX_IDLE_SLEEP: Duration = Duration::from_millis(5);
+
+// How long a finished run waits for outstanding critical alerts.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub gateway: String,
+    
+    pub sensors: u32,
+    
+    pub first_device_id: u32,
+    
+    // Readings per second, per sensor.
+    pub rate_hz: f64,
+    
+    pub anomaly_probability: f64,
+    
+    pub loss_probability: f64,
+    
+    pub battery_percent: f64,
+    
+    pub drain: DrainCurve,
+    
+    // Runs until interrupted when unset.
+    pub duration: Option<Duration>,
+    
+    pub seed: u64,
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.into_iter();
+        let gateway = args.next().filter(|arg| !arg.starts_with("--")).ok_or("missing gateway address")?;
+        
+        let mut options = Self {
+            gateway,
+            sensors: 10,
+            first_device_id: 1,
+            rate_hz: 1.0,
+            anomaly_probability: 0.01,
+            loss_probability: 0.0,
+            battery_percent: 100.0,
+            drain: DrainCurve::None,
+            duration: None,
+            seed: 1,
+        };
+        
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or_else(|| format!("{} needs a value", flag))?;
+            let invalid = || format!("invalid value for {}: {}", flag, value);
+            
+            match flag.as_str() {
+                "--sensors" => options.sensors = value.parse().map_err(|_| invalid())?,
+                "--first-device-id" => options.first_device_id = value.parse().map_err(|_| invalid())?,
+                "--rate" => options.rate_hz = value.parse().map_err(|_| invalid())?,
+                "--anomaly-probability" => options.anomaly_probability = value.parse().map_err(|_| invalid())?,
+                "--loss" => options.loss_probability = value.parse().map_err(|_| invalid())?,
+                "--battery" => options.battery_percent = value.parse().map_err(|_| invalid())?,
+                "--drain" => options.drain = DrainCurve::parse(&value).ok_or_else(invalid)?,
+                "--duration" => {
+                    let seconds = value.parse().map_err(|_| invalid())?;
+                    options.duration = Some(Duration::try_from_secs_f64(seconds).map_err(|_| invalid())?)
+                }
+                "--seed" => options.seed = value.parse().map_err(|_| invalid())?,
+                _ => return Err(format!("unknown option {}", flag)),
+            }
+        }
+        
+        if options.sensors == 0 || options.first_device_id == 0 {
+            return Err("--sensors and --first-device-id must be at least 1".to_string());
+        }
+        
+        if options.first_device_id.checked_add(options.sensors - 1).is_none() {
+            return Err("device ids overflow u32".to_string());
+        }
+        
+        let interval = Duration::try_from_secs_f64(1.0 / options.rate_hz);
+        if !(options.rate_hz > 0.0 && options.rate_hz.is_finite() && interval.is_ok()) {
+            return Err("--rate must be positive".to_string());
+        }
+        
+        let probabilities = [options.anomaly_probability, options.loss_probability];
+        if probabilities.iter().any(|p| !(0.0..=1.0).contains(p)) {
+            return Err("probabilities must be between 0 and 1".to_string());
+        }
+        
+        Ok(options)
+    }
+    
+    fn send_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.rate_hz)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SimulationStats {
+    pub readings: u64,
+    
+    pub sent: u64,
+    
+    pub lost: u64,
+    
+    pub anomalies: u64,
+    
+    pub acked: u64,
+    
+    pub exhausted: u64,
+    
+    pub rejected: u64,
+    
+    pub send_errors: u64,
+}
+
+impl SimulationStats {
+    fn record_event(&mut self, event: RetransmissionEvent) {
+        match event {
+            RetransmissionEvent::Acked { .. } => self.acked += 1,
+            RetransmissionEvent::Exhausted { .. } => self.exhausted += 1,
+            RetransmissionEvent::Rejected { .. } => self.rejected += 1,
+        }
+    }
+}
+
+struct Device {
+    sensor: VirtualSensor,
+    client: SensorClient,
+    next_reading: Instant,
+    last_reading: Instant,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn connect_devices(options: &Options, gateway: SocketAddr, start: Instant) -> cynda_core::Result<Vec<Device>> {
+    let bind_address: SocketAddr = if gateway.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" }.parse().unwrap();
+    let interval = options.send_interval();
+    
+    (0..options.sensors)
+        .map(|index| {
+            let sensor = VirtualSensor::new(options.first_device_id + index, options.seed)
+                .with_battery_level(options.battery_percent)
+                .with_drain(options.drain)
+                .with_anomaly_probability(options.anomaly_probability)
+                .with_loss_probability(options.loss_probability);
+            
+            // Staggered so the fleet does not send in lockstep.
+            let offset = interval.mul_f64(f64::from(index) / f64::from(options.sensors));
+            
+            Ok(Device {
+                sensor,
+                client: SensorClient::connect(bind_address, gateway)?,
+                next_reading: start + offset,
+                last_reading: start,
+            })
+        })
+        .collect()
+}
+
+// Anomalous readings go out as tracked critical alerts; the rest are
+// fire-and-forget. A lost reading still uses up its sequence number.
+fn send_reading(device: &mut Device, now: Instant, stats: &mut SimulationStats) {
+    let elapsed = now.duration_since(device.last_reading);
+    device.last_reading = now;
+    
+    let reading = match device.sensor.next_reading(now_ms(), elapsed) {
+        Ok(reading) => reading,
+        Err(_) => {
+            stats.send_errors += 1;
+            return;
+        }
+    };
+    stats.readings += 1;
+    stats.anomalies += u64::from(reading.anomalous);
+    
+    if reading.lost {
+        device.client.skip_sequence();
+        stats.lost += 1;
+        return;
+    }
+    
+    let result = if reading.anomalous {
+        device.client.send_critical(&reading.payload)
+    } else {
+        device.client.send(&reading.payload)
+    };
+    
+    match result {
+        Ok(_) => stats.sent += 1,
+        Err(_) => stats.send_errors += 1,
+    }
+}
+
+fn service(device: &mut Device, stats: &mut SimulationStats) {
+    if device.client.is_idle() {
+        return;
+    }
+    
+    if device.client.poll().is_err() {
+        stats.send_errors += 1;
+    }
+    
+    for event in device.client.events() {
+        stats.record_event(event);
+    }
+}
+
+fn report(stats: &SimulationStats, devices: &[Device], elapsed: Duration) {
+    let mean_battery = devices.iter().map(|device| device.sensor.battery_level()).sum::<f64>() / devices.len() as f64;
+    
+    eprintln!(
+        "[{:>7.1}s] readings {} sent {} lost {} anomalies {} acked {} exhausted {} rejected {} errors {} battery {:.1}%",
+        elapsed.as_secs_f64(),
+        stats.readings,
+        stats.sent,
+        stats.lost,
+        stats.anomalies,
+        stats.acked,
+        stats.exhausted,
+        stats.rejected,
+        stats.send_errors,
+        mean_battery,
+    );
+}
+
+pub fn simulate(options: &Options) -> cynda_core::Result<SimulationStats> {
+    let gateway = cynda_core::socket::resolve(options.gateway.as_str())?;
+    let start = Instant::now();
+    let deadline = options.duration.map(|duration| start + duration);
+    let interval = options.send_interval();
+    
+    let mut devices = connect_devices(options, gateway, start)?;
+    let mut stats = SimulationStats::default();
+    let mut next_report = start + REPORT_INTERVAL;
+    
+    loop {
+        let now = Instant::now();
+        if deadline.is_some_and(|deadline| now >= deadline) {
+            break;
+        }
+        
+        for device in devices.iter_mut() {
+            if device.next_reading <= now {
+                send_reading(device, now, &mut stats);
+                device.next_reading += interval;
+            }
+            service(device, &mut stats);
+        }
+        
+        if now >= next_report {
+            report(&stats, &devices, now.duration_since(start));
+            next_report += REPORT_INTERVAL;
+        }
+        
+        let next_reading = devices.iter().map(|device| device.next_reading).min().unwrap_or(now);
+        let wakeup = next_reading.min(now + MAX_IDLE_SLEEP);
+        std::thread::sleep(wakeup.saturating_duration_since(Instant::now()));
+    }
+    
+    let drain_deadline = Instant::now() + DRAIN_TIMEOUT;
+    while Instant::now() < drain_deadline && devices.iter().any(|device| !device.client.is_idle()) {
+        for device in devices.iter_mut() {
+            service(device, &mut stats);
+        }
+        std::thread::sleep(MAX_IDLE_SLEEP);
+    }
+    
+    report(&stats, &devices, start.elapsed());
+    Ok(stats)
+}
+
+fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("cynda-simulate: {}\n{}", message, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    
+    eprintln!(
+        "cynda-simulate: {} sensor(s) at {} Hz against {}",
+        options.sensors, options.rate_hz, options.gateway,
+    );
+    
+    match simulate(&options) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("cynda-simulate: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cynda_core::server::GatewayServer;
+    
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(str::to_string).collect()
+    }
+    
+    #[test]
+    fn test_parse_options() {
+        let options = Options::parse(args(
+            "127.0.0.1:8080 --sensors 50 --rate 20 --loss 0.1 --drain knee:5 --duration 2.5",
+        )).unwrap();
+        
+        assert_eq!(options.gateway, "127.0.0.1:8080");
+        assert_eq!(options.sensors, 50);
+        assert_eq!(options.send_interval(), Duration::from_millis(50));
+        assert_eq!(options.loss_probability, 0.1);
+        assert_eq!(options.drain, DrainCurve::Knee { percent_per_hour: 5.0 });
+        assert_eq!(options.duration, Some(Duration::from_millis(2500)));
+        
+        assert!(Options::parse(args("--sensors 5")).is_err());
+        assert!(Options::parse(args("127.0.0.1:8080 --sensors")).is_err());
+        assert!(Options::parse(args("127.0.0.1:8080 --loss 1.5")).is_err());
+        assert!(Options::parse(args("127.0.0.1:8080 --rate 0")).is_err());
+        assert!(Options::parse(args("127.0.0.1:8080 --rate 1e-300")).is_err());
+        assert!(Options::parse(args("127.0.0.1:8080 --duration -1")).is_err());
+        assert!(Options::parse(args("127.0.0.1:8080 --duration nan")).is_err());
+        assert!(Options::parse(args("127.0.0.1:8080 --verbose 1")).is_err());
+    }
+    
+    #[test]
+    fn test_simulate_against_gateway() {
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_poll_interval_ms(20)
+            .spawn()
+            .unwrap();
+        
+        let options = Options {
+            sensors: 4,
+            rate_hz: 50.0,
+            anomaly_probability: 0.5,
+            loss_probability: 0.2,
+            duration: Some(Duration::from_millis(300)),
+            ..Options::parse(args(&gateway.local_address().to_string())).unwrap()
+        };
+        
+        let stats = simulate(&options).unwrap();
+        assert!(stats.readings > 0);
+        assert_eq!(stats.readings, stats.sent + stats.lost);
+        assert!(stats.anomalies > 0 && stats.lost > 0);
+        assert_eq!(stats.send_errors, 0);
+        assert!(stats.acked > 0);
+        
+        let metrics = gateway.metrics();
+        assert!(metrics.received > 0 && metrics.received <= stats.sent);
+        gateway.shutdown();
+    }
+}
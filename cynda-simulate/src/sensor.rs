@@ -0,0 +1,189 @@
+use std::time::Duration;
+
+use cynda_core::contracts::{SensorPayload, ANOMALY_VECTOR_SIZE};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+pub const SENSOR_MODEL_VERSION: u16 = 1;
+
+pub const READING_TTL_MS: u16 = 30_000;
+
+// Below this level a Li-ion cell's voltage falls off and the reported charge
+// drops much faster.
+pub const KNEE_BATTERY_PERCENT: f64 = 20.0;
+
+pub const KNEE_DRAIN_FACTOR: f64 = 4.0;
+
+// Quiet components stay well under the gateway's default critical score
+// threshold; injected anomalies sit above it.
+const QUIET_COMPONENT_MAX: f32 = 0.1;
+
+const ANOMALY_COMPONENT_MIN: f32 = 0.85;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DrainCurve {
+    None,
+    
+    Linear { percent_per_hour: f64 },
+    
+    Knee { percent_per_hour: f64 },
+}
+
+impl DrainCurve {
+    // `none`, `linear:<percent per hour>` or `knee:<percent per hour>`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (kind, rate) = text.split_once(':').unwrap_or((text, ""));
+        let percent_per_hour = || rate.parse::<f64>().ok().filter(|rate| *rate >= 0.0);
+        
+        match kind {
+            "none" if rate.is_empty() => Some(Self::None),
+            "linear" => percent_per_hour().map(|percent_per_hour| Self::Linear { percent_per_hour }),
+            "knee" => percent_per_hour().map(|percent_per_hour| Self::Knee { percent_per_hour }),
+            _ => None,
+        }
+    }
+    
+    pub fn drain(&self, level: f64, elapsed: Duration) -> f64 {
+        let hours = elapsed.as_secs_f64() / 3600.0;
+        let drained = match *self {
+            Self::None => 0.0,
+            Self::Linear { percent_per_hour } => percent_per_hour * hours,
+            Self::Knee { percent_per_hour } if level <= KNEE_BATTERY_PERCENT => {
+                percent_per_hour * KNEE_DRAIN_FACTOR * hours
+            }
+            Self::Knee { percent_per_hour } => percent_per_hour * hours,
+        };
+        
+        (level - drained).max(0.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Reading {
+    pub payload: SensorPayload,
+    
+    pub anomalous: bool,
+    
+    // Decided up front so the caller can skip the send but still consume
+    // the sequence number.
+    pub lost: bool,
+}
+
+// Generates the readings of one emulated device. Owns no socket, so it can be
+// driven by any client or by tests directly.
+pub struct VirtualSensor {
+    device_id: u32,
+    battery_level: f64,
+    drain: DrainCurve,
+    anomaly_probability: f64,
+    loss_probability: f64,
+    rng: StdRng,
+}
+
+impl VirtualSensor {
+    pub fn new(device_id: u32, seed: u64) -> Self {
+        Self {
+            device_id,
+            battery_level: 100.0,
+            drain: DrainCurve::None,
+            anomaly_probability: 0.0,
+            loss_probability: 0.0,
+            rng: StdRng::seed_from_u64(seed ^ u64::from(device_id)),
+        }
+    }
+    
+    pub fn with_battery_level(mut self, percent: f64) -> Self {
+        self.battery_level = percent.clamp(0.0, 100.0);
+        self
+    }
+    
+    pub fn with_drain(mut self, drain: DrainCurve) -> Self {
+        self.drain = drain;
+        self
+    }
+    
+    pub fn with_anomaly_probability(mut self, probability: f64) -> Self {
+        self.anomaly_probability = probability.clamp(0.0, 1.0);
+        self
+    }
+    
+    pub fn with_loss_probability(mut self, probability: f64) -> Self {
+        self.loss_probability = probability.clamp(0.0, 1.0);
+        self
+    }
+    
+    pub fn battery_level(&self) -> f64 {
+        self.battery_level
+    }
+    
+    // `elapsed` is the time since the previous reading and drives the
+    // battery curve.
+    pub fn next_reading(&mut self, timestamp_ms: u64, elapsed: Duration) -> cynda_core::Result<Reading> {
+        self.battery_level = self.drain.drain(self.battery_level, elapsed);
+        
+        let anomalous = self.rng.gen_bool(self.anomaly_probability);
+        let lost = self.rng.gen_bool(self.loss_probability);
+        
+        let range = if anomalous { ANOMALY_COMPONENT_MIN..1.0 } else { 0.0..QUIET_COMPONENT_MAX };
+        let mut vector = [0.0f32; ANOMALY_VECTOR_SIZE];
+        for component in vector.iter_mut() {
+            *component = self.rng.gen_range(range.clone());
+        }
+        
+        let payload = SensorPayload::new(
+            self.device_id,
+            timestamp_ms,
+            SENSOR_MODEL_VERSION,
+            self.battery_level.ceil() as u8,
+            READING_TTL_MS,
+            0,
+            vector,
+        )?;
+        
+        Ok(Reading { payload, anomalous, lost })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_drain_curves() {
+        assert_eq!(DrainCurve::parse("none"), Some(DrainCurve::None));
+        assert_eq!(DrainCurve::parse("linear:2.5"), Some(DrainCurve::Linear { percent_per_hour: 2.5 }));
+        assert_eq!(DrainCurve::parse("knee:10"), Some(DrainCurve::Knee { percent_per_hour: 10.0 }));
+        assert_eq!(DrainCurve::parse("linear"), None);
+        assert_eq!(DrainCurve::parse("linear:-1"), None);
+        assert_eq!(DrainCurve::parse("cubic:1"), None);
+        
+        let hour = Duration::from_secs(3600);
+        assert_eq!(DrainCurve::Linear { percent_per_hour: 10.0 }.drain(50.0, hour), 40.0);
+        assert_eq!(DrainCurve::Linear { percent_per_hour: 10.0 }.drain(5.0, hour), 0.0);
+        assert_eq!(DrainCurve::Knee { percent_per_hour: 2.0 }.drain(50.0, hour), 48.0);
+        assert_eq!(DrainCurve::Knee { percent_per_hour: 2.0 }.drain(20.0, hour), 12.0);
+    }
+    
+    #[test]
+    fn test_readings_follow_probabilities() {
+        let mut quiet = VirtualSensor::new(7, 1);
+        let mut faulty = VirtualSensor::new(8, 1)
+            .with_anomaly_probability(1.0)
+            .with_loss_probability(1.0)
+            .with_battery_level(30.0)
+            .with_drain(DrainCurve::Linear { percent_per_hour: 3600.0 });
+        
+        for timestamp in 0..10 {
+            let reading = quiet.next_reading(timestamp, Duration::from_secs(1)).unwrap();
+            assert!(!reading.anomalous && !reading.lost);
+            assert!(reading.payload.anomaly_ai_vector.iter().all(|&x| x < QUIET_COMPONENT_MAX));
+            assert_eq!(reading.payload.battery_level_percent, 100);
+            
+            let reading = faulty.next_reading(timestamp, Duration::from_secs(1)).unwrap();
+            assert!(reading.anomalous && reading.lost);
+            assert!(reading.payload.anomaly_ai_vector.iter().all(|&x| x >= ANOMALY_COMPONENT_MIN));
+        }
+        
+        assert_eq!(faulty.battery_level(), 20.0);
+    }
+}
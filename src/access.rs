@@ -0,0 +1,101 @@
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+
+use crate::errors::{CyDnAError, Result};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccessMetrics {
+    pub allowed: u64,
+    
+    pub rejected: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DeviceAccessList {
+    devices: HashSet<u32>,
+    ranges: Vec<RangeInclusive<u32>>,
+    denied: HashSet<u32>,
+    metrics: AccessMetrics,
+}
+
+impl DeviceAccessList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    pub fn with_device(mut self, device_id: u32) -> Self {
+        self.allow_device(device_id);
+        self
+    }
+    
+    pub fn with_range(mut self, range: RangeInclusive<u32>) -> Self {
+        self.allow_range(range);
+        self
+    }
+    
+    pub fn allow_device(&mut self, device_id: u32) {
+        self.denied.remove(&device_id);
+        self.devices.insert(device_id);
+    }
+    
+    pub fn allow_range(&mut self, range: RangeInclusive<u32>) {
+        if !range.is_empty() {
+            self.ranges.push(range);
+        }
+    }
+    
+    // Explicit denials win over both single entries and ranges, so a
+    // compromised sensor can be cut off without splitting its range.
+    pub fn deny_device(&mut self, device_id: u32) {
+        self.devices.remove(&device_id);
+        self.denied.insert(device_id);
+    }
+    
+    pub fn is_allowed(&self, device_id: u32) -> bool {
+        if self.denied.contains(&device_id) {
+            return false;
+        }
+        
+        self.devices.contains(&device_id)
+            || self.ranges.iter().any(|range| range.contains(&device_id))
+    }
+    
+    pub fn check(&mut self, device_id: u32) -> Result<()> {
+        if self.is_allowed(device_id) {
+            self.metrics.allowed += 1;
+            Ok(())
+        } else {
+            self.metrics.rejected += 1;
+            Err(CyDnAError::DeviceNotAllowed(device_id))
+        }
+    }
+    
+    pub fn metrics(&self) -> AccessMetrics {
+        self.metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_allow_list_and_ranges() {
+        let mut acl = DeviceAccessList::new()
+            .with_device(7)
+            .with_range(100..=199);
+        
+        assert!(acl.check(7).is_ok());
+        assert!(acl.check(150).is_ok());
+        assert!(matches!(acl.check(200), Err(CyDnAError::DeviceNotAllowed(200))));
+        
+        acl.deny_device(150);
+        assert!(acl.check(150).is_err());
+        assert!(acl.is_allowed(151));
+        
+        acl.allow_device(150);
+        assert!(acl.is_allowed(150));
+        
+        assert_eq!(acl.metrics(), AccessMetrics { allowed: 2, rejected: 2 });
+    }
+}
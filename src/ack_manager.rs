@@ -1,46 +1,96 @@
-use std::net::UdpSocket;
-use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::collections::{HashMap, VecDeque};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use rkyv::to_bytes;
+use rkyv::{check_archived_root, to_bytes};
 
-use crate::contracts::{AckPacket, SensorPayload};
+use crate::clock::{Clock, SharedClock, SystemClock};
+use crate::contracts::{AckPacket, ExtendedAckPacket, NackReason, SensorPayload};
 use crate::errors::{CyDnAError, Result};
+use crate::framing::{encode_frame, FrameHeader, MessageType};
+use crate::transport::FrameTransport;
+
+// Longest `send_windowed` blocks on the socket before re-reading its clock.
+pub const WINDOW_CLOCK_POLL_MS: u64 = 100;
 
 pub struct AckManager;
 
+#[derive(Debug, Clone, Copy)]
+pub enum AckMessage {
+    Single(AckPacket),
+    
+    Extended(ExtendedAckPacket),
+}
+
 impl AckManager {
-    fn serialize_ack(ack: &AckPacket) -> Result<Vec<u8>> {
+    pub(crate) fn serialize_ack(ack: &AckPacket) -> Result<Vec<u8>> {
         to_bytes::<_, 256>(ack)
             .map(|aligned_vec| aligned_vec.to_vec())
             .map_err(|_| CyDnAError::SerializationError(
-                "Failed to serialize ACK packet".to_string()
+                "Failed to serialize ACK packet"
             ))
     }
     
+    pub(crate) fn encode_ack(ack: &AckPacket) -> Result<Vec<u8>> {
+        let bytes = Self::serialize_ack(ack)?;
+        
+        encode_frame(MessageType::Ack, &bytes)
+    }
+    
     pub fn send_ack(
         socket: &UdpSocket,
         device_unique_id: u32,
         original_timestamp_ms: u64,
-        destination: &str,
+        destination: impl ToSocketAddrs,
     ) -> Result<usize> {
         let ack = AckPacket::ack(device_unique_id, original_timestamp_ms);
-        let bytes = Self::serialize_ack(&ack)?;
+        let bytes = Self::encode_ack(&ack)?;
         
         socket.send_to(&bytes, destination)
-            .map_err(|e| CyDnAError::IoError(e.to_string()))
+            .map_err(|e| CyDnAError::IoError(e.kind()))
     }
     
     pub fn send_nack(
         socket: &UdpSocket,
         device_unique_id: u32,
         original_timestamp_ms: u64,
-        destination: &str,
+        destination: impl ToSocketAddrs,
     ) -> Result<usize> {
         let nack = AckPacket::nack(device_unique_id, original_timestamp_ms);
-        let bytes = Self::serialize_ack(&nack)?;
+        let bytes = Self::encode_ack(&nack)?;
         
         socket.send_to(&bytes, destination)
-            .map_err(|e| CyDnAError::IoError(e.to_string()))
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    pub fn send_extended_ack(
+        socket: &UdpSocket,
+        ack: &ExtendedAckPacket,
+        destination: impl ToSocketAddrs,
+    ) -> Result<usize> {
+        let bytes = to_bytes::<_, 256>(ack)
+            .map_err(|_| CyDnAError::SerializationError(
+                "Failed to serialize extended ACK packet"
+            ))?;
+        let frame = encode_frame(MessageType::ExtendedAck, &bytes)?;
+        
+        socket.send_to(&frame, destination)
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    pub fn send_nack_with_reason(
+        socket: &UdpSocket,
+        device_unique_id: u32,
+        original_timestamp_ms: u64,
+        reason: NackReason,
+        destination: impl ToSocketAddrs,
+    ) -> Result<usize> {
+        let nack = AckPacket::nack_with_reason(device_unique_id, original_timestamp_ms, reason);
+        let bytes = Self::encode_ack(&nack)?;
+        
+        socket.send_to(&bytes, destination)
+            .map_err(|e| CyDnAError::IoError(e.kind()))
     }
     
     pub fn wait_for_ack(
@@ -50,31 +100,76 @@ impl AckManager {
         buffer: &mut [u8],
     ) -> Result<bool> {
         match socket.recv_from(buffer) {
-            Ok((bytes_received, _)) => {
-                if bytes_received < 16 {
-                    return Ok(false);
-                }
-                
-                use rkyv::check_archived_root;
-                let archived = check_archived_root::<AckPacket>(&buffer[..bytes_received])
-                    .map_err(|_| CyDnAError::DeserializationError(
-                        "Failed to parse ACK packet".to_string()
-                    ))?;
-                
-                if archived.device_unique_id == device_unique_id 
-                    && archived.original_timestamp_ms == original_timestamp_ms
-                    && archived.is_ack() {
-                    Ok(true)
-                } else {
-                    Ok(false)
-                }
-            }
+            Ok((bytes_received, _)) => Self::matches_ack(
+                &buffer[..bytes_received],
+                device_unique_id,
+                original_timestamp_ms,
+            ),
             Err(e) if e.kind() == std::io::ErrorKind::WouldBlock 
                    || e.kind() == std::io::ErrorKind::TimedOut => {
                 Ok(false)
             }
-            Err(e) => Err(CyDnAError::IoError(e.to_string())),
+            Err(e) => Err(CyDnAError::IoError(e.kind())),
+        }
+    }
+    
+    pub(crate) fn matches_ack(
+        bytes: &[u8],
+        device_unique_id: u32,
+        original_timestamp_ms: u64,
+    ) -> Result<bool> {
+        Ok(match Self::parse_ack(bytes)? {
+            Some(ack) => ack.device_unique_id == device_unique_id
+                && ack.original_timestamp_ms == original_timestamp_ms
+                && ack.is_ack(),
+            None => false,
+        })
+    }
+    
+    pub fn parse_ack_message(bytes: &[u8]) -> Result<Option<AckMessage>> {
+        let header = FrameHeader::decode(bytes)?;
+        
+        match header.message_type {
+            MessageType::Ack => Ok(Self::parse_ack(bytes)?.map(AckMessage::Single)),
+            MessageType::ExtendedAck => {
+                let archived = check_archived_root::<ExtendedAckPacket>(&bytes[header.body_range()])
+                    .map_err(|_| CyDnAError::DeserializationError(
+                        "Failed to parse extended ACK packet"
+                    ))?;
+                
+                Ok(Some(AckMessage::Extended(ExtendedAckPacket::new(
+                    archived.device_unique_id,
+                    archived.cumulative_sequence,
+                    archived.selective_bitmap,
+                ))))
+            }
+            _ => Ok(None),
+        }
+    }
+    
+    pub(crate) fn parse_ack(bytes: &[u8]) -> Result<Option<AckPacket>> {
+        let header = FrameHeader::decode(bytes)?;
+        if header.message_type != MessageType::Ack {
+            return Ok(None);
         }
+        
+        let body = &bytes[header.body_range()];
+        if body.len() < 16 {
+            return Ok(None);
+        }
+        
+        let archived = check_archived_root::<AckPacket>(body)
+            .map_err(|_| CyDnAError::DeserializationError(
+                "Failed to parse ACK packet"
+            ))?;
+        
+        Ok(Some(AckPacket {
+            device_unique_id: archived.device_unique_id,
+            original_timestamp_ms: archived.original_timestamp_ms,
+            ack_type: archived.ack_type,
+            nack_reason: archived.nack_reason,
+            _padding: [0; 2],
+        }))
     }
     
     pub fn calculate_backoff_ms(
@@ -92,16 +187,31 @@ impl AckManager {
     pub fn send_critical_alert(
         socket: &UdpSocket,
         payload: &SensorPayload,
-        gateway_address: &str,
+        gateway_address: impl ToSocketAddrs,
+        max_retries: u32,
+        base_timeout_ms: u64,
+    ) -> Result<bool> {
+        let gateway_address = crate::socket::resolve(gateway_address)?;
+        let mut transport = crate::transport::UdpPeer::new(socket, gateway_address);
+        
+        Self::send_critical_alert_via(&mut transport, payload, max_retries, base_timeout_ms)
+    }
+    
+    // Same retry loop over any transport, e.g. TCP or the impaired in-process
+    // link of the `testing` feature.
+    pub fn send_critical_alert_via(
+        transport: &mut impl FrameTransport,
+        payload: &SensorPayload,
         max_retries: u32,
         base_timeout_ms: u64,
     ) -> Result<bool> {
         use crate::transmitter::Transmitter;
         
+        let frame = Transmitter::frame_payload(payload)?;
         let mut ack_buffer = vec![0u8; 256];
         
         for attempt in 0..max_retries {
-            Transmitter::send(socket, payload, gateway_address)?;
+            transport.send_frame(&frame)?;
             
             let timeout_ms = Self::calculate_backoff_ms(
                 attempt,
@@ -109,16 +219,29 @@ impl AckManager {
                 base_timeout_ms * 10, // Max 10x base timeout
             );
             
-            socket.set_read_timeout(Some(Duration::from_millis(timeout_ms)))
-                .map_err(|e| CyDnAError::IoError(e.to_string()))?;
+            let received = transport.recv_frame(&mut ack_buffer, Some(Duration::from_millis(timeout_ms)))?;
+            let ack = match received {
+                Some(bytes_received) => Self::parse_ack(&ack_buffer[..bytes_received])?,
+                None => None,
+            };
             
-            if Self::wait_for_ack(
-                socket,
-                payload.device_unique_id,
-                payload.timestamp_ms_utc,
-                &mut ack_buffer,
-            )? {
-                return Ok(true);
+            match ack {
+                Some(ack) if ack.device_unique_id == payload.device_unique_id
+                    && ack.original_timestamp_ms == payload.timestamp_ms_utc => {
+                    if ack.is_ack() {
+                        return Ok(true);
+                    }
+                    
+                    let reason = ack.reason();
+                    if !reason.should_retransmit() {
+                        return Err(CyDnAError::PayloadRejected(reason));
+                    }
+                    
+                    if !reason.retransmit_immediately() {
+                        std::thread::sleep(Duration::from_millis(timeout_ms));
+                    }
+                }
+                _ => {}
             }
             
             if attempt == max_retries - 1 {
@@ -128,6 +251,163 @@ impl AckManager {
         
         Err(CyDnAError::MaxRetriesExceeded)
     }
+    
+    pub fn send_windowed(
+        socket: &UdpSocket,
+        payloads: &[SensorPayload],
+        gateway_address: impl ToSocketAddrs,
+        window_size: usize,
+        max_retries: u32,
+        base_timeout_ms: u64,
+    ) -> Result<WindowedTransmitReport> {
+        Self::send_windowed_with_clock(
+            socket, payloads, gateway_address, window_size, max_retries, base_timeout_ms, &SystemClock,
+        )
+    }
+    
+    // Retry deadlines are read from `clock`. The socket still waits for ACKs
+    // in real time, at most WINDOW_CLOCK_POLL_MS at a time, so the clock must
+    // advance while this runs.
+    pub fn send_windowed_with_clock(
+        socket: &UdpSocket,
+        payloads: &[SensorPayload],
+        gateway_address: impl ToSocketAddrs,
+        window_size: usize,
+        max_retries: u32,
+        base_timeout_ms: u64,
+        clock: &dyn Clock,
+    ) -> Result<WindowedTransmitReport> {
+        use crate::transmitter::Transmitter;
+        
+        let gateway_address = crate::socket::resolve(gateway_address)?;
+        let window_size = window_size.max(1);
+        let mut report = WindowedTransmitReport::default();
+        let mut in_flight: Vec<(usize, RetransmissionState)> = Vec::with_capacity(window_size);
+        let mut next_index = 0;
+        let mut ack_buffer = vec![0u8; 256];
+        
+        while next_index < payloads.len() || !in_flight.is_empty() {
+            while in_flight.len() < window_size && next_index < payloads.len() {
+                let payload = &payloads[next_index];
+                Transmitter::send(socket, payload, gateway_address)?;
+                report.transmissions += 1;
+                
+                let now = clock.now();
+                let mut state = RetransmissionState::new_at(
+                    payload.device_unique_id,
+                    payload.timestamp_ms_utc,
+                    now,
+                );
+                state.schedule_next_retry_at(base_timeout_ms, now);
+                in_flight.push((next_index, state));
+                next_index += 1;
+            }
+            
+            let now = clock.now();
+            let earliest = in_flight.iter()
+                .map(|(_, state)| state.next_retry)
+                .min()
+                .unwrap_or(now);
+            let wait = earliest
+                .saturating_duration_since(now)
+                .clamp(Duration::from_millis(1), Duration::from_millis(WINDOW_CLOCK_POLL_MS));
+            
+            socket.set_read_timeout(Some(wait))
+                .map_err(|e| CyDnAError::IoError(e.kind()))?;
+            
+            match Self::receive_ack_message(socket, &mut ack_buffer)? {
+                Some(AckMessage::Single(ack)) => {
+                    let matched = in_flight.iter().position(|(_, state)| {
+                        state.device_id == ack.device_unique_id
+                            && state.payload_timestamp_ms == ack.original_timestamp_ms
+                    });
+                    
+                    if let Some(pos) = matched {
+                        let reason = ack.reason();
+                        if ack.is_ack() {
+                            let (index, _) = in_flight.swap_remove(pos);
+                            report.acknowledged.push(index);
+                        } else if !reason.should_retransmit() {
+                            let (index, _) = in_flight.swap_remove(pos);
+                            report.rejected.push((index, reason));
+                        } else if reason.retransmit_immediately() {
+                            in_flight[pos].1.next_retry = clock.now();
+                        }
+                    }
+                }
+                Some(AckMessage::Extended(ack)) => {
+                    let mut pos = 0;
+                    while pos < in_flight.len() {
+                        let payload = &payloads[in_flight[pos].0];
+                        if payload.device_unique_id == ack.device_unique_id
+                            && ack.acknowledges(payload.sequence_number) {
+                            let (index, _) = in_flight.swap_remove(pos);
+                            report.acknowledged.push(index);
+                        } else {
+                            pos += 1;
+                        }
+                    }
+                }
+                None => {}
+            }
+            
+            let now = clock.now();
+            let mut pos = 0;
+            while pos < in_flight.len() {
+                if !in_flight[pos].1.is_ready_for_retry_at(now) {
+                    pos += 1;
+                    continue;
+                }
+                
+                if in_flight[pos].1.attempt >= max_retries {
+                    let (index, _) = in_flight.swap_remove(pos);
+                    report.failed.push(index);
+                    continue;
+                }
+                
+                let (index, state) = &mut in_flight[pos];
+                Transmitter::send(socket, &payloads[*index], gateway_address)?;
+                report.transmissions += 1;
+                state.schedule_next_retry_at(base_timeout_ms, now);
+                pos += 1;
+            }
+        }
+        
+        report.acknowledged.sort_unstable();
+        report.failed.sort_unstable();
+        report.rejected.sort_unstable_by_key(|(index, _)| *index);
+        Ok(report)
+    }
+    
+    // A stray or malformed datagram reads as nothing received, so one bad
+    // packet cannot abort a window with payloads still in flight.
+    fn receive_ack_message(socket: &UdpSocket, buffer: &mut [u8]) -> Result<Option<AckMessage>> {
+        match socket.recv_from(buffer) {
+            Ok((bytes_received, _)) => Ok(Self::parse_ack_message(&buffer[..bytes_received]).unwrap_or(None)),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock
+                   || e.kind() == std::io::ErrorKind::TimedOut => {
+                Ok(None)
+            }
+            Err(e) => Err(CyDnAError::IoError(e.kind())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WindowedTransmitReport {
+    pub acknowledged: Vec<usize>,
+    
+    pub failed: Vec<usize>,
+    
+    pub rejected: Vec<(usize, NackReason)>,
+    
+    pub transmissions: u32,
+}
+
+impl WindowedTransmitReport {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && self.rejected.is_empty()
+    }
 }
 
 pub struct RetransmissionState {
@@ -144,7 +424,10 @@ pub struct RetransmissionState {
 
 impl RetransmissionState {
     pub fn new(device_id: u32, payload_timestamp_ms: u64) -> Self {
-        let now = Instant::now();
+        Self::new_at(device_id, payload_timestamp_ms, Instant::now())
+    }
+    
+    pub fn new_at(device_id: u32, payload_timestamp_ms: u64, now: Instant) -> Self {
         Self {
             device_id,
             payload_timestamp_ms,
@@ -155,19 +438,27 @@ impl RetransmissionState {
     }
     
     pub fn is_ready_for_retry(&self) -> bool {
-        Instant::now() >= self.next_retry
+        self.is_ready_for_retry_at(Instant::now())
+    }
+    
+    pub fn is_ready_for_retry_at(&self, now: Instant) -> bool {
+        now >= self.next_retry
     }
     
     pub fn schedule_next_retry(&mut self, base_timeout_ms: u64) {
+        self.schedule_next_retry_at(base_timeout_ms, Instant::now());
+    }
+    
+    pub fn schedule_next_retry_at(&mut self, base_timeout_ms: u64, now: Instant) {
         let backoff_ms = AckManager::calculate_backoff_ms(
             self.attempt,
             base_timeout_ms,
             base_timeout_ms * 10,
         );
         
-        self.next_retry = Instant::now() + Duration::from_millis(backoff_ms);
+        self.next_retry = now + Duration::from_millis(backoff_ms);
         self.attempt += 1;
-        self.last_sent = Instant::now();
+        self.last_sent = now;
     }
     
     pub fn is_exhausted(&self) -> bool {
@@ -175,6 +466,242 @@ impl RetransmissionState {
     }
 }
 
+#[derive(Debug, Clone)]
+pub enum RetransmissionEvent {
+    Acked { device_id: u32, timestamp_ms: u64, attempts: u32 },
+    
+    Exhausted { payload: SensorPayload, attempts: u32 },
+    
+    Rejected { payload: SensorPayload, reason: NackReason },
+}
+
+struct PendingPayload {
+    payload: SensorPayload,
+    state: RetransmissionState,
+}
+
+pub struct RetransmissionScheduler {
+    pending: HashMap<(u32, u64), PendingPayload>,
+    events: VecDeque<RetransmissionEvent>,
+    rtt_samples: VecDeque<Duration>,
+    max_retries: u32,
+    base_timeout_ms: u64,
+    clock: SharedClock,
+}
+
+impl RetransmissionScheduler {
+    pub fn new(max_retries: u32, base_timeout_ms: u64) -> Self {
+        Self {
+            pending: HashMap::new(),
+            events: VecDeque::new(),
+            rtt_samples: VecDeque::new(),
+            max_retries,
+            base_timeout_ms,
+            clock: Arc::new(SystemClock),
+        }
+    }
+    
+    // Retry timers and RTT samples are read from `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+    
+    // Meant for setup: timers already running keep deadlines read from the
+    // previous clock.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock;
+    }
+    
+    // Applies to retries scheduled from now on; in-flight timers are kept.
+    pub fn set_retry_policy(&mut self, max_retries: u32, base_timeout_ms: u64) {
+        self.max_retries = max_retries;
+        self.base_timeout_ms = base_timeout_ms;
+    }
+    
+    // Registers a payload that has just been sent for the first time.
+    pub fn track(&mut self, payload: SensorPayload) -> bool {
+        let key = (payload.device_unique_id, payload.timestamp_ms_utc);
+        if self.pending.contains_key(&key) {
+            return false;
+        }
+        
+        let now = self.clock.now();
+        let mut state = RetransmissionState::new_at(key.0, key.1, now);
+        state.schedule_next_retry_at(self.base_timeout_ms, now);
+        self.pending.insert(key, PendingPayload { payload, state });
+        true
+    }
+    
+    pub fn cancel(&mut self, device_id: u32, timestamp_ms: u64) -> Option<SensorPayload> {
+        self.pending.remove(&(device_id, timestamp_ms)).map(|entry| entry.payload)
+    }
+    
+    pub fn next_wakeup(&self) -> Option<Instant> {
+        self.pending.values().map(|entry| entry.state.next_retry).min()
+    }
+    
+    pub fn time_until_next_wakeup(&self) -> Option<Duration> {
+        self.next_wakeup()
+            .map(|wakeup| wakeup.saturating_duration_since(self.clock.now()))
+    }
+    
+    pub fn handle_ack(&mut self, ack: &AckPacket) -> bool {
+        let key = (ack.device_unique_id, ack.original_timestamp_ms);
+        
+        if !ack.is_ack() {
+            let reason = ack.reason();
+            
+            if !reason.should_retransmit() {
+                return match self.pending.remove(&key) {
+                    Some(entry) => {
+                        self.events.push_back(RetransmissionEvent::Rejected {
+                            payload: entry.payload,
+                            reason,
+                        });
+                        true
+                    }
+                    None => false,
+                };
+            }
+            
+            return match self.pending.get_mut(&key) {
+                Some(entry) => {
+                    if reason.retransmit_immediately() {
+                        entry.state.next_retry = self.clock.now();
+                    }
+                    true
+                }
+                None => false,
+            };
+        }
+        
+        match self.pending.remove(&key) {
+            Some(entry) => {
+                self.record_rtt(&entry.state);
+                self.events.push_back(RetransmissionEvent::Acked {
+                    device_id: key.0,
+                    timestamp_ms: key.1,
+                    attempts: entry.state.attempt,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+    
+    pub fn handle_extended_ack(&mut self, ack: &ExtendedAckPacket) -> usize {
+        let confirmed: Vec<(u32, u64)> = self.pending.iter()
+            .filter(|(key, entry)| {
+                key.0 == ack.device_unique_id && ack.acknowledges(entry.payload.sequence_number)
+            })
+            .map(|(key, _)| *key)
+            .collect();
+        
+        for key in &confirmed {
+            if let Some(entry) = self.pending.remove(key) {
+                self.record_rtt(&entry.state);
+                self.events.push_back(RetransmissionEvent::Acked {
+                    device_id: key.0,
+                    timestamp_ms: key.1,
+                    attempts: entry.state.attempt,
+                });
+            }
+        }
+        
+        confirmed.len()
+    }
+    
+    // Karn's rule: only payloads acknowledged on their first transmission
+    // give an unambiguous RTT sample.
+    fn record_rtt(&mut self, state: &RetransmissionState) {
+        if state.attempt == 1 {
+            if self.rtt_samples.len() >= 64 {
+                self.rtt_samples.pop_front();
+            }
+            let rtt = self.clock.now().saturating_duration_since(state.last_sent);
+            self.rtt_samples.push_back(rtt);
+        }
+    }
+    
+    pub fn drain_rtt_samples(&mut self) -> Vec<Duration> {
+        self.rtt_samples.drain(..).collect()
+    }
+    
+    pub fn handle_ack_datagram(&mut self, bytes: &[u8]) -> Result<bool> {
+        match AckManager::parse_ack_message(bytes)? {
+            Some(AckMessage::Single(ack)) => Ok(self.handle_ack(&ack)),
+            Some(AckMessage::Extended(ack)) => Ok(self.handle_extended_ack(&ack) > 0),
+            None => Ok(false),
+        }
+    }
+    
+    // Returns payloads whose retry timer fired and reschedules them; entries
+    // that used up their attempts are dropped and reported as Exhausted.
+    pub fn due_retransmissions(&mut self) -> Vec<SensorPayload> {
+        let now = self.clock.now();
+        let mut due = Vec::new();
+        let mut exhausted = Vec::new();
+        
+        for (key, entry) in self.pending.iter_mut() {
+            if entry.state.next_retry > now {
+                continue;
+            }
+            
+            if entry.state.attempt >= self.max_retries {
+                exhausted.push(*key);
+                continue;
+            }
+            
+            entry.state.schedule_next_retry_at(self.base_timeout_ms, now);
+            due.push(entry.payload);
+        }
+        
+        for key in exhausted {
+            if let Some(entry) = self.pending.remove(&key) {
+                self.events.push_back(RetransmissionEvent::Exhausted {
+                    payload: entry.payload,
+                    attempts: entry.state.attempt,
+                });
+            }
+        }
+        
+        due
+    }
+    
+    pub fn service(&mut self, socket: &UdpSocket, gateway_address: impl ToSocketAddrs) -> Result<usize> {
+        use crate::transmitter::Transmitter;
+        
+        let gateway_address = crate::socket::resolve(gateway_address)?;
+        let due = self.due_retransmissions();
+        for payload in &due {
+            Transmitter::send(socket, payload, gateway_address)?;
+        }
+        
+        Ok(due.len())
+    }
+    
+    pub fn poll_event(&mut self) -> Option<RetransmissionEvent> {
+        self.events.pop_front()
+    }
+    
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+    
+    // Stops tracking everything still unacknowledged, oldest first, without
+    // raising events; for handing the payloads over to persistent storage.
+    pub fn drain_pending(&mut self) -> Vec<SensorPayload> {
+        let mut pending: Vec<SensorPayload> = self.pending.drain().map(|(_, entry)| entry.payload).collect();
+        pending.sort_by_key(|payload| payload.timestamp_ms_utc);
+        pending
+    }
+    
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty() && self.events.is_empty()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct AckContext {
     pub device_id: u32,
@@ -194,10 +721,16 @@ impl AckContext {
         timestamp_ms: u64,
         is_ack: bool,
     ) -> Self {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis() as u64;
+        Self::from_clock(device_id, timestamp_ms, is_ack, &SystemClock)
+    }
+    
+    pub fn from_clock(
+        device_id: u32,
+        timestamp_ms: u64,
+        is_ack: bool,
+        clock: &dyn Clock,
+    ) -> Self {
+        let now = clock.now_ms();
         
         Self {
             device_id,
@@ -235,6 +768,334 @@ mod tests {
         assert!(!state.is_ready_for_retry()); // Just scheduled
     }
     
+    #[test]
+    fn test_send_windowed_retransmits_dropped_payload() {
+        use crate::receiver::Receiver;
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let payloads: Vec<_> = (1..=6)
+            .map(|id| SensorPayload::new(
+                id, 1000 + id as u64, 1, 50, 1000, id,
+                [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+            ).unwrap())
+            .collect();
+        
+        let gateway_thread = std::thread::spawn(move || {
+            let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+            let mut dropped = false;
+            let mut acked = 0;
+            while acked < 6 {
+                let (archived, _, sender) = Receiver::receive(&gateway, &mut buffer).unwrap();
+                if archived.device_unique_id == 3 && !dropped {
+                    dropped = true;
+                    gateway.send_to(b"not an ack", sender).unwrap();
+                    continue;
+                }
+                let (id, ts) = (archived.device_unique_id, archived.timestamp_ms_utc);
+                AckManager::send_ack(&gateway, id, ts, sender).unwrap();
+                acked += 1;
+            }
+        });
+        
+        let report = AckManager::send_windowed(&sensor, &payloads, &gateway_addr, 4, 3, 100)
+            .unwrap();
+        gateway_thread.join().unwrap();
+        
+        assert!(report.is_complete());
+        assert_eq!(report.acknowledged, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(report.transmissions, 7);
+    }
+    
+    #[test]
+    fn test_send_windowed_reports_failures() {
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sink = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sink_addr = sink.local_addr().unwrap().to_string();
+        
+        let payloads = vec![
+            SensorPayload::new(1, 1000, 1, 50, 1000, 1, [0.0; crate::contracts::ANOMALY_VECTOR_SIZE])
+                .unwrap(),
+            SensorPayload::new(2, 2000, 1, 50, 1000, 2, [0.0; crate::contracts::ANOMALY_VECTOR_SIZE])
+                .unwrap(),
+        ];
+        
+        let report = AckManager::send_windowed(&sensor, &payloads, &sink_addr, 2, 2, 5).unwrap();
+        
+        assert!(!report.is_complete());
+        assert_eq!(report.failed, vec![0, 1]);
+        assert_eq!(report.transmissions, 4);
+    }
+    
+    #[test]
+    fn test_send_windowed_follows_the_clock() {
+        use crate::clock::MockClock;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sink = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sink_addr = sink.local_addr().unwrap().to_string();
+        let payloads = vec![
+            SensorPayload::new(1, 1000, 1, 50, 1000, 1, [0.0; crate::contracts::ANOMALY_VECTOR_SIZE])
+                .unwrap(),
+        ];
+        
+        // Minute-long retry timers expire in milliseconds of real time.
+        let clock = MockClock::new(0);
+        let running = Arc::new(AtomicBool::new(true));
+        let ticker = {
+            let (clock, running) = (clock.clone(), Arc::clone(&running));
+            std::thread::spawn(move || while running.load(Ordering::SeqCst) {
+                clock.advance(Duration::from_secs(10));
+                std::thread::sleep(Duration::from_millis(2));
+            })
+        };
+        
+        let started = Instant::now();
+        let report = AckManager::send_windowed_with_clock(&sensor, &payloads, &sink_addr, 1, 2, 60_000, &clock)
+            .unwrap();
+        running.store(false, Ordering::SeqCst);
+        ticker.join().unwrap();
+        
+        assert_eq!((report.failed.clone(), report.transmissions), (vec![0], 2));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+    
+    #[test]
+    fn test_retransmission_scheduler() {
+        let payload = |id: u32| SensorPayload::new(
+            id, 1000 * id as u64, 1, 50, 1000, id,
+            [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap();
+        
+        let mut scheduler = RetransmissionScheduler::new(3, 0);
+        assert!(scheduler.next_wakeup().is_none());
+        
+        assert!(scheduler.track(payload(1)));
+        assert!(scheduler.track(payload(2)));
+        assert!(!scheduler.track(payload(2)));
+        assert_eq!(scheduler.pending_count(), 2);
+        assert!(scheduler.next_wakeup().is_some());
+        
+        assert!(scheduler.handle_ack(&AckPacket::ack(1, 1000)));
+        assert!(!scheduler.handle_ack(&AckPacket::ack(1, 1000)));
+        assert_eq!(scheduler.drain_rtt_samples().len(), 1);
+        assert!(matches!(
+            scheduler.poll_event(),
+            Some(RetransmissionEvent::Acked { device_id: 1, timestamp_ms: 1000, attempts: 1 })
+        ));
+        
+        assert_eq!(scheduler.due_retransmissions().len(), 1);
+        assert_eq!(scheduler.due_retransmissions().len(), 1);
+        assert!(scheduler.due_retransmissions().is_empty());
+        
+        match scheduler.poll_event() {
+            Some(RetransmissionEvent::Exhausted { payload, attempts }) => {
+                assert_eq!(payload.device_unique_id, 2);
+                assert_eq!(attempts, 3);
+            }
+            other => panic!("expected Exhausted, got {:?}", other),
+        }
+        assert!(scheduler.is_idle());
+    }
+    
+    #[test]
+    fn test_scheduler_waits_for_timer() {
+        let mut scheduler = RetransmissionScheduler::new(3, 10_000);
+        let payload = SensorPayload::new(
+            9, 1000, 1, 50, 1000, 9,
+            [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap();
+        
+        scheduler.track(payload);
+        assert!(scheduler.due_retransmissions().is_empty());
+        assert!(scheduler.time_until_next_wakeup().unwrap() > Duration::from_millis(5_000));
+        
+        assert!(scheduler.handle_ack(&AckPacket::nack(9, 1000)));
+        assert_eq!(scheduler.due_retransmissions().len(), 1);
+        assert_eq!(scheduler.cancel(9, 1000).map(|p| p.device_unique_id), Some(9));
+        assert!(scheduler.is_idle());
+    }
+    
+    #[test]
+    fn test_scheduler_backoff_on_mock_clock() {
+        use crate::clock::MockClock;
+        
+        let clock = MockClock::new(1_000);
+        let mut scheduler = RetransmissionScheduler::new(3, 100).with_clock(Arc::new(clock.clone()));
+        let payload = SensorPayload::new(
+            4, 1000, 1, 50, 1000, 4,
+            [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap();
+        
+        scheduler.track(payload);
+        assert_eq!(scheduler.time_until_next_wakeup(), Some(Duration::from_millis(100)));
+        clock.advance_ms(99);
+        assert!(scheduler.due_retransmissions().is_empty());
+        clock.advance_ms(1);
+        assert_eq!(scheduler.due_retransmissions().len(), 1);
+        
+        // The second wait doubles, and a wall-clock step changes nothing.
+        clock.set_epoch_ms(0);
+        clock.advance_ms(199);
+        assert!(scheduler.due_retransmissions().is_empty());
+        clock.advance_ms(1);
+        assert_eq!(scheduler.due_retransmissions().len(), 1);
+        
+        clock.advance_ms(400);
+        assert!(scheduler.due_retransmissions().is_empty());
+        assert!(matches!(
+            scheduler.poll_event(),
+            Some(RetransmissionEvent::Exhausted { attempts: 3, .. })
+        ));
+    }
+    
+    #[test]
+    fn test_scheduler_reacts_to_nack_reason() {
+        let mut scheduler = RetransmissionScheduler::new(3, 10_000);
+        let payload = |id: u32| SensorPayload::new(
+            id, 1000, 1, 50, 1000, id,
+            [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap();
+        
+        scheduler.track(payload(1));
+        scheduler.track(payload(2));
+        scheduler.track(payload(3));
+        
+        scheduler.handle_ack(&AckPacket::nack_with_reason(1, 1000, NackReason::CrcMismatch));
+        scheduler.handle_ack(&AckPacket::nack_with_reason(2, 1000, NackReason::RateLimited));
+        scheduler.handle_ack(&AckPacket::nack_with_reason(3, 1000, NackReason::ExpiredTtl));
+        
+        let due = scheduler.due_retransmissions();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].device_unique_id, 1);
+        
+        assert!(matches!(
+            scheduler.poll_event(),
+            Some(RetransmissionEvent::Rejected { reason: NackReason::ExpiredTtl, .. })
+        ));
+        assert_eq!(scheduler.pending_count(), 2);
+    }
+    
+    #[test]
+    fn test_windowed_with_extended_ack() {
+        use crate::receiver::Receiver;
+        use crate::sequence::SequenceTracker;
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let payloads: Vec<_> = (0..8)
+            .map(|seq| SensorPayload::new(
+                4, 1000 + seq as u64, 1, 50, 1000, seq,
+                [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+            ).unwrap().with_sequence_number(seq))
+            .collect();
+        
+        let gateway_thread = std::thread::spawn(move || {
+            let mut tracker = SequenceTracker::new();
+            let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+            let mut sender = None;
+            for _ in 0..8 {
+                let (archived, _, from) = Receiver::receive(&gateway, &mut buffer).unwrap();
+                tracker.observe(archived.device_unique_id, archived.sequence_number);
+                sender = Some(from);
+            }
+            let ack = tracker.extended_ack(4).unwrap();
+            AckManager::send_extended_ack(&gateway, &ack, sender.unwrap()).unwrap();
+        });
+        
+        let report = AckManager::send_windowed(&sensor, &payloads, &gateway_addr, 8, 3, 500)
+            .unwrap();
+        gateway_thread.join().unwrap();
+        
+        assert!(report.is_complete());
+        assert_eq!(report.acknowledged, (0..8).collect::<Vec<_>>());
+        assert_eq!(report.transmissions, 8);
+    }
+    
+    #[test]
+    fn test_scheduler_extended_ack() {
+        let mut scheduler = RetransmissionScheduler::new(3, 10_000);
+        for seq in 0..4u32 {
+            scheduler.track(SensorPayload::new(
+                2, 1000 + seq as u64, 1, 50, 1000, seq,
+                [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+            ).unwrap().with_sequence_number(seq));
+        }
+        
+        let ack = ExtendedAckPacket::new(2, 1, 0b10);
+        assert_eq!(scheduler.handle_extended_ack(&ack), 3);
+        assert_eq!(scheduler.pending_count(), 1);
+        assert!(scheduler.cancel(2, 1002).is_some());
+    }
+    
+    #[test]
+    fn test_critical_alert_stops_on_expired_nack() {
+        use crate::receiver::Receiver;
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let gateway_thread = std::thread::spawn(move || {
+            let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+            let (archived, _, sender) = Receiver::receive(&gateway, &mut buffer).unwrap();
+            let error = Receiver::check_ttl(archived, 10_000).unwrap_err();
+            AckManager::send_nack_with_reason(
+                &gateway,
+                archived.device_unique_id,
+                archived.timestamp_ms_utc,
+                NackReason::from_error(&error),
+                sender,
+            ).unwrap();
+        });
+        
+        let payload = SensorPayload::new(
+            5, 1000, 1, 50, 100, 5,
+            [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap();
+        let result = AckManager::send_critical_alert(&sensor, &payload, &gateway_addr, 3, 500);
+        gateway_thread.join().unwrap();
+        
+        assert!(matches!(result, Err(CyDnAError::PayloadRejected(NackReason::ExpiredTtl))));
+    }
+    
+    #[cfg(feature = "testing")]
+    #[test]
+    fn test_critical_alert_over_impaired_link() {
+        use crate::testing::{AckResponder, Impairment, LossyTransport};
+        
+        let payload = SensorPayload::new(
+            9, 5000, 1, 80, 1000, 0,
+            [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap();
+        let impairment = Impairment::new(3)
+            .with_drop(0.5)
+            .with_duplication(0.3)
+            .with_latency(Duration::from_millis(1), Duration::from_millis(2));
+        
+        let mut attempts = Vec::new();
+        for _ in 0..2 {
+            let (mut sensor, gateway) = LossyTransport::pair(impairment);
+            let responder = AckResponder::spawn(gateway);
+            assert!(AckManager::send_critical_alert_via(&mut sensor, &payload, 20, 10).unwrap());
+            assert!(responder.stop() >= 1);
+            attempts.push(sensor.outgoing_stats().sent);
+        }
+        assert_eq!(attempts[0], attempts[1]);
+        
+        let (mut sensor, gateway) = LossyTransport::pair(Impairment::new(3).with_drop(1.0));
+        let responder = AckResponder::spawn(gateway);
+        let result = AckManager::send_critical_alert_via(&mut sensor, &payload, 3, 5);
+        assert!(matches!(result, Err(CyDnAError::MaxRetriesExceeded)));
+        assert_eq!(sensor.outgoing_stats().sent, 3);
+        assert_eq!(responder.stop(), 0);
+    }
+    
     #[test]
     fn test_ack_context() {
         let ctx = AckContext::new(1, 1000, true);
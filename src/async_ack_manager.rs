@@ -0,0 +1,222 @@
+use std::time::Duration;
+
+use tokio::net::{ToSocketAddrs, UdpSocket};
+use tokio::time::{timeout_at, Instant};
+pub use tokio_util::sync::CancellationToken;
+
+use crate::ack_manager::AckManager;
+use crate::async_transmitter::AsyncTransmitter;
+use crate::contracts::{AckPacket, SensorPayload};
+use crate::errors::{CyDnAError, Result};
+
+pub struct AsyncAckManager;
+
+impl AsyncAckManager {
+    pub async fn wait_for_ack_until(
+        socket: &UdpSocket,
+        device_unique_id: u32,
+        original_timestamp_ms: u64,
+        buffer: &mut [u8],
+        deadline: Instant,
+    ) -> Result<bool> {
+        loop {
+            match Self::wait_for_response_until(
+                socket,
+                device_unique_id,
+                original_timestamp_ms,
+                buffer,
+                deadline,
+            ).await? {
+                Some(ack) if ack.is_ack() => return Ok(true),
+                Some(_) => continue,
+                None => return Ok(false),
+            }
+        }
+    }
+    
+    pub async fn wait_for_response_until(
+        socket: &UdpSocket,
+        device_unique_id: u32,
+        original_timestamp_ms: u64,
+        buffer: &mut [u8],
+        deadline: Instant,
+    ) -> Result<Option<AckPacket>> {
+        loop {
+            let received = match timeout_at(deadline, socket.recv_from(buffer)).await {
+                Ok(received) => received,
+                Err(_) => return Ok(None),
+            };
+            
+            let (bytes_received, _) = received
+                .map_err(|e| CyDnAError::IoError(e.kind()))?;
+            
+            // Stray or malformed datagrams do not end the attempt early.
+            if let Ok(Some(ack)) = AckManager::parse_ack(&buffer[..bytes_received]) {
+                if ack.device_unique_id == device_unique_id
+                    && ack.original_timestamp_ms == original_timestamp_ms {
+                    return Ok(Some(ack));
+                }
+            }
+        }
+    }
+    
+    pub async fn send_critical_alert(
+        socket: &UdpSocket,
+        payload: &SensorPayload,
+        gateway_address: impl ToSocketAddrs,
+        max_retries: u32,
+        base_timeout_ms: u64,
+        cancel: &CancellationToken,
+    ) -> Result<bool> {
+        let gateway_address = tokio::net::lookup_host(gateway_address).await
+            .map_err(|e| CyDnAError::IoError(e.kind()))?
+            .next()
+            .ok_or(CyDnAError::IoError(std::io::ErrorKind::InvalidInput))?;
+        let mut ack_buffer = vec![0u8; 256];
+        
+        for attempt in 0..max_retries {
+            if cancel.is_cancelled() {
+                return Err(CyDnAError::Cancelled);
+            }
+            
+            AsyncTransmitter::send(socket, payload, gateway_address).await?;
+            
+            let timeout_ms = AckManager::calculate_backoff_ms(
+                attempt,
+                base_timeout_ms,
+                base_timeout_ms * 10,
+            );
+            let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+            
+            let response = tokio::select! {
+                _ = cancel.cancelled() => return Err(CyDnAError::Cancelled),
+                response = Self::wait_for_response_until(
+                    socket,
+                    payload.device_unique_id,
+                    payload.timestamp_ms_utc,
+                    &mut ack_buffer,
+                    deadline,
+                ) => response?,
+            };
+            
+            let ack = match response {
+                Some(ack) => ack,
+                None => continue,
+            };
+            
+            if ack.is_ack() {
+                return Ok(true);
+            }
+            
+            let reason = ack.reason();
+            if !reason.should_retransmit() {
+                return Err(CyDnAError::PayloadRejected(reason));
+            }
+            
+            if !reason.retransmit_immediately() {
+                tokio::select! {
+                    _ = cancel.cancelled() => return Err(CyDnAError::Cancelled),
+                    _ = tokio::time::sleep_until(deadline) => {}
+                }
+            }
+        }
+        
+        Err(CyDnAError::MaxRetriesExceeded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_receiver::AsyncReceiver;
+    
+    fn payload() -> SensorPayload {
+        SensorPayload::new(
+            4, 5000, 1, 50, 1000, 0x12345678,
+            [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap()
+    }
+    
+    #[tokio::test]
+    async fn test_async_critical_alert_acked_after_retry() {
+        let sensor = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let gateway_task = tokio::spawn(async move {
+            let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+            AsyncReceiver::receive(&gateway, &mut buffer).await.unwrap();
+            
+            let (archived, _, sender) = AsyncReceiver::receive(&gateway, &mut buffer).await.unwrap();
+            let (id, ts) = (archived.device_unique_id, archived.timestamp_ms_utc);
+            AsyncReceiver::send_ack(&gateway, id, ts, sender).await.unwrap();
+        });
+        
+        let cancel = CancellationToken::new();
+        let acked = AsyncAckManager::send_critical_alert(
+            &sensor, &payload(), &gateway_addr, 3, 20, &cancel,
+        ).await.unwrap();
+        
+        assert!(acked);
+        gateway_task.await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_async_critical_alert_cancelled() {
+        let sensor = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sink = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sink_addr = sink.local_addr().unwrap().to_string();
+        
+        let cancel = CancellationToken::new();
+        let canceller = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            canceller.cancel();
+        });
+        
+        let started = Instant::now();
+        let result = AsyncAckManager::send_critical_alert(
+            &sensor, &payload(), &sink_addr, 5, 1000, &cancel,
+        ).await;
+        
+        assert!(matches!(result, Err(CyDnAError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_millis(1000));
+    }
+    
+    #[tokio::test]
+    async fn test_async_critical_alert_rejected_unauthorized() {
+        use crate::contracts::NackReason;
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        tokio::spawn(async move {
+            let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+            let (archived, _, sender) = AsyncReceiver::receive(&gateway, &mut buffer).await.unwrap();
+            let (id, ts) = (archived.device_unique_id, archived.timestamp_ms_utc);
+            AsyncReceiver::send_nack_with_reason(&gateway, id, ts, NackReason::Unauthorized, sender)
+                .await
+                .unwrap();
+        });
+        
+        let result = AsyncAckManager::send_critical_alert(
+            &sensor, &payload(), &gateway_addr, 3, 500, &CancellationToken::new(),
+        ).await;
+        
+        assert!(matches!(result, Err(CyDnAError::PayloadRejected(NackReason::Unauthorized))));
+    }
+    
+    #[tokio::test]
+    async fn test_async_critical_alert_exhausted() {
+        let sensor = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sink = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sink_addr = sink.local_addr().unwrap().to_string();
+        
+        let result = AsyncAckManager::send_critical_alert(
+            &sensor, &payload(), &sink_addr, 2, 5, &CancellationToken::new(),
+        ).await;
+        
+        assert!(matches!(result, Err(CyDnAError::MaxRetriesExceeded)));
+    }
+}
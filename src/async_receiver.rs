@@ -0,0 +1,163 @@
+use std::net::SocketAddr;
+
+use tokio::net::UdpSocket;
+
+use crate::ack_manager::AckManager;
+use crate::contracts::{AckPacket, ArchivedSensorPayload, NackReason};
+use crate::errors::{CyDnAError, Result};
+use crate::receiver::Receiver;
+use crate::validation::{ValidationContext, ValidationPipeline};
+
+pub struct AsyncReceiver;
+
+impl AsyncReceiver {
+    pub async fn receive<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+    ) -> Result<(&'a ArchivedSensorPayload, usize, SocketAddr)> {
+        let (bytes_received, sender_addr) = socket.recv_from(buffer).await
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let frame_len = crate::compression::inflate_in_place(buffer, bytes_received)?;
+        let archived = Receiver::archive_frame(&buffer[..frame_len])?;
+        
+        Ok((archived, bytes_received, sender_addr))
+    }
+    
+    pub async fn receive_packed<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+    ) -> Result<(Vec<&'a ArchivedSensorPayload>, usize, SocketAddr)> {
+        let (bytes_received, sender_addr) = socket.recv_from(buffer).await
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let frame_len = crate::compression::inflate_in_place(buffer, bytes_received)?;
+        let payloads = Receiver::archive_packed(&buffer[..frame_len])?;
+        
+        Ok((payloads, bytes_received, sender_addr))
+    }
+    
+    pub async fn receive_with_ttl_check<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+        current_time_ms: u64,
+    ) -> Result<(&'a ArchivedSensorPayload, usize, SocketAddr)> {
+        let (archived, bytes_received, sender_addr) = Self::receive(socket, buffer).await?;
+        
+        Receiver::check_ttl(archived, current_time_ms)?;
+        
+        Ok((archived, bytes_received, sender_addr))
+    }
+    
+    pub async fn receive_validated<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+        current_time_ms: u64,
+    ) -> Result<(&'a ArchivedSensorPayload, usize, SocketAddr)> {
+        Self::receive_with_pipeline(
+            socket,
+            buffer,
+            &ValidationContext::new(current_time_ms),
+            crate::validation::standard_pipeline(),
+        ).await
+    }
+    
+    pub async fn receive_with_pipeline<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+        context: &ValidationContext<'_>,
+        pipeline: &ValidationPipeline,
+    ) -> Result<(&'a ArchivedSensorPayload, usize, SocketAddr)> {
+        let (archived, bytes_received, sender_addr) = Self::receive(socket, buffer).await?;
+        
+        pipeline.validate(archived, context)?;
+        
+        Ok((archived, bytes_received, sender_addr))
+    }
+    
+    pub async fn receive_verified<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+        current_time_ms: u64,
+        raw_data: &[u8],
+    ) -> Result<(&'a ArchivedSensorPayload, usize, SocketAddr)> {
+        let (archived, bytes_received, sender_addr) = Self::receive_validated(
+            socket,
+            buffer,
+            current_time_ms,
+        ).await?;
+        
+        archived.verify_raw_data(raw_data)?;
+        
+        Ok((archived, bytes_received, sender_addr))
+    }
+    
+    pub async fn send_ack(
+        socket: &UdpSocket,
+        device_unique_id: u32,
+        original_timestamp_ms: u64,
+        destination: SocketAddr,
+    ) -> Result<usize> {
+        let bytes = AckManager::encode_ack(&AckPacket::ack(device_unique_id, original_timestamp_ms))?;
+        
+        socket.send_to(&bytes, destination).await
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    pub async fn send_nack(
+        socket: &UdpSocket,
+        device_unique_id: u32,
+        original_timestamp_ms: u64,
+        destination: SocketAddr,
+    ) -> Result<usize> {
+        let bytes = AckManager::encode_ack(&AckPacket::nack(device_unique_id, original_timestamp_ms))?;
+        
+        socket.send_to(&bytes, destination).await
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    pub async fn send_nack_with_reason(
+        socket: &UdpSocket,
+        device_unique_id: u32,
+        original_timestamp_ms: u64,
+        reason: NackReason,
+        destination: SocketAddr,
+    ) -> Result<usize> {
+        let nack = AckPacket::nack_with_reason(device_unique_id, original_timestamp_ms, reason);
+        let bytes = AckManager::encode_ack(&nack)?;
+        
+        socket.send_to(&bytes, destination).await
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_transmitter::AsyncTransmitter;
+    use crate::contracts::SensorPayload;
+    
+    #[tokio::test]
+    async fn test_async_receive_validated() {
+        let sensor = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let payload = SensorPayload::new(
+            3, 1000, 1, 80, 500, 0x12345678,
+            [0.2; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap();
+        AsyncTransmitter::send(&sensor, &payload, &gateway_addr).await.unwrap();
+        
+        let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+        let (archived, _, sender) = AsyncReceiver::receive_validated(&gateway, &mut buffer, 1200)
+            .await
+            .unwrap();
+        assert_eq!(archived.device_unique_id, 3);
+        assert_eq!(sender, sensor.local_addr().unwrap());
+        
+        AsyncTransmitter::send(&sensor, &payload, &gateway_addr).await.unwrap();
+        let result = AsyncReceiver::receive_validated(&gateway, &mut buffer, 5000).await;
+        assert!(matches!(result, Err(CyDnAError::PayloadExpired { .. })));
+    }
+}
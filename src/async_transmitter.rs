@@ -0,0 +1,87 @@
+use tokio::net::{ToSocketAddrs, UdpSocket};
+
+use crate::ack_manager::AckManager;
+use crate::contracts::SensorPayload;
+use crate::errors::{CyDnAError, Result};
+use crate::transmitter::Transmitter;
+
+pub struct AsyncTransmitter;
+
+impl AsyncTransmitter {
+    pub async fn send(
+        socket: &UdpSocket,
+        payload: &SensorPayload,
+        destination: impl ToSocketAddrs,
+    ) -> Result<usize> {
+        let frame = Transmitter::frame_payload(payload)?;
+        
+        Self::send_raw(socket, &frame, destination).await
+    }
+    
+    pub async fn send_packed(
+        socket: &UdpSocket,
+        payloads: &[SensorPayload],
+        destination: impl ToSocketAddrs,
+    ) -> Result<usize> {
+        let frame = Transmitter::frame_packed(payloads)?;
+        
+        Self::send_raw(socket, &frame, destination).await
+    }
+    
+    pub async fn send_raw(
+        socket: &UdpSocket,
+        bytes: &[u8],
+        destination: impl ToSocketAddrs,
+    ) -> Result<usize> {
+        Transmitter::check_datagram_size(bytes.len())?;
+        
+        socket.send_to(bytes, destination).await
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    pub async fn wait_for_ack(
+        socket: &UdpSocket,
+        device_unique_id: u32,
+        original_timestamp_ms: u64,
+        buffer: &mut [u8],
+    ) -> Result<bool> {
+        let (bytes_received, _) = socket.recv_from(buffer).await
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        AckManager::matches_ack(
+            &buffer[..bytes_received],
+            device_unique_id,
+            original_timestamp_ms,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[tokio::test]
+    async fn test_async_send_and_ack() {
+        let sensor = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        let sensor_addr = sensor.local_addr().unwrap().to_string();
+        
+        let payload = SensorPayload::new(
+            7, 1000, 1, 50, 1000, 0x12345678,
+            [0.1; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap();
+        
+        let sent = AsyncTransmitter::send(&sensor, &payload, &gateway_addr).await.unwrap();
+        
+        let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+        let (received, _) = gateway.recv_from(&mut buffer).await.unwrap();
+        assert_eq!(sent, received);
+        
+        let ack = AckManager::encode_ack(&crate::contracts::AckPacket::ack(7, 1000)).unwrap();
+        gateway.send_to(&ack, &sensor_addr).await.unwrap();
+        
+        let acked = AsyncTransmitter::wait_for_ack(&sensor, 7, 1000, &mut buffer).await.unwrap();
+        assert!(acked);
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::errors::{CyDnAError, Result};
+use crate::framing::{encode_frame, FrameHeader, MessageType, FRAME_HEADER_SIZE};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const AUTH_TAG_SIZE: usize = 16;
+
+pub const AUTH_PREFIX_SIZE: usize = 8;
+
+pub const MIN_KEY_SIZE: usize = 16;
+
+// Authenticated body: device_id (u32 LE) | reserved (4) | inner frame | tag.
+// The tag is HMAC-SHA256 over the outer header and everything before the tag,
+// truncated to 16 bytes. The 8-byte prefix keeps the inner frame aligned.
+// A device's previous key, kept during a key rotation, still verifies but
+// never signs.
+pub struct DatagramAuthenticator {
+    keys: HashMap<u32, HmacSha256>,
+    previous_keys: HashMap<u32, HmacSha256>,
+}
+
+impl DatagramAuthenticator {
+    pub fn new() -> Self {
+        Self {
+            keys: HashMap::new(),
+            previous_keys: HashMap::new(),
+        }
+    }
+    
+    fn mac_for(key: &[u8]) -> Result<HmacSha256> {
+        if key.len() < MIN_KEY_SIZE {
+            return Err(CyDnAError::BufferTooSmall {
+                required: MIN_KEY_SIZE,
+                available: key.len(),
+            });
+        }
+        
+        HmacSha256::new_from_slice(key)
+            .map_err(|_| CyDnAError::EncryptionError("Invalid HMAC key"))
+    }
+    
+    pub fn provision_key(&mut self, device_id: u32, key: &[u8]) -> Result<()> {
+        self.provision_keys(device_id, key, None)
+    }
+    
+    pub fn provision_keys(&mut self, device_id: u32, current: &[u8], previous: Option<&[u8]>) -> Result<()> {
+        let current = Self::mac_for(current)?;
+        let previous = previous.map(Self::mac_for).transpose()?;
+        
+        self.keys.insert(device_id, current);
+        match previous {
+            Some(mac) => self.previous_keys.insert(device_id, mac),
+            None => self.previous_keys.remove(&device_id),
+        };
+        Ok(())
+    }
+    
+    pub fn revoke_key(&mut self, device_id: u32) -> bool {
+        self.previous_keys.remove(&device_id);
+        self.keys.remove(&device_id).is_some()
+    }
+    
+    pub fn has_key(&self, device_id: u32) -> bool {
+        self.keys.contains_key(&device_id)
+    }
+    
+    pub fn provisioned_devices(&self) -> usize {
+        self.keys.len()
+    }
+    
+    pub fn seal(&self, device_id: u32, inner_frame: &[u8]) -> Result<Vec<u8>> {
+        let mac = self.keys.get(&device_id)
+            .ok_or(CyDnAError::UnknownDeviceKey(device_id))?;
+        
+        let body_len = AUTH_PREFIX_SIZE + inner_frame.len() + AUTH_TAG_SIZE;
+        let mut body = Vec::with_capacity(body_len);
+        body.extend_from_slice(&device_id.to_le_bytes());
+        body.extend_from_slice(&[0u8; 4]);
+        body.extend_from_slice(inner_frame);
+        body.extend_from_slice(&[0u8; AUTH_TAG_SIZE]);
+        
+        let mut frame = encode_frame(MessageType::Authenticated, &body)?;
+        let tag_offset = frame.len() - AUTH_TAG_SIZE;
+        
+        let mut mac = mac.clone();
+        mac.update(&frame[..tag_offset]);
+        let tag = mac.finalize().into_bytes();
+        frame[tag_offset..].copy_from_slice(&tag[..AUTH_TAG_SIZE]);
+        
+        Ok(frame)
+    }
+    
+    pub fn open<'a>(&self, datagram: &'a [u8]) -> Result<(u32, &'a [u8])> {
+        let header = FrameHeader::decode(datagram)?;
+        header.expect_type(MessageType::Authenticated)?;
+        
+        let minimum = AUTH_PREFIX_SIZE + FRAME_HEADER_SIZE + AUTH_TAG_SIZE;
+        if (header.payload_len as usize) < minimum {
+            return Err(CyDnAError::InvalidPacketLength {
+                expected: FRAME_HEADER_SIZE + minimum,
+                received: datagram.len(),
+            });
+        }
+        
+        let body = &datagram[header.body_range()];
+        let device_id = u32::from_le_bytes([body[0], body[1], body[2], body[3]]);
+        
+        let mac = self.keys.get(&device_id)
+            .ok_or(CyDnAError::UnknownDeviceKey(device_id))?;
+        
+        let tag_offset = datagram.len() - AUTH_TAG_SIZE;
+        let verifies = |mac: &HmacSha256| {
+            let mut mac = mac.clone();
+            mac.update(&datagram[..tag_offset]);
+            mac.verify_truncated_left(&datagram[tag_offset..]).is_ok()
+        };
+        
+        if !verifies(mac) && !self.previous_keys.get(&device_id).is_some_and(verifies) {
+            return Err(CyDnAError::AuthenticationFailed(device_id));
+        }
+        
+        Ok((device_id, &datagram[FRAME_HEADER_SIZE + AUTH_PREFIX_SIZE..tag_offset]))
+    }
+}
+
+impl Default for DatagramAuthenticator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_seal_and_open() {
+        let mut auth = DatagramAuthenticator::new();
+        auth.provision_key(3, b"0123456789abcdef").unwrap();
+        
+        let inner = encode_frame(MessageType::Ack, b"inner body").unwrap();
+        let sealed = auth.seal(3, &inner).unwrap();
+        
+        let (device_id, opened) = auth.open(&sealed).unwrap();
+        assert_eq!(device_id, 3);
+        assert_eq!(opened, &inner[..]);
+    }
+    
+    #[test]
+    fn test_spoofed_datagram_rejected() {
+        let mut auth = DatagramAuthenticator::new();
+        auth.provision_key(3, b"0123456789abcdef").unwrap();
+        auth.provision_key(4, b"fedcba9876543210").unwrap();
+        assert!(auth.provision_key(5, b"short").is_err());
+        
+        let inner = encode_frame(MessageType::Ack, b"inner body").unwrap();
+        let sealed = auth.seal(3, &inner).unwrap();
+        
+        let mut tampered = sealed.clone();
+        tampered[FRAME_HEADER_SIZE + AUTH_PREFIX_SIZE + 9] ^= 0x01;
+        assert!(matches!(auth.open(&tampered), Err(CyDnAError::AuthenticationFailed(3))));
+        
+        let mut impersonated = sealed.clone();
+        impersonated[FRAME_HEADER_SIZE] = 4;
+        assert!(matches!(auth.open(&impersonated), Err(CyDnAError::AuthenticationFailed(4))));
+        
+        assert!(auth.revoke_key(3));
+        assert!(matches!(auth.open(&sealed), Err(CyDnAError::UnknownDeviceKey(3))));
+    }
+}
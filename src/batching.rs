@@ -0,0 +1,273 @@
+use std::net::{ToSocketAddrs, UdpSocket};
+
+use crate::contracts::SensorPayload;
+use crate::errors::Result;
+use crate::framing::Priority;
+use crate::transmitter::Transmitter;
+use crate::{ACK_TIMEOUT_MS, MAX_RETRANSMIT_ATTEMPTS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryTier {
+    pub min_battery_percent: u8,
+    
+    pub batch_window_ms: u64,
+    
+    pub max_batch_size: usize,
+    
+    pub max_retries: u32,
+    
+    pub base_timeout_ms: u64,
+}
+
+// Tiers are kept sorted from the highest battery threshold down; the first
+// tier whose threshold the battery level meets applies.
+#[derive(Debug, Clone)]
+pub struct BatchingPolicy {
+    tiers: Vec<BatteryTier>,
+}
+
+impl BatchingPolicy {
+    pub fn new(lowest: BatteryTier) -> Self {
+        Self { tiers: vec![BatteryTier { min_battery_percent: 0, ..lowest }] }
+    }
+    
+    pub fn with_tier(mut self, tier: BatteryTier) -> Self {
+        self.tiers.retain(|existing| existing.min_battery_percent != tier.min_battery_percent);
+        self.tiers.push(tier);
+        self.tiers.sort_by_key(|tier| std::cmp::Reverse(tier.min_battery_percent));
+        self
+    }
+    
+    pub fn tier_for(&self, battery_level_percent: u8) -> &BatteryTier {
+        self.tiers.iter()
+            .find(|tier| battery_level_percent >= tier.min_battery_percent)
+            .unwrap_or(&self.tiers[self.tiers.len() - 1])
+    }
+}
+
+impl Default for BatchingPolicy {
+    fn default() -> Self {
+        let max_batch = Transmitter::max_packed_payloads();
+        
+        Self::new(BatteryTier {
+            min_battery_percent: 0,
+            batch_window_ms: 10_000,
+            max_batch_size: max_batch,
+            max_retries: 1,
+            base_timeout_ms: ACK_TIMEOUT_MS * 4,
+        })
+        .with_tier(BatteryTier {
+            min_battery_percent: 20,
+            batch_window_ms: 2_000,
+            max_batch_size: max_batch.min(4),
+            max_retries: 2,
+            base_timeout_ms: ACK_TIMEOUT_MS * 2,
+        })
+        .with_tier(BatteryTier {
+            min_battery_percent: 50,
+            batch_window_ms: 0,
+            max_batch_size: 1,
+            max_retries: MAX_RETRANSMIT_ATTEMPTS,
+            base_timeout_ms: ACK_TIMEOUT_MS,
+        })
+    }
+}
+
+// How long before a held payload's TTL runs out the batch is sent, leaving
+// time for the datagram and its ACK.
+pub const DEFAULT_EXPIRY_MARGIN_MS: u64 = ACK_TIMEOUT_MS * 4;
+
+// Buffers payloads into packed datagrams. The tier is chosen from the most
+// recent payload's battery level, so a draining battery widens the window
+// for the batch already being collected. The window never outlasts a held
+// payload's TTL: the batch goes out `expiry_margin_ms` before the earliest
+// one would expire, so the gateway does not NACK it as stale.
+pub struct AdaptiveBatcher {
+    policy: BatchingPolicy,
+    pending: Vec<SensorPayload>,
+    window_started_ms: Option<u64>,
+    earliest_expiry_ms: Option<u64>,
+    expiry_margin_ms: u64,
+}
+
+impl AdaptiveBatcher {
+    pub fn new(policy: BatchingPolicy) -> Self {
+        Self {
+            policy,
+            pending: Vec::new(),
+            window_started_ms: None,
+            earliest_expiry_ms: None,
+            expiry_margin_ms: DEFAULT_EXPIRY_MARGIN_MS,
+        }
+    }
+    
+    pub fn with_expiry_margin_ms(mut self, margin_ms: u64) -> Self {
+        self.expiry_margin_ms = margin_ms;
+        self
+    }
+    
+    pub fn policy(&self) -> &BatchingPolicy {
+        &self.policy
+    }
+    
+    pub fn current_tier(&self) -> Option<&BatteryTier> {
+        self.pending.last().map(|payload| self.policy.tier_for(payload.battery_level_percent))
+    }
+    
+    // Returns a batch as soon as it is full; otherwise holds the payload
+    // until `poll` sees the window close.
+    pub fn push(&mut self, payload: SensorPayload, current_time_ms: u64) -> Option<Vec<SensorPayload>> {
+        self.push_with_priority(payload, Priority::Normal, current_time_ms)
+    }
+    
+    // A critical alert is never held: it goes out at once, taking whatever
+    // was pending with it.
+    pub fn push_with_priority(
+        &mut self,
+        payload: SensorPayload,
+        priority: Priority,
+        current_time_ms: u64,
+    ) -> Option<Vec<SensorPayload>> {
+        self.window_started_ms.get_or_insert(current_time_ms);
+        self.pending.push(payload);
+        
+        let expiry = payload.timestamp_ms_utc.saturating_add(payload.time_to_live_ms as u64);
+        self.earliest_expiry_ms = Some(self.earliest_expiry_ms.map_or(expiry, |earliest| earliest.min(expiry)));
+        if priority == Priority::Critical {
+            return self.flush();
+        }
+        
+        let tier = self.policy.tier_for(payload.battery_level_percent);
+        let max_batch = tier.max_batch_size.clamp(1, Transmitter::max_packed_payloads());
+        if self.pending.len() >= max_batch {
+            return self.flush();
+        }
+        
+        self.poll(current_time_ms)
+    }
+    
+    pub fn poll(&mut self, current_time_ms: u64) -> Option<Vec<SensorPayload>> {
+        if current_time_ms >= self.flush_deadline_ms()? {
+            return self.flush();
+        }
+        
+        None
+    }
+    
+    fn flush_deadline_ms(&self) -> Option<u64> {
+        let tier = self.current_tier()?;
+        let window_deadline = self.window_started_ms?.saturating_add(tier.batch_window_ms);
+        
+        Some(match self.earliest_expiry_ms {
+            Some(expiry) => window_deadline.min(expiry.saturating_sub(self.expiry_margin_ms)),
+            None => window_deadline,
+        })
+    }
+    
+    pub fn time_until_flush_ms(&self, current_time_ms: u64) -> Option<u64> {
+        Some(self.flush_deadline_ms()?.saturating_sub(current_time_ms))
+    }
+    
+    pub fn flush(&mut self) -> Option<Vec<SensorPayload>> {
+        self.window_started_ms = None;
+        self.earliest_expiry_ms = None;
+        
+        if self.pending.is_empty() {
+            None
+        } else {
+            Some(std::mem::take(&mut self.pending))
+        }
+    }
+    
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+    
+    // Single payloads go out as plain frames, larger batches packed.
+    pub fn send_batch(socket: &UdpSocket, batch: &[SensorPayload], destination: impl ToSocketAddrs) -> Result<usize> {
+        match batch {
+            [single] => Transmitter::send(socket, single, destination),
+            _ => Transmitter::send_packed(socket, batch, destination),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn payload(id: u32, battery: u8) -> SensorPayload {
+        SensorPayload::new(
+            id, 1000, 1, battery, 60_000, id,
+            [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap()
+    }
+    
+    #[test]
+    fn test_policy_tiers() {
+        let policy = BatchingPolicy::default();
+        
+        assert_eq!(policy.tier_for(100).max_batch_size, 1);
+        assert_eq!(policy.tier_for(50).batch_window_ms, 0);
+        assert_eq!(policy.tier_for(49).max_retries, 2);
+        assert_eq!(policy.tier_for(5).max_retries, 1);
+        assert!(policy.tier_for(5).batch_window_ms > policy.tier_for(30).batch_window_ms);
+        assert!(Transmitter::max_packed_payloads() >= 4);
+    }
+    
+    #[test]
+    fn test_batcher_widens_window_on_low_battery() {
+        let mut batcher = AdaptiveBatcher::new(BatchingPolicy::default());
+        
+        assert_eq!(batcher.push(payload(1, 90), 0).map(|batch| batch.len()), Some(1));
+        
+        assert!(batcher.push(payload(2, 30), 0).is_none());
+        assert!(batcher.push(payload(3, 30), 500).is_none());
+        assert_eq!(batcher.time_until_flush_ms(500), Some(1_500));
+        assert_eq!(batcher.poll(2_000).map(|batch| batch.len()), Some(2));
+        
+        for id in 0..3 {
+            assert!(batcher.push(payload(10 + id, 30), 3_000).is_none());
+        }
+        assert_eq!(batcher.push(payload(13, 30), 3_000).map(|batch| batch.len()), Some(4));
+        
+        assert!(batcher.push(payload(20, 10), 4_000).is_none());
+        assert!(batcher.poll(9_000).is_none());
+        assert_eq!(batcher.poll(14_000).map(|batch| batch.len()), Some(1));
+        assert_eq!(batcher.pending_count(), 0);
+    }
+    
+    #[test]
+    fn test_window_capped_by_ttl_and_critical_sent_at_once() {
+        let mut batcher = AdaptiveBatcher::new(BatchingPolicy::default()).with_expiry_margin_ms(200);
+        
+        // Low battery means a 10 s window, but this payload expires at 3 s.
+        let short_lived = SensorPayload::new(1, 1_000, 1, 10, 2_000, 0, [0.0; crate::contracts::ANOMALY_VECTOR_SIZE])
+            .unwrap();
+        assert!(batcher.push(short_lived, 1_000).is_none());
+        assert_eq!(batcher.time_until_flush_ms(1_000), Some(1_800));
+        assert!(batcher.poll(2_799).is_none());
+        assert_eq!(batcher.poll(2_800).map(|batch| batch.len()), Some(1));
+        
+        assert!(batcher.push(payload(2, 10), 3_000).is_none());
+        let batch = batcher.push_with_priority(payload(3, 10), Priority::Critical, 3_000).unwrap();
+        assert_eq!(batch.iter().map(|payload| payload.device_unique_id).collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(batcher.pending_count(), 0);
+    }
+    
+    #[test]
+    fn test_send_batch_packs_multiple_payloads() {
+        use crate::receiver::Receiver;
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let batch = vec![payload(1, 10), payload(2, 10), payload(3, 10)];
+        AdaptiveBatcher::send_batch(&sensor, &batch, &gateway_addr).unwrap();
+        
+        let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+        let (archived, _, _) = Receiver::receive_packed(&gateway, &mut buffer).unwrap();
+        assert_eq!(archived.len(), 3);
+    }
+}
@@ -0,0 +1,519 @@
+use crate::contracts::{DLTTransactionRecord, SensorPayload};
+use crate::errors::{CyDnAError, Result};
+
+pub const DEFAULT_PAYLOAD_TOPIC: &str = "cynda/payloads/{device_id}";
+
+pub const DEFAULT_RECORD_TOPIC: &str = "cynda/dlt/{gateway_id}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BridgeFormat {
+    #[default]
+    Json,
+    
+    // Needs the `cbor` feature.
+    Cbor,
+}
+
+impl BridgeFormat {
+    pub fn is_supported(self) -> bool {
+        match self {
+            Self::Json => true,
+            Self::Cbor => cfg!(feature = "cbor"),
+        }
+    }
+    
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Cbor => "application/cbor",
+        }
+    }
+    
+    fn encode<T: serde::Serialize>(self, value: &T) -> Result<Vec<u8>> {
+        match self {
+            Self::Json => serde_json::to_vec(value)
+                .map_err(|_| CyDnAError::SerializationError("Failed to encode bridge message as JSON")),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)
+                    .map_err(|_| CyDnAError::SerializationError("Failed to encode bridge message as CBOR"))?;
+                Ok(bytes)
+            }
+            #[cfg(not(feature = "cbor"))]
+            Self::Cbor => Err(CyDnAError::SerializationError("CBOR bridge format needs the cbor feature")),
+        }
+    }
+}
+
+// One message for the downstream platform. `key` is the big-endian device
+// (or gateway) id, so every message from one source lands on the same
+// Kafka partition and stays in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BridgeMessage {
+    pub topic: String,
+    
+    pub key: [u8; 4],
+    
+    pub body: Vec<u8>,
+    
+    pub format: BridgeFormat,
+}
+
+impl BridgeMessage {
+    // For sinks that pick partitions themselves; stable across restarts and
+    // processes, unlike `std`'s randomly seeded hasher.
+    pub fn partition(&self, partition_count: u32) -> u32 {
+        crc32fast::hash(&self.key) % partition_count.max(1)
+    }
+}
+
+// A message broker connection: MQTT, Kafka or anything else that accepts
+// keyed messages on named topics. Kafka clients are heavyweight native
+// dependencies, so a Kafka sink is a thin implementation of this trait over
+// the producer the deployment already uses.
+pub trait BridgeSink {
+    fn publish(&mut self, message: &BridgeMessage) -> Result<()>;
+    
+    // Called by `Bridge::flush`; sinks that publish synchronously need not
+    // override it.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BridgeMetrics {
+    pub payloads_published: u64,
+    
+    pub records_published: u64,
+    
+    pub bytes_published: u64,
+    
+    pub publish_failures: u64,
+}
+
+// Forwards validated payloads and signed DLT records to a `BridgeSink`.
+// Topics are templates in which `{device_id}` and `{gateway_id}` are
+// replaced per message, e.g. `plant-7/{device_id}/telemetry` for MQTT or a
+// fixed `cynda.payloads` for Kafka.
+pub struct Bridge<S: BridgeSink> {
+    sink: S,
+    format: BridgeFormat,
+    payload_topic: String,
+    record_topic: String,
+    metrics: BridgeMetrics,
+}
+
+impl<S: BridgeSink> Bridge<S> {
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            format: BridgeFormat::Json,
+            payload_topic: DEFAULT_PAYLOAD_TOPIC.to_string(),
+            record_topic: DEFAULT_RECORD_TOPIC.to_string(),
+            metrics: BridgeMetrics::default(),
+        }
+    }
+    
+    pub fn with_format(mut self, format: BridgeFormat) -> Self {
+        self.format = format;
+        self
+    }
+    
+    pub fn with_payload_topic(mut self, template: &str) -> Self {
+        self.payload_topic = template.to_string();
+        self
+    }
+    
+    pub fn with_record_topic(mut self, template: &str) -> Self {
+        self.record_topic = template.to_string();
+        self
+    }
+    
+    pub fn format(&self) -> BridgeFormat {
+        self.format
+    }
+    
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+    
+    pub fn metrics(&self) -> BridgeMetrics {
+        self.metrics
+    }
+    
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+    
+    // Only hand over payloads the receive path has accepted; the bridge
+    // does not validate them again.
+    pub fn publish_payload(&mut self, payload: &SensorPayload) -> Result<()> {
+        let topic = self.payload_topic.replace("{device_id}", &payload.device_unique_id.to_string());
+        let message = BridgeMessage {
+            topic,
+            key: payload.device_unique_id.to_be_bytes(),
+            body: self.format.encode(payload)?,
+            format: self.format,
+        };
+        
+        self.publish(&message)?;
+        self.metrics.payloads_published += 1;
+        Ok(())
+    }
+    
+    pub fn publish_record(&mut self, record: &DLTTransactionRecord) -> Result<()> {
+        let topic = self.record_topic.replace("{gateway_id}", &record.gateway_unique_id.to_string());
+        let message = BridgeMessage {
+            topic,
+            key: record.gateway_unique_id.to_be_bytes(),
+            body: self.format.encode(record)?,
+            format: self.format,
+        };
+        
+        self.publish(&message)?;
+        self.metrics.records_published += 1;
+        Ok(())
+    }
+    
+    pub fn flush(&mut self) -> Result<()> {
+        self.sink.flush()
+    }
+    
+    fn publish(&mut self, message: &BridgeMessage) -> Result<()> {
+        match self.sink.publish(message) {
+            Ok(()) => {
+                self.metrics.bytes_published += message.body.len() as u64;
+                Ok(())
+            }
+            Err(e) => {
+                self.metrics.publish_failures += 1;
+                Err(e)
+            }
+        }
+    }
+}
+
+pub use mqtt::{MqttPublisher, MqttQos};
+
+// Minimal MQTT 3.1.1 publisher over plain TCP: CONNECT with a clean session,
+// then PUBLISH at QoS 0 or 1. Nothing is subscribed to, so the only packets
+// read back are CONNACK and PUBACK.
+mod mqtt {
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::time::Duration;
+    
+    use super::{BridgeMessage, BridgeSink};
+    use crate::errors::{CyDnAError, Result};
+    
+    pub const DEFAULT_MQTT_TIMEOUT_MS: u64 = 5_000;
+    
+    // Keep-alive disabled: the broker never drops an idle publisher, and a
+    // dead connection is noticed on the next publish and re-established.
+    const KEEP_ALIVE_SECS: u16 = 0;
+    
+    const CONNECT: u8 = 0x10;
+    const CONNACK: u8 = 0x20;
+    const PUBLISH: u8 = 0x30;
+    const PUBACK: u8 = 0x40;
+    
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum MqttQos {
+        AtMostOnce,
+        
+        // Waits for the broker's PUBACK before `publish` returns.
+        #[default]
+        AtLeastOnce,
+    }
+    
+    pub struct MqttPublisher {
+        address: SocketAddr,
+        client_id: String,
+        qos: MqttQos,
+        timeout: Duration,
+        stream: Option<TcpStream>,
+        next_packet_id: u16,
+    }
+    
+    impl MqttPublisher {
+        // Connects lazily on the first publish.
+        pub fn new(address: &str, client_id: &str) -> Result<Self> {
+            if client_id.len() > u16::MAX as usize {
+                return Err(CyDnAError::IoError(std::io::ErrorKind::InvalidInput));
+            }
+            
+            Ok(Self {
+                address: crate::socket::resolve(address)?,
+                client_id: client_id.to_string(),
+                qos: MqttQos::default(),
+                timeout: Duration::from_millis(DEFAULT_MQTT_TIMEOUT_MS),
+                stream: None,
+                next_packet_id: 1,
+            })
+        }
+        
+        pub fn with_qos(mut self, qos: MqttQos) -> Self {
+            self.qos = qos;
+            self
+        }
+        
+        pub fn with_timeout_ms(mut self, timeout_ms: u64) -> Self {
+            self.timeout = Duration::from_millis(timeout_ms.max(1));
+            self
+        }
+        
+        pub fn address(&self) -> SocketAddr {
+            self.address
+        }
+        
+        pub fn is_connected(&self) -> bool {
+            self.stream.is_some()
+        }
+        
+        fn connect(&self) -> Result<TcpStream> {
+            let mut stream = TcpStream::connect_timeout(&self.address, self.timeout).map_err(io_error)?;
+            stream.set_read_timeout(Some(self.timeout)).map_err(io_error)?;
+            stream.set_write_timeout(Some(self.timeout)).map_err(io_error)?;
+            stream.set_nodelay(true).map_err(io_error)?;
+            
+            let mut body = Vec::with_capacity(12 + self.client_id.len());
+            put_string(&mut body, "MQTT");
+            body.push(4);
+            body.push(0x02);
+            body.extend_from_slice(&KEEP_ALIVE_SECS.to_be_bytes());
+            put_string(&mut body, &self.client_id);
+            write_packet(&mut stream, CONNECT, &body)?;
+            
+            let connack = read_packet(&mut stream, CONNACK)?;
+            match connack.as_slice() {
+                [_, 0] => Ok(stream),
+                _ => Err(CyDnAError::IoError(std::io::ErrorKind::ConnectionRefused)),
+            }
+        }
+        
+        fn send(&mut self, stream: &mut TcpStream, message: &BridgeMessage) -> Result<()> {
+            let mut body = Vec::with_capacity(message.topic.len() + message.body.len() + 4);
+            put_string(&mut body, &message.topic);
+            
+            if self.qos == MqttQos::AtMostOnce {
+                body.extend_from_slice(&message.body);
+                return write_packet(stream, PUBLISH, &body);
+            }
+            
+            let packet_id = self.next_packet_id;
+            self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+            body.extend_from_slice(&packet_id.to_be_bytes());
+            body.extend_from_slice(&message.body);
+            write_packet(stream, PUBLISH | 0x02, &body)?;
+            
+            let puback = read_packet(stream, PUBACK)?;
+            if puback != packet_id.to_be_bytes() {
+                return Err(CyDnAError::DeserializationError("Unexpected MQTT PUBACK"));
+            }
+            
+            Ok(())
+        }
+    }
+    
+    impl BridgeSink for MqttPublisher {
+        // A failed publish drops the connection; the next call reconnects.
+        fn publish(&mut self, message: &BridgeMessage) -> Result<()> {
+            if message.topic.is_empty() || message.topic.len() > u16::MAX as usize {
+                return Err(CyDnAError::IoError(std::io::ErrorKind::InvalidInput));
+            }
+            
+            let mut stream = match self.stream.take() {
+                Some(stream) => stream,
+                None => self.connect()?,
+            };
+            
+            self.send(&mut stream, message)?;
+            self.stream = Some(stream);
+            Ok(())
+        }
+    }
+    
+    fn io_error(error: std::io::Error) -> CyDnAError {
+        CyDnAError::IoError(error.kind())
+    }
+    
+    fn put_string(buffer: &mut Vec<u8>, value: &str) {
+        buffer.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        buffer.extend_from_slice(value.as_bytes());
+    }
+    
+    fn write_packet(stream: &mut TcpStream, first_byte: u8, body: &[u8]) -> Result<()> {
+        // Remaining length: seven bits per byte, high bit set on all but the last.
+        let mut packet = Vec::with_capacity(body.len() + 5);
+        packet.push(first_byte);
+        let mut remaining = body.len();
+        loop {
+            let byte = (remaining % 128) as u8;
+            remaining /= 128;
+            packet.push(if remaining > 0 { byte | 0x80 } else { byte });
+            if remaining == 0 {
+                break;
+            }
+        }
+        packet.extend_from_slice(body);
+        
+        stream.write_all(&packet).map_err(io_error)
+    }
+    
+    fn read_packet(stream: &mut TcpStream, expected_type: u8) -> Result<Vec<u8>> {
+        let malformed = CyDnAError::DeserializationError("Malformed MQTT packet");
+        
+        let mut byte = [0u8; 1];
+        stream.read_exact(&mut byte).map_err(io_error)?;
+        if byte[0] & 0xf0 != expected_type {
+            return Err(malformed);
+        }
+        
+        let mut length = 0usize;
+        for shift in (0..4).map(|index| index * 7) {
+            stream.read_exact(&mut byte).map_err(io_error)?;
+            length |= usize::from(byte[0] & 0x7f) << shift;
+            if byte[0] & 0x80 == 0 {
+                let mut body = vec![0u8; length];
+                stream.read_exact(&mut body).map_err(io_error)?;
+                return Ok(body);
+            }
+        }
+        
+        Err(malformed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::ANOMALY_VECTOR_SIZE;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    
+    #[derive(Default)]
+    struct RecordingSink {
+        messages: Vec<BridgeMessage>,
+        fail: bool,
+    }
+    
+    impl BridgeSink for RecordingSink {
+        fn publish(&mut self, message: &BridgeMessage) -> Result<()> {
+            if self.fail {
+                return Err(CyDnAError::IoError(std::io::ErrorKind::BrokenPipe));
+            }
+            
+            self.messages.push(message.clone());
+            Ok(())
+        }
+    }
+    
+    fn payload(device_id: u32) -> SensorPayload {
+        SensorPayload::new(device_id, 1_700_000_000_000, 1, 64, 5_000, 7, [0.25; ANOMALY_VECTOR_SIZE]).unwrap()
+    }
+    
+    #[test]
+    fn test_bridge_topics_keys_and_metrics() {
+        let mut bridge = Bridge::new(RecordingSink::default()).with_payload_topic("plant-7/{device_id}/telemetry");
+        bridge.publish_payload(&payload(42)).unwrap();
+        
+        let record = DLTTransactionRecord::new(9, 0.5, false, 0, [0xab; 32], [0x01; 64]).unwrap();
+        bridge.publish_record(&record).unwrap();
+        
+        let messages = &bridge.sink().messages;
+        assert_eq!(messages[0].topic, "plant-7/42/telemetry");
+        assert_eq!(messages[0].key, 42u32.to_be_bytes());
+        assert_eq!(SensorPayload::from_json(std::str::from_utf8(&messages[0].body).unwrap()).unwrap().device_unique_id, 42);
+        assert_eq!(messages[1].topic, "cynda/dlt/9");
+        assert!(String::from_utf8_lossy(&messages[1].body).contains(&"ab".repeat(32)));
+        
+        assert_eq!(messages[0].partition(12), messages[0].partition(12));
+        assert!(messages[0].partition(12) < 12);
+        assert_eq!(messages[0].partition(0), 0);
+        
+        let metrics = bridge.metrics();
+        assert_eq!((metrics.payloads_published, metrics.records_published), (1, 1));
+        assert_eq!(metrics.bytes_published, messages.iter().map(|m| m.body.len() as u64).sum::<u64>());
+        
+        let mut failing = Bridge::new(RecordingSink { fail: true, ..Default::default() });
+        assert!(failing.publish_payload(&payload(1)).is_err());
+        assert_eq!(failing.metrics().publish_failures, 1);
+        assert_eq!(failing.metrics().payloads_published, 0);
+    }
+    
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_bridge_cbor_format() {
+        let mut bridge = Bridge::new(RecordingSink::default()).with_format(BridgeFormat::Cbor);
+        bridge.publish_payload(&payload(5)).unwrap();
+        
+        let message = &bridge.sink().messages[0];
+        assert_eq!(message.format.content_type(), "application/cbor");
+        let decoded: SensorPayload = ciborium::from_reader(message.body.as_slice()).unwrap();
+        assert_eq!(decoded.device_unique_id, 5);
+    }
+    
+    // Reads one MQTT packet off the broker side of the connection.
+    fn read_client_packet(stream: &mut std::net::TcpStream) -> (u8, Vec<u8>) {
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header).unwrap();
+        assert!(header[1] < 0x80, "test packets fit a one-byte length");
+        
+        let mut body = vec![0u8; usize::from(header[1])];
+        stream.read_exact(&mut body).unwrap();
+        (header[0], body)
+    }
+    
+    #[test]
+    fn test_mqtt_publisher_against_broker() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        
+        let broker = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let (connect, body) = read_client_packet(&mut stream);
+            assert_eq!(connect, 0x10);
+            assert_eq!(&body[..7], b"\x00\x04MQTT\x04");
+            assert!(body.ends_with(b"gw-1"));
+            stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+            
+            let mut published = Vec::new();
+            for _ in 0..2 {
+                let (first_byte, body) = read_client_packet(&mut stream);
+                let topic_len = usize::from(u16::from_be_bytes([body[0], body[1]]));
+                let topic = String::from_utf8(body[2..2 + topic_len].to_vec()).unwrap();
+                
+                let mut rest = &body[2 + topic_len..];
+                if first_byte & 0x06 == 0x02 {
+                    stream.write_all(&[0x40, 0x02, rest[0], rest[1]]).unwrap();
+                    rest = &rest[2..];
+                }
+                published.push((first_byte, topic, rest.to_vec()));
+            }
+            published
+        });
+        
+        let mut publisher = MqttPublisher::new(&address, "gw-1").unwrap().with_timeout_ms(2_000);
+        let message = BridgeMessage {
+            topic: "cynda/t".to_string(),
+            key: [0, 0, 0, 1],
+            body: b"{}".to_vec(),
+            format: BridgeFormat::Json,
+        };
+        
+        publisher.publish(&message).unwrap();
+        assert!(publisher.is_connected());
+        
+        let mut publisher = publisher.with_qos(MqttQos::AtMostOnce);
+        publisher.publish(&message).unwrap();
+        
+        let published = broker.join().unwrap();
+        assert_eq!(published[0], (0x32, "cynda/t".to_string(), b"{}".to_vec()));
+        assert_eq!(published[1], (0x30, "cynda/t".to_string(), b"{}".to_vec()));
+        
+        let empty = BridgeMessage { topic: String::new(), ..message };
+        assert!(publisher.publish(&empty).is_err());
+    }
+}
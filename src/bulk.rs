@@ -0,0 +1,395 @@
+use std::collections::VecDeque;
+use std::net::{SocketAddr, UdpSocket};
+
+use rkyv::{check_archived_root, to_bytes, Deserialize};
+
+use crate::contracts::{compute_crc32, verify_crc32, RawDataChunk, RawDataRequest};
+use crate::errors::{CyDnAError, Result};
+use crate::framing::{
+    decode_frame, encode_frame, encode_frame_with_priority, MessageType, Priority,
+};
+
+// Leaves room for the frame header and the archived chunk fields within
+// MAX_PAYLOAD_SIZE.
+pub const RAW_CHUNK_SIZE: usize = 896;
+
+pub const MAX_RAW_DATA_LEN: usize = RAW_CHUNK_SIZE * u16::MAX as usize;
+
+pub fn encode_request(request: &RawDataRequest) -> Result<Vec<u8>> {
+    let bytes = to_bytes::<_, 64>(request)
+        .map_err(|_| CyDnAError::SerializationError(
+            "Failed to serialize RawDataRequest"
+        ))?;
+    
+    encode_frame(MessageType::RawDataRequest, &bytes)
+}
+
+pub fn parse_request(datagram: &[u8]) -> Result<RawDataRequest> {
+    let body = decode_frame(datagram, MessageType::RawDataRequest)?;
+    
+    let archived = check_archived_root::<RawDataRequest>(body)
+        .map_err(|_| CyDnAError::DeserializationError(
+            "Failed to validate RawDataRequest"
+        ))?;
+    
+    Ok(RawDataRequest {
+        device_unique_id: archived.device_unique_id,
+        raw_data_hash_crc: archived.raw_data_hash_crc,
+        first_chunk: archived.first_chunk,
+        chunk_count: archived.chunk_count,
+    })
+}
+
+// Chunks always travel with Bulk priority so priority-aware queues on either
+// side service payloads and ACKs first.
+pub fn encode_chunk(chunk: &RawDataChunk) -> Result<Vec<u8>> {
+    let bytes = to_bytes::<_, 1024>(chunk)
+        .map_err(|_| CyDnAError::SerializationError(
+            "Failed to serialize RawDataChunk"
+        ))?;
+    
+    encode_frame_with_priority(MessageType::RawDataChunk, Priority::Bulk, &bytes)
+}
+
+pub fn parse_chunk(datagram: &[u8]) -> Result<RawDataChunk> {
+    let body = decode_frame(datagram, MessageType::RawDataChunk)?;
+    
+    let archived = check_archived_root::<RawDataChunk>(body)
+        .map_err(|_| CyDnAError::DeserializationError(
+            "Failed to validate RawDataChunk"
+        ))?;
+    
+    archived.deserialize(&mut rkyv::Infallible)
+        .map_err(|_| CyDnAError::DeserializationError(
+            "Failed to deserialize RawDataChunk"
+        ))
+}
+
+pub fn send_request(
+    socket: &UdpSocket,
+    request: &RawDataRequest,
+    destination: SocketAddr,
+) -> Result<usize> {
+    let frame = encode_request(request)?;
+    
+    socket.send_to(&frame, destination)
+        .map_err(|e| CyDnAError::IoError(e.kind()))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RawStoreMetrics {
+    pub stored: u64,
+    
+    pub evicted: u64,
+    
+    pub requests_served: u64,
+    
+    pub chunks_framed: u64,
+    
+    pub unknown_requests: u64,
+}
+
+// Sensor side: keeps the most recent raw vibration blocks, keyed by the CRC32
+// that went out in the payload, until the gateway asks for them.
+pub struct RawDataStore {
+    device_unique_id: u32,
+    capacity: usize,
+    blocks: VecDeque<(u32, Vec<u8>)>,
+    metrics: RawStoreMetrics,
+}
+
+impl RawDataStore {
+    pub fn new(device_unique_id: u32, capacity: usize) -> Self {
+        Self {
+            device_unique_id,
+            capacity: capacity.max(1),
+            blocks: VecDeque::new(),
+            metrics: RawStoreMetrics::default(),
+        }
+    }
+    
+    // Returns the CRC32 to put in the payload's `raw_data_hash_crc`.
+    pub fn insert(&mut self, raw_data: Vec<u8>) -> Result<u32> {
+        if raw_data.len() > MAX_RAW_DATA_LEN {
+            return Err(CyDnAError::BufferTooSmall {
+                required: raw_data.len(),
+                available: MAX_RAW_DATA_LEN,
+            });
+        }
+        
+        let crc = compute_crc32(&raw_data);
+        self.blocks.retain(|(stored_crc, _)| *stored_crc != crc);
+        
+        if self.blocks.len() == self.capacity {
+            self.blocks.pop_front();
+            self.metrics.evicted += 1;
+        }
+        
+        self.blocks.push_back((crc, raw_data));
+        self.metrics.stored += 1;
+        Ok(crc)
+    }
+    
+    pub fn get(&self, raw_data_hash_crc: u32) -> Option<&[u8]> {
+        self.blocks.iter()
+            .find(|(crc, _)| *crc == raw_data_hash_crc)
+            .map(|(_, data)| data.as_slice())
+    }
+    
+    pub fn chunk_count(&self, raw_data_hash_crc: u32) -> Option<u16> {
+        self.get(raw_data_hash_crc).map(|data| chunk_count_for(data.len()))
+    }
+    
+    pub fn chunk(&self, raw_data_hash_crc: u32, chunk_index: u16) -> Result<RawDataChunk> {
+        let data = self.get(raw_data_hash_crc)
+            .ok_or(CyDnAError::UnknownRawData(raw_data_hash_crc))?;
+        let chunk_count = chunk_count_for(data.len());
+        
+        if chunk_index >= chunk_count {
+            return Err(CyDnAError::InvalidRawDataChunk(chunk_index));
+        }
+        
+        let start = chunk_index as usize * RAW_CHUNK_SIZE;
+        let end = (start + RAW_CHUNK_SIZE).min(data.len());
+        
+        Ok(RawDataChunk {
+            device_unique_id: self.device_unique_id,
+            raw_data_hash_crc,
+            chunk_index,
+            chunk_count,
+            total_len: data.len() as u32,
+            data: data[start..end].to_vec(),
+        })
+    }
+    
+    // Frames every chunk the request covers, clamped to the block's length.
+    pub fn frame_request(&mut self, request: &RawDataRequest) -> Result<Vec<Vec<u8>>> {
+        self.frame_request_up_to(request, usize::MAX)
+    }
+    
+    // Like `frame_request`, but frames no more than `max_chunks`; the
+    // requester asks again for whatever is still missing.
+    pub fn frame_request_up_to(&mut self, request: &RawDataRequest, max_chunks: usize) -> Result<Vec<Vec<u8>>> {
+        let chunk_count = match self.chunk_count(request.raw_data_hash_crc) {
+            Some(count) if request.device_unique_id == self.device_unique_id => count,
+            _ => {
+                self.metrics.unknown_requests += 1;
+                return Err(CyDnAError::UnknownRawData(request.raw_data_hash_crc));
+            }
+        };
+        
+        let last = match request.chunk_count {
+            0 => chunk_count,
+            count => request.first_chunk.saturating_add(count).min(chunk_count),
+        };
+        
+        let last = last.min(request.first_chunk.saturating_add(max_chunks.min(u16::MAX as usize) as u16));
+        
+        let frames = (request.first_chunk..last)
+            .map(|index| encode_chunk(&self.chunk(request.raw_data_hash_crc, index)?))
+            .collect::<Result<Vec<_>>>()?;
+        
+        self.metrics.requests_served += 1;
+        self.metrics.chunks_framed += frames.len() as u64;
+        Ok(frames)
+    }
+    
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+    
+    pub fn metrics(&self) -> RawStoreMetrics {
+        self.metrics
+    }
+}
+
+fn chunk_count_for(len: usize) -> u16 {
+    len.div_ceil(RAW_CHUNK_SIZE).max(1) as u16
+}
+
+// Gateway side: collects the chunks of one block in any order and verifies
+// the reassembled data against the CRC32 the payload carried.
+pub struct RawDataAssembler {
+    device_unique_id: u32,
+    raw_data_hash_crc: u32,
+    total_len: Option<u32>,
+    chunks: Vec<Option<Vec<u8>>>,
+}
+
+impl RawDataAssembler {
+    pub fn new(device_unique_id: u32, raw_data_hash_crc: u32) -> Self {
+        Self {
+            device_unique_id,
+            raw_data_hash_crc,
+            total_len: None,
+            chunks: Vec::new(),
+        }
+    }
+    
+    pub fn device_unique_id(&self) -> u32 {
+        self.device_unique_id
+    }
+    
+    pub fn raw_data_hash_crc(&self) -> u32 {
+        self.raw_data_hash_crc
+    }
+    
+    pub fn matches(&self, chunk: &RawDataChunk) -> bool {
+        chunk.device_unique_id == self.device_unique_id
+            && chunk.raw_data_hash_crc == self.raw_data_hash_crc
+    }
+    
+    // Requests whatever has not arrived yet, starting at the first gap.
+    pub fn next_request(&self) -> Option<RawDataRequest> {
+        if self.total_len.is_none() {
+            return Some(self.request(0, 0));
+        }
+        
+        let first = self.chunks.iter().position(Option::is_none)?;
+        let run = self.chunks[first..].iter()
+            .take_while(|chunk| chunk.is_none())
+            .count();
+        
+        Some(self.request(first as u16, run as u16))
+    }
+    
+    fn request(&self, first_chunk: u16, chunk_count: u16) -> RawDataRequest {
+        RawDataRequest {
+            device_unique_id: self.device_unique_id,
+            raw_data_hash_crc: self.raw_data_hash_crc,
+            first_chunk,
+            chunk_count,
+        }
+    }
+    
+    // Returns the verified block once the last missing chunk arrives. A CRC
+    // mismatch discards everything received so the pull can start over.
+    pub fn accept(&mut self, chunk: RawDataChunk) -> Result<Option<Vec<u8>>> {
+        if !self.matches(&chunk) {
+            return Err(CyDnAError::UnknownRawData(chunk.raw_data_hash_crc));
+        }
+        
+        let total_len = chunk.total_len as usize;
+        let invalid = Err(CyDnAError::InvalidRawDataChunk(chunk.chunk_index));
+        
+        if total_len > MAX_RAW_DATA_LEN
+            || chunk.chunk_count != chunk_count_for(total_len)
+            || chunk.chunk_index >= chunk.chunk_count
+            || self.total_len.is_some_and(|expected| expected != chunk.total_len)
+        {
+            return invalid;
+        }
+        
+        let start = chunk.chunk_index as usize * RAW_CHUNK_SIZE;
+        if chunk.data.len() != (total_len - start).min(RAW_CHUNK_SIZE) {
+            return invalid;
+        }
+        
+        if self.total_len.is_none() {
+            self.total_len = Some(chunk.total_len);
+            self.chunks = vec![None; chunk.chunk_count as usize];
+        }
+        
+        self.chunks[chunk.chunk_index as usize] = Some(chunk.data);
+        
+        if !self.is_complete() {
+            return Ok(None);
+        }
+        
+        let data: Vec<u8> = self.chunks.iter_mut()
+            .flat_map(|chunk| chunk.take().unwrap_or_default())
+            .collect();
+        self.total_len = None;
+        self.chunks.clear();
+        
+        verify_crc32(self.raw_data_hash_crc, &data)?;
+        Ok(Some(data))
+    }
+    
+    pub fn received_chunks(&self) -> usize {
+        self.chunks.iter().filter(|chunk| chunk.is_some()).count()
+    }
+    
+    pub fn is_complete(&self) -> bool {
+        !self.chunks.is_empty() && self.chunks.iter().all(Option::is_some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn vibration_block(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+    
+    #[test]
+    fn test_raw_data_chunked_roundtrip() {
+        let raw = vibration_block(RAW_CHUNK_SIZE * 3 + 100);
+        let mut store = RawDataStore::new(9, 2);
+        let crc = store.insert(raw.clone()).unwrap();
+        assert_eq!(store.chunk_count(crc), Some(4));
+        
+        let mut assembler = RawDataAssembler::new(9, crc);
+        let request = assembler.next_request().unwrap();
+        assert_eq!((request.first_chunk, request.chunk_count), (0, 0));
+        
+        let datagram = encode_request(&request).unwrap();
+        let frames = store.frame_request(&parse_request(&datagram).unwrap()).unwrap();
+        assert_eq!(frames.len(), 4);
+        assert!(frames.iter().all(|frame| frame.len() <= crate::MAX_PAYLOAD_SIZE));
+        
+        let header = crate::framing::FrameHeader::decode(&frames[0]).unwrap();
+        assert_eq!(header.priority(), Priority::Bulk);
+        
+        // Chunk 1 is lost; the follow-up request only covers the gap.
+        for index in [3, 0, 2] {
+            assert_eq!(assembler.accept(parse_chunk(&frames[index]).unwrap()).unwrap(), None);
+        }
+        let retry = assembler.next_request().unwrap();
+        assert_eq!((retry.first_chunk, retry.chunk_count), (1, 1));
+        
+        let resent = store.frame_request(&retry).unwrap();
+        assert_eq!(resent.len(), 1);
+        let block = assembler.accept(parse_chunk(&resent[0]).unwrap()).unwrap().unwrap();
+        assert_eq!(block, raw);
+        
+        assert_eq!(store.metrics().requests_served, 2);
+        assert_eq!(store.metrics().chunks_framed, 5);
+    }
+    
+    #[test]
+    fn test_raw_data_rejects_corruption_and_unknown_blocks() {
+        let raw = vibration_block(RAW_CHUNK_SIZE + 10);
+        let mut store = RawDataStore::new(9, 1);
+        let crc = store.insert(raw).unwrap();
+        
+        let mut assembler = RawDataAssembler::new(9, crc);
+        let mut first = store.chunk(crc, 0).unwrap();
+        first.data[0] ^= 0xff;
+        assembler.accept(first).unwrap();
+        assert!(matches!(
+            assembler.accept(store.chunk(crc, 1).unwrap()),
+            Err(CyDnAError::IntegrityCheckFailed { .. })
+        ));
+        assert_eq!(assembler.received_chunks(), 0);
+        
+        let mut truncated = store.chunk(crc, 1).unwrap();
+        truncated.data.pop();
+        assert!(matches!(assembler.accept(truncated), Err(CyDnAError::InvalidRawDataChunk(1))));
+        
+        // Capacity 1: the newer block evicts the first one.
+        store.insert(vibration_block(10)).unwrap();
+        assert!(store.get(crc).is_none());
+        assert!(matches!(
+            store.frame_request(&assembler.next_request().unwrap()),
+            Err(CyDnAError::UnknownRawData(_))
+        ));
+        assert_eq!(store.metrics().evicted, 1);
+        assert_eq!(store.metrics().unknown_requests, 1);
+    }
+}
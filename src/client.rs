@@ -0,0 +1,1371 @@
+use std::collections::VecDeque;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::ack_manager::{AckManager, RetransmissionEvent, RetransmissionScheduler};
+use crate::bulk::RawDataStore;
+use crate::clock::{SharedClock, SystemClock};
+use crate::control::ControlInbox;
+use crate::contracts::{ControlMessage, Heartbeat, SensorPayload, SensorPayloadV2};
+use crate::errors::{CyDnAError, Result};
+use crate::framing::{negotiate_version, FrameHeader, MessageType, Priority};
+use crate::pacing::Pacer;
+use crate::sequence::SequenceCounter;
+use crate::shutdown::ShutdownToken;
+use crate::socket::SocketBuilder;
+use crate::store_forward::StoreAndForwardQueue;
+use crate::transmitter::Transmitter;
+use crate::transport::{FallbackMetrics, FallbackTransport, FrameTransport, TransportKind, UdpTransport};
+use crate::{ACK_TIMEOUT_MS, MAX_PAYLOAD_SIZE, MAX_RETRANSMIT_ATTEMPTS};
+
+// Raw-data chunks sent per `poll`, after retransmissions, so a bulk pull
+// never delays the latency-critical traffic by more than a few datagrams.
+pub const BULK_CHUNKS_PER_POLL: usize = 4;
+
+// Raw-data chunks framed but not yet sent. Requests arriving while the
+// backlog is full are served only up to this limit; the gateway asks again
+// for the rest.
+pub const MAX_BULK_BACKLOG: usize = 64;
+
+// What `SensorClient::shutdown` did with the outstanding critical payloads.
+#[derive(Debug, Clone, Default)]
+pub struct DrainReport {
+    // Every delivery event not yet polled, including those raised while
+    // draining.
+    pub events: Vec<RetransmissionEvent>,
+    
+    // Unacknowledged but within TTL, moved to the store-and-forward queue
+    // and forwarded by the first `poll` after the restart.
+    pub persisted: usize,
+    
+    pub expired: usize,
+    
+    // Within TTL but with no queue attached, or a queue full of newer
+    // critical alerts.
+    pub dropped: usize,
+}
+
+impl DrainReport {
+    pub fn is_clean(&self) -> bool {
+        self.dropped == 0
+    }
+}
+
+// How a client holding a keyring seals its payloads; the gateway must be
+// built with the matching `with_cipher` or `with_authenticator`.
+#[cfg(feature = "encryption")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Envelope {
+    #[default]
+    Encrypted,
+    #[cfg(feature = "authentication")]
+    Authenticated,
+}
+
+// Holds the keyring's key for whichever envelope is in use, reloaded when
+// an `InstallKey` moves the keyring to another generation. The cipher is
+// kept across reloads so its nonces never repeat.
+#[cfg(feature = "encryption")]
+#[derive(Default)]
+struct Sealer {
+    envelope: Envelope,
+    generation: Option<u32>,
+    cipher: crate::encryption::PayloadCipher,
+    #[cfg(feature = "authentication")]
+    authenticator: crate::authentication::DatagramAuthenticator,
+}
+
+#[cfg(feature = "encryption")]
+impl Sealer {
+    fn frame(&mut self, keyring: &crate::key_rotation::DeviceKeyring, payload: &SensorPayload, priority: Priority) -> Result<Vec<u8>> {
+        if self.generation != Some(keyring.generation()) {
+            self.cipher.set_device_keys(keyring.device_id(), *keyring.key(), None);
+            #[cfg(feature = "authentication")]
+            self.authenticator.provision_key(keyring.device_id(), keyring.key())?;
+            self.generation = Some(keyring.generation());
+        }
+        
+        match self.envelope {
+            Envelope::Encrypted => {
+                let bytes = Transmitter::serialize_payload(payload)?;
+                let envelope = self.cipher.seal(payload.device_unique_id, &bytes)?;
+                crate::framing::encode_frame_with_priority(MessageType::EncryptedPayload, priority, &envelope)
+            }
+            #[cfg(feature = "authentication")]
+            Envelope::Authenticated => {
+                let frame = Transmitter::frame_payload_with_priority(payload, priority)?;
+                self.authenticator.seal(payload.device_unique_id, &frame)
+            }
+        }
+    }
+}
+
+struct HeartbeatSchedule {
+    device_id: u32,
+    interval: Duration,
+    battery_level_percent: u8,
+    // Last datagram of any kind; the gateway counts each as a sign of life.
+    last_sent: Option<Instant>,
+}
+
+pub struct SensorClient {
+    transport: FallbackTransport,
+    gateway: SocketAddr,
+    scheduler: RetransmissionScheduler,
+    sequence: SequenceCounter,
+    buffer: Vec<u8>,
+    store: Option<StoreAndForwardQueue>,
+    // Set when a tracked payload runs out of attempts or a send fails, and
+    // cleared by the next ACK or NACK from the gateway.
+    offline: bool,
+    // (device, timestamp, critical) of the stored payload last sent to probe
+    // the gateway, so it is parked with its own priority if it goes unanswered.
+    probe: Option<(u32, u64, bool)>,
+    pacer: Option<Pacer>,
+    protocol_version: u16,
+    raw_data: Option<RawDataStore>,
+    bulk_backlog: VecDeque<Vec<u8>>,
+    control: Option<ControlInbox>,
+    heartbeat: Option<HeartbeatSchedule>,
+    shutdown: ShutdownToken,
+    clock: SharedClock,
+    #[cfg(feature = "encryption")]
+    keyring: Option<crate::key_rotation::DeviceKeyring>,
+    #[cfg(feature = "encryption")]
+    sealer: Sealer,
+}
+
+impl SensorClient {
+    pub fn connect(bind_address: impl ToSocketAddrs, gateway_address: impl ToSocketAddrs) -> Result<Self> {
+        Self::connect_with(&SocketBuilder::new(bind_address)?, gateway_address)
+    }
+    
+    // Binds through `builder` so QoS marking and buffer sizes apply to the
+    // client's socket. Blocking mode and timeouts are managed per receive.
+    pub fn connect_with(builder: &SocketBuilder, gateway_address: impl ToSocketAddrs) -> Result<Self> {
+        let udp = UdpTransport::connect(builder.bind()?, gateway_address)?;
+        let gateway = udp.peer_address()?;
+        
+        Ok(Self {
+            transport: FallbackTransport::new(udp),
+            gateway,
+            scheduler: RetransmissionScheduler::new(MAX_RETRANSMIT_ATTEMPTS, ACK_TIMEOUT_MS),
+            sequence: SequenceCounter::new(),
+            buffer: vec![0u8; MAX_PAYLOAD_SIZE],
+            store: None,
+            offline: false,
+            probe: None,
+            pacer: None,
+            protocol_version: crate::CYNDA_VERSION,
+            raw_data: None,
+            bulk_backlog: VecDeque::new(),
+            control: None,
+            heartbeat: None,
+            shutdown: ShutdownToken::new(),
+            clock: Arc::new(SystemClock),
+            #[cfg(feature = "encryption")]
+            keyring: None,
+            #[cfg(feature = "encryption")]
+            sealer: Sealer::default(),
+        })
+    }
+    
+    // Connects to the first gateway heard on the discovery group.
+    pub fn connect_discovered(bind_address: impl ToSocketAddrs, timeout: Duration) -> Result<Self> {
+        let gateway = crate::discovery::discover_gateways(timeout)?
+            .into_iter()
+            .next()
+            .ok_or(CyDnAError::NoGatewayDiscovered)?;
+        
+        Ok(Self::connect(bind_address, gateway.address)?
+            .with_protocol_version(gateway.protocol_version))
+    }
+    
+    pub fn with_retransmission(mut self, max_retries: u32, base_timeout_ms: u64) -> Self {
+        self.scheduler = RetransmissionScheduler::new(max_retries, base_timeout_ms)
+            .with_clock(self.clock.clone());
+        self
+    }
+    
+    // Retransmission timers, pacing and the timestamps the client answers
+    // time-sync probes with are all read from `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.scheduler.set_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+    
+    // `poll` sends a heartbeat for `device_id` whenever nothing else has gone
+    // out for `interval`. It reports the level last passed to
+    // `set_battery_level` or `adapt_to_battery`, 100 until then.
+    pub fn with_heartbeat_interval(mut self, device_id: u32, interval: Duration) -> Self {
+        self.heartbeat = Some(HeartbeatSchedule {
+            device_id,
+            interval,
+            battery_level_percent: 100,
+            last_sent: None,
+        });
+        self
+    }
+    
+    pub fn set_battery_level(&mut self, battery_level_percent: u8) {
+        if let Some(heartbeat) = self.heartbeat.as_mut() {
+            heartbeat.battery_level_percent = battery_level_percent.min(100);
+        }
+    }
+    
+    pub fn with_sequence_start(mut self, next: u32) -> Self {
+        self.sequence = SequenceCounter::starting_at(next);
+        self
+    }
+    
+    // Takes the gateway's advertised maximum; `send_v2` uses the highest
+    // version both sides understand.
+    pub fn with_protocol_version(mut self, gateway_max_version: u16) -> Self {
+        self.protocol_version = negotiate_version(gateway_max_version);
+        self
+    }
+    
+    pub fn protocol_version(&self) -> u16 {
+        self.protocol_version
+    }
+    
+    // Switches to length-prefixed TCP on the gateway's address and port after
+    // `failure_threshold` consecutive retransmissions without an ACK, for
+    // sites whose firewalls drop UDP.
+    pub fn with_tcp_fallback(mut self, failure_threshold: u32) -> Self {
+        self.transport = self.transport.with_tcp_fallback(self.gateway, failure_threshold);
+        self
+    }
+    
+    pub fn transport_kind(&self) -> TransportKind {
+        self.transport.kind()
+    }
+    
+    pub fn transport_metrics(&self) -> FallbackMetrics {
+        self.transport.metrics()
+    }
+    
+    // Critical payloads that exhaust their retries are parked in the queue,
+    // as are routine sends while the gateway is unreachable. `poll` probes
+    // with one stored payload at a time, tracked like a critical one, and
+    // forwards the rest as soon as the gateway answers again.
+    pub fn with_store_and_forward(mut self, queue: StoreAndForwardQueue) -> Self {
+        self.store = Some(queue);
+        self
+    }
+    
+    // Lets the gateway pull raw blocks referenced by `raw_data_hash_crc`.
+    pub fn with_raw_data_store(mut self, store: RawDataStore) -> Self {
+        self.raw_data = Some(store);
+        self
+    }
+    
+    pub fn raw_data_store(&self) -> Option<&RawDataStore> {
+        self.raw_data.as_ref()
+    }
+    
+    // Keeps the block for a later pull and returns its CRC32 for the payload.
+    pub fn store_raw_data(&mut self, raw_data: Vec<u8>) -> Result<u32> {
+        match self.raw_data.as_mut() {
+            Some(store) => store.insert(raw_data),
+            None => Ok(crate::contracts::compute_crc32(&raw_data)),
+        }
+    }
+    
+    pub fn bulk_backlog_len(&self) -> usize {
+        self.bulk_backlog.len()
+    }
+    
+    // Accepts gateway control commands addressed to `device_id`; each one is
+    // ACKed as it arrives and handed out once through `next_control_command`.
+    pub fn with_control(mut self, device_id: u32) -> Self {
+        self.control = Some(ControlInbox::new(device_id));
+        self
+    }
+    
+    pub fn next_control_command(&mut self) -> Option<ControlMessage> {
+        self.control.as_mut()?.next_command()
+    }
+    
+    // Seals every payload under the keyring's current key and applies
+    // `InstallKey` commands to it before ACKing them, refusing any the
+    // keyring cannot apply (and every `RotateKey`). Payloads for another
+    // device fail with `UnknownDeviceKey`. Needs `with_control` for the
+    // keyring's device to follow rotations.
+    #[cfg(feature = "encryption")]
+    pub fn with_keyring(mut self, keyring: crate::key_rotation::DeviceKeyring) -> Self {
+        self.keyring = Some(keyring);
+        self
+    }
+    
+    #[cfg(feature = "encryption")]
+    pub fn with_envelope(mut self, envelope: Envelope) -> Self {
+        self.sealer.envelope = envelope;
+        self
+    }
+    
+    #[cfg(feature = "encryption")]
+    pub fn keyring(&self) -> Option<&crate::key_rotation::DeviceKeyring> {
+        self.keyring.as_ref()
+    }
+    
+    #[cfg(feature = "encryption")]
+    pub fn key_generation(&self) -> Option<u32> {
+        self.keyring.as_ref().map(|keyring| keyring.generation())
+    }
+    
+    // Once the token is triggered, new sends fail with `Cancelled`; call
+    // `shutdown` to drain what is already in flight.
+    pub fn with_shutdown_token(mut self, token: ShutdownToken) -> Self {
+        self.shutdown = token;
+        self
+    }
+    
+    pub fn shutdown_token(&self) -> ShutdownToken {
+        self.shutdown.clone()
+    }
+    
+    // With a pacer attached, routine sends block until the current rate
+    // allows them; ACK RTTs and retransmissions feed back into the rate.
+    pub fn with_pacer(mut self, pacer: Pacer) -> Self {
+        self.pacer = Some(pacer);
+        self
+    }
+    
+    pub fn pacer(&self) -> Option<&Pacer> {
+        self.pacer.as_ref()
+    }
+    
+    // Scales retransmission aggressiveness down as the battery drains.
+    pub fn adapt_to_battery(&mut self, policy: &crate::batching::BatchingPolicy, battery_level_percent: u8) {
+        let tier = policy.tier_for(battery_level_percent);
+        self.scheduler.set_retry_policy(tier.max_retries, tier.base_timeout_ms);
+        self.set_battery_level(battery_level_percent);
+    }
+    
+    pub fn gateway_address(&self) -> SocketAddr {
+        self.gateway
+    }
+    
+    pub fn local_address(&self) -> Result<SocketAddr> {
+        self.transport.udp().socket().local_addr()
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    // Fire-and-forget: the payload is stamped with the next sequence number
+    // but no delivery event will be produced for it.
+    pub fn send(&mut self, payload: &SensorPayload) -> Result<u32> {
+        self.send_with_priority(payload, Priority::Normal)
+    }
+    
+    // Consumes the next sequence number without sending anything, so the
+    // gateway sees the gap a frame lost in transit would leave.
+    pub fn skip_sequence(&mut self) -> u32 {
+        self.sequence.next_sequence()
+    }
+    
+    // With a store-and-forward queue attached, routine payloads are stored
+    // instead while the gateway is unreachable, or if the send itself fails.
+    pub fn send_with_priority(&mut self, payload: &SensorPayload, priority: Priority) -> Result<u32> {
+        self.check_accepting()?;
+        let payload = payload.with_sequence_number(self.sequence.next_sequence());
+        let buffered = priority != Priority::Critical && self.store.is_some();
+        
+        if buffered && self.offline {
+            self.store_routine(payload)?;
+            return Ok(payload.sequence_number);
+        }
+        
+        match self.transmit(&payload, priority) {
+            Err(CyDnAError::IoError(_)) if buffered => {
+                self.offline = true;
+                self.store_routine(payload)?;
+            }
+            result => {
+                result?;
+            }
+        }
+        Ok(payload.sequence_number)
+    }
+    
+    fn store_routine(&mut self, payload: SensorPayload) -> Result<()> {
+        if let Some(store) = self.store.as_mut() {
+            // A queue full of critical alerts drops the new routine payload.
+            store.push(payload, false)?;
+        }
+        Ok(())
+    }
+    
+    // Sends and tracks the payload until it is acknowledged, rejected or runs
+    // out of attempts; the outcome is reported through `poll_event`/`events`.
+    pub fn send_critical(&mut self, payload: &SensorPayload) -> Result<u32> {
+        self.check_accepting()?;
+        let payload = payload.with_sequence_number(self.sequence.next_sequence());
+        self.transmit(&payload, Priority::Critical)?;
+        self.scheduler.track(payload);
+        Ok(payload.sequence_number)
+    }
+    
+    // Sent as a version 2 frame when negotiated, otherwise downgraded to a
+    // SensorPayload, which fails if the vector is not ANOMALY_VECTOR_SIZE long.
+    // Gateways only open envelopes around SensorPayload frames, so a client
+    // with a keyring always downgrades.
+    pub fn send_v2(&mut self, payload: &SensorPayloadV2) -> Result<u32> {
+        self.check_accepting()?;
+        #[cfg(feature = "encryption")]
+        let sealed = self.keyring.is_some();
+        #[cfg(not(feature = "encryption"))]
+        let sealed = false;
+        if sealed || self.protocol_version < crate::CYNDA_VERSION_V2 {
+            return self.send(&SensorPayload::try_from(payload)?);
+        }
+        
+        let payload = payload.clone().with_sequence_number(self.sequence.next_sequence());
+        let frame = Transmitter::frame_payload_v2(&payload)?;
+        self.transmit_frame(&frame)?;
+        Ok(payload.sequence_number)
+    }
+    
+    pub fn send_heartbeat(&mut self, heartbeat: &Heartbeat) -> Result<usize> {
+        let frame = Transmitter::frame_heartbeat(heartbeat)?;
+        
+        self.mark_sent();
+        self.transport.send_frame(&frame)
+    }
+    
+    fn send_heartbeat_if_due(&mut self) -> Result<bool> {
+        let now = self.clock.now();
+        let Some(schedule) = self.heartbeat.as_ref() else {
+            return Ok(false);
+        };
+        
+        let due = schedule.last_sent
+            .map(|last| now.saturating_duration_since(last) >= schedule.interval)
+            .unwrap_or(true);
+        if !due {
+            return Ok(false);
+        }
+        
+        let heartbeat = Heartbeat::new(schedule.device_id, self.clock.now_ms(), schedule.battery_level_percent);
+        self.send_heartbeat(&heartbeat)?;
+        Ok(true)
+    }
+    
+    fn mark_sent(&mut self) {
+        if let Some(heartbeat) = self.heartbeat.as_mut() {
+            heartbeat.last_sent = Some(self.clock.now());
+        }
+    }
+    
+    fn check_accepting(&self) -> Result<()> {
+        match self.shutdown.is_triggered() {
+            true => Err(CyDnAError::Cancelled),
+            false => Ok(()),
+        }
+    }
+    
+    fn transmit(&mut self, payload: &SensorPayload, priority: Priority) -> Result<usize> {
+        #[cfg(feature = "encryption")]
+        let frame = match self.keyring.as_ref() {
+            Some(keyring) => self.sealer.frame(keyring, payload, priority)?,
+            None => Transmitter::frame_payload_with_priority(payload, priority)?,
+        };
+        #[cfg(not(feature = "encryption"))]
+        let frame = Transmitter::frame_payload_with_priority(payload, priority)?;
+        self.transmit_frame_with(&frame, priority)
+    }
+    
+    fn transmit_frame(&mut self, frame: &[u8]) -> Result<usize> {
+        self.transmit_frame_with(frame, Priority::Normal)
+    }
+    
+    // Critical frames, retransmissions included, never wait for the pacer;
+    // they still count against its budget so routine traffic backs off.
+    fn transmit_frame_with(&mut self, frame: &[u8], priority: Priority) -> Result<usize> {
+        if let Some(pacer) = self.pacer.as_mut() {
+            if priority != Priority::Critical {
+                std::thread::sleep(pacer.time_until_send(self.clock.now()));
+            }
+            pacer.on_send(self.clock.now());
+        }
+        
+        self.mark_sent();
+        self.transport.send_frame(frame)
+    }
+    
+    // Drains every ACK already queued on the socket, retransmits whatever
+    // is due, sends a few pending raw-data chunks and a heartbeat if one is
+    // due. Never blocks; returns the number of retransmissions sent.
+    pub fn poll(&mut self) -> Result<usize> {
+        while self.receive_ack(None)? {}
+        
+        let rtt_samples = self.scheduler.drain_rtt_samples();
+        if !rtt_samples.is_empty() {
+            self.transport.on_delivery_success();
+        }
+        
+        let retransmitted = self.retransmit_due()?;
+        self.forward_or_probe()?;
+        
+        for _ in 0..BULK_CHUNKS_PER_POLL {
+            let Some(frame) = self.bulk_backlog.pop_front() else {
+                break;
+            };
+            self.transmit_frame(&frame)?;
+        }
+        
+        if !self.shutdown.is_triggered() {
+            self.send_heartbeat_if_due()?;
+        }
+        
+        if let Some(pacer) = self.pacer.as_mut() {
+            let now = self.clock.now();
+            for &rtt in &rtt_samples {
+                pacer.on_ack(Some(rtt), now);
+            }
+            if retransmitted > 0 {
+                pacer.on_loss(now);
+            }
+        }
+        
+        Ok(retransmitted)
+    }
+    
+    // Blocks until a delivery event is available or `timeout` elapses,
+    // servicing retransmission timers in the meantime.
+    pub fn wait_for_event(&mut self, timeout: Duration) -> Result<Option<RetransmissionEvent>> {
+        let deadline = Instant::now() + timeout;
+        
+        loop {
+            self.poll()?;
+            
+            if let Some(event) = self.next_event() {
+                return Ok(Some(event));
+            }
+            
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            
+            let mut wait = deadline - now;
+            if let Some(wakeup) = self.scheduler.time_until_next_wakeup() {
+                wait = wait.min(wakeup);
+            }
+            
+            self.receive_ack(Some(wait.max(Duration::from_millis(1))))?;
+        }
+    }
+    
+    pub fn poll_event(&mut self) -> Option<RetransmissionEvent> {
+        self.next_event()
+    }
+    
+    pub fn events(&mut self) -> impl Iterator<Item = RetransmissionEvent> + '_ {
+        std::iter::from_fn(move || self.next_event())
+    }
+    
+    fn next_event(&mut self) -> Option<RetransmissionEvent> {
+        let event = self.scheduler.poll_event();
+        
+        if let Some(RetransmissionEvent::Exhausted { payload, .. }) = &event {
+            self.offline = true;
+            
+            let key = (payload.device_unique_id, payload.timestamp_ms_utc);
+            let critical = match self.probe {
+                Some((device_id, timestamp_ms, critical)) if (device_id, timestamp_ms) == key => critical,
+                _ => true,
+            };
+            if let Some(store) = self.store.as_mut() {
+                // A full queue of newer critical alerts wins; the event still reports the loss.
+                let _ = store.push(*payload, critical);
+            }
+        }
+        
+        event
+    }
+    
+    // Forwards everything stored while the gateway is answering. While it
+    // is not, and nothing else is in flight, sends the next stored payload
+    // as a tracked probe; its ACK brings the client back online.
+    fn forward_or_probe(&mut self) -> Result<()> {
+        if self.shutdown.is_triggered() || self.stored_count() == 0 {
+            return Ok(());
+        }
+        
+        let now_ms = self.clock.now_ms();
+        if !self.offline {
+            return match self.forward_stored(now_ms) {
+                Err(CyDnAError::IoError(_)) => {
+                    self.offline = true;
+                    Ok(())
+                }
+                result => result.map(|_| ()),
+            };
+        }
+        
+        if self.scheduler.pending_count() > 0 {
+            return Ok(());
+        }
+        
+        let Some(stored) = self.store.as_mut().map(|store| store.pop_live(now_ms)).transpose()?.flatten() else {
+            return Ok(());
+        };
+        
+        let priority = if stored.critical { Priority::Critical } else { Priority::Normal };
+        match self.transmit(&stored.payload, priority) {
+            Ok(_) => {
+                self.probe = Some((stored.payload.device_unique_id, stored.payload.timestamp_ms_utc, stored.critical));
+                self.scheduler.track(stored.payload);
+                Ok(())
+            }
+            Err(e) => {
+                if let Some(store) = self.store.as_mut() {
+                    store.push(stored.payload, stored.critical)?;
+                }
+                match e {
+                    CyDnAError::IoError(_) => Ok(()),
+                    e => Err(e),
+                }
+            }
+        }
+    }
+    
+    // Re-sends every stored payload that is still within its TTL, keeping
+    // its original sequence number. Critical ones are tracked again.
+    pub fn forward_stored(&mut self, current_time_ms: u64) -> Result<usize> {
+        self.check_accepting()?;
+        let mut forwarded = 0;
+        
+        while let Some(stored) = match self.store.as_mut() {
+            Some(store) => store.pop_live(current_time_ms)?,
+            None => None,
+        } {
+            let priority = if stored.critical { Priority::Critical } else { Priority::Normal };
+            if let Err(e) = self.transmit(&stored.payload, priority) {
+                if let Some(store) = self.store.as_mut() {
+                    store.push(stored.payload, stored.critical)?;
+                }
+                return Err(e);
+            }
+            
+            if stored.critical {
+                self.scheduler.track(stored.payload);
+            }
+            forwarded += 1;
+        }
+        
+        Ok(forwarded)
+    }
+    
+    // Triggers the shutdown token, then keeps servicing ACKs, retransmissions
+    // and raw-data chunks until nothing is in flight or `timeout` elapses.
+    // Critical payloads still unacknowledged after that, and those that ran
+    // out of attempts, are parked in the store-and-forward queue (synced to
+    // disk if file-backed) unless their TTL has passed.
+    pub fn shutdown(&mut self, timeout: Duration) -> Result<DrainReport> {
+        self.shutdown.trigger();
+        let deadline = Instant::now() + timeout;
+        
+        // An unreachable gateway ends the drain early; whatever is left is
+        // persisted below either way.
+        while self.poll().is_ok() && !(self.scheduler.pending_count() == 0 && self.bulk_backlog.is_empty()) {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            
+            let mut wait = deadline - now;
+            if let Some(wakeup) = self.scheduler.time_until_next_wakeup() {
+                wait = wait.min(wakeup);
+            }
+            if self.receive_ack(Some(wait.max(Duration::from_millis(1)))).is_err() {
+                break;
+            }
+        }
+        
+        let now_ms = self.clock.now_ms();
+        let mut report = DrainReport::default();
+        
+        while let Some(event) = self.scheduler.poll_event() {
+            if let RetransmissionEvent::Exhausted { payload, .. } = &event {
+                self.park(*payload, now_ms, &mut report)?;
+            }
+            report.events.push(event);
+        }
+        for payload in self.scheduler.drain_pending() {
+            self.park(payload, now_ms, &mut report)?;
+        }
+        
+        if let Some(store) = self.store.as_mut() {
+            store.sync()?;
+        }
+        Ok(report)
+    }
+    
+    fn park(&mut self, payload: SensorPayload, now_ms: u64, report: &mut DrainReport) -> Result<()> {
+        let expiry = payload.timestamp_ms_utc.saturating_add(payload.time_to_live_ms as u64);
+        
+        if now_ms > expiry {
+            report.expired += 1;
+        } else if let Some(store) = self.store.as_mut() {
+            match store.push(payload, true)? {
+                true => report.persisted += 1,
+                false => report.dropped += 1,
+            }
+        } else {
+            report.dropped += 1;
+        }
+        Ok(())
+    }
+    
+    pub fn stored_count(&self) -> usize {
+        self.store.as_ref().map(|store| store.len()).unwrap_or(0)
+    }
+    
+    pub fn pending_count(&self) -> usize {
+        self.scheduler.pending_count()
+    }
+    
+    pub fn is_idle(&self) -> bool {
+        self.scheduler.is_idle()
+    }
+    
+    fn retransmit_due(&mut self) -> Result<usize> {
+        // A due retransmission means the previous attempt went unanswered;
+        // reporting it first lets the attempt that crosses the threshold
+        // already go out over TCP.
+        let due = self.scheduler.due_retransmissions();
+        for payload in &due {
+            self.transport.on_delivery_failure();
+            self.transmit(payload, Priority::Critical)?;
+        }
+        
+        Ok(due.len())
+    }
+    
+    // `None` reads without blocking. Returns whether a frame was consumed.
+    fn receive_ack(&mut self, timeout: Option<Duration>) -> Result<bool> {
+        match self.transport.recv_frame(&mut self.buffer, timeout)? {
+            Some(bytes_received) => {
+                let datagram = &self.buffer[..bytes_received];
+                let message_type = FrameHeader::decode(datagram).map(|header| header.message_type);
+                
+                // Stray or malformed datagrams are not fatal to the client.
+                match message_type {
+                    Ok(MessageType::RawDataRequest) => {
+                        let room = MAX_BULK_BACKLOG.saturating_sub(self.bulk_backlog.len());
+                        if let Some(store) = self.raw_data.as_mut().filter(|_| room > 0) {
+                            if let Ok(frames) = crate::bulk::parse_request(datagram)
+                                .and_then(|request| store.frame_request_up_to(&request, room))
+                            {
+                                self.bulk_backlog.extend(frames);
+                            }
+                        }
+                    }
+                    Ok(MessageType::ControlMessage) => {
+                        if let Some(inbox) = self.control.as_mut() {
+                            let message = crate::control::parse_control(datagram);
+                            #[cfg(feature = "encryption")]
+                            let ack = message.and_then(|message| inbox.receive_with(message, |command| {
+                                match self.keyring.as_mut() {
+                                    Some(keyring) => keyring.apply(command).map(|_| ()),
+                                    None => Ok(()),
+                                }
+                            }));
+                            #[cfg(not(feature = "encryption"))]
+                            let ack = message.and_then(|message| inbox.receive(message));
+                            
+                            if let Ok(Some(ack)) = ack {
+                                self.transport.send_frame(&ack)?;
+                            }
+                        }
+                    }
+                    Ok(MessageType::TimeSyncRequest) => {
+                        // Both stamps are taken here: the reply leaves
+                        // straight away, so the difference is negligible.
+                        if let Ok(request) = crate::time_sync::parse_time_sync_request(datagram) {
+                            let now_ms = self.clock.now_ms();
+                            self.transport.send_frame(&crate::time_sync::respond(&request, now_ms, now_ms)?)?;
+                        }
+                    }
+                    _ => {
+                        // Any ACK or NACK, tracked or not, shows the gateway is back.
+                        if let Ok(Some(_)) = AckManager::parse_ack_message(datagram) {
+                            self.offline = false;
+                        }
+                        let _ = self.scheduler.handle_ack_datagram(datagram);
+                    }
+                }
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::{NackReason, ANOMALY_VECTOR_SIZE};
+    use crate::receiver::Receiver;
+    use std::net::UdpSocket;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    
+    fn payload(device_id: u32) -> SensorPayload {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        SensorPayload::new(device_id, now, 1, 80, 1000, device_id, [0.0; ANOMALY_VECTOR_SIZE])
+            .unwrap()
+    }
+    
+    #[test]
+    fn test_client_critical_delivery() {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        gateway.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let mut client = SensorClient::connect("127.0.0.1:0", &gateway_addr).unwrap()
+            .with_retransmission(3, 200);
+        let client_addr = client.local_address().unwrap().to_string();
+        
+        assert_eq!(client.send(&payload(1)).unwrap(), 0);
+        assert_eq!(client.send_critical(&payload(2)).unwrap(), 1);
+        assert_eq!(client.pending_count(), 1);
+        
+        let mut buffer = vec![0u8; MAX_PAYLOAD_SIZE];
+        for expected_sequence in 0..2 {
+            let (archived, _, _) = Receiver::receive(&gateway, &mut buffer).unwrap();
+            assert_eq!(archived.sequence_number, expected_sequence);
+            let (device_id, timestamp) = (archived.device_unique_id, archived.timestamp_ms_utc);
+            AckManager::send_ack(&gateway, device_id, timestamp, &client_addr).unwrap();
+        }
+        
+        match client.wait_for_event(Duration::from_secs(2)).unwrap() {
+            Some(RetransmissionEvent::Acked { device_id, attempts, .. }) => {
+                assert_eq!(device_id, 2);
+                assert_eq!(attempts, 1);
+            }
+            other => panic!("expected Acked, got {:?}", other),
+        }
+        assert!(client.is_idle());
+    }
+    
+    #[test]
+    fn test_client_stores_exhausted_alerts() {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        gateway.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let mut client = SensorClient::connect("127.0.0.1:0", &gateway_addr).unwrap()
+            .with_retransmission(1, 5)
+            .with_store_and_forward(StoreAndForwardQueue::new(4));
+        
+        let alert = payload(5);
+        client.send_critical(&alert).unwrap();
+        assert!(matches!(
+            client.wait_for_event(Duration::from_secs(2)).unwrap(),
+            Some(RetransmissionEvent::Exhausted { .. })
+        ));
+        assert_eq!(client.stored_count(), 1);
+        
+        let mut buffer = vec![0u8; MAX_PAYLOAD_SIZE];
+        Receiver::receive(&gateway, &mut buffer).unwrap();
+        
+        assert_eq!(client.forward_stored(alert.timestamp_ms_utc).unwrap(), 1);
+        assert_eq!(client.stored_count(), 0);
+        assert_eq!(client.pending_count(), 1);
+        
+        let (archived, _, _) = Receiver::receive(&gateway, &mut buffer).unwrap();
+        assert_eq!(archived.device_unique_id, 5);
+        assert_eq!(archived.sequence_number, 0);
+    }
+    
+    #[test]
+    fn test_client_buffers_while_offline_and_drains_on_reconnect() {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        gateway.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let mut client = SensorClient::connect("127.0.0.1:0", &gateway_addr).unwrap()
+            .with_retransmission(1, 5)
+            .with_store_and_forward(StoreAndForwardQueue::new(4));
+        let client_addr = client.local_address().unwrap().to_string();
+        let mut buffer = vec![0u8; MAX_PAYLOAD_SIZE];
+        
+        let alert = payload(5);
+        client.send_critical(&alert).unwrap();
+        assert!(matches!(
+            client.wait_for_event(Duration::from_secs(2)).unwrap(),
+            Some(RetransmissionEvent::Exhausted { .. })
+        ));
+        Receiver::receive(&gateway, &mut buffer).unwrap();
+        
+        // Offline: the routine reading is stored rather than sent.
+        client.send(&payload(6)).unwrap();
+        assert_eq!(client.stored_count(), 2);
+        
+        // The next poll probes with the stored alert; its ACK brings the
+        // client back and the routine reading follows on its own.
+        client.poll().unwrap();
+        let (archived, _, _) = Receiver::receive(&gateway, &mut buffer).unwrap();
+        assert_eq!(archived.device_unique_id, 5);
+        AckManager::send_ack(&gateway, 5, alert.timestamp_ms_utc, &client_addr).unwrap();
+        
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while client.stored_count() > 0 && Instant::now() < deadline {
+            client.poll().unwrap();
+        }
+        let (archived, _, _) = Receiver::receive(&gateway, &mut buffer).unwrap();
+        assert_eq!(archived.device_unique_id, 6);
+        assert_eq!(client.store.as_ref().unwrap().metrics().forwarded, 2);
+    }
+    
+    #[test]
+    fn test_poll_sends_heartbeats_when_idle() {
+        use crate::clock::MockClock;
+        
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        gateway.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        let clock = MockClock::new(50_000);
+        
+        let mut client = SensorClient::connect("127.0.0.1:0", &gateway_addr).unwrap()
+            .with_clock(Arc::new(clock.clone()))
+            .with_heartbeat_interval(9, Duration::from_secs(30));
+        client.set_battery_level(64);
+        let mut buffer = vec![0u8; MAX_PAYLOAD_SIZE];
+        
+        client.poll().unwrap();
+        let (len, _) = gateway.recv_from(&mut buffer).unwrap();
+        let heartbeat = Receiver::parse_heartbeat(&buffer[..len]).unwrap();
+        assert_eq!((heartbeat.device_unique_id, heartbeat.battery_level_percent), (9, 64));
+        assert_eq!(heartbeat.timestamp_ms_utc, 50_000);
+        
+        // A payload sent in the meantime counts as a sign of life and pushes
+        // the next heartbeat back.
+        clock.advance(Duration::from_secs(20));
+        client.send(&payload(9)).unwrap();
+        gateway.recv_from(&mut buffer).unwrap();
+        clock.advance(Duration::from_secs(20));
+        client.poll().unwrap();
+        assert!(gateway.recv_from(&mut buffer).is_err());
+        
+        clock.advance(Duration::from_secs(10));
+        client.poll().unwrap();
+        let (len, _) = gateway.recv_from(&mut buffer).unwrap();
+        assert_eq!(Receiver::parse_heartbeat(&buffer[..len]).unwrap().timestamp_ms_utc, 100_000);
+    }
+    
+    #[test]
+    fn test_pacer_does_not_delay_critical_alerts() {
+        use crate::pacing::PacingConfig;
+        
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        let pacer = Pacer::new(PacingConfig { initial_rate_pps: 1.0, ..Default::default() }).unwrap();
+        let mut client = SensorClient::connect("127.0.0.1:0", &gateway_addr).unwrap()
+            .with_pacer(pacer);
+        
+        let start = Instant::now();
+        for device_id in 1..=3 {
+            client.send_critical(&payload(device_id)).unwrap();
+        }
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(!client.pacer().unwrap().can_send(Instant::now()));
+    }
+    
+    #[test]
+    fn test_client_shutdown_persists_unacked_alerts() {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        gateway.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let mut client = SensorClient::connect("127.0.0.1:0", &gateway_addr).unwrap()
+            .with_retransmission(10, 1_000)
+            .with_store_and_forward(StoreAndForwardQueue::new(4));
+        let client_addr = client.local_address().unwrap().to_string();
+        
+        let now = payload(1).timestamp_ms_utc;
+        let stale = SensorPayload::new(3, now - 5_000, 1, 80, 1000, 0, [0.0; ANOMALY_VECTOR_SIZE]).unwrap();
+        for alert in [payload(1), payload(2), stale] {
+            client.send_critical(&alert).unwrap();
+        }
+        
+        let mut buffer = vec![0u8; MAX_PAYLOAD_SIZE];
+        for _ in 0..3 {
+            Receiver::receive(&gateway, &mut buffer).unwrap();
+        }
+        AckManager::send_ack(&gateway, 1, now, &client_addr).unwrap();
+        
+        let report = client.shutdown(Duration::from_millis(100)).unwrap();
+        assert!(matches!(report.events[..], [RetransmissionEvent::Acked { device_id: 1, .. }]));
+        assert_eq!((report.persisted, report.expired, report.dropped), (1, 1, 0));
+        assert!(report.is_clean());
+        assert_eq!((client.pending_count(), client.stored_count()), (0, 1));
+        
+        assert_eq!(client.send(&payload(4)), Err(CyDnAError::Cancelled));
+        assert_eq!(client.forward_stored(now), Err(CyDnAError::Cancelled));
+    }
+    
+    #[test]
+    fn test_client_negotiates_payload_version() {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        gateway.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        let mut buffer = vec![0u8; MAX_PAYLOAD_SIZE];
+        
+        let reading = SensorPayloadV2::from(&payload(6)).with_temperature_centi_celsius(2150);
+        
+        let mut legacy = SensorClient::connect("127.0.0.1:0", &gateway_addr).unwrap();
+        assert_eq!(legacy.protocol_version(), crate::CYNDA_VERSION);
+        legacy.send_v2(&reading).unwrap();
+        let (archived, _, _) = Receiver::receive(&gateway, &mut buffer).unwrap();
+        assert_eq!(archived.device_unique_id, 6);
+        
+        let short = SensorPayloadV2::new(6, 0, 1, 80, 1000, 0, vec![0.0; 4]).unwrap();
+        assert!(matches!(legacy.send_v2(&short), Err(CyDnAError::InvalidVectorLength(4))));
+        
+        let mut client = SensorClient::connect("127.0.0.1:0", &gateway_addr).unwrap()
+            .with_protocol_version(crate::CYNDA_VERSION_V2);
+        assert_eq!(client.send_v2(&reading).unwrap(), 0);
+        let (view, _, _) = Receiver::receive_versioned(
+            &gateway, &mut buffer, reading.timestamp_ms_utc,
+        ).unwrap();
+        assert_eq!(view.version(), crate::CYNDA_VERSION_V2);
+        assert_eq!(view.temperature_centi_celsius(), Some(2150));
+    }
+    
+    #[test]
+    fn test_client_serves_raw_data_pull() {
+        use crate::bulk::{parse_chunk, send_request, RawDataAssembler, RAW_CHUNK_SIZE};
+        
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        gateway.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let mut client = SensorClient::connect("127.0.0.1:0", &gateway_addr).unwrap()
+            .with_raw_data_store(RawDataStore::new(4, 2));
+        let raw: Vec<u8> = (0..RAW_CHUNK_SIZE * 5).map(|i| i as u8).collect();
+        let crc = client.store_raw_data(raw.clone()).unwrap();
+        
+        let mut assembler = RawDataAssembler::new(4, crc);
+        send_request(&gateway, &assembler.next_request().unwrap(), client.local_address().unwrap())
+            .unwrap();
+        
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while client.bulk_backlog_len() == 0 && Instant::now() < deadline {
+            client.poll().unwrap();
+        }
+        assert_eq!(client.bulk_backlog_len(), 5 - BULK_CHUNKS_PER_POLL);
+        client.poll().unwrap();
+        
+        let mut buffer = vec![0u8; MAX_PAYLOAD_SIZE];
+        let mut block = None;
+        while block.is_none() {
+            let (len, _) = gateway.recv_from(&mut buffer).unwrap();
+            block = assembler.accept(parse_chunk(&buffer[..len]).unwrap()).unwrap();
+        }
+        assert_eq!(block.unwrap(), raw);
+    }
+    
+    #[test]
+    fn test_bulk_backlog_is_capped() {
+        use crate::bulk::{send_request, RawDataAssembler, RAW_CHUNK_SIZE};
+        use crate::contracts::RawDataRequest;
+        
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let mut client = SensorClient::connect("127.0.0.1:0", &gateway_addr).unwrap()
+            .with_raw_data_store(RawDataStore::new(4, 2));
+        let crc = client.store_raw_data(vec![7u8; RAW_CHUNK_SIZE * (MAX_BULK_BACKLOG + 40)]).unwrap();
+        
+        // A partial request and then a whole-block one arrive before a single
+        // poll has sent anything.
+        let whole = RawDataAssembler::new(4, crc).next_request().unwrap();
+        let partial = RawDataRequest { chunk_count: 40, ..whole };
+        let client_addr = client.local_address().unwrap();
+        send_request(&gateway, &partial, client_addr).unwrap();
+        send_request(&gateway, &whole, client_addr).unwrap();
+        
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while client.raw_data_store().unwrap().metrics().requests_served < 2 && Instant::now() < deadline {
+            while client.receive_ack(None).unwrap() {}
+        }
+        assert_eq!(client.bulk_backlog_len(), MAX_BULK_BACKLOG);
+    }
+    
+    #[test]
+    fn test_client_acks_control_commands() {
+        use crate::contracts::ControlCommand;
+        use crate::control::{ControlChannel, ControlEvent};
+        
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        gateway.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let mut client = SensorClient::connect("127.0.0.1:0", &gateway_addr).unwrap()
+            .with_control(7);
+        let client_addr = client.local_address().unwrap();
+        
+        let mut channel = ControlChannel::new();
+        let command = ControlCommand::SetTimeToLive { time_to_live_ms: 2_000 };
+        let command_id = channel.send(&gateway, client_addr, 7, command).unwrap();
+        
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let mut received = None;
+        while received.is_none() && Instant::now() < deadline {
+            client.poll().unwrap();
+            received = client.next_control_command();
+        }
+        assert_eq!(received.map(|message| (message.command_id, message.command)), Some((command_id, command)));
+        
+        let mut buffer = vec![0u8; MAX_PAYLOAD_SIZE];
+        let len = gateway.recv(&mut buffer).unwrap();
+        assert!(channel.handle_datagram(&buffer[..len]));
+        assert_eq!(channel.poll_event(), Some(ControlEvent::Delivered { device_id: 7, command_id, attempts: 1 }));
+    }
+    
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_client_installs_rotated_key() {
+        use crate::control::ControlChannel;
+        use crate::encryption::{PayloadCipher, KEY_SIZE};
+        use crate::key_rotation::{DeviceKeyring, KeyRotation, RotationEvent};
+        
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        gateway.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let mut client = SensorClient::connect("127.0.0.1:0", &gateway_addr).unwrap()
+            .with_control(7)
+            .with_keyring(DeviceKeyring::new(7, 1, [1u8; KEY_SIZE]));
+        
+        let mut cipher = PayloadCipher::new();
+        cipher.add_device_key(7, [1u8; KEY_SIZE]);
+        let mut rotation = KeyRotation::new();
+        rotation.provision(7, 1, [1u8; KEY_SIZE]);
+        let mut channel = ControlChannel::new();
+        rotation.begin(&mut channel, &gateway, client.local_address().unwrap(), 7, [2u8; KEY_SIZE], &mut cipher).unwrap();
+        
+        let mut buffer = vec![0u8; MAX_PAYLOAD_SIZE];
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while channel.pending_count() > 0 && Instant::now() < deadline {
+            client.poll().unwrap();
+            if let Ok(len) = gateway.recv(&mut buffer) {
+                channel.handle_datagram(&buffer[..len]);
+            }
+        }
+        
+        let event = channel.poll_event().unwrap();
+        rotation.handle_event(&event, 0, &mut cipher).unwrap();
+        assert_eq!(rotation.poll_event(), Some(RotationEvent::Completed { device_id: 7, generation: 2 }));
+        assert_eq!(client.key_generation(), Some(2));
+        assert_eq!(client.keyring().unwrap().key(), &[2u8; KEY_SIZE]);
+    }
+    
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_client_reaches_encrypted_gateway_across_rotation() {
+        use crate::control::ControlChannel;
+        use crate::encryption::{PayloadCipher, KEY_SIZE};
+        use crate::key_rotation::{DeviceKeyring, KeyRotation, RotationEvent};
+        use crate::server::GatewayServer;
+        use std::sync::Mutex;
+        
+        let mut cipher = PayloadCipher::new();
+        cipher.add_device_key(7, [1u8; KEY_SIZE]);
+        let mut keys = Arc::new(Mutex::new(cipher));
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_shared_cipher(Arc::clone(&keys))
+            .with_poll_interval_ms(20)
+            .spawn()
+            .unwrap();
+        let gateway_addr = gateway.local_address();
+        
+        // The client only hears its gateway's address, so the control
+        // commands go out from a relay that forwards everything else.
+        let relay = UdpSocket::bind("127.0.0.1:0").unwrap();
+        relay.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+        let mut client = SensorClient::connect("127.0.0.1:0", relay.local_addr().unwrap()).unwrap()
+            .with_control(7)
+            .with_keyring(DeviceKeyring::new(7, 1, [1u8; KEY_SIZE]));
+        let client_addr = client.local_address().unwrap();
+        
+        let mut channel = ControlChannel::new();
+        let mut buffer = vec![0u8; MAX_PAYLOAD_SIZE];
+        let mut relay_until = |client: &mut SensorClient, channel: &mut ControlChannel, done: &dyn Fn(&ControlChannel) -> bool| {
+            let deadline = Instant::now() + Duration::from_secs(2);
+            while !done(channel) && Instant::now() < deadline {
+                client.poll().unwrap();
+                let Ok((len, from)) = relay.recv_from(&mut buffer) else {
+                    continue;
+                };
+                if from != client_addr {
+                    relay.send_to(&buffer[..len], client_addr).unwrap();
+                } else if !channel.handle_datagram(&buffer[..len]) {
+                    relay.send_to(&buffer[..len], gateway_addr).unwrap();
+                }
+            }
+        };
+        
+        client.send(&payload(7)).unwrap();
+        relay_until(&mut client, &mut channel, &|_| gateway.metrics().accepted == 1);
+        assert_eq!(gateway.metrics().accepted, 1);
+        
+        let mut rotation = KeyRotation::new().with_overlap_ms(0);
+        rotation.provision(7, 1, [1u8; KEY_SIZE]);
+        rotation.begin(&mut channel, &relay, client_addr, 7, [2u8; KEY_SIZE], &mut keys).unwrap();
+        relay_until(&mut client, &mut channel, &|channel| channel.pending_count() == 0);
+        
+        let event = channel.poll_event().unwrap();
+        rotation.handle_event(&event, 0, &mut keys).unwrap();
+        assert_eq!(rotation.poll_event(), Some(RotationEvent::Completed { device_id: 7, generation: 2 }));
+        assert_eq!(rotation.service(0, &mut keys).unwrap(), 1);
+        assert_eq!(client.key_generation(), Some(2));
+        
+        // Only the new key is accepted now.
+        client.send(&payload(7)).unwrap();
+        relay_until(&mut client, &mut channel, &|_| gateway.metrics().accepted == 2);
+        assert_eq!(gateway.metrics().accepted, 2);
+        
+        let mut retired = PayloadCipher::new();
+        retired.add_device_key(7, [1u8; KEY_SIZE]);
+        Transmitter::send_encrypted(&relay, &payload(7), &mut retired, gateway_addr).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while gateway.metrics().unauthenticated == 0 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(gateway.metrics().unauthenticated, 1);
+        assert_eq!(gateway.metrics().accepted, 2);
+        gateway.shutdown();
+    }
+    
+    #[cfg(all(feature = "encryption", feature = "authentication"))]
+    #[test]
+    fn test_client_authenticates_with_keyring() {
+        use crate::authentication::DatagramAuthenticator;
+        use crate::key_rotation::DeviceKeyring;
+        use crate::server::GatewayServer;
+        
+        let mut authenticator = DatagramAuthenticator::new();
+        authenticator.provision_key(7, &[3u8; 32]).unwrap();
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_authenticator(authenticator)
+            .with_poll_interval_ms(20)
+            .spawn()
+            .unwrap();
+        
+        let mut client = SensorClient::connect("127.0.0.1:0", gateway.local_address()).unwrap()
+            .with_keyring(DeviceKeyring::new(7, 1, [3u8; 32]))
+            .with_envelope(Envelope::Authenticated);
+        client.send_critical(&payload(7)).unwrap();
+        assert!(matches!(
+            client.wait_for_event(Duration::from_secs(2)).unwrap(),
+            Some(RetransmissionEvent::Acked { device_id: 7, .. })
+        ));
+        
+        // The keyring only covers its own device.
+        assert_eq!(client.send(&payload(8)), Err(CyDnAError::UnknownDeviceKey(8)));
+        gateway.shutdown();
+    }
+    
+    #[test]
+    fn test_client_answers_time_sync() {
+        use crate::time_sync::ClockSkewTracker;
+        
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        gateway.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        let mut client = SensorClient::connect("127.0.0.1:0", &gateway_addr).unwrap();
+        
+        let tracker = ClockSkewTracker::new();
+        let now = payload(1).timestamp_ms_utc;
+        tracker.send_request(&gateway, client.local_address().unwrap(), 7, now).unwrap();
+        
+        let mut buffer = vec![0u8; MAX_PAYLOAD_SIZE];
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let mut sample = None;
+        while sample.is_none() && Instant::now() < deadline {
+            client.poll().unwrap();
+            if let Ok(len) = gateway.recv(&mut buffer) {
+                let arrived = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+                sample = tracker.handle_datagram(&buffer[..len], arrived);
+            }
+        }
+        
+        // Same host, same clock.
+        assert!(sample.unwrap().offset_ms.abs() < 1_000);
+        assert!(tracker.offset_ms(7).is_some());
+    }
+    
+    #[test]
+    fn test_client_exhausts_and_rejects() {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let mut client = SensorClient::connect("127.0.0.1:0", &gateway_addr).unwrap()
+            .with_retransmission(2, 5);
+        let client_addr = client.local_address().unwrap().to_string();
+        
+        let rejected = payload(3);
+        client.send_critical(&payload(4)).unwrap();
+        client.send_critical(&rejected).unwrap();
+        AckManager::send_nack_with_reason(
+            &gateway, 3, rejected.timestamp_ms_utc, NackReason::Unauthorized, &client_addr,
+        ).unwrap();
+        
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let mut events = Vec::new();
+        while events.len() < 2 && Instant::now() < deadline {
+            events.extend(client.wait_for_event(Duration::from_millis(50)).unwrap());
+            events.extend(client.events());
+        }
+        
+        assert!(events.iter().any(|event| matches!(
+            event,
+            RetransmissionEvent::Rejected { reason: NackReason::Unauthorized, .. }
+        )));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            RetransmissionEvent::Exhausted { attempts: 2, .. }
+        )));
+    }
+    
+    #[test]
+    fn test_client_falls_back_to_tcp() {
+        use crate::transport::{FrameTransport, TcpTransport};
+        
+        // Nothing answers UDP on this port, only TCP.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = listener.local_addr().unwrap();
+        
+        let mut client = SensorClient::connect("127.0.0.1:0", gateway_addr).unwrap()
+            .with_retransmission(5, 20)
+            .with_tcp_fallback(1);
+        client.send_critical(&payload(7)).unwrap();
+        
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while client.transport_kind() == TransportKind::Udp && Instant::now() < deadline {
+            client.wait_for_event(Duration::from_millis(20)).unwrap();
+        }
+        assert_eq!(client.transport_kind(), TransportKind::Tcp);
+        
+        let (mut gateway, _) = TcpTransport::accept(&listener).unwrap();
+        let mut buffer = vec![0u8; MAX_PAYLOAD_SIZE];
+        let len = gateway.recv_frame(&mut buffer, Some(Duration::from_secs(2))).unwrap().unwrap();
+        let archived = Receiver::archive_frame(&buffer[..len]).unwrap();
+        assert_eq!(archived.device_unique_id, 7);
+        
+        let ack = crate::contracts::AckPacket::ack(7, archived.timestamp_ms_utc);
+        gateway.send_frame(&AckManager::encode_ack(&ack).unwrap()).unwrap();
+        
+        match client.wait_for_event(Duration::from_secs(2)).unwrap() {
+            Some(RetransmissionEvent::Acked { device_id: 7, .. }) => {}
+            other => panic!("expected Acked, got {:?}", other),
+        }
+        assert_eq!(client.transport_metrics().fallbacks, 1);
+    }
+}
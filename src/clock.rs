@@ -0,0 +1,138 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Where the protocol reads the time. `now_ms` is Unix epoch milliseconds, the
+// scale payload timestamps and TTLs are on; `now` is for timers and backoff
+// and must never go backwards.
+pub trait Clock: Send + Sync {
+    fn now_ms(&self) -> u64;
+    
+    fn now(&self) -> Instant;
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+// The host clocks as they are. Epoch time follows every wall-clock step, so a
+// jump moves TTL verdicts with it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(0)
+    }
+    
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// Reads the wall clock once and advances with the monotonic clock from there,
+// so stepping the system time (NTP corrections, an operator fixing the date)
+// cannot make fresh payloads look expired or old ones look fresh. Drifts from
+// the wall clock over long uptimes; re-anchor with a new instance if that
+// matters.
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    anchor: Instant,
+    anchor_ms: u64,
+}
+
+impl MonotonicClock {
+    pub fn new() -> Self {
+        Self::anchored_at(SystemClock.now_ms())
+    }
+    
+    pub fn anchored_at(epoch_ms: u64) -> Self {
+        Self { anchor: Instant::now(), anchor_ms: epoch_ms }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now_ms(&self) -> u64 {
+        self.anchor_ms + self.anchor.elapsed().as_millis() as u64
+    }
+    
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+#[derive(Debug)]
+struct MockState {
+    epoch_ms: u64,
+    elapsed: Duration,
+}
+
+// A clock that only moves when told to, for deterministic tests of TTL and
+// retry timing. Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    base: Instant,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockClock {
+    pub fn new(epoch_ms: u64) -> Self {
+        Self {
+            base: Instant::now(),
+            state: Arc::new(Mutex::new(MockState { epoch_ms, elapsed: Duration::ZERO })),
+        }
+    }
+    
+    // Moves both clocks forward together, like real time passing.
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.epoch_ms += duration.as_millis() as u64;
+        state.elapsed += duration;
+    }
+    
+    pub fn advance_ms(&self, ms: u64) {
+        self.advance(Duration::from_millis(ms));
+    }
+    
+    // Steps only the epoch clock, like an operator or NTP resetting the date;
+    // the monotonic side does not move.
+    pub fn set_epoch_ms(&self, epoch_ms: u64) {
+        self.state.lock().unwrap().epoch_ms = epoch_ms;
+    }
+}
+
+impl Clock for MockClock {
+    fn now_ms(&self) -> u64 {
+        self.state.lock().unwrap().epoch_ms
+    }
+    
+    fn now(&self) -> Instant {
+        self.base + self.state.lock().unwrap().elapsed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_monotonic_clock_ignores_wall_steps() {
+        let clock = MonotonicClock::anchored_at(1_000);
+        let first = clock.now_ms();
+        assert!(first >= 1_000);
+        assert!(clock.now_ms() >= first);
+        
+        let mock = MockClock::new(5_000);
+        let started = mock.now();
+        mock.advance_ms(250);
+        mock.set_epoch_ms(0);
+        assert_eq!(mock.now_ms(), 0);
+        assert_eq!(mock.now() - started, Duration::from_millis(250));
+    }
+}
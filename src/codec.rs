@@ -0,0 +1,165 @@
+use rkyv::{to_bytes, Deserialize};
+
+use crate::contracts::SensorPayload;
+use crate::errors::{CyDnAError, Result};
+use crate::framing::{encode_frame_with_header, FrameHeader, MessageType, WireFormat};
+
+// Pluggable body encoding for sensor payload frames. The rkyv codec stays the
+// default and is the only one `Receiver::receive` reads in place; the serde
+// based codecs exist so gateway components outside Rust can produce and
+// consume the same frames.
+pub trait WireCodec {
+    const FORMAT: WireFormat;
+    
+    fn encode_payload(payload: &SensorPayload) -> Result<Vec<u8>>;
+    
+    fn decode_payload(body: &[u8]) -> Result<SensorPayload>;
+}
+
+pub struct RkyvCodec;
+
+impl WireCodec for RkyvCodec {
+    const FORMAT: WireFormat = WireFormat::Rkyv;
+    
+    fn encode_payload(payload: &SensorPayload) -> Result<Vec<u8>> {
+        to_bytes::<_, 256>(payload)
+            .map(|bytes| bytes.to_vec())
+            .map_err(|_| CyDnAError::SerializationError(
+                "Failed to serialize SensorPayload"
+            ))
+    }
+    
+    fn decode_payload(body: &[u8]) -> Result<SensorPayload> {
+        let mut aligned = rkyv::AlignedVec::with_capacity(body.len());
+        aligned.extend_from_slice(body);
+        
+        crate::receiver::Receiver::archive(&aligned)?
+            .deserialize(&mut rkyv::Infallible)
+            .map_err(|_| CyDnAError::DeserializationError(
+                "Failed to deserialize SensorPayload"
+            ))
+    }
+}
+
+#[cfg(feature = "cbor")]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl WireCodec for CborCodec {
+    const FORMAT: WireFormat = WireFormat::Cbor;
+    
+    fn encode_payload(payload: &SensorPayload) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(payload, &mut bytes)
+            .map_err(|_| CyDnAError::SerializationError("Failed to encode SensorPayload as CBOR"))?;
+        Ok(bytes)
+    }
+    
+    fn decode_payload(body: &[u8]) -> Result<SensorPayload> {
+        ciborium::from_reader(body)
+            .map_err(|_| CyDnAError::DeserializationError("Failed to decode CBOR SensorPayload"))
+    }
+}
+
+#[cfg(feature = "postcard")]
+pub struct PostcardCodec;
+
+#[cfg(feature = "postcard")]
+impl WireCodec for PostcardCodec {
+    const FORMAT: WireFormat = WireFormat::Postcard;
+    
+    fn encode_payload(payload: &SensorPayload) -> Result<Vec<u8>> {
+        postcard::to_allocvec(payload)
+            .map_err(|_| CyDnAError::SerializationError("Failed to encode SensorPayload as postcard"))
+    }
+    
+    fn decode_payload(body: &[u8]) -> Result<SensorPayload> {
+        postcard::from_bytes(body)
+            .map_err(|_| CyDnAError::DeserializationError("Failed to decode postcard SensorPayload"))
+    }
+}
+
+pub fn frame_payload<C: WireCodec>(payload: &SensorPayload) -> Result<Vec<u8>> {
+    let header = FrameHeader::new(MessageType::SensorPayload, 0).with_format(C::FORMAT);
+    
+    encode_frame_with_header(header, &C::encode_payload(payload)?)
+}
+
+// Decodes a sensor payload frame in whichever format its header names;
+// formats whose feature is not compiled in are reported as unsupported.
+pub fn decode_payload_frame(datagram: &[u8]) -> Result<SensorPayload> {
+    let header = FrameHeader::decode(datagram)?;
+    header.expect_type(MessageType::SensorPayload)?;
+    header.expect_version(crate::CYNDA_VERSION)?;
+    
+    let body = &datagram[header.body_range()];
+    
+    match header.format()? {
+        WireFormat::Rkyv => RkyvCodec::decode_payload(body),
+        #[cfg(feature = "cbor")]
+        WireFormat::Cbor => CborCodec::decode_payload(body),
+        #[cfg(feature = "postcard")]
+        WireFormat::Postcard => PostcardCodec::decode_payload(body),
+        #[allow(unreachable_patterns)]
+        other => Err(CyDnAError::UnsupportedWireFormat(other as u8)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::ANOMALY_VECTOR_SIZE;
+    
+    fn payload() -> SensorPayload {
+        SensorPayload::new(12, 1_700_000_000_000, 2, 64, 3000, 0xfeed, [0.125; ANOMALY_VECTOR_SIZE])
+            .unwrap()
+            .with_sequence_number(99)
+    }
+    
+    fn assert_roundtrip<C: WireCodec>() {
+        let frame = frame_payload::<C>(&payload()).unwrap();
+        assert_eq!(FrameHeader::decode(&frame).unwrap().format().unwrap(), C::FORMAT);
+        
+        let decoded = decode_payload_frame(&frame).unwrap();
+        assert_eq!(decoded.device_unique_id, 12);
+        assert_eq!(decoded.sequence_number, 99);
+        assert_eq!(decoded.anomaly_ai_vector, payload().anomaly_ai_vector);
+    }
+    
+    #[test]
+    fn test_rkyv_codec_matches_zero_copy_path() {
+        assert_roundtrip::<RkyvCodec>();
+        
+        let frame = frame_payload::<RkyvCodec>(&payload()).unwrap();
+        assert_eq!(frame, crate::transmitter::Transmitter::frame_payload(&payload()).unwrap());
+    }
+    
+    #[test]
+    fn test_unavailable_format_is_rejected() {
+        let header = FrameHeader::new(MessageType::SensorPayload, 0).with_format(WireFormat::Cbor);
+        let frame = encode_frame_with_header(header, &[0xa0]).unwrap();
+        
+        assert!(matches!(
+            crate::receiver::Receiver::archive_frame(&frame),
+            Err(CyDnAError::UnsupportedWireFormat(1))
+        ));
+        if !WireFormat::Cbor.is_available() {
+            assert!(matches!(
+                decode_payload_frame(&frame),
+                Err(CyDnAError::UnsupportedWireFormat(1))
+            ));
+        }
+    }
+    
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor_codec_roundtrip() {
+        assert_roundtrip::<CborCodec>();
+    }
+    
+    #[cfg(feature = "postcard")]
+    #[test]
+    fn test_postcard_codec_roundtrip() {
+        assert_roundtrip::<PostcardCodec>();
+    }
+}
@@ -0,0 +1,165 @@
+use rkyv::AlignedVec;
+
+use crate::errors::{CyDnAError, Result};
+use crate::framing::{Compression, FrameHeader, FRAME_HEADER_SIZE};
+use crate::MAX_PAYLOAD_SIZE;
+
+pub const ZSTD_LEVEL: i32 = 3;
+
+pub const MAX_DECOMPRESSED_BODY: usize = MAX_PAYLOAD_SIZE - FRAME_HEADER_SIZE;
+
+fn compress_body(body: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    match compression {
+        Compression::None => Ok(body.to_vec()),
+        #[cfg(feature = "compression-lz4")]
+        Compression::Lz4 => Ok(lz4_flex::block::compress_prepend_size(body)),
+        #[cfg(feature = "compression-zstd")]
+        Compression::Zstd => zstd::bulk::compress(body, ZSTD_LEVEL)
+            .map_err(|_| CyDnAError::CompressionError("Zstd compression failed")),
+        #[allow(unreachable_patterns)]
+        other => Err(CyDnAError::UnsupportedCompression(other as u8)),
+    }
+}
+
+// Output is capped so a small datagram can never inflate past one frame.
+fn decompress_body(body: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    match compression {
+        Compression::None => Ok(body.to_vec()),
+        #[cfg(feature = "compression-lz4")]
+        Compression::Lz4 => {
+            if body.len() < 4 {
+                return Err(CyDnAError::CompressionError("Truncated LZ4 block"));
+            }
+            
+            let declared = u32::from_le_bytes([body[0], body[1], body[2], body[3]]) as usize;
+            if declared > MAX_DECOMPRESSED_BODY {
+                return Err(CyDnAError::BufferTooSmall {
+                    required: declared,
+                    available: MAX_DECOMPRESSED_BODY,
+                });
+            }
+            
+            lz4_flex::block::decompress_size_prepended(body)
+                .map_err(|_| CyDnAError::CompressionError("Corrupt LZ4 block"))
+        }
+        #[cfg(feature = "compression-zstd")]
+        Compression::Zstd => zstd::bulk::decompress(body, MAX_DECOMPRESSED_BODY)
+            .map_err(|_| CyDnAError::CompressionError("Corrupt or oversized Zstd frame")),
+        #[allow(unreachable_patterns)]
+        other => Err(CyDnAError::UnsupportedCompression(other as u8)),
+    }
+}
+
+// Compresses the body of an encoded frame and marks the codec in the header
+// flags. If the codec does not make the frame smaller the original frame is
+// returned untouched, so receivers never pay for a useless decompression.
+pub fn compress_frame(frame: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    let header = FrameHeader::decode(frame)?;
+    
+    if compression == Compression::None || header.compression()? != Compression::None {
+        return Ok(frame.to_vec());
+    }
+    
+    let compressed = compress_body(&frame[header.body_range()], compression)?;
+    if compressed.len() >= header.payload_len as usize {
+        return Ok(frame.to_vec());
+    }
+    
+    let compressed_header = FrameHeader {
+        payload_len: compressed.len() as u16,
+        ..header
+    }
+    .with_compression(compression);
+    
+    let mut output = Vec::with_capacity(FRAME_HEADER_SIZE + compressed.len());
+    output.extend_from_slice(&compressed_header.encode());
+    output.extend_from_slice(&compressed);
+    Ok(output)
+}
+
+pub fn decompress_frame(datagram: &[u8]) -> Result<AlignedVec> {
+    let header = FrameHeader::decode(datagram)?;
+    let compression = header.compression()?;
+    
+    let body = decompress_body(&datagram[header.body_range()], compression)?;
+    let plain_header = FrameHeader {
+        payload_len: body.len() as u16,
+        ..header
+    }
+    .with_compression(Compression::None);
+    
+    let mut output = AlignedVec::with_capacity(FRAME_HEADER_SIZE + body.len());
+    output.extend_from_slice(&plain_header.encode());
+    output.extend_from_slice(&body);
+    Ok(output)
+}
+
+// Rewrites a compressed datagram in `buffer` as its plain frame so the rest of
+// the receive path can validate it in place. Returns the new length.
+pub(crate) fn inflate_in_place(buffer: &mut [u8], len: usize) -> Result<usize> {
+    let header = FrameHeader::decode(&buffer[..len])?;
+    if header.compression()? == Compression::None {
+        return Ok(len);
+    }
+    
+    let plain = decompress_frame(&buffer[..len])?;
+    if plain.len() > buffer.len() {
+        return Err(CyDnAError::BufferTooSmall {
+            required: plain.len(),
+            available: buffer.len(),
+        });
+    }
+    
+    buffer[..plain.len()].copy_from_slice(&plain);
+    Ok(plain.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing::{encode_frame, MessageType};
+    
+    #[test]
+    fn test_passthrough_when_not_smaller() {
+        let frame = encode_frame(MessageType::SensorPayload, &[7u8; 16]).unwrap();
+        
+        assert_eq!(compress_frame(&frame, Compression::None).unwrap(), frame);
+        assert_eq!(decompress_frame(&frame).unwrap().as_slice(), frame.as_slice());
+        
+        let mut buffer = frame.clone();
+        assert_eq!(inflate_in_place(&mut buffer, frame.len()).unwrap(), frame.len());
+    }
+    
+    #[test]
+    fn test_unknown_codec_rejected() {
+        let mut frame = encode_frame(MessageType::SensorPayload, &[0u8; 8]).unwrap();
+        frame[5] = 0b0000_1100;
+        
+        assert!(matches!(
+            decompress_frame(&frame),
+            Err(CyDnAError::UnsupportedCompression(3))
+        ));
+    }
+    
+    #[cfg(any(feature = "compression-lz4", feature = "compression-zstd"))]
+    #[test]
+    fn test_compressed_roundtrip() {
+        let body: Vec<u8> = (0..512u32).map(|i| (i % 8) as u8).collect();
+        let frame = encode_frame(MessageType::PackedPayloads, &body).unwrap();
+        
+        for codec in [Compression::Lz4, Compression::Zstd] {
+            if !codec.is_available() {
+                continue;
+            }
+            
+            let compressed = compress_frame(&frame, codec).unwrap();
+            assert!(compressed.len() < frame.len());
+            assert_eq!(FrameHeader::decode(&compressed).unwrap().compression().unwrap(), codec);
+            
+            let mut buffer = vec![0u8; MAX_PAYLOAD_SIZE];
+            buffer[..compressed.len()].copy_from_slice(&compressed);
+            let len = inflate_in_place(&mut buffer, compressed.len()).unwrap();
+            assert_eq!(&buffer[..len], frame.as_slice());
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use std::time::Duration;
+
+use crate::access::DeviceAccessList;
+use crate::errors::{CyDnAError, Result};
+use crate::journal::{DedupJournal, DEFAULT_JOURNAL_RETENTION_MS};
+use crate::overload::{OverloadDetector, DEFAULT_OVERLOAD_LATENCY};
+use crate::replay::ReplayGuard;
+use crate::server::{
+    GatewayServer, DEFAULT_REPLAY_MAX_AGE_MS, DEFAULT_REPLAY_MAX_DEVICES, DEFAULT_SHUTDOWN_POLL_MS,
+};
+use crate::socket::SocketBuilder;
+use crate::validation::AccessValidator;
+
+pub const DEFAULT_BIND_ADDRESS: &str = "0.0.0.0:8080";
+
+pub const DEFAULT_CRITICAL_SCORE_THRESHOLD: f32 = 0.8;
+
+// One entry of `device_keys_path`: device id (u32 LE) then its 32-byte key.
+pub const DEVICE_KEY_RECORD_SIZE: usize = 4 + 32;
+
+// Deployment settings for a gateway process. Every field has a default, so
+// a config file only needs to name what differs (`serde` feature).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct CyDnAConfig {
+    pub gateway_id: u32,
+    
+    pub bind_address: String,
+    
+    pub shards: usize,
+    
+    pub pool_capacity: usize,
+    
+    pub poll_interval_ms: u64,
+    
+    pub recv_buffer_size: Option<usize>,
+    
+    pub tcp_fallback: bool,
+    
+    pub replay_max_devices: usize,
+    
+    pub replay_max_age_ms: u64,
+    
+    // Processed-payload journal that survives restarts; disabled when unset.
+    pub dedup_journal_path: Option<String>,
+    
+    pub dedup_retention_ms: u64,
+    
+    pub consensus_mode: u8,
+    
+    pub critical_score_threshold: f32,
+    
+    // 32-byte raw Ed25519 secret key used to sign DLT records.
+    pub signing_key_path: Option<String>,
+    
+    // JSON lines of signed DLT records; stdout when unset.
+    pub dlt_output_path: Option<String>,
+    
+    // HTTP `/health` and `/metrics`; disabled when unset.
+    pub admin_address: Option<String>,
+    
+    // gRPC ingestion endpoint (`grpc` feature); disabled when unset.
+    pub grpc_address: Option<String>,
+    
+    // Device keys for the two settings below, as DEVICE_KEY_RECORD_SIZE
+    // records.
+    pub device_keys_path: Option<String>,
+    
+    // Only accept HMAC-authenticated frames (`authentication` feature).
+    pub require_authentication: bool,
+    
+    // Only accept AES-GCM encrypted payloads (`encryption` feature). With
+    // both set, either envelope is accepted.
+    pub require_encryption: bool,
+    
+    // Inclusive device id ranges allowed to report; every device when unset.
+    pub allowed_devices: Option<Vec<[u32; 2]>>,
+    
+    // Datagrams read off the sockets but not yet answered, summed over the
+    // shards, that switch on load shedding; disabled when unset. No stream is
+    // attached by the daemon, so this backlog is the whole queue depth.
+    pub overload_queue_depth: Option<usize>,
+    
+    pub overload_latency_ms: u64,
+}
+
+impl Default for CyDnAConfig {
+    fn default() -> Self {
+        Self {
+            gateway_id: 1,
+            bind_address: DEFAULT_BIND_ADDRESS.to_string(),
+            shards: 1,
+            pool_capacity: crate::pool::DEFAULT_POOL_CAPACITY,
+            poll_interval_ms: DEFAULT_SHUTDOWN_POLL_MS,
+            recv_buffer_size: None,
+            tcp_fallback: false,
+            replay_max_devices: DEFAULT_REPLAY_MAX_DEVICES,
+            replay_max_age_ms: DEFAULT_REPLAY_MAX_AGE_MS,
+            dedup_journal_path: None,
+            dedup_retention_ms: DEFAULT_JOURNAL_RETENTION_MS,
+            consensus_mode: 0,
+            critical_score_threshold: DEFAULT_CRITICAL_SCORE_THRESHOLD,
+            signing_key_path: None,
+            dlt_output_path: None,
+            admin_address: None,
+            grpc_address: None,
+            device_keys_path: None,
+            require_authentication: false,
+            require_encryption: false,
+            allowed_devices: None,
+            overload_queue_depth: None,
+            overload_latency_ms: DEFAULT_OVERLOAD_LATENCY.as_millis() as u64,
+        }
+    }
+}
+
+impl CyDnAConfig {
+    // Catches the mistakes that would otherwise only surface on the first
+    // signed record or bind attempt.
+    pub fn validate(&self) -> Result<()> {
+        if self.gateway_id == 0 {
+            return Err(CyDnAError::InvalidGatewayId(self.gateway_id));
+        }
+        
+        if self.consensus_mode > 1 {
+            return Err(CyDnAError::InvalidConsensusMode(self.consensus_mode));
+        }
+        
+        if self.shards == 0 {
+            return Err(CyDnAError::InvalidConfig("shards must be at least 1"));
+        }
+        if self.pool_capacity == 0 {
+            return Err(CyDnAError::InvalidConfig("pool_capacity must be at least 1"));
+        }
+        if self.replay_max_devices == 0 {
+            return Err(CyDnAError::InvalidConfig("replay_max_devices must be at least 1"));
+        }
+        
+        crate::socket::resolve(self.bind_address.as_str())?;
+        if let Some(admin_address) = &self.admin_address {
+            crate::socket::resolve(admin_address.as_str())?;
+        }
+        
+        if let Some(grpc_address) = &self.grpc_address {
+            if !cfg!(feature = "grpc") {
+                return Err(CyDnAError::InvalidConfig("grpc_address needs the grpc feature"));
+            }
+            crate::socket::resolve(grpc_address.as_str())?;
+        }
+        
+        if self.require_authentication && !cfg!(feature = "authentication") {
+            return Err(CyDnAError::InvalidConfig("require_authentication needs the authentication feature"));
+        }
+        if self.require_encryption && !cfg!(feature = "encryption") {
+            return Err(CyDnAError::InvalidConfig("require_encryption needs the encryption feature"));
+        }
+        if (self.require_authentication || self.require_encryption) && self.device_keys_path.is_none() {
+            return Err(CyDnAError::InvalidConfig("an envelope is required but device_keys_path is unset"));
+        }
+        
+        if let Some(ranges) = &self.allowed_devices {
+            if ranges.iter().any(|[first, last]| first > last) {
+                return Err(CyDnAError::InvalidConfig("allowed_devices range ends before it starts"));
+            }
+        }
+        
+        if self.overload_queue_depth == Some(0) {
+            return Err(CyDnAError::InvalidConfig("overload_queue_depth must be positive"));
+        }
+        
+        Ok(())
+    }
+    
+    pub fn device_keys(&self) -> Result<Vec<(u32, [u8; 32])>> {
+        let Some(path) = &self.device_keys_path else {
+            return Ok(Vec::new());
+        };
+        
+        let bytes = std::fs::read(path).map_err(|e| CyDnAError::IoError(e.kind()))?;
+        if bytes.len() % DEVICE_KEY_RECORD_SIZE != 0 {
+            return Err(CyDnAError::InvalidConfig("device_keys_path is not a whole number of records"));
+        }
+        
+        Ok(bytes.chunks_exact(DEVICE_KEY_RECORD_SIZE)
+            .map(|record| {
+                let mut key = [0u8; 32];
+                key.copy_from_slice(&record[4..]);
+                (u32::from_le_bytes([record[0], record[1], record[2], record[3]]), key)
+            })
+            .collect())
+    }
+    
+    // The configured server without a handler; the caller adds one before
+    // `spawn_sharded(self.shards)`.
+    pub fn gateway_server(&self) -> Result<GatewayServer> {
+        self.validate()?;
+        
+        let mut socket = SocketBuilder::new(self.bind_address.as_str())?;
+        if let Some(bytes) = self.recv_buffer_size {
+            socket = socket.with_recv_buffer_size(bytes);
+        }
+        
+        let mut server = GatewayServer::new(socket.bind_address())?
+            .with_socket_builder(socket)
+            .with_replay_guard(ReplayGuard::new(self.replay_max_devices, self.replay_max_age_ms))
+            .with_pool_capacity(self.pool_capacity)
+            .with_poll_interval_ms(self.poll_interval_ms)
+            .with_tcp_fallback(self.tcp_fallback);
+        if let Some(path) = &self.dedup_journal_path {
+            server = server.with_dedup_journal(DedupJournal::open(path, self.dedup_retention_ms)?);
+        }
+        
+        if let Some(ranges) = &self.allowed_devices {
+            let access = ranges.iter()
+                .fold(DeviceAccessList::new(), |access, &[first, last]| access.with_range(first..=last));
+            server = server.with_validator(AccessValidator::new(access));
+        }
+        
+        if let Some(queue_depth) = self.overload_queue_depth {
+            let latency = Duration::from_millis(self.overload_latency_ms);
+            server = server.with_overload_detector(OverloadDetector::new(queue_depth, latency));
+        }
+        
+        #[cfg(feature = "authentication")]
+        if self.require_authentication {
+            let mut authenticator = crate::authentication::DatagramAuthenticator::new();
+            for (device_id, key) in self.device_keys()? {
+                authenticator.provision_key(device_id, &key)?;
+            }
+            server = server.with_authenticator(authenticator);
+        }
+        
+        #[cfg(feature = "encryption")]
+        if self.require_encryption {
+            let mut cipher = crate::encryption::PayloadCipher::new();
+            for (device_id, key) in self.device_keys()? {
+                cipher.add_device_key(device_id, key);
+            }
+            server = server.with_cipher(cipher);
+        }
+        
+        #[cfg(feature = "grpc")]
+        let server = match &self.grpc_address {
+            Some(grpc_address) => server.with_grpc(crate::socket::resolve(grpc_address.as_str())?),
+            None => server,
+        };
+        
+        Ok(server)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_config_validation() {
+        assert!(CyDnAConfig::default().validate().is_ok());
+        
+        let config = CyDnAConfig { gateway_id: 0, ..Default::default() };
+        assert!(matches!(config.validate(), Err(CyDnAError::InvalidGatewayId(0))));
+        
+        let config = CyDnAConfig { consensus_mode: 2, ..Default::default() };
+        assert!(matches!(config.validate(), Err(CyDnAError::InvalidConsensusMode(2))));
+        
+        let config = CyDnAConfig { bind_address: "not an address".to_string(), ..Default::default() };
+        assert!(config.gateway_server().is_err());
+        
+        let config = CyDnAConfig { grpc_address: Some("127.0.0.1:0".to_string()), ..Default::default() };
+        assert_eq!(config.validate().is_ok(), cfg!(feature = "grpc"));
+        
+        let config = CyDnAConfig { require_authentication: true, ..Default::default() };
+        assert!(matches!(config.validate(), Err(CyDnAError::InvalidConfig(_))));
+        
+        let config = CyDnAConfig { shards: 0, ..Default::default() };
+        assert_eq!(config.validate(), Err(CyDnAError::InvalidConfig("shards must be at least 1")));
+        
+        let config = CyDnAConfig { allowed_devices: Some(vec![[1, 10], [20, 5]]), ..Default::default() };
+        assert!(matches!(config.validate(), Err(CyDnAError::InvalidConfig(_))));
+    }
+    
+    #[test]
+    fn test_config_builds_gateway() {
+        let config = CyDnAConfig {
+            bind_address: "127.0.0.1:0".to_string(),
+            shards: 2,
+            poll_interval_ms: 20,
+            ..Default::default()
+        };
+        
+        let gateway = config.gateway_server().unwrap().spawn_sharded(config.shards).unwrap();
+        assert_eq!(gateway.shard_count(), 2);
+        assert!(gateway.local_address().ip().is_loopback());
+        gateway.shutdown();
+    }
+    
+    #[test]
+    fn test_overload_queue_depth_trips_without_a_stream() {
+        use crate::contracts::{SensorPayload, ANOMALY_VECTOR_SIZE};
+        use crate::transmitter::Transmitter;
+        use std::net::UdpSocket;
+        use std::time::{SystemTime, UNIX_EPOCH};
+        
+        let config = CyDnAConfig {
+            bind_address: "127.0.0.1:0".to_string(),
+            poll_interval_ms: 20,
+            overload_queue_depth: Some(4),
+            overload_latency_ms: 60_000,
+            ..Default::default()
+        };
+        let gateway = config.gateway_server().unwrap()
+            .with_handler(|_| std::thread::sleep(Duration::from_millis(20)))
+            .spawn_sharded(config.shards)
+            .unwrap();
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        for device_id in 1..=16 {
+            let payload = SensorPayload::new(device_id, now, 1, 80, 5_000, 0, [0.0; ANOMALY_VECTOR_SIZE]).unwrap();
+            Transmitter::send(&sensor, &payload, gateway.local_address()).unwrap();
+        }
+        
+        let mut buffer = [0u8; 64];
+        for _ in 1..=16 {
+            sensor.recv_from(&mut buffer).unwrap();
+        }
+        assert!(gateway.shedding_metrics().unwrap().activations >= 1);
+        gateway.shutdown();
+    }
+    
+    #[test]
+    fn test_device_keys_file() {
+        let path = std::env::temp_dir().join(format!("cynda-device-keys-{}", std::process::id()));
+        let mut records = Vec::new();
+        for device_id in [3u32, 9] {
+            records.extend_from_slice(&device_id.to_le_bytes());
+            records.extend_from_slice(&[device_id as u8; 32]);
+        }
+        std::fs::write(&path, &records).unwrap();
+        
+        let config = CyDnAConfig { device_keys_path: Some(path.to_string_lossy().into_owned()), ..Default::default() };
+        assert_eq!(config.device_keys().unwrap(), vec![(3, [3u8; 32]), (9, [9u8; 32])]);
+        
+        std::fs::write(&path, &records[..40]).unwrap();
+        assert!(matches!(config.device_keys(), Err(CyDnAError::InvalidConfig(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+    
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_partial_config_uses_defaults() {
+        let config: CyDnAConfig = serde_json::from_str(r#"{"gateway_id": 7, "shards": 4}"#).unwrap();
+        assert_eq!(config.gateway_id, 7);
+        assert_eq!(config.shards, 4);
+        assert_eq!(config.bind_address, DEFAULT_BIND_ADDRESS);
+        
+        assert!(serde_json::from_str::<CyDnAConfig>(r#"{"gatway_id": 7}"#).is_err());
+    }
+}
@@ -1,9 +1,32 @@
+use blake2::digest::consts::U32;
+use blake2::{Blake2b, Digest};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
 use rkyv::{Archive, Deserialize, Serialize};
 
 pub const ANOMALY_VECTOR_SIZE: usize = 32;
 
+pub const DLT_SIGNING_INPUT_SIZE: usize = 44;
+
+pub const MAX_VARIABLE_VECTOR_SIZE: usize = 240;
+
+// An AES-256 key wrapped for `ControlCommand::InstallKey`.
+pub const WRAPPED_KEY_SIZE: usize = 32;
+
+pub const WRAPPED_KEY_NONCE_SIZE: usize = 12;
+
+pub const WRAPPED_KEY_TAG_SIZE: usize = 16;
+
+pub fn compute_crc32(raw_data: &[u8]) -> u32 {
+    crc32fast::hash(raw_data)
+}
+
+pub fn compute_payload_hash(payload_bytes: &[u8]) -> [u8; 32] {
+    Blake2b::<U32>::digest(payload_bytes).into()
+}
+
 #[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy)]
 #[archive(check_bytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SensorPayload {
     pub device_unique_id: u32,
     
@@ -17,6 +40,8 @@ pub struct SensorPayload {
     
     pub raw_data_hash_crc: u32,
     
+    pub sequence_number: u32,
+    
     pub anomaly_ai_vector: [f32; ANOMALY_VECTOR_SIZE],
 }
 
@@ -47,21 +72,260 @@ impl SensorPayload {
             battery_level_percent,
             time_to_live_ms,
             raw_data_hash_crc,
+            sequence_number: 0,
             anomaly_ai_vector,
         })
     }
     
+    pub fn with_sequence_number(mut self, sequence_number: u32) -> Self {
+        self.sequence_number = sequence_number;
+        self
+    }
+    
+    pub fn with_raw_data(
+        device_unique_id: u32,
+        timestamp_ms_utc: u64,
+        sensor_model_version: u16,
+        battery_level_percent: u8,
+        time_to_live_ms: u16,
+        raw_data: &[u8],
+        anomaly_ai_vector: [f32; ANOMALY_VECTOR_SIZE],
+    ) -> crate::Result<Self> {
+        Self::new(
+            device_unique_id,
+            timestamp_ms_utc,
+            sensor_model_version,
+            battery_level_percent,
+            time_to_live_ms,
+            compute_crc32(raw_data),
+            anomaly_ai_vector,
+        )
+    }
+    
+    pub fn verify_raw_data(&self, raw_data: &[u8]) -> crate::Result<()> {
+        verify_crc32(self.raw_data_hash_crc, raw_data)
+    }
+    
     pub fn is_expired(&self, current_time_ms: u64) -> bool {
         current_time_ms > self.timestamp_ms_utc.saturating_add(self.time_to_live_ms as u64)
     }
     
+    pub fn is_expired_on(&self, clock: &dyn crate::clock::Clock) -> bool {
+        self.is_expired(clock.now_ms())
+    }
+    
     pub fn expiration_time_ms(&self) -> u64 {
         self.timestamp_ms_utc.saturating_add(self.time_to_live_ms as u64)
     }
 }
 
+impl ArchivedSensorPayload {
+    pub fn verify_raw_data(&self, raw_data: &[u8]) -> crate::Result<()> {
+        verify_crc32(self.raw_data_hash_crc, raw_data)
+    }
+}
+
+// Version 2 payload: same header fields as SensorPayload but the anomaly
+// vector is length-prefixed, so sensors running models with different
+// output sizes can share a gateway. Sent in frames with version 2.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[archive(check_bytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SensorPayloadV2 {
+    pub device_unique_id: u32,
+    
+    pub timestamp_ms_utc: u64,
+    
+    pub sensor_model_version: u16,
+    
+    pub battery_level_percent: u8,
+    
+    pub time_to_live_ms: u16,
+    
+    pub raw_data_hash_crc: u32,
+    
+    pub sequence_number: u32,
+    
+    pub anomaly_ai_vector: Vec<f32>,
+    
+    pub temperature_centi_celsius: Option<i16>,
+}
+
+impl SensorPayloadV2 {
+    pub fn new(
+        device_unique_id: u32,
+        timestamp_ms_utc: u64,
+        sensor_model_version: u16,
+        battery_level_percent: u8,
+        time_to_live_ms: u16,
+        raw_data_hash_crc: u32,
+        anomaly_ai_vector: Vec<f32>,
+    ) -> crate::Result<Self> {
+        use crate::errors::CyDnAError;
+        
+        if device_unique_id == 0 {
+            return Err(CyDnAError::InvalidDeviceId(device_unique_id));
+        }
+        
+        if battery_level_percent > 100 {
+            return Err(CyDnAError::InvalidBatteryLevel(battery_level_percent));
+        }
+        
+        if anomaly_ai_vector.len() > MAX_VARIABLE_VECTOR_SIZE {
+            return Err(CyDnAError::InvalidVectorLength(anomaly_ai_vector.len()));
+        }
+        
+        Ok(Self {
+            device_unique_id,
+            timestamp_ms_utc,
+            sensor_model_version,
+            battery_level_percent,
+            time_to_live_ms,
+            raw_data_hash_crc,
+            sequence_number: 0,
+            anomaly_ai_vector,
+            temperature_centi_celsius: None,
+        })
+    }
+    
+    pub fn with_sequence_number(mut self, sequence_number: u32) -> Self {
+        self.sequence_number = sequence_number;
+        self
+    }
+    
+    pub fn with_temperature_centi_celsius(mut self, temperature: i16) -> Self {
+        self.temperature_centi_celsius = Some(temperature);
+        self
+    }
+}
+
+impl From<&SensorPayload> for SensorPayloadV2 {
+    fn from(payload: &SensorPayload) -> Self {
+        Self {
+            device_unique_id: payload.device_unique_id,
+            timestamp_ms_utc: payload.timestamp_ms_utc,
+            sensor_model_version: payload.sensor_model_version,
+            battery_level_percent: payload.battery_level_percent,
+            time_to_live_ms: payload.time_to_live_ms,
+            raw_data_hash_crc: payload.raw_data_hash_crc,
+            sequence_number: payload.sequence_number,
+            anomaly_ai_vector: payload.anomaly_ai_vector.to_vec(),
+            temperature_centi_celsius: None,
+        }
+    }
+}
+
+// Downgrade for peers that only speak version 1: fields V1 has no room for
+// are dropped, and the vector must be exactly ANOMALY_VECTOR_SIZE long.
+impl TryFrom<&SensorPayloadV2> for SensorPayload {
+    type Error = crate::errors::CyDnAError;
+    
+    fn try_from(payload: &SensorPayloadV2) -> crate::Result<Self> {
+        let anomaly_ai_vector = payload.anomaly_ai_vector.as_slice().try_into()
+            .map_err(|_| crate::errors::CyDnAError::InvalidVectorLength(
+                payload.anomaly_ai_vector.len()
+            ))?;
+        
+        Ok(Self {
+            device_unique_id: payload.device_unique_id,
+            timestamp_ms_utc: payload.timestamp_ms_utc,
+            sensor_model_version: payload.sensor_model_version,
+            battery_level_percent: payload.battery_level_percent,
+            time_to_live_ms: payload.time_to_live_ms,
+            raw_data_hash_crc: payload.raw_data_hash_crc,
+            sequence_number: payload.sequence_number,
+            anomaly_ai_vector,
+        })
+    }
+}
+
+// SensorPayload with the anomaly vector in one of the compact encodings from
+// `crate::quantization`; travels as MessageType::QuantizedPayload.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[archive(check_bytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QuantizedSensorPayload {
+    pub device_unique_id: u32,
+    
+    pub timestamp_ms_utc: u64,
+    
+    pub sensor_model_version: u16,
+    
+    pub battery_level_percent: u8,
+    
+    pub time_to_live_ms: u16,
+    
+    pub raw_data_hash_crc: u32,
+    
+    pub sequence_number: u32,
+    
+    pub vector_encoding: u8,
+    
+    pub vector_param: u8,
+    
+    pub vector_data: Vec<u8>,
+}
+
+impl QuantizedSensorPayload {
+    pub fn from_payload(
+        payload: &SensorPayload,
+        encoding: crate::quantization::VectorEncoding,
+    ) -> crate::Result<Self> {
+        let (vector_encoding, vector_param) = encoding.to_wire();
+        
+        Ok(Self {
+            device_unique_id: payload.device_unique_id,
+            timestamp_ms_utc: payload.timestamp_ms_utc,
+            sensor_model_version: payload.sensor_model_version,
+            battery_level_percent: payload.battery_level_percent,
+            time_to_live_ms: payload.time_to_live_ms,
+            raw_data_hash_crc: payload.raw_data_hash_crc,
+            sequence_number: payload.sequence_number,
+            vector_encoding,
+            vector_param,
+            vector_data: crate::quantization::quantize(&payload.anomaly_ai_vector, encoding)?,
+        })
+    }
+    
+    pub fn encoding(&self) -> crate::Result<crate::quantization::VectorEncoding> {
+        crate::quantization::VectorEncoding::from_wire(self.vector_encoding, self.vector_param)
+    }
+    
+    pub fn to_payload(&self) -> crate::Result<SensorPayload> {
+        let decoded = crate::quantization::dequantize(
+            &self.vector_data,
+            self.encoding()?,
+            ANOMALY_VECTOR_SIZE,
+        )?;
+        
+        let mut anomaly_ai_vector = [0.0f32; ANOMALY_VECTOR_SIZE];
+        anomaly_ai_vector.copy_from_slice(&decoded);
+        
+        Ok(SensorPayload::new(
+            self.device_unique_id,
+            self.timestamp_ms_utc,
+            self.sensor_model_version,
+            self.battery_level_percent,
+            self.time_to_live_ms,
+            self.raw_data_hash_crc,
+            anomaly_ai_vector,
+        )?.with_sequence_number(self.sequence_number))
+    }
+}
+
+pub fn verify_crc32(expected: u32, raw_data: &[u8]) -> crate::Result<()> {
+    let actual = compute_crc32(raw_data);
+    
+    if actual != expected {
+        return Err(crate::errors::CyDnAError::IntegrityCheckFailed { expected, actual });
+    }
+    
+    Ok(())
+}
+
 #[derive(Archive, Serialize, Deserialize, Debug, Clone)]
 #[archive(check_bytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DLTTransactionRecord {
     pub gateway_unique_id: u32,
     
@@ -71,8 +335,10 @@ pub struct DLTTransactionRecord {
     
     pub consensus_mode_used: u8,
     
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::hex_bytes"))]
     pub source_payload_hash: [u8; 32],
     
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::hex_bytes"))]
     pub gateway_signature: [u8; 64],
 }
 
@@ -92,9 +358,7 @@ impl DLTTransactionRecord {
         }
         
         if consensus_mode_used > 1 {
-            return Err(CyDnAError::SerializationError(
-                format!("Invalid consensus_mode_used: {}", consensus_mode_used)
-            ));
+            return Err(CyDnAError::InvalidConsensusMode(consensus_mode_used));
         }
         
         Ok(Self {
@@ -106,10 +370,113 @@ impl DLTTransactionRecord {
             gateway_signature,
         })
     }
+    
+    pub fn builder() -> DLTTransactionRecordBuilder {
+        DLTTransactionRecordBuilder::new()
+    }
+    
+    pub fn signing_bytes(&self) -> [u8; DLT_SIGNING_INPUT_SIZE] {
+        let mut bytes = [0u8; DLT_SIGNING_INPUT_SIZE];
+        bytes[0..2].copy_from_slice(&crate::CYNDA_VERSION.to_le_bytes());
+        bytes[2..6].copy_from_slice(&self.gateway_unique_id.to_le_bytes());
+        bytes[6..10].copy_from_slice(&self.final_anomaly_score.to_bits().to_le_bytes());
+        bytes[10] = self.is_critical_alert as u8;
+        bytes[11] = self.consensus_mode_used;
+        bytes[12..44].copy_from_slice(&self.source_payload_hash);
+        bytes
+    }
+    
+    pub fn sign(&mut self, signing_key: &SigningKey) {
+        self.gateway_signature = signing_key.sign(&self.signing_bytes()).to_bytes();
+    }
+    
+    pub fn verify(&self, public_key: &VerifyingKey) -> crate::Result<()> {
+        let signature = Signature::from_bytes(&self.gateway_signature);
+        
+        public_key
+            .verify_strict(&self.signing_bytes(), &signature)
+            .map_err(|_| crate::errors::CyDnAError::SignatureVerificationFailed)
+    }
+}
+
+pub struct DLTTransactionRecordBuilder {
+    gateway_unique_id: u32,
+    final_anomaly_score: f32,
+    is_critical_alert: bool,
+    consensus_mode_used: u8,
+    source_payload_hash: Option<[u8; 32]>,
+}
+
+impl DLTTransactionRecordBuilder {
+    pub fn new() -> Self {
+        Self {
+            gateway_unique_id: 0,
+            final_anomaly_score: 0.0,
+            is_critical_alert: false,
+            consensus_mode_used: 0,
+            source_payload_hash: None,
+        }
+    }
+    
+    pub fn with_payload_bytes(mut self, payload_bytes: &[u8]) -> Self {
+        self.source_payload_hash = Some(compute_payload_hash(payload_bytes));
+        self
+    }
+    
+    pub fn with_gateway_id(mut self, gateway_unique_id: u32) -> Self {
+        self.gateway_unique_id = gateway_unique_id;
+        self
+    }
+    
+    pub fn with_anomaly_score(mut self, score: f32) -> Self {
+        self.final_anomaly_score = score;
+        self
+    }
+    
+    pub fn with_critical_alert(mut self, critical: bool) -> Self {
+        self.is_critical_alert = critical;
+        self
+    }
+    
+    pub fn with_consensus_mode(mut self, mode: u8) -> Self {
+        self.consensus_mode_used = mode;
+        self
+    }
+    
+    pub fn get_source_payload_hash(&self) -> Option<[u8; 32]> {
+        self.source_payload_hash
+    }
+    
+    pub fn build(self, signing_key: &SigningKey) -> crate::Result<DLTTransactionRecord> {
+        let source_payload_hash = self.source_payload_hash.ok_or(
+            crate::errors::CyDnAError::SerializationError(
+                "DLTTransactionRecord requires source payload bytes"
+            )
+        )?;
+        
+        let mut record = DLTTransactionRecord::new(
+            self.gateway_unique_id,
+            self.final_anomaly_score,
+            self.is_critical_alert,
+            self.consensus_mode_used,
+            source_payload_hash,
+            [0u8; 64],
+        )?;
+        record.sign(signing_key);
+        
+        Ok(record)
+    }
+}
+
+impl Default for DLTTransactionRecordBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy)]
 #[archive(check_bytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AckPacket {
     pub device_unique_id: u32,
     
@@ -117,7 +484,9 @@ pub struct AckPacket {
     
     pub ack_type: u8,
     
-    pub _padding: [u8; 3],
+    pub nack_reason: u8,
+    
+    pub _padding: [u8; 2],
 }
 
 impl AckPacket {
@@ -126,28 +495,346 @@ impl AckPacket {
             device_unique_id,
             original_timestamp_ms,
             ack_type: 0,
-            _padding: [0; 3],
+            nack_reason: NackReason::Unspecified as u8,
+            _padding: [0; 2],
         }
     }
     
     pub fn nack(device_unique_id: u32, original_timestamp_ms: u64) -> Self {
+        Self::nack_with_reason(device_unique_id, original_timestamp_ms, NackReason::Unspecified)
+    }
+    
+    pub fn nack_with_reason(
+        device_unique_id: u32,
+        original_timestamp_ms: u64,
+        reason: NackReason,
+    ) -> Self {
         Self {
             device_unique_id,
             original_timestamp_ms,
             ack_type: 1,
-            _padding: [0; 3],
+            nack_reason: reason as u8,
+            _padding: [0; 2],
         }
     }
     
     pub fn is_ack(&self) -> bool {
         self.ack_type == 0
     }
+    
+    pub fn reason(&self) -> NackReason {
+        NackReason::from_u8(self.nack_reason)
+    }
 }
 
 impl ArchivedAckPacket {
     pub fn is_ack(&self) -> bool {
         self.ack_type == 0
     }
+    
+    pub fn reason(&self) -> NackReason {
+        NackReason::from_u8(self.nack_reason)
+    }
+}
+
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[archive(check_bytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Heartbeat {
+    pub device_unique_id: u32,
+    
+    pub battery_level_percent: u8,
+    
+    pub _padding: [u8; 3],
+    
+    pub timestamp_ms_utc: u64,
+}
+
+impl Heartbeat {
+    pub fn new(device_unique_id: u32, timestamp_ms_utc: u64, battery_level_percent: u8) -> Self {
+        Self {
+            device_unique_id,
+            battery_level_percent,
+            _padding: [0; 3],
+            timestamp_ms_utc,
+        }
+    }
+}
+
+// Gateway -> sensor: asks for the raw block whose CRC32 was reported in a
+// payload's `raw_data_hash_crc`. A `chunk_count` of zero means "to the end".
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[archive(check_bytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RawDataRequest {
+    pub device_unique_id: u32,
+    
+    pub raw_data_hash_crc: u32,
+    
+    pub first_chunk: u16,
+    
+    pub chunk_count: u16,
+}
+
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[archive(check_bytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RawDataChunk {
+    pub device_unique_id: u32,
+    
+    pub raw_data_hash_crc: u32,
+    
+    pub chunk_index: u16,
+    
+    pub chunk_count: u16,
+    
+    pub total_len: u32,
+    
+    pub data: Vec<u8>,
+}
+
+// Remote reconfiguration, applied by the sensor application. `RotateKey`
+// never carries key material: it names the generation to switch to, taken
+// from provisioned key slots or a fresh session handshake. `InstallKey`
+// carries the next generation's key, AES-256-GCM sealed under the device's
+// current key (see `key_rotation`); `DeviceKeyring` accepts only that,
+// since `RotateKey` is unauthenticated.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[archive(check_bytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ControlCommand {
+    SetTimeToLive { time_to_live_ms: u16 },
+    
+    SetReportingInterval { interval_ms: u32 },
+    
+    RequestHeartbeat,
+    
+    RotateKey { key_generation: u32 },
+    
+    InstallKey {
+        key_generation: u32,
+        nonce: [u8; WRAPPED_KEY_NONCE_SIZE],
+        wrapped_key: [u8; WRAPPED_KEY_SIZE],
+        tag: [u8; WRAPPED_KEY_TAG_SIZE],
+    },
+}
+
+impl ControlCommand {
+    pub fn kind(&self) -> u8 {
+        match self {
+            Self::SetTimeToLive { .. } => 1,
+            Self::SetReportingInterval { .. } => 2,
+            Self::RequestHeartbeat => 3,
+            Self::RotateKey { .. } => 4,
+            Self::InstallKey { .. } => 5,
+        }
+    }
+    
+    // Zero TTLs and intervals would silence the sensor until someone
+    // visits it, so the sensor refuses them.
+    pub fn validate(&self) -> crate::Result<()> {
+        match self {
+            Self::SetTimeToLive { time_to_live_ms: 0 } | Self::SetReportingInterval { interval_ms: 0 } => {
+                Err(crate::errors::CyDnAError::InvalidControlCommand(self.kind()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Gateway -> sensor, retransmitted until the matching ControlAck arrives.
+// `command_id` identifies the command across retransmissions.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[archive(check_bytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ControlMessage {
+    pub device_unique_id: u32,
+    
+    pub command_id: u32,
+    
+    pub command: ControlCommand,
+}
+
+// Sensor -> gateway. `accepted` is false when the sensor refused the
+// command as invalid; a refusal is final and is not retransmitted.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[archive(check_bytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ControlAck {
+    pub device_unique_id: u32,
+    
+    pub command_id: u32,
+    
+    pub accepted: bool,
+    
+    pub _padding: [u8; 3],
+}
+
+impl ControlAck {
+    pub fn new(device_unique_id: u32, command_id: u32, accepted: bool) -> Self {
+        Self {
+            device_unique_id,
+            command_id,
+            accepted,
+            _padding: [0; 3],
+        }
+    }
+}
+
+// Gateway -> sensor, answered straight away with a TimeSyncResponse.
+// `origin_ms` is the gateway clock when the request was sent.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[archive(check_bytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimeSyncRequest {
+    pub device_unique_id: u32,
+    
+    pub request_id: u32,
+    
+    pub origin_ms: u64,
+}
+
+// Sensor -> gateway: the request's origin echoed back with the sensor clock
+// on arrival (`receive_ms`) and on reply (`transmit_ms`), as in NTP.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[archive(check_bytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimeSyncResponse {
+    pub device_unique_id: u32,
+    
+    pub request_id: u32,
+    
+    pub origin_ms: u64,
+    
+    pub receive_ms: u64,
+    
+    pub transmit_ms: u64,
+}
+
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[archive(check_bytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GatewayAnnouncement {
+    pub gateway_id: u32,
+    
+    pub service_port: u16,
+    
+    pub protocol_version: u16,
+    
+    pub timestamp_ms_utc: u64,
+}
+
+impl GatewayAnnouncement {
+    pub fn new(gateway_id: u32, service_port: u16) -> Self {
+        Self {
+            gateway_id,
+            service_port,
+            protocol_version: crate::CYNDA_VERSION,
+            timestamp_ms_utc: 0,
+        }
+    }
+}
+
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[archive(check_bytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtendedAckPacket {
+    pub device_unique_id: u32,
+    
+    pub cumulative_sequence: u32,
+    
+    pub selective_bitmap: u64,
+}
+
+impl ExtendedAckPacket {
+    pub fn new(device_unique_id: u32, cumulative_sequence: u32, selective_bitmap: u64) -> Self {
+        Self {
+            device_unique_id,
+            cumulative_sequence,
+            selective_bitmap,
+        }
+    }
+    
+    // Every sequence up to and including `cumulative_sequence` is confirmed;
+    // bit i of the bitmap confirms `cumulative_sequence + 1 + i`.
+    pub fn acknowledges(&self, sequence_number: u32) -> bool {
+        let distance = sequence_number.wrapping_sub(self.cumulative_sequence) as i32;
+        
+        if distance <= 0 {
+            return true;
+        }
+        
+        distance <= 64 && self.selective_bitmap & (1u64 << (distance - 1)) != 0
+    }
+    
+    pub fn selective_count(&self) -> u32 {
+        self.selective_bitmap.count_ones()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum NackReason {
+    Unspecified = 0,
+    CrcMismatch = 1,
+    ExpiredTtl = 2,
+    Malformed = 3,
+    RateLimited = 4,
+    Unauthorized = 5,
+}
+
+impl NackReason {
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::CrcMismatch,
+            2 => Self::ExpiredTtl,
+            3 => Self::Malformed,
+            4 => Self::RateLimited,
+            5 => Self::Unauthorized,
+            _ => Self::Unspecified,
+        }
+    }
+    
+    pub fn from_error(error: &crate::errors::CyDnAError) -> Self {
+        use crate::errors::CyDnAError;
+        
+        match error {
+            CyDnAError::IntegrityCheckFailed { .. } => Self::CrcMismatch,
+            CyDnAError::PayloadExpired { .. } | CyDnAError::PayloadTooOld { .. } => Self::ExpiredTtl,
+            CyDnAError::DeserializationError(_)
+            | CyDnAError::InvalidPacketLength { .. }
+            | CyDnAError::InvalidDeviceId(_)
+            | CyDnAError::InvalidBatteryLevel(_)
+            | CyDnAError::InvalidVectorLength(_)
+            | CyDnAError::UnknownVectorEncoding(_)
+            | CyDnAError::InvalidRawDataChunk(_)
+            | CyDnAError::InvalidControlCommand(_)
+            | CyDnAError::ValidationFailed(_)
+            | CyDnAError::InvalidFrameMagic(_)
+            | CyDnAError::UnsupportedVersion { .. }
+            | CyDnAError::UnknownMessageType(_)
+            | CyDnAError::UnexpectedMessageType { .. }
+            | CyDnAError::UnsupportedCompression(_)
+            | CyDnAError::UnsupportedWireFormat(_)
+            | CyDnAError::CompressionError(_) => Self::Malformed,
+            CyDnAError::SignatureVerificationFailed
+            | CyDnAError::AuthenticationFailed(_)
+            | CyDnAError::DecryptionFailed(_)
+            | CyDnAError::UnknownDeviceKey(_)
+            | CyDnAError::DeviceNotAllowed(_) => Self::Unauthorized,
+            CyDnAError::RateLimited(_) => Self::RateLimited,
+            _ => Self::Unspecified,
+        }
+    }
+    
+    pub fn should_retransmit(&self) -> bool {
+        !matches!(self, Self::ExpiredTtl | Self::Unauthorized)
+    }
+    
+    pub fn retransmit_immediately(&self) -> bool {
+        matches!(self, Self::Unspecified | Self::CrcMismatch | Self::Malformed)
+    }
 }
 
 #[cfg(test)]
@@ -206,6 +893,71 @@ mod tests {
         assert!(payload.is_expired(1101));
     }
     
+    #[test]
+    fn test_raw_data_crc() {
+        let raw = [0x5au8; 256];
+        let payload = SensorPayload::with_raw_data(
+            1,
+            1000,
+            1,
+            50,
+            100,
+            &raw,
+            [0.0; ANOMALY_VECTOR_SIZE],
+        ).unwrap();
+        
+        assert_eq!(payload.raw_data_hash_crc, compute_crc32(&raw));
+        assert!(payload.verify_raw_data(&raw).is_ok());
+        
+        let mut tampered = raw;
+        tampered[10] ^= 0xff;
+        match payload.verify_raw_data(&tampered) {
+            Err(crate::errors::CyDnAError::IntegrityCheckFailed { expected, actual }) => {
+                assert_eq!(expected, compute_crc32(&raw));
+                assert_eq!(actual, compute_crc32(&tampered));
+            }
+            other => panic!("expected IntegrityCheckFailed, got {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_nack_reason_roundtrip() {
+        let nack = AckPacket::nack_with_reason(1, 1000, NackReason::RateLimited);
+        assert!(!nack.is_ack());
+        assert_eq!(nack.reason(), NackReason::RateLimited);
+        assert_eq!(AckPacket::nack(1, 1000).reason(), NackReason::Unspecified);
+        assert_eq!(std::mem::size_of::<AckPacket>(), 16);
+        
+        assert_eq!(NackReason::from_u8(200), NackReason::Unspecified);
+        assert!(!NackReason::ExpiredTtl.should_retransmit());
+        assert!(!NackReason::Unauthorized.should_retransmit());
+        assert!(NackReason::RateLimited.should_retransmit());
+        assert!(!NackReason::RateLimited.retransmit_immediately());
+        
+        let crc_error = crate::errors::CyDnAError::IntegrityCheckFailed { expected: 1, actual: 2 };
+        assert_eq!(NackReason::from_error(&crc_error), NackReason::CrcMismatch);
+        let limited = crate::errors::CyDnAError::RateLimited(1);
+        assert_eq!(NackReason::from_error(&limited), NackReason::RateLimited);
+    }
+    
+    #[test]
+    fn test_extended_ack_coverage() {
+        let ack = ExtendedAckPacket::new(1, 100, 0b1010);
+        
+        assert!(ack.acknowledges(90));
+        assert!(ack.acknowledges(100));
+        assert!(!ack.acknowledges(101));
+        assert!(ack.acknowledges(102));
+        assert!(!ack.acknowledges(103));
+        assert!(ack.acknowledges(104));
+        assert!(!ack.acknowledges(200));
+        assert_eq!(ack.selective_count(), 2);
+        
+        let wrapped = ExtendedAckPacket::new(1, u32::MAX, 0b1);
+        assert!(wrapped.acknowledges(0));
+        assert!(!wrapped.acknowledges(1));
+    }
+    
     #[test]
     fn test_dlt_transaction_validation() {
         let result = DLTTransactionRecord::new(
@@ -228,4 +980,65 @@ mod tests {
         );
         assert!(result.is_err());
     }
+    
+    #[test]
+    fn test_dlt_sign_and_verify() {
+        let signing_key = SigningKey::from_bytes(&[42u8; 32]);
+        let public_key = signing_key.verifying_key();
+        
+        let mut record = DLTTransactionRecord::new(
+            7,
+            0.87,
+            true,
+            1,
+            [0xabu8; 32],
+            [0u8; 64],
+        ).unwrap();
+        
+        assert!(record.verify(&public_key).is_err());
+        
+        record.sign(&signing_key);
+        assert!(record.verify(&public_key).is_ok());
+        
+        let other_key = SigningKey::from_bytes(&[1u8; 32]).verifying_key();
+        assert!(record.verify(&other_key).is_err());
+        
+        record.final_anomaly_score = 0.12;
+        assert!(matches!(
+            record.verify(&public_key),
+            Err(crate::errors::CyDnAError::SignatureVerificationFailed)
+        ));
+    }
+    
+    #[test]
+    fn test_dlt_record_builder() {
+        let signing_key = SigningKey::from_bytes(&[42u8; 32]);
+        let payload_bytes = [0x11u8; 212];
+        
+        let record = DLTTransactionRecord::builder()
+            .with_payload_bytes(&payload_bytes)
+            .with_gateway_id(3)
+            .with_anomaly_score(0.93)
+            .with_critical_alert(true)
+            .build(&signing_key)
+            .unwrap();
+        
+        assert_eq!(record.source_payload_hash, compute_payload_hash(&payload_bytes));
+        assert_eq!(record.gateway_unique_id, 3);
+        assert!(record.is_critical_alert);
+        assert!(record.verify(&signing_key.verifying_key()).is_ok());
+        
+        let missing_payload = DLTTransactionRecord::builder()
+            .with_gateway_id(3)
+            .build(&signing_key);
+        assert!(missing_payload.is_err());
+        
+        let missing_gateway = DLTTransactionRecord::builder()
+            .with_payload_bytes(&payload_bytes)
+            .build(&signing_key);
+        assert!(matches!(
+            missing_gateway,
+            Err(crate::errors::CyDnAError::InvalidGatewayId(0))
+        ));
+    }
 }
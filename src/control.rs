@@ -0,0 +1,363 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use rkyv::{check_archived_root, to_bytes, Deserialize};
+
+use crate::ack_manager::AckManager;
+use crate::contracts::{ControlAck, ControlCommand, ControlMessage};
+use crate::errors::{CyDnAError, Result};
+use crate::framing::{decode_frame, encode_frame, encode_frame_with_priority, MessageType, Priority};
+
+pub const DEFAULT_CONTROL_MAX_ATTEMPTS: u32 = 5;
+
+// Sensors may be duty-cycled, so control retries back off from a much
+// longer base than payload ACKs do.
+pub const DEFAULT_CONTROL_RETRY_BASE_MS: u64 = 500;
+
+// Command ids a sensor remembers to recognise retransmissions.
+pub const CONTROL_HISTORY_SIZE: usize = 32;
+
+pub fn encode_control(message: &ControlMessage) -> Result<Vec<u8>> {
+    let bytes = to_bytes::<_, 64>(message)
+        .map_err(|_| CyDnAError::SerializationError(
+            "Failed to serialize ControlMessage"
+        ))?;
+    
+    encode_frame_with_priority(MessageType::ControlMessage, Priority::Critical, &bytes)
+}
+
+pub fn parse_control(datagram: &[u8]) -> Result<ControlMessage> {
+    let body = decode_frame(datagram, MessageType::ControlMessage)?;
+    
+    let archived = check_archived_root::<ControlMessage>(body)
+        .map_err(|_| CyDnAError::DeserializationError(
+            "Failed to validate ControlMessage"
+        ))?;
+    
+    archived.deserialize(&mut rkyv::Infallible)
+        .map_err(|_| CyDnAError::DeserializationError(
+            "Failed to deserialize ControlMessage"
+        ))
+}
+
+pub fn encode_control_ack(ack: &ControlAck) -> Result<Vec<u8>> {
+    let bytes = to_bytes::<_, 32>(ack)
+        .map_err(|_| CyDnAError::SerializationError(
+            "Failed to serialize ControlAck"
+        ))?;
+    
+    encode_frame(MessageType::ControlAck, &bytes)
+}
+
+pub fn parse_control_ack(datagram: &[u8]) -> Result<ControlAck> {
+    let body = decode_frame(datagram, MessageType::ControlAck)?;
+    
+    let archived = check_archived_root::<ControlAck>(body)
+        .map_err(|_| CyDnAError::DeserializationError(
+            "Failed to validate ControlAck"
+        ))?;
+    
+    Ok(ControlAck::new(archived.device_unique_id, archived.command_id, archived.accepted))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlEvent {
+    Delivered { device_id: u32, command_id: u32, attempts: u32 },
+    
+    Refused { message: ControlMessage },
+    
+    Exhausted { message: ControlMessage, attempts: u32 },
+}
+
+struct PendingCommand {
+    message: ControlMessage,
+    destination: SocketAddr,
+    attempts: u32,
+    next_retry: Instant,
+}
+
+// Gateway side: sends control commands to sensors and retransmits each one
+// until the sensor acknowledges it or the attempts run out. Send from the
+// socket the sensor talks to, since sensors only listen to their gateway.
+pub struct ControlChannel {
+    pending: HashMap<(u32, u32), PendingCommand>,
+    events: VecDeque<ControlEvent>,
+    next_command_id: u32,
+    max_attempts: u32,
+    retry_base_ms: u64,
+}
+
+impl ControlChannel {
+    // Command ids start at a random point so a restarted gateway does not
+    // reuse ids a sensor still remembers.
+    pub fn new() -> Self {
+        Self {
+            pending: HashMap::new(),
+            events: VecDeque::new(),
+            next_command_id: rand::random(),
+            max_attempts: DEFAULT_CONTROL_MAX_ATTEMPTS,
+            retry_base_ms: DEFAULT_CONTROL_RETRY_BASE_MS,
+        }
+    }
+    
+    pub fn with_retry_policy(mut self, max_attempts: u32, retry_base_ms: u64) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self.retry_base_ms = retry_base_ms.max(1);
+        self
+    }
+    
+    // Sends the first copy and returns the command id reported in events.
+    pub fn send(
+        &mut self,
+        socket: &UdpSocket,
+        destination: impl ToSocketAddrs,
+        device_id: u32,
+        command: ControlCommand,
+    ) -> Result<u32> {
+        if device_id == 0 {
+            return Err(CyDnAError::InvalidDeviceId(device_id));
+        }
+        
+        let destination = crate::socket::resolve(destination)?;
+        let command_id = self.next_command_id;
+        self.next_command_id = self.next_command_id.wrapping_add(1);
+        
+        let message = ControlMessage { device_unique_id: device_id, command_id, command };
+        let mut pending = PendingCommand { message, destination, attempts: 0, next_retry: Instant::now() };
+        self.transmit(socket, &mut pending)?;
+        
+        self.pending.insert((device_id, command_id), pending);
+        Ok(command_id)
+    }
+    
+    fn transmit(&self, socket: &UdpSocket, pending: &mut PendingCommand) -> Result<()> {
+        socket.send_to(&encode_control(&pending.message)?, pending.destination)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let backoff_ms = AckManager::calculate_backoff_ms(pending.attempts, self.retry_base_ms, self.retry_base_ms * 10);
+        pending.attempts += 1;
+        pending.next_retry = Instant::now() + Duration::from_millis(backoff_ms);
+        Ok(())
+    }
+    
+    pub fn handle_ack(&mut self, ack: &ControlAck) -> bool {
+        let Some(pending) = self.pending.remove(&(ack.device_unique_id, ack.command_id)) else {
+            return false;
+        };
+        
+        self.events.push_back(if ack.accepted {
+            ControlEvent::Delivered {
+                device_id: ack.device_unique_id,
+                command_id: ack.command_id,
+                attempts: pending.attempts,
+            }
+        } else {
+            ControlEvent::Refused { message: pending.message }
+        });
+        true
+    }
+    
+    // Returns whether the datagram was a ControlAck for a pending command;
+    // anything else is left to the caller.
+    pub fn handle_datagram(&mut self, datagram: &[u8]) -> bool {
+        parse_control_ack(datagram).is_ok_and(|ack| self.handle_ack(&ack))
+    }
+    
+    // Retransmits whatever is due; commands out of attempts are dropped and
+    // reported as Exhausted. Returns the number of retransmissions sent.
+    pub fn service(&mut self, socket: &UdpSocket) -> Result<usize> {
+        let now = Instant::now();
+        let due: Vec<(u32, u32)> = self.pending.iter()
+            .filter(|(_, pending)| pending.next_retry <= now)
+            .map(|(key, _)| *key)
+            .collect();
+        
+        let mut retransmitted = 0;
+        for key in due {
+            let Some(mut pending) = self.pending.remove(&key) else {
+                continue;
+            };
+            
+            if pending.attempts >= self.max_attempts {
+                self.events.push_back(ControlEvent::Exhausted {
+                    message: pending.message,
+                    attempts: pending.attempts,
+                });
+                continue;
+            }
+            
+            let sent = self.transmit(socket, &mut pending);
+            self.pending.insert(key, pending);
+            sent?;
+            retransmitted += 1;
+        }
+        
+        Ok(retransmitted)
+    }
+    
+    pub fn next_wakeup(&self) -> Option<Instant> {
+        self.pending.values().map(|pending| pending.next_retry).min()
+    }
+    
+    pub fn poll_event(&mut self) -> Option<ControlEvent> {
+        self.events.pop_front()
+    }
+    
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+    
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty() && self.events.is_empty()
+    }
+}
+
+impl Default for ControlChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Sensor side: acknowledges every copy of a command but hands each command
+// to the application only once.
+pub struct ControlInbox {
+    device_unique_id: u32,
+    seen: VecDeque<u32>,
+    commands: VecDeque<ControlMessage>,
+}
+
+impl ControlInbox {
+    pub fn new(device_unique_id: u32) -> Self {
+        Self {
+            device_unique_id,
+            seen: VecDeque::new(),
+            commands: VecDeque::new(),
+        }
+    }
+    
+    // Returns the ACK frame to send back, or `None` for a command addressed
+    // to another device. Invalid commands are refused and never queued.
+    pub fn receive(&mut self, message: ControlMessage) -> Result<Option<Vec<u8>>> {
+        self.receive_with(message, |_| Ok(()))
+    }
+    
+    // As `receive`, but a new command is only accepted if `apply` succeeds,
+    // for commands the sensor has to act on before acknowledging (installing
+    // a key). Retransmissions of an accepted command are ACKed without
+    // applying them again.
+    pub fn receive_with(
+        &mut self,
+        message: ControlMessage,
+        apply: impl FnOnce(&ControlCommand) -> Result<()>,
+    ) -> Result<Option<Vec<u8>>> {
+        if message.device_unique_id != self.device_unique_id {
+            return Ok(None);
+        }
+        
+        if self.seen.contains(&message.command_id) {
+            return encode_control_ack(&ControlAck::new(self.device_unique_id, message.command_id, true)).map(Some);
+        }
+        
+        let accepted = message.command.validate().and_then(|_| apply(&message.command)).is_ok();
+        if accepted {
+            if self.seen.len() == CONTROL_HISTORY_SIZE {
+                self.seen.pop_front();
+            }
+            self.seen.push_back(message.command_id);
+            self.commands.push_back(message);
+        }
+        
+        encode_control_ack(&ControlAck::new(self.device_unique_id, message.command_id, accepted)).map(Some)
+    }
+    
+    pub fn next_command(&mut self) -> Option<ControlMessage> {
+        self.commands.pop_front()
+    }
+    
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_control_frames_roundtrip() {
+        let message = ControlMessage {
+            device_unique_id: 7,
+            command_id: 42,
+            command: ControlCommand::SetReportingInterval { interval_ms: 30_000 },
+        };
+        assert_eq!(parse_control(&encode_control(&message).unwrap()).unwrap(), message);
+        
+        let ack = ControlAck::new(7, 42, false);
+        let frame = encode_control_ack(&ack).unwrap();
+        assert_eq!(parse_control_ack(&frame).unwrap(), ack);
+        assert!(matches!(parse_control(&frame), Err(CyDnAError::UnexpectedMessageType { .. })));
+    }
+    
+    #[test]
+    fn test_inbox_dedups_and_refuses() {
+        let mut inbox = ControlInbox::new(7);
+        let message = ControlMessage { device_unique_id: 7, command_id: 1, command: ControlCommand::RequestHeartbeat };
+        
+        for _ in 0..2 {
+            let ack = parse_control_ack(&inbox.receive(message).unwrap().unwrap()).unwrap();
+            assert!(ack.accepted);
+        }
+        assert_eq!(inbox.len(), 1);
+        
+        let invalid = ControlMessage { command_id: 2, command: ControlCommand::SetTimeToLive { time_to_live_ms: 0 }, ..message };
+        assert!(!parse_control_ack(&inbox.receive(invalid).unwrap().unwrap()).unwrap().accepted);
+        assert_eq!(inbox.receive(ControlMessage { device_unique_id: 8, ..message }).unwrap(), None);
+        
+        assert_eq!(inbox.next_command(), Some(message));
+        assert!(inbox.is_empty());
+    }
+    
+    #[test]
+    fn test_channel_retransmits_until_acked() {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        
+        let mut channel = ControlChannel::new().with_retry_policy(2, 5);
+        let rotate = ControlCommand::RotateKey { key_generation: 3 };
+        let command_id = channel.send(&gateway, sensor.local_addr().unwrap(), 7, rotate).unwrap();
+        
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(channel.service(&gateway).unwrap(), 1);
+        
+        let mut inbox = ControlInbox::new(7);
+        let mut buffer = [0u8; 128];
+        let mut ack_frame = None;
+        for _ in 0..2 {
+            let len = sensor.recv(&mut buffer).unwrap();
+            let message = parse_control(&buffer[..len]).unwrap();
+            assert_eq!((message.command_id, message.command), (command_id, rotate));
+            ack_frame = inbox.receive(message).unwrap();
+        }
+        assert_eq!(inbox.len(), 1);
+        
+        assert!(channel.handle_datagram(&ack_frame.unwrap()));
+        assert_eq!(channel.poll_event(), Some(ControlEvent::Delivered { device_id: 7, command_id, attempts: 2 }));
+        assert!(channel.is_idle());
+        
+        // Nobody answers the second command.
+        let interval = ControlCommand::SetReportingInterval { interval_ms: 1_000 };
+        channel.send(&gateway, sensor.local_addr().unwrap(), 7, interval).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while channel.pending_count() > 0 && Instant::now() < deadline {
+            channel.service(&gateway).unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(matches!(channel.poll_event(), Some(ControlEvent::Exhausted { attempts: 2, .. })));
+    }
+}
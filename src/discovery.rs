@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rkyv::{check_archived_root, to_bytes};
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::contracts::GatewayAnnouncement;
+use crate::errors::{CyDnAError, Result};
+use crate::framing::{decode_frame, encode_frame_with_header, local_compression_mask, FrameHeader, MessageType};
+use crate::MAX_PAYLOAD_SIZE;
+
+pub const DISCOVERY_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 67, 68);
+
+pub const DISCOVERY_PORT: u16 = 47_810;
+
+pub const DEFAULT_ANNOUNCE_INTERVAL_MS: u64 = 5_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiscoveredGateway {
+    pub gateway_id: u32,
+    
+    pub address: SocketAddr,
+    
+    pub protocol_version: u16,
+    
+    // Codecs the gateway decodes (see `negotiate_compression`); zero for a
+    // gateway that predates compression.
+    pub compression_mask: u8,
+    
+    pub announced_ms: u64,
+}
+
+// The header flags carry this build's compression mask; older receivers
+// ignore announcement flags, so the body layout is unchanged.
+pub fn encode_announcement(announcement: &GatewayAnnouncement) -> Result<Vec<u8>> {
+    let bytes = to_bytes::<_, 64>(announcement)
+        .map_err(|_| CyDnAError::SerializationError(
+            "Failed to serialize GatewayAnnouncement"
+        ))?;
+    
+    let mut header = FrameHeader::new(MessageType::GatewayAnnouncement, 0);
+    header.flags = local_compression_mask();
+    encode_frame_with_header(header, &bytes)
+}
+
+// The announced service port is paired with the datagram's source IP, so a
+// gateway never has to know which of its interfaces the sensor can reach.
+pub fn parse_announcement(datagram: &[u8], source: SocketAddr) -> Result<DiscoveredGateway> {
+    let body = decode_frame(datagram, MessageType::GatewayAnnouncement)?;
+    let compression_mask = FrameHeader::decode(datagram)?.flags;
+    
+    let archived = check_archived_root::<GatewayAnnouncement>(body)
+        .map_err(|_| CyDnAError::DeserializationError(
+            "Failed to validate GatewayAnnouncement"
+        ))?;
+    
+    Ok(DiscoveredGateway {
+        gateway_id: archived.gateway_id,
+        address: SocketAddr::new(source.ip(), archived.service_port),
+        protocol_version: archived.protocol_version,
+        compression_mask,
+        announced_ms: archived.timestamp_ms_utc,
+    })
+}
+
+pub struct GatewayAnnouncer {
+    socket: UdpSocket,
+    destination: SocketAddr,
+    announcement: GatewayAnnouncement,
+    interval: Duration,
+    last_announce: Option<Instant>,
+}
+
+impl GatewayAnnouncer {
+    pub fn new(gateway_id: u32, service_port: u16) -> Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        socket.set_multicast_ttl_v4(1)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        Ok(Self {
+            socket,
+            destination: SocketAddr::V4(SocketAddrV4::new(DISCOVERY_GROUP, DISCOVERY_PORT)),
+            announcement: GatewayAnnouncement::new(gateway_id, service_port),
+            interval: Duration::from_millis(DEFAULT_ANNOUNCE_INTERVAL_MS),
+            last_announce: None,
+        })
+    }
+    
+    pub fn with_destination(mut self, destination: SocketAddr) -> Self {
+        self.destination = destination;
+        self
+    }
+    
+    pub fn with_interval_ms(mut self, interval_ms: u64) -> Self {
+        self.interval = Duration::from_millis(interval_ms);
+        self
+    }
+    
+    // Highest payload version the gateway decodes; only advertise V2 when
+    // the receive loop uses `Receiver::receive_versioned`.
+    pub fn with_protocol_version(mut self, protocol_version: u16) -> Self {
+        self.announcement.protocol_version = protocol_version;
+        self
+    }
+    
+    pub fn announce(&mut self) -> Result<usize> {
+        self.announcement.timestamp_ms_utc = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(0);
+        let frame = encode_announcement(&self.announcement)?;
+        
+        let sent = self.socket.send_to(&frame, self.destination)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        self.last_announce = Some(Instant::now());
+        Ok(sent)
+    }
+    
+    pub fn announce_if_due(&mut self) -> Result<bool> {
+        let due = self.last_announce
+            .map(|last| last.elapsed() >= self.interval)
+            .unwrap_or(true);
+        
+        if due {
+            self.announce()?;
+        }
+        
+        Ok(due)
+    }
+}
+
+// SO_REUSEADDR lets several sensor processes on one host listen together.
+pub fn discovery_socket(group: Ipv4Addr, port: u16) -> Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))
+        .map_err(|e| CyDnAError::IoError(e.kind()))?;
+    socket.set_reuse_address(true)
+        .map_err(|e| CyDnAError::IoError(e.kind()))?;
+    socket.bind(&SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)).into())
+        .map_err(|e| CyDnAError::IoError(e.kind()))?;
+    
+    let socket: UdpSocket = socket.into();
+    socket.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)
+        .map_err(|e| CyDnAError::IoError(e.kind()))?;
+    
+    Ok(socket)
+}
+
+pub fn discover_gateways(timeout: Duration) -> Result<Vec<DiscoveredGateway>> {
+    let socket = discovery_socket(DISCOVERY_GROUP, DISCOVERY_PORT)?;
+    
+    collect_announcements(&socket, timeout)
+}
+
+// Listens for the whole timeout and keeps the newest announcement per
+// gateway; malformed or foreign datagrams on the group are ignored.
+pub fn collect_announcements(socket: &UdpSocket, timeout: Duration) -> Result<Vec<DiscoveredGateway>> {
+    let deadline = Instant::now() + timeout;
+    let mut buffer = vec![0u8; MAX_PAYLOAD_SIZE];
+    let mut gateways: HashMap<u32, DiscoveredGateway> = HashMap::new();
+    
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        
+        socket.set_read_timeout(Some(remaining))
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        match socket.recv_from(&mut buffer) {
+            Ok((bytes_received, source)) => {
+                if let Ok(gateway) = parse_announcement(&buffer[..bytes_received], source) {
+                    let newer = gateways.get(&gateway.gateway_id)
+                        .map(|known| gateway.announced_ms >= known.announced_ms)
+                        .unwrap_or(true);
+                    if newer {
+                        gateways.insert(gateway.gateway_id, gateway);
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock
+                   || e.kind() == std::io::ErrorKind::TimedOut => break,
+            Err(e) => return Err(CyDnAError::IoError(e.kind())),
+        }
+    }
+    
+    let mut discovered: Vec<DiscoveredGateway> = gateways.into_values().collect();
+    discovered.sort_by_key(|gateway| gateway.gateway_id);
+    Ok(discovered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing::encode_frame;
+    
+    #[test]
+    fn test_collect_announcements() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let listener_addr = listener.local_addr().unwrap();
+        
+        let mut first = GatewayAnnouncer::new(2, 9000).unwrap().with_destination(listener_addr);
+        let mut second = GatewayAnnouncer::new(1, 9001).unwrap().with_destination(listener_addr);
+        
+        assert!(first.announce_if_due().unwrap());
+        assert!(!first.announce_if_due().unwrap());
+        second.announce().unwrap();
+        second.announce().unwrap();
+        
+        let gateways = collect_announcements(&listener, Duration::from_millis(100)).unwrap();
+        assert_eq!(gateways.len(), 2);
+        assert_eq!(gateways[0].gateway_id, 1);
+        assert_eq!(gateways[0].address, "127.0.0.1:9001".parse::<SocketAddr>().unwrap());
+        assert_eq!(gateways[1].protocol_version, crate::CYNDA_VERSION);
+        assert_eq!(gateways[1].compression_mask, local_compression_mask());
+    }
+    
+    #[test]
+    fn test_parse_rejects_other_messages() {
+        let source: SocketAddr = "10.0.0.5:47810".parse().unwrap();
+        let frame = encode_frame(MessageType::Heartbeat, &[0u8; 16]).unwrap();
+        
+        assert!(matches!(
+            parse_announcement(&frame, source),
+            Err(CyDnAError::UnexpectedMessageType { .. })
+        ));
+    }
+}
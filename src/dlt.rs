@@ -0,0 +1,637 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+use crate::ack_manager::AckManager;
+use crate::contracts::{compute_payload_hash, DLTTransactionRecord, DLT_SIGNING_INPUT_SIZE};
+use crate::errors::{CyDnAError, Result};
+
+pub const DEFAULT_DLT_BATCH_SIZE: usize = 32;
+
+pub const DEFAULT_DLT_MAX_ATTEMPTS: u32 = 5;
+
+pub const DEFAULT_DLT_RETRY_BASE_MS: u64 = 200;
+
+pub const DLT_RETRY_MAX_DELAY_MS: u64 = 10_000;
+
+// Receipts kept for idempotent resubmission; the oldest are forgotten first.
+pub const DEFAULT_RECEIPT_CACHE_SIZE: usize = 4096;
+
+// Records waiting for the ledger; pushes beyond this are refused so a ledger
+// outage cannot grow the gateway's memory without limit.
+pub const DEFAULT_DLT_MAX_QUEUE: usize = 16_384;
+
+// Permanently rejected records kept for inspection; the oldest are dropped.
+pub const DEFAULT_DEAD_LETTER_CAPACITY: usize = 256;
+
+// Stable identifier of a signed record, used as its idempotency key: the
+// hash of everything the signature covers plus the signature itself.
+pub fn record_id(record: &DLTTransactionRecord) -> [u8; 32] {
+    let mut bytes = [0u8; DLT_SIGNING_INPUT_SIZE + 64];
+    bytes[..DLT_SIGNING_INPUT_SIZE].copy_from_slice(&record.signing_bytes());
+    bytes[DLT_SIGNING_INPUT_SIZE..].copy_from_slice(&record.gateway_signature);
+    
+    compute_payload_hash(&bytes)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnchorReceipt {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::hex_bytes"))]
+    pub record_id: [u8; 32],
+    
+    // Ledger-specific reference, e.g. a message or transaction id.
+    pub anchor_id: String,
+    
+    pub anchored_at_ms: u64,
+}
+
+// A ledger or anchoring service. Implementations anchor a whole batch or
+// fail it as a whole, return receipts in batch order, and must treat a
+// record they have already anchored as a success with its original receipt.
+pub trait DltSubmitter {
+    fn submit(&mut self, batch: &[DLTTransactionRecord]) -> Result<Vec<AnchorReceipt>>;
+}
+
+// Rejections other than overload are final; retrying the same batch would
+// only be rejected again.
+pub fn is_transient(error: &CyDnAError) -> bool {
+    match error {
+        CyDnAError::IoError(_) => true,
+        CyDnAError::AnchorRejected(status) => *status == 429 || *status >= 500,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubmitterMetrics {
+    pub batches: u64,
+    
+    pub records_anchored: u64,
+    
+    pub retries: u64,
+    
+    pub duplicates_skipped: u64,
+    
+    pub failed_batches: u64,
+    
+    pub dead_lettered: u64,
+    
+    pub dead_letters_dropped: u64,
+    
+    pub queue_full: u64,
+}
+
+// A record the ledger refused on its own with a non-transient error.
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub record: DLTTransactionRecord,
+    
+    pub error: CyDnAError,
+}
+
+// Queues records, submits them in batches with retry and backoff, and
+// answers resubmissions of anything already anchored from its receipt cache.
+// A batch refused with a non-transient error is resubmitted one record at a
+// time so a single bad record is dead-lettered instead of blocking the queue.
+pub struct BatchingSubmitter<S: DltSubmitter> {
+    submitter: S,
+    queue: Vec<DLTTransactionRecord>,
+    queued_ids: HashSet<[u8; 32]>,
+    max_queue: usize,
+    // Records still to be submitted singly after a batch was refused.
+    isolating: usize,
+    dead_letters: VecDeque<DeadLetter>,
+    dead_letter_capacity: usize,
+    batch_size: usize,
+    max_attempts: u32,
+    retry_base_ms: u64,
+    receipts: HashMap<[u8; 32], AnchorReceipt>,
+    receipt_order: VecDeque<[u8; 32]>,
+    receipt_cache_size: usize,
+    metrics: SubmitterMetrics,
+}
+
+impl<S: DltSubmitter> BatchingSubmitter<S> {
+    pub fn new(submitter: S) -> Self {
+        Self {
+            submitter,
+            queue: Vec::new(),
+            queued_ids: HashSet::new(),
+            max_queue: DEFAULT_DLT_MAX_QUEUE,
+            isolating: 0,
+            dead_letters: VecDeque::new(),
+            dead_letter_capacity: DEFAULT_DEAD_LETTER_CAPACITY,
+            batch_size: DEFAULT_DLT_BATCH_SIZE,
+            max_attempts: DEFAULT_DLT_MAX_ATTEMPTS,
+            retry_base_ms: DEFAULT_DLT_RETRY_BASE_MS,
+            receipts: HashMap::new(),
+            receipt_order: VecDeque::new(),
+            receipt_cache_size: DEFAULT_RECEIPT_CACHE_SIZE,
+            metrics: SubmitterMetrics::default(),
+        }
+    }
+    
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+    
+    pub fn with_retry_policy(mut self, max_attempts: u32, retry_base_ms: u64) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self.retry_base_ms = retry_base_ms;
+        self
+    }
+    
+    pub fn with_receipt_cache_size(mut self, size: usize) -> Self {
+        self.receipt_cache_size = size;
+        self
+    }
+    
+    pub fn with_max_queue(mut self, max_queue: usize) -> Self {
+        self.max_queue = max_queue.max(1);
+        self
+    }
+    
+    pub fn with_dead_letter_capacity(mut self, capacity: usize) -> Self {
+        self.dead_letter_capacity = capacity;
+        self
+    }
+    
+    pub fn submitter(&self) -> &S {
+        &self.submitter
+    }
+    
+    pub fn metrics(&self) -> SubmitterMetrics {
+        self.metrics
+    }
+    
+    pub fn pending_count(&self) -> usize {
+        self.queue.len()
+    }
+    
+    pub fn receipt(&self, record_id: &[u8; 32]) -> Option<&AnchorReceipt> {
+        self.receipts.get(record_id)
+    }
+    
+    pub fn dead_letter_count(&self) -> usize {
+        self.dead_letters.len()
+    }
+    
+    pub fn take_dead_letters(&mut self) -> Vec<DeadLetter> {
+        self.dead_letters.drain(..).collect()
+    }
+    
+    // Queues the record and submits once a full batch is waiting. Returns the
+    // receipts that became available: the cached one for an already anchored
+    // record, or those of a batch flushed by this call. Fails with
+    // `QueueFull` while the ledger is too far behind to take more.
+    pub fn push(&mut self, record: DLTTransactionRecord) -> Result<Vec<AnchorReceipt>> {
+        let id = record_id(&record);
+        
+        if let Some(receipt) = self.receipts.get(&id) {
+            self.metrics.duplicates_skipped += 1;
+            return Ok(vec![receipt.clone()]);
+        }
+        
+        if self.queued_ids.contains(&id) {
+            self.metrics.duplicates_skipped += 1;
+            return Ok(Vec::new());
+        }
+        
+        if self.queue.len() >= self.max_queue {
+            self.metrics.queue_full += 1;
+            return Err(CyDnAError::QueueFull(self.max_queue));
+        }
+        
+        self.queued_ids.insert(id);
+        
+        self.queue.push(record);
+        if self.queue.len() < self.batch_size {
+            return Ok(Vec::new());
+        }
+        
+        self.flush()
+    }
+    
+    // Submits everything queued. A transient failure that outlasts the retries
+    // leaves the failed batch and every later one queued for the next flush;
+    // a permanent rejection dead-letters the offending record and carries on.
+    // Receipts of the batches that did succeed are cached either way.
+    pub fn flush(&mut self) -> Result<Vec<AnchorReceipt>> {
+        let mut anchored = Vec::new();
+        
+        while !self.queue.is_empty() {
+            let batch_len = match self.isolating {
+                0 => self.queue.len().min(self.batch_size),
+                _ => 1,
+            };
+            let receipts = match self.submit_with_retry(batch_len) {
+                Ok(receipts) => receipts,
+                Err(e) if is_transient(&e) => {
+                    self.metrics.failed_batches += 1;
+                    return Err(e);
+                }
+                Err(e) => {
+                    self.metrics.failed_batches += 1;
+                    if batch_len > 1 {
+                        self.isolating = batch_len;
+                    } else {
+                        self.dead_letter(e);
+                    }
+                    continue;
+                }
+            };
+            
+            self.isolating = self.isolating.saturating_sub(batch_len);
+            for record in self.queue.drain(..batch_len) {
+                self.queued_ids.remove(&record_id(&record));
+            }
+            
+            self.metrics.batches += 1;
+            self.metrics.records_anchored += receipts.len() as u64;
+            for receipt in &receipts {
+                self.cache_receipt(receipt.clone());
+            }
+            anchored.extend(receipts);
+        }
+        
+        Ok(anchored)
+    }
+    
+    fn submit_with_retry(&mut self, batch_len: usize) -> Result<Vec<AnchorReceipt>> {
+        let batch = &self.queue[..batch_len];
+        let mut attempt = 0;
+        
+        loop {
+            let error = match self.submitter.submit(batch) {
+                Ok(receipts) if Self::receipts_match(batch, &receipts) => return Ok(receipts),
+                Ok(_) => CyDnAError::DeserializationError("Anchor receipts do not match the batch"),
+                Err(e) => e,
+            };
+            
+            attempt += 1;
+            if attempt >= self.max_attempts || !is_transient(&error) {
+                return Err(error);
+            }
+            
+            self.metrics.retries += 1;
+            let delay_ms = AckManager::calculate_backoff_ms(attempt - 1, self.retry_base_ms, DLT_RETRY_MAX_DELAY_MS);
+            std::thread::sleep(Duration::from_millis(delay_ms));
+        }
+    }
+    
+    fn dead_letter(&mut self, error: CyDnAError) {
+        let record = self.queue.remove(0);
+        self.queued_ids.remove(&record_id(&record));
+        self.isolating = self.isolating.saturating_sub(1);
+        self.metrics.dead_lettered += 1;
+        
+        if self.dead_letter_capacity == 0 {
+            self.metrics.dead_letters_dropped += 1;
+            return;
+        }
+        if self.dead_letters.len() == self.dead_letter_capacity {
+            self.dead_letters.pop_front();
+            self.metrics.dead_letters_dropped += 1;
+        }
+        self.dead_letters.push_back(DeadLetter { record, error });
+    }
+    
+    fn receipts_match(batch: &[DLTTransactionRecord], receipts: &[AnchorReceipt]) -> bool {
+        batch.len() == receipts.len()
+            && batch.iter().zip(receipts).all(|(record, receipt)| record_id(record) == receipt.record_id)
+    }
+    
+    fn cache_receipt(&mut self, receipt: AnchorReceipt) {
+        if self.receipt_cache_size == 0 {
+            return;
+        }
+        
+        if self.receipt_order.len() == self.receipt_cache_size {
+            if let Some(oldest) = self.receipt_order.pop_front() {
+                self.receipts.remove(&oldest);
+            }
+        }
+        
+        self.receipt_order.push_back(receipt.record_id);
+        self.receipts.insert(receipt.record_id, receipt);
+    }
+}
+
+#[cfg(feature = "serde")]
+pub use http::HttpAnchorSubmitter;
+
+// Generic HTTP anchoring service: POSTs `{"records": [...]}` as JSON and
+// expects `{"receipts": [...]}` back in the same order. The batch's
+// `Idempotency-Key` header lets the service recognise a retried request.
+#[cfg(feature = "serde")]
+mod http {
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::time::Duration;
+    
+    use super::{record_id, AnchorReceipt, DltSubmitter};
+    use crate::contracts::{compute_payload_hash, DLTTransactionRecord};
+    use crate::errors::{CyDnAError, Result};
+    
+    pub const DEFAULT_ANCHOR_TIMEOUT_MS: u64 = 5_000;
+    
+    #[derive(serde::Serialize)]
+    struct AnchorRequest<'a> {
+        records: &'a [DLTTransactionRecord],
+    }
+    
+    #[derive(serde::Deserialize)]
+    struct AnchorResponse {
+        receipts: Vec<AnchorReceipt>,
+    }
+    
+    pub struct HttpAnchorSubmitter {
+        address: SocketAddr,
+        host: String,
+        path: String,
+        timeout: Duration,
+    }
+    
+    impl HttpAnchorSubmitter {
+        // Plain `http://host[:port]/path` only; put a TLS-terminating proxy in
+        // front of services that require HTTPS.
+        pub fn new(url: &str) -> Result<Self> {
+            let invalid = CyDnAError::IoError(std::io::ErrorKind::InvalidInput);
+            let rest = url.strip_prefix("http://").ok_or(invalid)?;
+            let (host, path) = match rest.find('/') {
+                Some(index) => (&rest[..index], &rest[index..]),
+                None => (rest, "/"),
+            };
+            
+            if host.is_empty() {
+                return Err(invalid);
+            }
+            
+            let address = if host.contains(':') && !host.ends_with(']') {
+                crate::socket::resolve(host)?
+            } else {
+                crate::socket::resolve((host.trim_start_matches('[').trim_end_matches(']'), 80))?
+            };
+            
+            Ok(Self {
+                address,
+                host: host.to_string(),
+                path: path.to_string(),
+                timeout: Duration::from_millis(DEFAULT_ANCHOR_TIMEOUT_MS),
+            })
+        }
+        
+        pub fn with_timeout_ms(mut self, timeout_ms: u64) -> Self {
+            self.timeout = Duration::from_millis(timeout_ms.max(1));
+            self
+        }
+        
+        pub fn address(&self) -> SocketAddr {
+            self.address
+        }
+        
+        fn idempotency_key(batch: &[DLTTransactionRecord]) -> String {
+            let ids: Vec<u8> = batch.iter().flat_map(record_id).collect();
+            compute_payload_hash(&ids).iter().map(|byte| format!("{:02x}", byte)).collect()
+        }
+        
+        fn post(&self, body: &[u8], idempotency_key: &str) -> std::io::Result<Vec<u8>> {
+            let mut stream = TcpStream::connect_timeout(&self.address, self.timeout)?;
+            stream.set_read_timeout(Some(self.timeout))?;
+            stream.set_write_timeout(Some(self.timeout))?;
+            
+            // HTTP/1.0 so the response is never chunked and ends at EOF.
+            write!(
+                stream,
+                "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\n\
+                 Idempotency-Key: {}\r\nContent-Length: {}\r\n\r\n",
+                self.path,
+                self.host,
+                idempotency_key,
+                body.len(),
+            )?;
+            stream.write_all(body)?;
+            
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response)?;
+            Ok(response)
+        }
+        
+        fn parse_response(response: &[u8]) -> Result<Vec<AnchorReceipt>> {
+            let malformed = CyDnAError::DeserializationError("Malformed anchor response");
+            
+            let header_end = response.windows(4).position(|window| window == b"\r\n\r\n").ok_or(malformed)?;
+            let head = std::str::from_utf8(&response[..header_end]).map_err(|_| malformed)?;
+            let status: u16 = head.split_whitespace().nth(1).and_then(|code| code.parse().ok()).ok_or(malformed)?;
+            
+            if !(200..300).contains(&status) {
+                return Err(CyDnAError::AnchorRejected(status));
+            }
+            
+            let body: AnchorResponse = serde_json::from_slice(&response[header_end + 4..]).map_err(|_| malformed)?;
+            Ok(body.receipts)
+        }
+    }
+    
+    impl DltSubmitter for HttpAnchorSubmitter {
+        fn submit(&mut self, batch: &[DLTTransactionRecord]) -> Result<Vec<AnchorReceipt>> {
+            let body = serde_json::to_vec(&AnchorRequest { records: batch })
+                .map_err(|_| CyDnAError::SerializationError("Failed to encode anchor request"))?;
+            
+            let response = self.post(&body, &Self::idempotency_key(batch))
+                .map_err(|e| CyDnAError::IoError(e.kind()))?;
+            
+            Self::parse_response(&response)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+    
+    // Fails the first `failures` calls with `error`, then anchors everything
+    // except batches holding the poisoned record, which it always refuses.
+    struct FlakyLedger {
+        failures: u32,
+        error: CyDnAError,
+        poisoned: Option<[u8; 32]>,
+        calls: u32,
+        anchored: u64,
+    }
+    
+    impl DltSubmitter for FlakyLedger {
+        fn submit(&mut self, batch: &[DLTTransactionRecord]) -> Result<Vec<AnchorReceipt>> {
+            self.calls += 1;
+            if self.calls <= self.failures {
+                return Err(self.error);
+            }
+            if batch.iter().any(|record| Some(record_id(record)) == self.poisoned) {
+                return Err(CyDnAError::AnchorRejected(400));
+            }
+            
+            Ok(batch.iter().map(|record| {
+                self.anchored += 1;
+                AnchorReceipt {
+                    record_id: record_id(record),
+                    anchor_id: format!("tx-{}", self.anchored),
+                    anchored_at_ms: 1_000 + self.anchored,
+                }
+            }).collect())
+        }
+    }
+    
+    fn record(gateway_id: u32, payload: &[u8]) -> DLTTransactionRecord {
+        DLTTransactionRecord::builder()
+            .with_payload_bytes(payload)
+            .with_gateway_id(gateway_id)
+            .with_anomaly_score(0.5)
+            .build(&SigningKey::from_bytes(&[7u8; 32]))
+            .unwrap()
+    }
+    
+    fn ledger(failures: u32, error: CyDnAError) -> FlakyLedger {
+        FlakyLedger { failures, error, poisoned: None, calls: 0, anchored: 0 }
+    }
+    
+    #[test]
+    fn test_batches_retries_and_idempotency() {
+        let mut submitter = BatchingSubmitter::new(ledger(2, CyDnAError::AnchorRejected(503)))
+            .with_batch_size(3)
+            .with_retry_policy(3, 1);
+        
+        assert!(submitter.push(record(1, b"a")).unwrap().is_empty());
+        assert!(submitter.push(record(1, b"a")).unwrap().is_empty());
+        assert!(submitter.push(record(1, b"b")).unwrap().is_empty());
+        assert_eq!(submitter.pending_count(), 2);
+        
+        let receipts = submitter.push(record(1, b"c")).unwrap();
+        assert_eq!(receipts.len(), 3);
+        assert_eq!(receipts[0].record_id, record_id(&record(1, b"a")));
+        assert_eq!(submitter.pending_count(), 0);
+        
+        let cached = submitter.push(record(1, b"b")).unwrap();
+        assert_eq!(cached, vec![receipts[1].clone()]);
+        
+        let metrics = submitter.metrics();
+        assert_eq!((metrics.batches, metrics.records_anchored, metrics.retries), (1, 3, 2));
+        assert_eq!(metrics.duplicates_skipped, 2);
+        assert_eq!(submitter.submitter().calls, 3);
+    }
+    
+    #[test]
+    fn test_transient_failure_keeps_records_queued() {
+        let mut submitter = BatchingSubmitter::new(ledger(2, CyDnAError::AnchorRejected(503)))
+            .with_retry_policy(2, 1)
+            .with_max_queue(2);
+        
+        submitter.push(record(2, b"x")).unwrap();
+        submitter.push(record(2, b"y")).unwrap();
+        assert_eq!(submitter.push(record(2, b"z")), Err(CyDnAError::QueueFull(2)));
+        assert_eq!(submitter.flush(), Err(CyDnAError::AnchorRejected(503)));
+        assert_eq!(submitter.pending_count(), 2);
+        assert_eq!(submitter.metrics().failed_batches, 1);
+        
+        assert_eq!(submitter.flush().unwrap().len(), 2);
+        assert!(submitter.receipt(&record_id(&record(2, b"x"))).is_some());
+        assert_eq!(submitter.metrics().queue_full, 1);
+        assert!(is_transient(&CyDnAError::IoError(std::io::ErrorKind::TimedOut)));
+        assert!(!is_transient(&CyDnAError::SignatureVerificationFailed));
+    }
+    
+    #[test]
+    fn test_permanent_rejection_dead_letters_only_the_bad_record() {
+        let mut ledger = ledger(0, CyDnAError::AnchorRejected(503));
+        ledger.poisoned = Some(record_id(&record(3, b"bad")));
+        let mut submitter = BatchingSubmitter::new(ledger)
+            .with_batch_size(4)
+            .with_retry_policy(5, 1)
+            .with_dead_letter_capacity(1);
+        
+        for payload in [&b"a"[..], b"bad", b"b", b"c", b"d"] {
+            submitter.push(record(3, payload)).unwrap();
+        }
+        let receipts = submitter.flush().unwrap();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(submitter.pending_count(), 0);
+        
+        // The refused batch of four is resubmitted singly: three anchored, one
+        // dead-lettered. The fifth record then goes out as a normal batch.
+        assert!(["a", "b", "c", "d"].iter().all(|p| submitter.receipt(&record_id(&record(3, p.as_bytes()))).is_some()));
+        let dead = submitter.take_dead_letters();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(record_id(&dead[0].record), record_id(&record(3, b"bad")));
+        assert_eq!(dead[0].error, CyDnAError::AnchorRejected(400));
+        
+        let metrics = submitter.metrics();
+        assert_eq!((metrics.dead_lettered, metrics.records_anchored, metrics.retries), (1, 4, 0));
+        assert_eq!(submitter.submitter().calls, 1 + 4 + 1);
+    }
+    
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_http_anchor_roundtrip() {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+        
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/anchor", listener.local_addr().unwrap());
+        
+        let server = std::thread::spawn(move || {
+            let mut statuses = vec!["503 Service Unavailable", "200 OK"].into_iter();
+            let mut keys = Vec::new();
+            
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let (mut content_length, mut line) = (0, String::new());
+                
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    let lower = line.to_ascii_lowercase();
+                    if let Some(value) = lower.strip_prefix("content-length:") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                    if let Some(value) = lower.strip_prefix("idempotency-key:") {
+                        keys.push(value.trim().to_string());
+                    }
+                    line.clear();
+                }
+                
+                let mut body = vec![0u8; content_length];
+                reader.read_exact(&mut body).unwrap();
+                let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                let receipts: Vec<_> = request["records"].as_array().unwrap().iter()
+                    .map(|record| {
+                        let record = DLTTransactionRecord::from_json(&record.to_string()).unwrap();
+                        AnchorReceipt { record_id: record_id(&record), anchor_id: "msg-1".into(), anchored_at_ms: 9 }
+                    })
+                    .collect();
+                
+                let body = serde_json::json!({ "receipts": receipts }).to_string();
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n{}",
+                    statuses.next().unwrap(),
+                    body.len(),
+                    body,
+                ).unwrap();
+            }
+            keys
+        });
+        
+        let mut submitter = BatchingSubmitter::new(HttpAnchorSubmitter::new(&url).unwrap())
+            .with_retry_policy(2, 1);
+        submitter.push(record(3, b"http")).unwrap();
+        let receipts = submitter.flush().unwrap();
+        
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].anchor_id, "msg-1");
+        
+        let keys = server.join().unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0], keys[1]);
+        assert!(HttpAnchorSubmitter::new("https://ledger.example/anchor").is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use aes_gcm::aead::{AeadInPlace, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce, Tag};
+use rand::RngCore;
+
+use crate::errors::{CyDnAError, Result};
+
+pub const KEY_SIZE: usize = 32;
+
+pub const NONCE_SIZE: usize = 12;
+
+pub const TAG_SIZE: usize = 16;
+
+pub const ENVELOPE_HEADER_SIZE: usize = 4 + NONCE_SIZE;
+
+pub const ENVELOPE_OVERHEAD: usize = ENVELOPE_HEADER_SIZE + TAG_SIZE;
+
+// Envelope: device_id (u32 LE, also the AAD) | nonce | ciphertext | tag.
+// Nonce = random per-instance prefix + per-device 64-bit counter.
+// During a key rotation a device may also have a previous key, which is
+// still accepted by `open` but never used to seal.
+pub struct PayloadCipher {
+    keys: HashMap<u32, Aes256Gcm>,
+    previous_keys: HashMap<u32, Aes256Gcm>,
+    nonce_counters: HashMap<u32, u64>,
+    nonce_prefix: [u8; 4],
+}
+
+impl PayloadCipher {
+    pub fn new() -> Self {
+        let mut nonce_prefix = [0u8; 4];
+        rand::thread_rng().fill_bytes(&mut nonce_prefix);
+        
+        Self {
+            keys: HashMap::new(),
+            previous_keys: HashMap::new(),
+            nonce_counters: HashMap::new(),
+            nonce_prefix,
+        }
+    }
+    
+    pub fn add_device_key(&mut self, device_id: u32, key: [u8; KEY_SIZE]) {
+        self.keys.insert(device_id, Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)));
+        self.previous_keys.remove(&device_id);
+        self.nonce_counters.insert(device_id, 0);
+    }
+    
+    // Replaces the device's keys without resetting its nonce counter: a
+    // rollback may make an earlier key current again, and its nonces must
+    // not repeat.
+    pub fn set_device_keys(&mut self, device_id: u32, current: [u8; KEY_SIZE], previous: Option<[u8; KEY_SIZE]>) {
+        self.keys.insert(device_id, Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&current)));
+        match previous {
+            Some(key) => self.previous_keys.insert(device_id, Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))),
+            None => self.previous_keys.remove(&device_id),
+        };
+        self.nonce_counters.entry(device_id).or_insert(0);
+    }
+    
+    pub fn remove_device_key(&mut self, device_id: u32) -> bool {
+        self.nonce_counters.remove(&device_id);
+        self.previous_keys.remove(&device_id);
+        self.keys.remove(&device_id).is_some()
+    }
+    
+    pub fn has_device_key(&self, device_id: u32) -> bool {
+        self.keys.contains_key(&device_id)
+    }
+    
+    fn next_nonce(&mut self, device_id: u32) -> Result<[u8; NONCE_SIZE]> {
+        let counter = self.nonce_counters.entry(device_id).or_insert(0);
+        let current = *counter;
+        *counter = current.checked_add(1)
+            .ok_or(CyDnAError::NonceExhausted(device_id))?;
+        
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce[..4].copy_from_slice(&self.nonce_prefix);
+        nonce[4..].copy_from_slice(&current.to_be_bytes());
+        Ok(nonce)
+    }
+    
+    pub fn seal(&mut self, device_id: u32, plaintext: &[u8]) -> Result<Vec<u8>> {
+        if !self.keys.contains_key(&device_id) {
+            return Err(CyDnAError::UnknownDeviceKey(device_id));
+        }
+        
+        let nonce = self.next_nonce(device_id)?;
+        let aad = device_id.to_le_bytes();
+        
+        let mut envelope = Vec::with_capacity(plaintext.len() + ENVELOPE_OVERHEAD);
+        envelope.extend_from_slice(&aad);
+        envelope.extend_from_slice(&nonce);
+        envelope.extend_from_slice(plaintext);
+        
+        let tag = self.keys[&device_id]
+            .encrypt_in_place_detached(
+                Nonce::from_slice(&nonce),
+                &aad,
+                &mut envelope[ENVELOPE_HEADER_SIZE..],
+            )
+            .map_err(|_| CyDnAError::EncryptionError(
+                "AES-GCM encryption failed"
+            ))?;
+        
+        envelope.extend_from_slice(&tag);
+        Ok(envelope)
+    }
+    
+    pub fn open<'a>(&self, envelope: &'a mut [u8]) -> Result<(u32, &'a [u8])> {
+        if envelope.len() < ENVELOPE_OVERHEAD {
+            return Err(CyDnAError::InvalidPacketLength {
+                expected: ENVELOPE_OVERHEAD,
+                received: envelope.len(),
+            });
+        }
+        
+        let mut aad = [0u8; 4];
+        aad.copy_from_slice(&envelope[..4]);
+        let device_id = u32::from_le_bytes(aad);
+        
+        let cipher = self.keys.get(&device_id)
+            .ok_or(CyDnAError::UnknownDeviceKey(device_id))?;
+        
+        let (header, body) = envelope.split_at_mut(ENVELOPE_HEADER_SIZE);
+        let (ciphertext, tag) = body.split_at_mut(body.len() - TAG_SIZE);
+        let nonce = Nonce::from_slice(&header[4..]);
+        let tag = Tag::from_slice(tag);
+        
+        // A failed attempt leaves the buffer scrambled, so the previous key
+        // needs its own copy of the ciphertext.
+        let previous = self.previous_keys.get(&device_id);
+        let sealed = previous.map(|_| ciphertext.to_vec());
+        
+        if cipher.decrypt_in_place_detached(nonce, &aad, ciphertext, tag).is_err() {
+            let (Some(previous), Some(sealed)) = (previous, sealed) else {
+                return Err(CyDnAError::DecryptionFailed(device_id));
+            };
+            
+            ciphertext.copy_from_slice(&sealed);
+            previous.decrypt_in_place_detached(nonce, &aad, ciphertext, tag)
+                .map_err(|_| CyDnAError::DecryptionFailed(device_id))?;
+        }
+        
+        Ok((device_id, ciphertext))
+    }
+}
+
+impl Default for PayloadCipher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_seal_open_roundtrip() {
+        let mut cipher = PayloadCipher::new();
+        cipher.add_device_key(9, [7u8; KEY_SIZE]);
+        
+        let mut envelope = cipher.seal(9, b"anomaly vector").unwrap();
+        assert_eq!(envelope.len(), 14 + ENVELOPE_OVERHEAD);
+        
+        let (device_id, plaintext) = cipher.open(&mut envelope).unwrap();
+        assert_eq!(device_id, 9);
+        assert_eq!(plaintext, b"anomaly vector");
+    }
+    
+    #[test]
+    fn test_nonces_are_unique() {
+        let mut cipher = PayloadCipher::new();
+        cipher.add_device_key(1, [1u8; KEY_SIZE]);
+        
+        let first = cipher.seal(1, b"same").unwrap();
+        let second = cipher.seal(1, b"same").unwrap();
+        assert_ne!(first[4..ENVELOPE_HEADER_SIZE], second[4..ENVELOPE_HEADER_SIZE]);
+        assert_ne!(first, second);
+    }
+    
+    #[test]
+    fn test_tampered_envelope_rejected() {
+        let mut cipher = PayloadCipher::new();
+        cipher.add_device_key(1, [1u8; KEY_SIZE]);
+        
+        let mut envelope = cipher.seal(1, b"payload").unwrap();
+        envelope[ENVELOPE_HEADER_SIZE] ^= 0x01;
+        assert!(matches!(cipher.open(&mut envelope), Err(CyDnAError::DecryptionFailed(1))));
+        
+        let mut other = PayloadCipher::new();
+        other.add_device_key(2, [1u8; KEY_SIZE]);
+        let mut envelope = cipher.seal(1, b"payload").unwrap();
+        assert!(matches!(other.open(&mut envelope), Err(CyDnAError::UnknownDeviceKey(1))));
+    }
+}
@@ -3,13 +3,15 @@ use std::io;
 
 pub type Result<T> = std::result::Result<T, CyDnAError>;
 
-#[derive(Debug, Clone)]
+// Every variant is Copy so failures on the receive path never allocate;
+// messages are static and the numbers keep their original context.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CyDnAError {
-    IoError(String),
+    IoError(io::ErrorKind),
     
-    SerializationError(String),
+    SerializationError(&'static str),
     
-    DeserializationError(String),
+    DeserializationError(&'static str),
     
     IntegrityCheckFailed { expected: u32, actual: u32 },
     
@@ -30,12 +32,75 @@ pub enum CyDnAError {
     InvalidGatewayId(u32),
     
     BufferTooSmall { required: usize, available: usize },
+    
+    EncryptionError(&'static str),
+    
+    DecryptionFailed(u32),
+    
+    UnknownDeviceKey(u32),
+    
+    InvalidFrameMagic([u8; 2]),
+    
+    UnsupportedVersion { expected: u16, received: u16 },
+    
+    UnknownMessageType(u8),
+    
+    UnexpectedMessageType { expected: u8, received: u8 },
+    
+    Cancelled,
+    
+    PayloadRejected(crate::contracts::NackReason),
+    
+    ReplayDetected { device_id: u32, sequence_number: u32 },
+    
+    AuthenticationFailed(u32),
+    
+    HandshakeFailed(&'static str),
+    
+    RateLimited(u32),
+    
+    DeviceNotAllowed(u32),
+    
+    NoGatewayDiscovered,
+    
+    UnsupportedCompression(u8),
+    
+    CompressionError(&'static str),
+    
+    InvalidVectorLength(usize),
+    
+    UnknownRawData(u32),
+    
+    InvalidRawDataChunk(u16),
+    
+    UnsupportedWireFormat(u8),
+    
+    NonceExhausted(u32),
+    
+    InvalidConsensusMode(u8),
+    
+    UnknownVectorEncoding(u8),
+    
+    AnchorRejected(u16),
+    
+    InvalidControlCommand(u8),
+    
+    ValidationFailed(&'static str),
+    
+    KeyRotationInProgress(u32),
+    
+    QueueFull(usize),
+    
+    InvalidConfig(&'static str),
+    
+    // Older than the replay window, so it can no longer be told from a replay.
+    PayloadTooOld { timestamp_ms: u64, max_age_ms: u64 },
 }
 
 impl fmt::Display for CyDnAError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::IoError(msg) => write!(f, "I/O error: {}", msg),
+            Self::IoError(kind) => write!(f, "I/O error: {}", kind),
             Self::SerializationError(msg) => write!(f, "Serialization error: {}", msg),
             Self::DeserializationError(msg) => write!(f, "Deserialization error: {}", msg),
             Self::IntegrityCheckFailed { expected, actual } => {
@@ -56,6 +121,101 @@ impl fmt::Display for CyDnAError {
             Self::BufferTooSmall { required, available } => {
                 write!(f, "Buffer too small: required {}, available {}", required, available)
             }
+            Self::EncryptionError(msg) => write!(f, "Encryption error: {}", msg),
+            Self::DecryptionFailed(id) => write!(f, "Decryption failed for device {}", id),
+            Self::UnknownDeviceKey(id) => write!(f, "No encryption key for device {}", id),
+            Self::InvalidFrameMagic(magic) => write!(f, "Invalid frame magic: {:02x?}", magic),
+            Self::UnsupportedVersion { expected, received } => {
+                write!(f, "Unsupported protocol version: expected {}, received {}", expected, received)
+            }
+            Self::UnknownMessageType(kind) => write!(f, "Unknown message type: {}", kind),
+            Self::UnexpectedMessageType { expected, received } => {
+                write!(f, "Unexpected message type: expected {}, received {}", expected, received)
+            }
+            Self::Cancelled => write!(f, "Operation cancelled"),
+            Self::PayloadRejected(reason) => write!(f, "Payload rejected by gateway: {:?}", reason),
+            Self::ReplayDetected { device_id, sequence_number } => {
+                write!(f, "Replay detected: device {} sequence {}", device_id, sequence_number)
+            }
+            Self::AuthenticationFailed(id) => write!(f, "Datagram authentication failed for device {}", id),
+            Self::HandshakeFailed(msg) => write!(f, "Session handshake failed: {}", msg),
+            Self::RateLimited(id) => write!(f, "Device {} exceeded its rate limit", id),
+            Self::DeviceNotAllowed(id) => write!(f, "Device {} is not on the access list", id),
+            Self::NoGatewayDiscovered => write!(f, "No gateway announced itself before the timeout"),
+            Self::UnsupportedCompression(codec) => write!(f, "Unsupported compression codec: {}", codec),
+            Self::CompressionError(msg) => write!(f, "Compression error: {}", msg),
+            Self::InvalidVectorLength(len) => write!(f, "Invalid anomaly vector length: {}", len),
+            Self::UnknownRawData(crc) => write!(f, "No raw data block with CRC32 {:#x}", crc),
+            Self::InvalidRawDataChunk(index) => write!(f, "Invalid raw data chunk {}", index),
+            Self::UnsupportedWireFormat(format) => write!(f, "Unsupported wire format: {}", format),
+            Self::NonceExhausted(id) => write!(f, "Nonce space exhausted for device {}", id),
+            Self::InvalidConsensusMode(mode) => write!(f, "Invalid consensus mode: {}", mode),
+            Self::UnknownVectorEncoding(kind) => write!(f, "Unknown vector encoding: {}", kind),
+            Self::AnchorRejected(status) => write!(f, "DLT anchor rejected the batch with status {}", status),
+            Self::InvalidControlCommand(kind) => write!(f, "Invalid control command of kind {}", kind),
+            Self::ValidationFailed(msg) => write!(f, "Payload failed validation: {}", msg),
+            Self::KeyRotationInProgress(id) => write!(f, "Key rotation already in progress for device {}", id),
+            Self::QueueFull(capacity) => write!(f, "Queue full at {} entries", capacity),
+            Self::InvalidConfig(msg) => write!(f, "Invalid configuration: {}", msg),
+            Self::PayloadTooOld { timestamp_ms, max_age_ms } => write!(
+                f,
+                "Payload from {} is older than the {} ms replay window",
+                timestamp_ms, max_age_ms
+            ),
+        }
+    }
+}
+
+// Stable numeric codes for logs and FFI. The hundreds digit is the category:
+// 1 transport, 2 framing/encoding, 3 payload validation, 4 security, 5 flow
+// control. Codes are never reused or renumbered.
+impl CyDnAError {
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::IoError(_) => 100,
+            Self::AckTimeout => 101,
+            Self::MaxRetriesExceeded => 102,
+            Self::Cancelled => 103,
+            Self::NoGatewayDiscovered => 104,
+            Self::AnchorRejected(_) => 105,
+            Self::SerializationError(_) => 200,
+            Self::DeserializationError(_) => 201,
+            Self::InvalidPacketLength { .. } => 202,
+            Self::BufferTooSmall { .. } => 203,
+            Self::InvalidFrameMagic(_) => 204,
+            Self::UnsupportedVersion { .. } => 205,
+            Self::UnknownMessageType(_) => 206,
+            Self::UnexpectedMessageType { .. } => 207,
+            Self::UnsupportedCompression(_) => 208,
+            Self::CompressionError(_) => 209,
+            Self::UnsupportedWireFormat(_) => 210,
+            Self::InvalidVectorLength(_) => 211,
+            Self::InvalidRawDataChunk(_) => 212,
+            Self::UnknownVectorEncoding(_) => 213,
+            Self::IntegrityCheckFailed { .. } => 300,
+            Self::PayloadExpired { .. } => 301,
+            Self::InvalidDeviceId(_) => 302,
+            Self::InvalidBatteryLevel(_) => 303,
+            Self::InvalidGatewayId(_) => 304,
+            Self::ReplayDetected { .. } => 305,
+            Self::UnknownRawData(_) => 306,
+            Self::InvalidConsensusMode(_) => 307,
+            Self::InvalidControlCommand(_) => 308,
+            Self::ValidationFailed(_) => 309,
+            Self::InvalidConfig(_) => 310,
+            Self::PayloadTooOld { .. } => 311,
+            Self::SignatureVerificationFailed => 400,
+            Self::EncryptionError(_) => 401,
+            Self::DecryptionFailed(_) => 402,
+            Self::UnknownDeviceKey(_) => 403,
+            Self::AuthenticationFailed(_) => 404,
+            Self::HandshakeFailed(_) => 405,
+            Self::DeviceNotAllowed(_) => 406,
+            Self::NonceExhausted(_) => 407,
+            Self::KeyRotationInProgress(_) => 408,
+            Self::PayloadRejected(_) => 500,
+            Self::RateLimited(_) => 501,
+            Self::QueueFull(_) => 502,
         }
     }
 }
@@ -64,6 +224,30 @@ impl std::error::Error for CyDnAError {}
 
 impl From<io::Error> for CyDnAError {
     fn from(err: io::Error) -> Self {
-        Self::IoError(err.to_string())
+        Self::IoError(err.kind())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::NackReason;
+    
+    #[test]
+    fn test_error_codes_are_stable_and_distinct() {
+        let errors = [
+            CyDnAError::IoError(io::ErrorKind::TimedOut),
+            CyDnAError::DeserializationError("bad"),
+            CyDnAError::IntegrityCheckFailed { expected: 1, actual: 2 },
+            CyDnAError::SignatureVerificationFailed,
+            CyDnAError::PayloadRejected(NackReason::Malformed),
+            CyDnAError::RateLimited(3),
+        ];
+        let codes: Vec<u16> = errors.iter().map(CyDnAError::code).collect();
+        assert_eq!(codes, [100, 201, 300, 400, 500, 501]);
+        
+        let io_error: CyDnAError = io::Error::new(io::ErrorKind::WouldBlock, "busy").into();
+        assert_eq!(io_error, CyDnAError::IoError(io::ErrorKind::WouldBlock));
+        assert_eq!(io_error.to_string(), format!("I/O error: {}", io::ErrorKind::WouldBlock));
     }
 }
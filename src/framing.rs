@@ -0,0 +1,470 @@
+use crate::errors::{CyDnAError, Result};
+
+pub const FRAME_MAGIC: [u8; 2] = *b"CY";
+
+pub const FRAME_HEADER_SIZE: usize = 8;
+
+pub const PACKED_HEADER_SIZE: usize = 8;
+
+pub const FLAG_PRIORITY_MASK: u8 = 0b0000_0011;
+
+pub const FLAG_COMPRESSION_MASK: u8 = 0b0000_1100;
+
+const FLAG_COMPRESSION_SHIFT: u8 = 2;
+
+pub const FLAG_FORMAT_MASK: u8 = 0b0011_0000;
+
+const FLAG_FORMAT_SHIFT: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MessageType {
+    SensorPayload = 1,
+    Ack = 2,
+    EncryptedPayload = 3,
+    PackedPayloads = 4,
+    ExtendedAck = 5,
+    Authenticated = 6,
+    HandshakeInit = 7,
+    HandshakeResponse = 8,
+    Heartbeat = 9,
+    GatewayAnnouncement = 10,
+    QuantizedPayload = 11,
+    RawDataRequest = 12,
+    RawDataChunk = 13,
+    ControlMessage = 14,
+    ControlAck = 15,
+    TimeSyncRequest = 16,
+    TimeSyncResponse = 17,
+}
+
+impl MessageType {
+    pub fn from_u8(value: u8) -> Result<Self> {
+        match value {
+            1 => Ok(Self::SensorPayload),
+            2 => Ok(Self::Ack),
+            3 => Ok(Self::EncryptedPayload),
+            4 => Ok(Self::PackedPayloads),
+            5 => Ok(Self::ExtendedAck),
+            6 => Ok(Self::Authenticated),
+            7 => Ok(Self::HandshakeInit),
+            8 => Ok(Self::HandshakeResponse),
+            9 => Ok(Self::Heartbeat),
+            10 => Ok(Self::GatewayAnnouncement),
+            11 => Ok(Self::QuantizedPayload),
+            12 => Ok(Self::RawDataRequest),
+            13 => Ok(Self::RawDataChunk),
+            14 => Ok(Self::ControlMessage),
+            15 => Ok(Self::ControlAck),
+            16 => Ok(Self::TimeSyncRequest),
+            17 => Ok(Self::TimeSyncResponse),
+            other => Err(CyDnAError::UnknownMessageType(other)),
+        }
+    }
+}
+
+// Carried in the low bits of the frame flags; frames from older senders
+// have zero flags and therefore read as Normal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(u8)]
+pub enum Priority {
+    #[default]
+    Normal = 0,
+    Critical = 1,
+    Bulk = 2,
+}
+
+impl Priority {
+    pub fn from_flags(flags: u8) -> Self {
+        match flags & FLAG_PRIORITY_MASK {
+            1 => Self::Critical,
+            2 => Self::Bulk,
+            _ => Self::Normal,
+        }
+    }
+    
+    // Lower rank is serviced first.
+    pub fn rank(&self) -> usize {
+        match self {
+            Self::Critical => 0,
+            Self::Normal => 1,
+            Self::Bulk => 2,
+        }
+    }
+    
+    // Critical alerts ride in the expedited queue, bulk pulls in the
+    // lower-effort class so they never compete with them on a shared uplink.
+    pub fn dscp(&self) -> u8 {
+        match self {
+            Self::Critical => crate::socket::DSCP_EXPEDITED_FORWARDING,
+            Self::Normal => crate::socket::DSCP_DEFAULT,
+            Self::Bulk => crate::socket::DSCP_LOW_PRIORITY,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(u8)]
+pub enum Compression {
+    #[default]
+    None = 0,
+    Lz4 = 1,
+    Zstd = 2,
+}
+
+impl Compression {
+    pub fn from_flags(flags: u8) -> Result<Self> {
+        match (flags & FLAG_COMPRESSION_MASK) >> FLAG_COMPRESSION_SHIFT {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Lz4),
+            2 => Ok(Self::Zstd),
+            other => Err(CyDnAError::UnsupportedCompression(other)),
+        }
+    }
+    
+    pub fn is_available(&self) -> bool {
+        match self {
+            Self::None => true,
+            Self::Lz4 => cfg!(feature = "compression-lz4"),
+            Self::Zstd => cfg!(feature = "compression-zstd"),
+        }
+    }
+    
+    // Bit this codec occupies in a capability mask.
+    pub fn mask_bit(&self) -> u8 {
+        1 << *self as u8
+    }
+}
+
+// Codecs this build decodes, one `mask_bit` each; advertised by gateways in
+// their discovery announcements.
+pub fn local_compression_mask() -> u8 {
+    [Compression::None, Compression::Lz4, Compression::Zstd].iter()
+        .filter(|codec| codec.is_available())
+        .fold(0, |mask, codec| mask | codec.mask_bit())
+}
+
+// `preferred` if both this build and the peer support it, otherwise no
+// compression; a peer that never advertised a mask gets plain frames.
+pub fn negotiate_compression(preferred: Compression, peer_mask: u8) -> Compression {
+    match preferred.is_available() && peer_mask & preferred.mask_bit() != 0 {
+        true => preferred,
+        false => Compression::None,
+    }
+}
+
+// Body encoding of sensor payload frames. rkyv is the zero-copy default and
+// what every frame from an older sender (zero flags) uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(u8)]
+pub enum WireFormat {
+    #[default]
+    Rkyv = 0,
+    Cbor = 1,
+    Postcard = 2,
+}
+
+impl WireFormat {
+    pub fn from_flags(flags: u8) -> Result<Self> {
+        match (flags & FLAG_FORMAT_MASK) >> FLAG_FORMAT_SHIFT {
+            0 => Ok(Self::Rkyv),
+            1 => Ok(Self::Cbor),
+            2 => Ok(Self::Postcard),
+            other => Err(CyDnAError::UnsupportedWireFormat(other)),
+        }
+    }
+    
+    pub fn is_available(&self) -> bool {
+        match self {
+            Self::Rkyv => true,
+            Self::Cbor => cfg!(feature = "cbor"),
+            Self::Postcard => cfg!(feature = "postcard"),
+        }
+    }
+}
+
+// Wire layout: magic (2) | version u16 LE | message type | flags | body length u16 LE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub version: u16,
+    
+    pub message_type: MessageType,
+    
+    pub flags: u8,
+    
+    pub payload_len: u16,
+}
+
+impl FrameHeader {
+    pub fn new(message_type: MessageType, payload_len: u16) -> Self {
+        Self {
+            version: crate::CYNDA_VERSION,
+            message_type,
+            flags: 0,
+            payload_len,
+        }
+    }
+    
+    pub fn with_version(mut self, version: u16) -> Self {
+        self.version = version;
+        self
+    }
+    
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.flags = (self.flags & !FLAG_PRIORITY_MASK) | priority as u8;
+        self
+    }
+    
+    pub fn priority(&self) -> Priority {
+        Priority::from_flags(self.flags)
+    }
+    
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.flags = (self.flags & !FLAG_COMPRESSION_MASK)
+            | ((compression as u8) << FLAG_COMPRESSION_SHIFT);
+        self
+    }
+    
+    pub fn compression(&self) -> Result<Compression> {
+        Compression::from_flags(self.flags)
+    }
+    
+    pub fn with_format(mut self, format: WireFormat) -> Self {
+        self.flags = (self.flags & !FLAG_FORMAT_MASK) | ((format as u8) << FLAG_FORMAT_SHIFT);
+        self
+    }
+    
+    pub fn format(&self) -> Result<WireFormat> {
+        WireFormat::from_flags(self.flags)
+    }
+    
+    pub fn expect_format(&self, expected_format: WireFormat) -> Result<()> {
+        let format = self.format()?;
+        if format != expected_format {
+            return Err(CyDnAError::UnsupportedWireFormat(format as u8));
+        }
+        
+        Ok(())
+    }
+    
+    pub fn encode(&self) -> [u8; FRAME_HEADER_SIZE] {
+        let mut bytes = [0u8; FRAME_HEADER_SIZE];
+        bytes[0..2].copy_from_slice(&FRAME_MAGIC);
+        bytes[2..4].copy_from_slice(&self.version.to_le_bytes());
+        bytes[4] = self.message_type as u8;
+        bytes[5] = self.flags;
+        bytes[6..8].copy_from_slice(&self.payload_len.to_le_bytes());
+        bytes
+    }
+    
+    pub fn decode(datagram: &[u8]) -> Result<Self> {
+        if datagram.len() < FRAME_HEADER_SIZE {
+            return Err(CyDnAError::InvalidPacketLength {
+                expected: FRAME_HEADER_SIZE,
+                received: datagram.len(),
+            });
+        }
+        
+        if datagram[0..2] != FRAME_MAGIC {
+            return Err(CyDnAError::InvalidFrameMagic([datagram[0], datagram[1]]));
+        }
+        
+        let version = u16::from_le_bytes([datagram[2], datagram[3]]);
+        if !(crate::CYNDA_VERSION..=crate::CYNDA_VERSION_V2).contains(&version) {
+            return Err(CyDnAError::UnsupportedVersion {
+                expected: crate::CYNDA_VERSION_V2,
+                received: version,
+            });
+        }
+        
+        let header = Self {
+            version,
+            message_type: MessageType::from_u8(datagram[4])?,
+            flags: datagram[5],
+            payload_len: u16::from_le_bytes([datagram[6], datagram[7]]),
+        };
+        
+        let frame_len = FRAME_HEADER_SIZE + header.payload_len as usize;
+        if datagram.len() != frame_len {
+            return Err(CyDnAError::InvalidPacketLength {
+                expected: frame_len,
+                received: datagram.len(),
+            });
+        }
+        
+        Ok(header)
+    }
+    
+    pub fn expect_version(&self, expected_version: u16) -> Result<()> {
+        if self.version != expected_version {
+            return Err(CyDnAError::UnsupportedVersion {
+                expected: expected_version,
+                received: self.version,
+            });
+        }
+        
+        Ok(())
+    }
+    
+    pub fn expect_type(&self, expected_type: MessageType) -> Result<()> {
+        if self.message_type != expected_type {
+            return Err(CyDnAError::UnexpectedMessageType {
+                expected: expected_type as u8,
+                received: self.message_type as u8,
+            });
+        }
+        
+        Ok(())
+    }
+    
+    pub fn body_range(&self) -> std::ops::Range<usize> {
+        FRAME_HEADER_SIZE..FRAME_HEADER_SIZE + self.payload_len as usize
+    }
+}
+
+pub fn encode_frame(message_type: MessageType, body: &[u8]) -> Result<Vec<u8>> {
+    encode_frame_with_priority(message_type, Priority::Normal, body)
+}
+
+pub fn encode_frame_with_priority(
+    message_type: MessageType,
+    priority: Priority,
+    body: &[u8],
+) -> Result<Vec<u8>> {
+    encode_frame_with_header(FrameHeader::new(message_type, 0).with_priority(priority), body)
+}
+
+// `payload_len` is taken from the body; every other header field is kept.
+pub fn encode_frame_with_header(header: FrameHeader, body: &[u8]) -> Result<Vec<u8>> {
+    let frame_len = FRAME_HEADER_SIZE + body.len();
+    if frame_len > crate::MAX_PAYLOAD_SIZE {
+        return Err(CyDnAError::BufferTooSmall {
+            required: frame_len,
+            available: crate::MAX_PAYLOAD_SIZE,
+        });
+    }
+    
+    let header = FrameHeader {
+        payload_len: body.len() as u16,
+        ..header
+    };
+    
+    let mut frame = Vec::with_capacity(frame_len);
+    frame.extend_from_slice(&header.encode());
+    frame.extend_from_slice(body);
+    Ok(frame)
+}
+
+// Only sensor payloads have a version 2 layout; every other message, and the
+// fixed-vector payload, is decoded strictly as version 1.
+pub fn decode_frame(datagram: &[u8], expected_type: MessageType) -> Result<&[u8]> {
+    let header = FrameHeader::decode(datagram)?;
+    header.expect_type(expected_type)?;
+    header.expect_version(crate::CYNDA_VERSION)?;
+    
+    Ok(&datagram[header.body_range()])
+}
+
+// Both sides speak every version up to their own maximum, so the highest
+// common version is simply the lower of the two maxima.
+pub fn negotiate_version(peer_max_version: u16) -> u16 {
+    peer_max_version.clamp(crate::CYNDA_VERSION, crate::CYNDA_VERSION_MAX)
+}
+
+pub fn packed_stride(entry_len: usize) -> usize {
+    (entry_len + 7) & !7
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_frame_roundtrip() {
+        let frame = encode_frame(MessageType::Ack, b"body").unwrap();
+        assert_eq!(frame.len(), FRAME_HEADER_SIZE + 4);
+        
+        let header = FrameHeader::decode(&frame).unwrap();
+        assert_eq!(header.version, crate::CYNDA_VERSION);
+        assert_eq!(header.message_type, MessageType::Ack);
+        assert_eq!(header.payload_len, 4);
+        
+        assert_eq!(decode_frame(&frame, MessageType::Ack).unwrap(), b"body");
+        assert!(matches!(
+            decode_frame(&frame, MessageType::SensorPayload),
+            Err(CyDnAError::UnexpectedMessageType { expected: 1, received: 2 })
+        ));
+    }
+    
+    #[test]
+    fn test_frame_priority_flags() {
+        let frame = encode_frame_with_priority(MessageType::SensorPayload, Priority::Critical, b"x")
+            .unwrap();
+        let header = FrameHeader::decode(&frame).unwrap();
+        assert_eq!(header.priority(), Priority::Critical);
+        assert_eq!(header.with_priority(Priority::Bulk).flags, 2);
+        
+        let plain = encode_frame(MessageType::SensorPayload, b"x").unwrap();
+        assert_eq!(FrameHeader::decode(&plain).unwrap().priority(), Priority::Normal);
+        assert_eq!(Priority::from_flags(0b1111_1111), Priority::Normal);
+        
+        let header = FrameHeader::new(MessageType::SensorPayload, 0)
+            .with_priority(Priority::Critical)
+            .with_compression(Compression::Zstd)
+            .with_format(WireFormat::Postcard);
+        assert_eq!(header.priority(), Priority::Critical);
+        assert_eq!(header.compression().unwrap(), Compression::Zstd);
+        assert_eq!(header.format().unwrap(), WireFormat::Postcard);
+        assert_eq!(WireFormat::from_flags(0).unwrap(), WireFormat::Rkyv);
+        assert!(matches!(
+            WireFormat::from_flags(FLAG_FORMAT_MASK),
+            Err(CyDnAError::UnsupportedWireFormat(3))
+        ));
+    }
+    
+    #[test]
+    fn test_frame_rejects_bad_headers() {
+        let frame = encode_frame(MessageType::SensorPayload, &[0u8; 16]).unwrap();
+        
+        let mut bad_magic = frame.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(FrameHeader::decode(&bad_magic), Err(CyDnAError::InvalidFrameMagic(_))));
+        
+        let mut bad_version = frame.clone();
+        bad_version[2..4].copy_from_slice(&99u16.to_le_bytes());
+        assert!(matches!(
+            FrameHeader::decode(&bad_version),
+            Err(CyDnAError::UnsupportedVersion { received: 99, .. })
+        ));
+        
+        let mut bad_type = frame.clone();
+        bad_type[4] = 0xee;
+        assert!(matches!(FrameHeader::decode(&bad_type), Err(CyDnAError::UnknownMessageType(0xee))));
+        
+        assert!(matches!(
+            FrameHeader::decode(&frame[..frame.len() - 1]),
+            Err(CyDnAError::InvalidPacketLength { .. })
+        ));
+        
+        assert!(encode_frame(MessageType::SensorPayload, &[0u8; crate::MAX_PAYLOAD_SIZE]).is_err());
+        
+        let v2 = encode_frame_with_header(
+            FrameHeader::new(MessageType::SensorPayload, 0).with_version(crate::CYNDA_VERSION_V2),
+            &[0u8; 16],
+        ).unwrap();
+        assert_eq!(FrameHeader::decode(&v2).unwrap().payload_len, 16);
+        assert_eq!(negotiate_version(0), crate::CYNDA_VERSION);
+        assert_eq!(negotiate_version(crate::CYNDA_VERSION_V2), crate::CYNDA_VERSION_V2);
+        assert_eq!(negotiate_version(40), crate::CYNDA_VERSION_MAX);
+        assert_eq!(negotiate_compression(Compression::Zstd, 0), Compression::None);
+        assert_eq!(negotiate_compression(Compression::Lz4, Compression::Zstd.mask_bit()), Compression::None);
+        assert_eq!(
+            negotiate_compression(Compression::Lz4, local_compression_mask()),
+            if cfg!(feature = "compression-lz4") { Compression::Lz4 } else { Compression::None }
+        );
+        assert!(matches!(
+            decode_frame(&v2, MessageType::SensorPayload),
+            Err(CyDnAError::UnsupportedVersion { expected: 1, received: 2 })
+        ));
+    }
+}
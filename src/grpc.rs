@@ -0,0 +1,257 @@
+use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+use std::pin::Pin;
+use std::time::Duration;
+
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::ack_manager::AckManager;
+use crate::errors::{CyDnAError, Result};
+use crate::pool::PacketPool;
+use crate::server::Pipeline;
+use crate::shutdown::ShutdownToken;
+
+// Generated from `proto/cynda.proto`; `proto::ingestion_client::IngestionClient`
+// is the client for sensors and aggregators.
+pub mod proto {
+    tonic::include_proto!("cynda.v1");
+}
+
+use proto::ingestion_server::{Ingestion, IngestionServer};
+use proto::{IngestReply, PayloadFrame};
+
+// Replies queued per stream before reading the next frame waits for the
+// client to catch up.
+const STREAM_REPLY_BUFFER: usize = 64;
+
+#[derive(Clone)]
+struct IngestionService {
+    pool: PacketPool,
+    pipeline: Pipeline,
+}
+
+impl IngestionService {
+    fn ingest(&self, frame: &[u8], peer: SocketAddr) -> Result<IngestReply> {
+        let packet = self.pipeline.load(&self.pool, frame, peer).inspect_err(|e| self.pipeline.record_unreadable(e))?;
+        
+        let reply = self.pipeline.decide(&packet);
+        let ack_frame = AckManager::encode_ack(&reply)?;
+        
+        Ok(IngestReply {
+            device_unique_id: reply.device_unique_id,
+            original_timestamp_ms: reply.original_timestamp_ms,
+            accepted: reply.is_ack(),
+            nack_reason: u32::from(reply.nack_reason),
+            ack_frame,
+        })
+    }
+}
+
+// Only the reply encoding can fail on the gateway's side; everything else is
+// a frame the client should not have sent.
+fn status(error: CyDnAError) -> Status {
+    match error {
+        CyDnAError::SerializationError(_) => Status::internal(error.to_string()),
+        _ => Status::invalid_argument(error.to_string()),
+    }
+}
+
+// Frames carry no trustworthy sender address, so replay tracking and the
+// handler see the connection's peer.
+fn peer_address<T>(request: &Request<T>) -> SocketAddr {
+    request.remote_addr().unwrap_or_else(|| SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+}
+
+#[tonic::async_trait]
+impl Ingestion for IngestionService {
+    type StreamPayloadsStream = Pin<Box<dyn Stream<Item = std::result::Result<IngestReply, Status>> + Send>>;
+    
+    async fn submit_payload(
+        &self,
+        request: Request<PayloadFrame>,
+    ) -> std::result::Result<Response<IngestReply>, Status> {
+        let peer = peer_address(&request);
+        self.ingest(&request.get_ref().frame, peer).map(Response::new).map_err(status)
+    }
+    
+    async fn stream_payloads(
+        &self,
+        request: Request<Streaming<PayloadFrame>>,
+    ) -> std::result::Result<Response<Self::StreamPayloadsStream>, Status> {
+        let peer = peer_address(&request);
+        let mut frames = request.into_inner();
+        let (replies, receiver) = tokio::sync::mpsc::channel(STREAM_REPLY_BUFFER);
+        
+        let service = self.clone();
+        tokio::spawn(async move {
+            while let Some(frame) = frames.next().await {
+                let reply = match frame {
+                    Ok(frame) => service.ingest(&frame.frame, peer).map_err(status),
+                    Err(e) => Err(e),
+                };
+                let failed = reply.is_err();
+                
+                if replies.send(reply).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+}
+
+// The gRPC listener of a `ShardedGateway`, served from its own thread on a
+// single-threaded runtime. Handlers run inline, as on the UDP shards.
+pub(crate) struct GrpcEndpoint {
+    listener: TcpListener,
+    local_address: SocketAddr,
+    runtime: tokio::runtime::Runtime,
+    service: IngestionService,
+    poll_interval: Duration,
+    shutdown: ShutdownToken,
+}
+
+impl GrpcEndpoint {
+    // Binds up front so a taken port fails `spawn_sharded` rather than the
+    // serving thread.
+    pub(crate) fn bind(
+        address: SocketAddr,
+        pool: PacketPool,
+        pipeline: Pipeline,
+        poll_interval: Duration,
+        shutdown: ShutdownToken,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind(address)
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        let local_address = listener.local_addr()
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        Ok(Self {
+            listener,
+            local_address,
+            runtime,
+            service: IngestionService { pool, pipeline },
+            poll_interval,
+            shutdown,
+        })
+    }
+    
+    pub(crate) fn local_address(&self) -> SocketAddr {
+        self.local_address
+    }
+    
+    pub(crate) fn pipeline(&self) -> &Pipeline {
+        &self.service.pipeline
+    }
+    
+    pub(crate) fn run(self) {
+        let Self { listener, runtime, service, poll_interval, shutdown, .. } = self;
+        
+        runtime.block_on(async move {
+            let Ok(listener) = tokio::net::TcpListener::from_std(listener) else {
+                return;
+            };
+            
+            let stopped = async {
+                while !shutdown.is_triggered() {
+                    tokio::time::sleep(poll_interval).await;
+                }
+            };
+            
+            // Dropping the server aborts open streams instead of waiting for
+            // clients to hang up, so shutdown is as prompt as on the shards.
+            let server = tonic::transport::Server::builder()
+                .add_service(IngestionServer::new(service))
+                .serve_with_incoming(TcpListenerStream::new(listener));
+            
+            tokio::select! {
+                _ = server => {}
+                _ = stopped => {}
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proto::ingestion_client::IngestionClient;
+    use super::*;
+    use crate::contracts::{NackReason, SensorPayload, ANOMALY_VECTOR_SIZE};
+    use crate::server::GatewayServer;
+    use crate::transmitter::Transmitter;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    
+    fn frame(device_id: u32, sequence_number: u32, age_ms: u64) -> Vec<u8> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        let payload = SensorPayload::new(device_id, now - age_ms, 1, 80, 5_000, 0, [0.0; ANOMALY_VECTOR_SIZE])
+            .unwrap()
+            .with_sequence_number(sequence_number);
+        
+        Transmitter::frame_payload(&payload).unwrap()
+    }
+    
+    #[test]
+    fn test_grpc_ingestion_shares_pipeline() {
+        let handled = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&handled);
+        
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_handler(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .with_poll_interval_ms(20)
+            .with_grpc("127.0.0.1:0".parse().unwrap())
+            .spawn()
+            .unwrap();
+        let endpoint = format!("http://{}", gateway.grpc_address().unwrap());
+        
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let mut client = IngestionClient::connect(endpoint).await.unwrap();
+            
+            let first = frame(1, 0, 0);
+            let reply = client.submit_payload(PayloadFrame { frame: first.clone() }).await.unwrap().into_inner();
+            assert!(reply.accepted);
+            assert_eq!(reply.device_unique_id, 1);
+            assert!(AckManager::parse_ack_message(&reply.ack_frame).unwrap().is_some());
+            
+            let malformed = client.submit_payload(PayloadFrame { frame: vec![0xff; 12] }).await.unwrap_err();
+            assert_eq!(malformed.code(), tonic::Code::InvalidArgument);
+            
+            // A retransmission of the first frame, a fresh one and an expired one.
+            let frames = vec![
+                PayloadFrame { frame: first },
+                PayloadFrame { frame: frame(2, 0, 0) },
+                PayloadFrame { frame: frame(3, 0, 60_000) },
+            ];
+            let replies: Vec<IngestReply> = client.stream_payloads(tokio_stream::iter(frames))
+                .await
+                .unwrap()
+                .into_inner()
+                .map(|reply| reply.unwrap())
+                .collect()
+                .await;
+            
+            assert_eq!(replies.iter().map(|reply| reply.accepted).collect::<Vec<_>>(), [true, true, false]);
+            assert_eq!(replies[2].nack_reason, NackReason::ExpiredTtl as u32);
+        });
+        
+        assert_eq!(handled.load(Ordering::SeqCst), 2);
+        let metrics = gateway.grpc_metrics().unwrap();
+        assert_eq!((metrics.received, metrics.accepted), (5, 2));
+        assert_eq!((metrics.duplicates, metrics.rejected, metrics.malformed), (1, 1, 1));
+        assert_eq!(gateway.metrics(), metrics);
+        
+        gateway.shutdown();
+    }
+}
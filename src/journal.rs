@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::errors::{CyDnAError, Result};
+
+pub const JOURNAL_FILE_MAGIC: [u8; 4] = *b"CYDJ";
+
+pub const JOURNAL_HEADER_SIZE: usize = 8;
+
+pub const JOURNAL_RECORD_SIZE: usize = 24;
+
+// Longer than the largest TTL a payload can carry (u16 ms) and the replay
+// guard's age limit: anything older is rejected before the journal is asked.
+pub const DEFAULT_JOURNAL_RETENTION_MS: u64 = 300_000;
+
+// Appends between compaction checks; a compaction only happens if at least
+// half of the file is expired entries.
+pub const DEFAULT_COMPACTION_THRESHOLD: usize = 4_096;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JournalMetrics {
+    pub restored: u64,
+    
+    pub recorded: u64,
+    
+    pub duplicates: u64,
+    
+    pub expired: u64,
+    
+    pub compactions: u64,
+}
+
+type JournalKey = (u32, u32, u64);
+
+// An on-disk record of the payloads a gateway has already processed, so a
+// retransmission arriving after a restart is recognised even though the
+// in-memory ReplayGuard starts empty.
+//
+// File layout: `CYDJ` magic | version u16 LE | reserved (2), then fixed-size
+// records of device id u32 | sequence number u32 | payload timestamp u64 |
+// recorded-at u64, all LE. A torn record at the tail from a crash mid-write
+// is ignored on open.
+pub struct DedupJournal {
+    path: PathBuf,
+    file: File,
+    entries: HashMap<JournalKey, u64>,
+    file_records: usize,
+    appended_since_check: usize,
+    retention_ms: u64,
+    compaction_threshold: usize,
+    metrics: JournalMetrics,
+}
+
+impl DedupJournal {
+    // Opens or creates the journal at `path`, restoring its entries. Entries
+    // past `retention_ms` are dropped on the first `evict_expired`.
+    pub fn open(path: impl AsRef<Path>, retention_ms: u64) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let mut entries = HashMap::new();
+        let valid = bytes.len() >= JOURNAL_HEADER_SIZE && bytes[0..4] == JOURNAL_FILE_MAGIC;
+        if valid {
+            for record in bytes[JOURNAL_HEADER_SIZE..].chunks_exact(JOURNAL_RECORD_SIZE) {
+                let (key, recorded_at) = decode_record(record);
+                entries.insert(key, recorded_at);
+            }
+        }
+        
+        let mut journal = Self {
+            path,
+            file,
+            file_records: entries.len(),
+            metrics: JournalMetrics { restored: entries.len() as u64, ..Default::default() },
+            entries,
+            appended_since_check: 0,
+            retention_ms,
+            compaction_threshold: DEFAULT_COMPACTION_THRESHOLD,
+        };
+        
+        // An unknown or torn file is rewritten, which also drops a partial
+        // trailing record.
+        let expected_len = JOURNAL_HEADER_SIZE + journal.file_records * JOURNAL_RECORD_SIZE;
+        if !valid || bytes.len() != expected_len {
+            journal.rewrite()?;
+        }
+        
+        Ok(journal)
+    }
+    
+    pub fn with_compaction_threshold(mut self, appends: usize) -> Self {
+        self.compaction_threshold = appends.max(1);
+        self
+    }
+    
+    pub fn retention_ms(&self) -> u64 {
+        self.retention_ms
+    }
+    
+    pub fn contains(&self, device_id: u32, sequence_number: u32, timestamp_ms: u64) -> bool {
+        self.entries.contains_key(&(device_id, sequence_number, timestamp_ms))
+    }
+    
+    // Returns false, writing nothing, if the payload was already recorded.
+    pub fn record(
+        &mut self,
+        device_id: u32,
+        sequence_number: u32,
+        timestamp_ms: u64,
+        current_time_ms: u64,
+    ) -> Result<bool> {
+        let key = (device_id, sequence_number, timestamp_ms);
+        if self.entries.contains_key(&key) {
+            self.metrics.duplicates += 1;
+            return Ok(false);
+        }
+        
+        self.file.write_all(&encode_record(key, current_time_ms))
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        self.entries.insert(key, current_time_ms);
+        self.file_records += 1;
+        self.metrics.recorded += 1;
+        
+        self.appended_since_check += 1;
+        if self.appended_since_check >= self.compaction_threshold {
+            self.appended_since_check = 0;
+            self.evict_expired(current_time_ms);
+            if self.file_records >= 2 * self.entries.len().max(1) {
+                self.compact()?;
+            }
+        }
+        
+        Ok(true)
+    }
+    
+    // Forgets entries recorded more than the retention window ago; the file
+    // keeps them until the next compaction.
+    pub fn evict_expired(&mut self, current_time_ms: u64) -> usize {
+        let retention_ms = self.retention_ms;
+        let before = self.entries.len();
+        self.entries.retain(|_, recorded_at| current_time_ms.saturating_sub(*recorded_at) <= retention_ms);
+        
+        let expired = before - self.entries.len();
+        self.metrics.expired += expired as u64;
+        expired
+    }
+    
+    // Rewrites the file with only the live entries.
+    pub fn compact(&mut self) -> Result<()> {
+        self.rewrite()?;
+        self.metrics.compactions += 1;
+        Ok(())
+    }
+    
+    // Writes a fresh file next to the journal and renames it over the old
+    // one, so a crash mid-compaction leaves one of the two intact.
+    fn rewrite(&mut self) -> Result<()> {
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".compact");
+        let temporary = PathBuf::from(temporary);
+        
+        let mut bytes = Vec::with_capacity(JOURNAL_HEADER_SIZE + self.entries.len() * JOURNAL_RECORD_SIZE);
+        bytes.extend_from_slice(&JOURNAL_FILE_MAGIC);
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&[0u8; 2]);
+        for (key, recorded_at) in &self.entries {
+            bytes.extend_from_slice(&encode_record(*key, *recorded_at));
+        }
+        
+        let written = File::create(&temporary)
+            .and_then(|mut file| file.write_all(&bytes).and_then(|_| file.sync_all()))
+            .and_then(|_| std::fs::rename(&temporary, &self.path))
+            .and_then(|_| OpenOptions::new().append(true).open(&self.path));
+        
+        self.file = written.map_err(|e| CyDnAError::IoError(e.kind()))?;
+        self.file_records = self.entries.len();
+        Ok(())
+    }
+    
+    pub fn sync(&mut self) -> Result<()> {
+        self.file.sync_data()
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    
+    pub fn metrics(&self) -> JournalMetrics {
+        self.metrics
+    }
+}
+
+fn encode_record((device_id, sequence_number, timestamp_ms): JournalKey, recorded_at: u64) -> [u8; JOURNAL_RECORD_SIZE] {
+    let mut record = [0u8; JOURNAL_RECORD_SIZE];
+    record[0..4].copy_from_slice(&device_id.to_le_bytes());
+    record[4..8].copy_from_slice(&sequence_number.to_le_bytes());
+    record[8..16].copy_from_slice(&timestamp_ms.to_le_bytes());
+    record[16..24].copy_from_slice(&recorded_at.to_le_bytes());
+    record
+}
+
+fn decode_record(record: &[u8]) -> (JournalKey, u64) {
+    let u32_at = |at: usize| u32::from_le_bytes(record[at..at + 4].try_into().unwrap());
+    let u64_at = |at: usize| u64::from_le_bytes(record[at..at + 8].try_into().unwrap());
+    
+    ((u32_at(0), u32_at(4), u64_at(8)), u64_at(16))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn journal_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("cynda-journal-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+    
+    #[test]
+    fn test_journal_survives_reopen_and_torn_tail() {
+        let path = journal_path("reopen");
+        
+        let mut journal = DedupJournal::open(&path, 10_000).unwrap();
+        assert!(journal.record(1, 0, 1_000, 1_000).unwrap());
+        assert!(journal.record(1, 1, 2_000, 2_000).unwrap());
+        assert!(!journal.record(1, 0, 1_000, 2_500).unwrap());
+        drop(journal);
+        
+        // A crash halfway through the next append.
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&[7u8; 10]).unwrap();
+        
+        let mut journal = DedupJournal::open(&path, 10_000).unwrap();
+        assert_eq!(journal.metrics().restored, 2);
+        assert!(journal.contains(1, 1, 2_000));
+        assert!(!journal.record(1, 1, 2_000, 3_000).unwrap());
+        assert!(journal.record(2, 0, 3_000, 3_000).unwrap());
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len() as usize,
+            JOURNAL_HEADER_SIZE + 3 * JOURNAL_RECORD_SIZE,
+        );
+        
+        std::fs::remove_file(&path).unwrap();
+    }
+    
+    #[test]
+    fn test_journal_retention_and_compaction() {
+        let path = journal_path("compact");
+        
+        let mut journal = DedupJournal::open(&path, 1_000).unwrap().with_compaction_threshold(8);
+        for sequence in 0..7 {
+            journal.record(1, sequence, u64::from(sequence), u64::from(sequence)).unwrap();
+        }
+        assert_eq!(journal.metrics().compactions, 0);
+        
+        // The eighth append triggers a check: the first seven are past the
+        // retention window, so the file is rewritten with just the newest.
+        journal.record(1, 7, 5_000, 5_000).unwrap();
+        let metrics = journal.metrics();
+        assert_eq!((metrics.expired, metrics.compactions), (7, 1));
+        assert_eq!(journal.len(), 1);
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len() as usize,
+            JOURNAL_HEADER_SIZE + JOURNAL_RECORD_SIZE,
+        );
+        drop(journal);
+        
+        let journal = DedupJournal::open(&path, 1_000).unwrap();
+        assert!(journal.contains(1, 7, 5_000) && !journal.contains(1, 0, 0));
+        
+        std::fs::remove_file(&path).unwrap();
+    }
+}
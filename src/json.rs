@@ -0,0 +1,124 @@
+use crate::contracts::{
+    AckPacket, ControlAck, ControlMessage, DLTTransactionRecord, ExtendedAckPacket, GatewayAnnouncement,
+    Heartbeat, QuantizedSensorPayload, RawDataChunk, RawDataRequest, SensorPayload, SensorPayloadV2,
+    TimeSyncRequest, TimeSyncResponse,
+};
+use crate::errors::{CyDnAError, Result};
+use crate::stats::StatsSnapshot;
+
+// JSON is for logs, fixtures and external tooling only; the wire format
+// stays rkyv. `from_json` does not re-run constructor validation, so a
+// fixture can describe a payload the receiver is expected to reject.
+macro_rules! impl_json {
+    ($($contract:ty),* $(,)?) => {
+        $(
+            impl $contract {
+                pub fn to_json(&self) -> Result<String> {
+                    serde_json::to_string(self)
+                        .map_err(|_| CyDnAError::SerializationError("Failed to encode JSON"))
+                }
+                
+                pub fn to_json_pretty(&self) -> Result<String> {
+                    serde_json::to_string_pretty(self)
+                        .map_err(|_| CyDnAError::SerializationError("Failed to encode JSON"))
+                }
+                
+                pub fn from_json(json: &str) -> Result<Self> {
+                    serde_json::from_str(json)
+                        .map_err(|_| CyDnAError::DeserializationError("Failed to decode JSON"))
+                }
+            }
+        )*
+    };
+}
+
+impl_json!(
+    SensorPayload,
+    SensorPayloadV2,
+    QuantizedSensorPayload,
+    DLTTransactionRecord,
+    AckPacket,
+    ExtendedAckPacket,
+    Heartbeat,
+    RawDataRequest,
+    RawDataChunk,
+    GatewayAnnouncement,
+    ControlMessage,
+    ControlAck,
+    TimeSyncRequest,
+    TimeSyncResponse,
+    StatsSnapshot,
+);
+
+// Hashes and signatures read as hex strings rather than arrays of numbers.
+pub(crate) mod hex_bytes {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    
+    pub fn serialize<S: Serializer, const N: usize>(
+        bytes: &[u8; N],
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        serializer.serialize_str(&hex)
+    }
+    
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> std::result::Result<[u8; N], D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        if hex.len() != N * 2 || !hex.is_ascii() {
+            return Err(D::Error::custom(format!("expected {} hex characters", N * 2)));
+        }
+        
+        let mut bytes = [0u8; N];
+        for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(D::Error::custom)?;
+            *byte = u8::from_str_radix(pair, 16).map_err(D::Error::custom)?;
+        }
+        
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::{NackReason, ANOMALY_VECTOR_SIZE};
+    
+    #[test]
+    fn test_sensor_payload_json_roundtrip() {
+        let payload = SensorPayload::new(42, 1_700_000_000_000, 3, 88, 5000, 0xdead_beef, [0.5; ANOMALY_VECTOR_SIZE])
+            .unwrap()
+            .with_sequence_number(7);
+        
+        let json = payload.to_json().unwrap();
+        assert!(json.contains("\"device_unique_id\":42"));
+        
+        let decoded = SensorPayload::from_json(&json).unwrap();
+        assert_eq!(decoded.sequence_number, 7);
+        assert_eq!(decoded.anomaly_ai_vector, payload.anomaly_ai_vector);
+        
+        assert!(matches!(
+            SensorPayload::from_json("{\"device_unique_id\":1}"),
+            Err(CyDnAError::DeserializationError(_))
+        ));
+    }
+    
+    #[test]
+    fn test_dlt_record_json_uses_hex() {
+        let record = DLTTransactionRecord::new(7, 0.93, true, 1, [0xab; 32], [0x01; 64]).unwrap();
+        
+        let json = record.to_json_pretty().unwrap();
+        assert!(json.contains(&"ab".repeat(32)));
+        
+        let decoded = DLTTransactionRecord::from_json(&json).unwrap();
+        assert_eq!(decoded.source_payload_hash, record.source_payload_hash);
+        assert_eq!(decoded.gateway_signature, record.gateway_signature);
+        
+        let truncated = json.replace(&"01".repeat(64), "0101");
+        assert!(DLTTransactionRecord::from_json(&truncated).is_err());
+        
+        assert_eq!(serde_json::to_string(&NackReason::ExpiredTtl).unwrap(), "\"ExpiredTtl\"");
+    }
+}
@@ -0,0 +1,625 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::{ToSocketAddrs, UdpSocket};
+
+use aes_gcm::aead::{AeadInPlace, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce, Tag};
+use rand::RngCore;
+
+use crate::contracts::{ControlCommand, WRAPPED_KEY_NONCE_SIZE, WRAPPED_KEY_TAG_SIZE};
+use crate::control::{ControlChannel, ControlEvent};
+use crate::encryption::{PayloadCipher, KEY_SIZE};
+use crate::errors::{CyDnAError, Result};
+
+// How long the key a device rotated away from stays accepted, covering
+// payloads sealed under it that are still in flight or being retransmitted.
+pub const DEFAULT_KEY_OVERLAP_MS: u64 = 60_000;
+
+pub type DeviceKey = [u8; KEY_SIZE];
+
+// Seals `key` for `ControlCommand::InstallKey` under the device's current
+// key. The device id and generation are bound in as associated data, so a
+// wrapped key cannot be replayed to another device or as another generation.
+pub fn wrap_key(current_key: &DeviceKey, device_id: u32, key_generation: u32, key: &DeviceKey) -> Result<ControlCommand> {
+    let mut nonce = [0u8; WRAPPED_KEY_NONCE_SIZE];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    
+    let mut wrapped_key = *key;
+    let tag = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(current_key))
+        .encrypt_in_place_detached(
+            Nonce::from_slice(&nonce),
+            &wrap_aad(device_id, key_generation),
+            &mut wrapped_key,
+        )
+        .map_err(|_| CyDnAError::EncryptionError("Failed to wrap device key"))?;
+    
+    let mut tag_bytes = [0u8; WRAPPED_KEY_TAG_SIZE];
+    tag_bytes.copy_from_slice(&tag);
+    Ok(ControlCommand::InstallKey { key_generation, nonce, wrapped_key, tag: tag_bytes })
+}
+
+// Returns the generation and key carried by an `InstallKey` command.
+pub fn unwrap_key(current_key: &DeviceKey, device_id: u32, command: &ControlCommand) -> Result<(u32, DeviceKey)> {
+    let ControlCommand::InstallKey { key_generation, nonce, wrapped_key, tag } = command else {
+        return Err(CyDnAError::InvalidControlCommand(command.kind()));
+    };
+    
+    let mut key = *wrapped_key;
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(current_key))
+        .decrypt_in_place_detached(
+            Nonce::from_slice(nonce),
+            &wrap_aad(device_id, *key_generation),
+            &mut key,
+            Tag::from_slice(tag),
+        )
+        .map_err(|_| CyDnAError::DecryptionFailed(device_id))?;
+    
+    Ok((*key_generation, key))
+}
+
+fn wrap_aad(device_id: u32, key_generation: u32) -> [u8; 8] {
+    let mut aad = [0u8; 8];
+    aad[..4].copy_from_slice(&device_id.to_le_bytes());
+    aad[4..].copy_from_slice(&key_generation.to_le_bytes());
+    aad
+}
+
+// Whatever verifies or decrypts device traffic on the gateway. The rotation
+// manager pushes the full key set on every change: `current` signs and seals,
+// `previous` is only accepted on receive.
+pub trait RotatingKeyStore {
+    fn set_device_keys(&mut self, device_id: u32, current: &DeviceKey, previous: Option<&DeviceKey>) -> Result<()>;
+}
+
+impl RotatingKeyStore for PayloadCipher {
+    fn set_device_keys(&mut self, device_id: u32, current: &DeviceKey, previous: Option<&DeviceKey>) -> Result<()> {
+        PayloadCipher::set_device_keys(self, device_id, *current, previous.copied());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "authentication")]
+impl RotatingKeyStore for crate::authentication::DatagramAuthenticator {
+    fn set_device_keys(&mut self, device_id: u32, current: &DeviceKey, previous: Option<&DeviceKey>) -> Result<()> {
+        self.provision_keys(device_id, current, previous.map(|key| key.as_slice()))
+    }
+}
+
+// A store a running gateway shares, e.g. one passed to
+// `GatewayServer::with_shared_cipher`.
+impl<T: RotatingKeyStore> RotatingKeyStore for std::sync::Arc<std::sync::Mutex<T>> {
+    fn set_device_keys(&mut self, device_id: u32, current: &DeviceKey, previous: Option<&DeviceKey>) -> Result<()> {
+        crate::lock(self).set_device_keys(device_id, current, previous)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct KeySlot {
+    generation: u32,
+    key: DeviceKey,
+}
+
+#[derive(Clone, Copy)]
+struct PendingRotation {
+    // The generation the device is being moved to.
+    target: KeySlot,
+    command_id: u32,
+    rollback: bool,
+}
+
+struct DeviceKeys {
+    current: KeySlot,
+    previous: Option<KeySlot>,
+    overlap_until_ms: Option<u64>,
+    pending: Option<PendingRotation>,
+    // Highest generation ever sent, so every command names a new one.
+    last_generation: u32,
+    // `previous` is an attempt that was never ACKed, not a key the device
+    // rotated away from.
+    unconfirmed: bool,
+}
+
+impl DeviceKeys {
+    // While a command is in flight the device may already be on the target
+    // key, so both are accepted; the gateway keeps signing with the one the
+    // device is known to have.
+    fn accepted(&self) -> (KeySlot, Option<KeySlot>) {
+        match self.pending {
+            Some(pending) => (self.current, Some(pending.target)),
+            None => (self.current, self.previous),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyStatus {
+    pub generation: u32,
+    
+    // Generation being installed or rolled back to, until the device ACKs.
+    pub pending_generation: Option<u32>,
+    
+    // Still accepted on receive until `overlap_until_ms`.
+    pub previous_generation: Option<u32>,
+    
+    pub overlap_until_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationEvent {
+    // The device ACKed the new key; its old one stays accepted for the
+    // overlap window.
+    Completed { device_id: u32, generation: u32 },
+    
+    // The device confirmed switching back to an earlier generation.
+    RolledBack { device_id: u32, generation: u32 },
+    
+    // The device could not apply the command and is still on `generation`.
+    Refused { device_id: u32, generation: u32 },
+    
+    // No ACK before the control channel gave up. The device may or may not
+    // have switched to `attempted`, so that key stays accepted for the
+    // overlap window alongside `generation`, which the gateway keeps using.
+    Unconfirmed { device_id: u32, generation: u32, attempted: u32 },
+    
+    // The overlap window closed and `generation` is no longer accepted.
+    Retired { device_id: u32, generation: u32 },
+}
+
+// Gateway side of credential rotation. New keys travel over a ControlChannel
+// wrapped under the device's current key; the manager tracks which generation
+// each device is on and keeps the key stores accepting every key the device
+// might be using at that moment.
+pub struct KeyRotation {
+    devices: HashMap<u32, DeviceKeys>,
+    events: VecDeque<RotationEvent>,
+    overlap_ms: u64,
+}
+
+impl KeyRotation {
+    pub fn new() -> Self {
+        Self {
+            devices: HashMap::new(),
+            events: VecDeque::new(),
+            overlap_ms: DEFAULT_KEY_OVERLAP_MS,
+        }
+    }
+    
+    pub fn with_overlap_ms(mut self, overlap_ms: u64) -> Self {
+        self.overlap_ms = overlap_ms;
+        self
+    }
+    
+    // Records the key a device was provisioned with. The key stores are
+    // expected to hold it already.
+    pub fn provision(&mut self, device_id: u32, generation: u32, key: DeviceKey) {
+        self.devices.insert(device_id, DeviceKeys {
+            current: KeySlot { generation, key },
+            previous: None,
+            overlap_until_ms: None,
+            pending: None,
+            last_generation: generation,
+            unconfirmed: false,
+        });
+    }
+    
+    // Sends `key` to the device as the next generation and returns that
+    // generation. One rotation per device at a time.
+    pub fn begin(
+        &mut self,
+        channel: &mut ControlChannel,
+        socket: &UdpSocket,
+        destination: impl ToSocketAddrs,
+        device_id: u32,
+        key: DeviceKey,
+        store: &mut impl RotatingKeyStore,
+    ) -> Result<u32> {
+        let device = self.devices.get(&device_id)
+            .ok_or(CyDnAError::UnknownDeviceKey(device_id))?;
+        if device.pending.is_some() {
+            return Err(CyDnAError::KeyRotationInProgress(device_id));
+        }
+        
+        // Past any unconfirmed attempt, so a device that did take it sees a
+        // generation it has not had yet.
+        let generation = device.last_generation.wrapping_add(1);
+        let command = wrap_key(&device.current.key, device_id, generation, &key)?;
+        let command_id = channel.send(socket, destination, device_id, command)?;
+        
+        self.start(device_id, PendingRotation { target: KeySlot { generation, key }, command_id, rollback: false }, store)?;
+        Ok(generation)
+    }
+    
+    // Moves the device back to the key it rotated away from and returns the
+    // generation that key now goes by. The key is re-sent wrapped under the
+    // current one as a new generation, like any install, so a rollback
+    // cannot be spoofed. Only possible within the overlap window, while the
+    // gateway still holds the old key.
+    pub fn rollback(
+        &mut self,
+        channel: &mut ControlChannel,
+        socket: &UdpSocket,
+        destination: impl ToSocketAddrs,
+        device_id: u32,
+        store: &mut impl RotatingKeyStore,
+    ) -> Result<u32> {
+        let device = self.devices.get(&device_id)
+            .ok_or(CyDnAError::UnknownDeviceKey(device_id))?;
+        if device.pending.is_some() {
+            return Err(CyDnAError::KeyRotationInProgress(device_id));
+        }
+        
+        let previous = device.previous
+            .filter(|_| !device.unconfirmed)
+            .ok_or(CyDnAError::UnknownDeviceKey(device_id))?;
+        let generation = device.last_generation.wrapping_add(1);
+        let command = wrap_key(&device.current.key, device_id, generation, &previous.key)?;
+        let command_id = channel.send(socket, destination, device_id, command)?;
+        
+        let target = KeySlot { generation, key: previous.key };
+        self.start(device_id, PendingRotation { target, command_id, rollback: true }, store)?;
+        Ok(generation)
+    }
+    
+    fn start(&mut self, device_id: u32, pending: PendingRotation, store: &mut impl RotatingKeyStore) -> Result<()> {
+        let device = self.devices.get_mut(&device_id)
+            .ok_or(CyDnAError::UnknownDeviceKey(device_id))?;
+        device.pending = Some(pending);
+        device.last_generation = pending.target.generation;
+        Self::push(device_id, device, store)
+    }
+    
+    fn push(device_id: u32, device: &DeviceKeys, store: &mut impl RotatingKeyStore) -> Result<()> {
+        let (current, previous) = device.accepted();
+        store.set_device_keys(device_id, &current.key, previous.as_ref().map(|slot| &slot.key))
+    }
+    
+    // Feed every event from the ControlChannel the rotations were sent on.
+    // Returns whether the event belonged to a rotation.
+    pub fn handle_event(&mut self, event: &ControlEvent, current_time_ms: u64, store: &mut impl RotatingKeyStore) -> Result<bool> {
+        let (device_id, command_id) = match event {
+            ControlEvent::Delivered { device_id, command_id, .. } => (*device_id, *command_id),
+            ControlEvent::Refused { message } | ControlEvent::Exhausted { message, .. } => {
+                (message.device_unique_id, message.command_id)
+            }
+        };
+        
+        let Some(device) = self.devices.get_mut(&device_id) else {
+            return Ok(false);
+        };
+        let Some(pending) = device.pending.filter(|pending| pending.command_id == command_id) else {
+            return Ok(false);
+        };
+        device.pending = None;
+        
+        let overlap_until_ms = current_time_ms.saturating_add(self.overlap_ms);
+        let generation = pending.target.generation;
+        let event = match event {
+            ControlEvent::Delivered { .. } => {
+                device.previous = Some(device.current);
+                device.current = pending.target;
+                device.overlap_until_ms = Some(overlap_until_ms);
+                device.unconfirmed = false;
+                
+                match pending.rollback {
+                    true => RotationEvent::RolledBack { device_id, generation },
+                    false => RotationEvent::Completed { device_id, generation },
+                }
+            }
+            ControlEvent::Refused { .. } => {
+                // Devices only unwrap under their current key, so refusing
+                // one wrapped under ours after an unconfirmed attempt means
+                // the attempt did land. The next rotation wraps under it.
+                if device.unconfirmed {
+                    if let Some(attempted) = device.previous.replace(device.current) {
+                        device.current = attempted;
+                    }
+                    device.unconfirmed = false;
+                }
+                RotationEvent::Refused { device_id, generation: device.current.generation }
+            }
+            ControlEvent::Exhausted { .. } => {
+                device.previous = Some(pending.target);
+                device.overlap_until_ms = Some(overlap_until_ms);
+                device.unconfirmed = true;
+                RotationEvent::Unconfirmed { device_id, generation: device.current.generation, attempted: generation }
+            }
+        };
+        
+        Self::push(device_id, device, store)?;
+        self.events.push_back(event);
+        Ok(true)
+    }
+    
+    // Stops accepting keys whose overlap window has closed.
+    pub fn service(&mut self, current_time_ms: u64, store: &mut impl RotatingKeyStore) -> Result<usize> {
+        let mut retired = 0;
+        
+        for (&device_id, device) in self.devices.iter_mut() {
+            let closed = device.overlap_until_ms.is_some_and(|until| current_time_ms >= until);
+            if !closed || device.pending.is_some() {
+                continue;
+            }
+            
+            device.overlap_until_ms = None;
+            device.unconfirmed = false;
+            if let Some(previous) = device.previous.take() {
+                Self::push(device_id, device, store)?;
+                self.events.push_back(RotationEvent::Retired { device_id, generation: previous.generation });
+                retired += 1;
+            }
+        }
+        
+        Ok(retired)
+    }
+    
+    pub fn poll_event(&mut self) -> Option<RotationEvent> {
+        self.events.pop_front()
+    }
+    
+    pub fn generation(&self, device_id: u32) -> Option<u32> {
+        self.devices.get(&device_id).map(|device| device.current.generation)
+    }
+    
+    pub fn status(&self, device_id: u32) -> Option<KeyStatus> {
+        self.devices.get(&device_id).map(|device| KeyStatus {
+            generation: device.current.generation,
+            pending_generation: device.pending.map(|pending| pending.target.generation),
+            previous_generation: device.previous.map(|previous| previous.generation),
+            overlap_until_ms: device.overlap_until_ms,
+        })
+    }
+    
+    // Devices whose current key is `generation`, in id order; e.g. to find
+    // the ones a rollout has not reached yet.
+    pub fn devices_on_generation(&self, generation: u32) -> Vec<u32> {
+        let mut devices: Vec<u32> = self.devices.iter()
+            .filter(|(_, device)| device.current.generation == generation)
+            .map(|(&device_id, _)| device_id)
+            .collect();
+        devices.sort_unstable();
+        devices
+    }
+    
+    pub fn is_rotating(&self, device_id: u32) -> bool {
+        self.devices.get(&device_id).is_some_and(|device| device.pending.is_some())
+    }
+}
+
+impl Default for KeyRotation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Sensor side: the device's current key. Nothing it rotated away from is
+// kept, so a leaked old key cannot be used to push a new one; the gateway
+// alone covers the overlap.
+#[derive(Clone)]
+pub struct DeviceKeyring {
+    device_id: u32,
+    current: KeySlot,
+}
+
+impl DeviceKeyring {
+    pub fn new(device_id: u32, generation: u32, key: DeviceKey) -> Self {
+        Self { device_id, current: KeySlot { generation, key } }
+    }
+    
+    pub fn device_id(&self) -> u32 {
+        self.device_id
+    }
+    
+    pub fn generation(&self) -> u32 {
+        self.current.generation
+    }
+    
+    pub fn key(&self) -> &DeviceKey {
+        &self.current.key
+    }
+    
+    // Applies `InstallKey`, which must unwrap under the current key and name
+    // a later generation, so a recorded install cannot be replayed. Rollbacks
+    // arrive the same way; the unauthenticated `RotateKey` is refused.
+    // Other commands are ignored. Returns whether the current key changed.
+    pub fn apply(&mut self, command: &ControlCommand) -> Result<bool> {
+        match *command {
+            ControlCommand::InstallKey { key_generation, .. } => {
+                if key_generation == self.current.generation {
+                    return Ok(false);
+                }
+                if key_generation < self.current.generation {
+                    return Err(CyDnAError::InvalidControlCommand(command.kind()));
+                }
+                
+                let (generation, key) = unwrap_key(&self.current.key, self.device_id, command)?;
+                self.current = KeySlot { generation, key };
+                Ok(true)
+            }
+            ControlCommand::RotateKey { .. } => Err(CyDnAError::InvalidControlCommand(command.kind())),
+            _ => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::{parse_control, ControlInbox};
+    use std::time::Duration;
+    
+    // Delivers whatever the channel sent to the inbox, applying key commands
+    // to the keyring, and feeds the ACK back.
+    fn exchange(
+        sensor: &UdpSocket,
+        channel: &mut ControlChannel,
+        inbox: &mut ControlInbox,
+        keyring: &mut DeviceKeyring,
+    ) -> ControlEvent {
+        let mut buffer = [0u8; 256];
+        let len = sensor.recv(&mut buffer).unwrap();
+        let message = parse_control(&buffer[..len]).unwrap();
+        let ack = inbox.receive_with(message, |command| keyring.apply(command).map(|_| ())).unwrap().unwrap();
+        
+        assert!(channel.handle_datagram(&ack));
+        channel.poll_event().unwrap()
+    }
+    
+    fn sockets() -> (UdpSocket, UdpSocket) {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        (gateway, sensor)
+    }
+    
+    fn seal(key: &DeviceKey, device_id: u32) -> Vec<u8> {
+        let mut cipher = PayloadCipher::new();
+        cipher.add_device_key(device_id, *key);
+        cipher.seal(device_id, b"reading").unwrap()
+    }
+    
+    #[test]
+    fn test_rotation_overlap_and_retirement() {
+        let (gateway, sensor) = sockets();
+        let (old_key, new_key) = ([1u8; KEY_SIZE], [2u8; KEY_SIZE]);
+        
+        let mut cipher = PayloadCipher::new();
+        cipher.add_device_key(7, old_key);
+        let mut rotation = KeyRotation::new().with_overlap_ms(1_000);
+        rotation.provision(7, 1, old_key);
+        
+        let mut channel = ControlChannel::new();
+        let mut inbox = ControlInbox::new(7);
+        let mut keyring = DeviceKeyring::new(7, 1, old_key);
+        
+        let address = sensor.local_addr().unwrap();
+        assert_eq!(rotation.begin(&mut channel, &gateway, address, 7, new_key, &mut cipher).unwrap(), 2);
+        assert!(matches!(
+            rotation.begin(&mut channel, &gateway, address, 7, new_key, &mut cipher),
+            Err(CyDnAError::KeyRotationInProgress(7))
+        ));
+        
+        // Both keys open while the command is in flight.
+        assert!(cipher.open(&mut seal(&new_key, 7)).is_ok());
+        assert!(cipher.open(&mut seal(&old_key, 7)).is_ok());
+        
+        let event = exchange(&sensor, &mut channel, &mut inbox, &mut keyring);
+        assert_eq!((keyring.generation(), keyring.key()), (2, &new_key));
+        assert!(rotation.handle_event(&event, 10_000, &mut cipher).unwrap());
+        assert_eq!(rotation.poll_event(), Some(RotationEvent::Completed { device_id: 7, generation: 2 }));
+        assert_eq!(rotation.status(7).unwrap(), KeyStatus {
+            generation: 2,
+            pending_generation: None,
+            previous_generation: Some(1),
+            overlap_until_ms: Some(11_000),
+        });
+        assert_eq!(rotation.devices_on_generation(2), [7]);
+        
+        assert_eq!(rotation.service(10_999, &mut cipher).unwrap(), 0);
+        assert!(cipher.open(&mut seal(&old_key, 7)).is_ok());
+        assert_eq!(rotation.service(11_000, &mut cipher).unwrap(), 1);
+        assert_eq!(rotation.poll_event(), Some(RotationEvent::Retired { device_id: 7, generation: 1 }));
+        assert!(matches!(cipher.open(&mut seal(&old_key, 7)), Err(CyDnAError::DecryptionFailed(7))));
+        assert!(cipher.open(&mut seal(&new_key, 7)).is_ok());
+    }
+    
+    #[test]
+    fn test_rollback_and_refusal() {
+        let (gateway, sensor) = sockets();
+        let address = sensor.local_addr().unwrap();
+        let (old_key, new_key) = ([3u8; KEY_SIZE], [4u8; KEY_SIZE]);
+        
+        let mut cipher = PayloadCipher::new();
+        cipher.add_device_key(5, old_key);
+        let mut rotation = KeyRotation::new();
+        rotation.provision(5, 10, old_key);
+        
+        let mut channel = ControlChannel::new();
+        let mut inbox = ControlInbox::new(5);
+        let mut keyring = DeviceKeyring::new(5, 10, old_key);
+        
+        rotation.begin(&mut channel, &gateway, address, 5, new_key, &mut cipher).unwrap();
+        let event = exchange(&sensor, &mut channel, &mut inbox, &mut keyring);
+        rotation.handle_event(&event, 0, &mut cipher).unwrap();
+        
+        // The old key comes back as a new generation.
+        assert_eq!(rotation.rollback(&mut channel, &gateway, address, 5, &mut cipher).unwrap(), 12);
+        let event = exchange(&sensor, &mut channel, &mut inbox, &mut keyring);
+        rotation.handle_event(&event, 0, &mut cipher).unwrap();
+        rotation.poll_event();
+        assert_eq!(rotation.poll_event(), Some(RotationEvent::RolledBack { device_id: 5, generation: 12 }));
+        assert_eq!((keyring.generation(), rotation.generation(5)), (12, Some(12)));
+        assert_eq!(keyring.key(), &old_key);
+        
+        // A key wrapped under a key the device never had is refused, and the
+        // gateway stays where it was.
+        let mut stranger = DeviceKeyring::new(5, 10, [9u8; KEY_SIZE]);
+        rotation.begin(&mut channel, &gateway, address, 5, [6u8; KEY_SIZE], &mut cipher).unwrap();
+        let mut fresh_inbox = ControlInbox::new(5);
+        let event = exchange(&sensor, &mut channel, &mut fresh_inbox, &mut stranger);
+        rotation.handle_event(&event, 0, &mut cipher).unwrap();
+        assert_eq!(rotation.poll_event(), Some(RotationEvent::Refused { device_id: 5, generation: 12 }));
+        assert!(!rotation.is_rotating(5));
+        assert_eq!(stranger.generation(), 10);
+    }
+    
+    #[test]
+    fn test_keyring_refuses_old_keys_and_spoofed_rollback() {
+        let (old_key, new_key, attacker_key) = ([1u8; KEY_SIZE], [2u8; KEY_SIZE], [8u8; KEY_SIZE]);
+        let first_install = wrap_key(&old_key, 3, 2, &new_key).unwrap();
+        
+        let mut keyring = DeviceKeyring::new(3, 1, old_key);
+        assert!(keyring.apply(&first_install).unwrap());
+        
+        // Holding the key the device rotated away from is not enough.
+        let forged = wrap_key(&old_key, 3, 3, &attacker_key).unwrap();
+        assert!(matches!(keyring.apply(&forged), Err(CyDnAError::DecryptionFailed(3))));
+        assert!(keyring.apply(&ControlCommand::RotateKey { key_generation: 1 }).is_err());
+        
+        // A recorded install for a generation already passed is refused.
+        let back = wrap_key(&new_key, 3, 1, &old_key).unwrap();
+        assert!(keyring.apply(&back).is_err());
+        assert_eq!((keyring.generation(), keyring.key()), (2, &new_key));
+    }
+    
+    #[test]
+    fn test_refusal_after_unconfirmed_attempt_adopts_it() {
+        use crate::contracts::ControlMessage;
+        
+        let (gateway, sensor) = sockets();
+        let address = sensor.local_addr().unwrap();
+        let (old_key, new_key) = ([1u8; KEY_SIZE], [2u8; KEY_SIZE]);
+        
+        let mut cipher = PayloadCipher::new();
+        cipher.add_device_key(4, old_key);
+        let mut rotation = KeyRotation::new();
+        rotation.provision(4, 1, old_key);
+        let mut channel = ControlChannel::new();
+        
+        // The device took generation 2 but every ACK was lost.
+        let attempted = rotation.begin(&mut channel, &gateway, address, 4, new_key, &mut cipher).unwrap();
+        let mut buffer = [0u8; 256];
+        let len = sensor.recv(&mut buffer).unwrap();
+        let message: ControlMessage = parse_control(&buffer[..len]).unwrap();
+        let mut keyring = DeviceKeyring::new(4, 1, old_key);
+        keyring.apply(&message.command).unwrap();
+        
+        let exhausted = ControlEvent::Exhausted { message, attempts: 5 };
+        rotation.handle_event(&exhausted, 0, &mut cipher).unwrap();
+        assert_eq!(
+            rotation.poll_event(),
+            Some(RotationEvent::Unconfirmed { device_id: 4, generation: 1, attempted }),
+        );
+        
+        rotation.begin(&mut channel, &gateway, address, 4, [3u8; KEY_SIZE], &mut cipher).unwrap();
+        let event = exchange(&sensor, &mut channel, &mut ControlInbox::new(4), &mut keyring);
+        rotation.handle_event(&event, 0, &mut cipher).unwrap();
+        assert_eq!(rotation.poll_event(), Some(RotationEvent::Refused { device_id: 4, generation: 2 }));
+        
+        let event = {
+            rotation.begin(&mut channel, &gateway, address, 4, [3u8; KEY_SIZE], &mut cipher).unwrap();
+            exchange(&sensor, &mut channel, &mut ControlInbox::new(4), &mut keyring)
+        };
+        rotation.handle_event(&event, 0, &mut cipher).unwrap();
+        assert!(matches!(rotation.poll_event(), Some(RotationEvent::Completed { device_id: 4, .. })));
+        assert_eq!(keyring.key(), &[3u8; KEY_SIZE]);
+    }
+}
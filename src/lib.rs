@@ -1,14 +1,75 @@
 pub mod errors;
+pub mod clock;
 pub mod contracts;
+pub mod framing;
 pub mod transmitter;
 pub mod receiver;
+pub mod validation;
 pub mod ack_manager;
+pub mod sequence;
+pub mod replay;
+pub mod journal;
+pub mod rate_limit;
+pub mod access;
+pub mod liveness;
+pub mod stats;
+pub mod dlt;
+pub mod merkle;
+pub mod discovery;
+pub mod store_forward;
+pub mod transmit_queue;
+pub mod pacing;
+pub mod batching;
+pub mod compression;
+pub mod codec;
+pub mod pool;
+pub mod server;
+pub mod shutdown;
+pub mod stream;
+pub mod overload;
+pub mod time_sync;
+pub mod config;
+pub mod transport;
+pub mod socket;
+pub mod quantization;
+pub mod bulk;
+pub mod control;
+pub mod client;
+
+#[cfg(feature = "encryption")]
+pub mod encryption;
+#[cfg(feature = "encryption")]
+pub mod key_rotation;
+#[cfg(feature = "authentication")]
+pub mod authentication;
+#[cfg(feature = "sessions")]
+pub mod session;
+#[cfg(feature = "serde")]
+pub mod json;
+#[cfg(feature = "bridge")]
+pub mod bridge;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "tokio")]
+pub mod async_transmitter;
+#[cfg(feature = "tokio")]
+pub mod async_receiver;
+#[cfg(feature = "tokio")]
+pub mod async_ack_manager;
 
 pub use contracts::{SensorPayload, DLTTransactionRecord};
+pub use client::SensorClient;
 pub use errors::{CyDnAError, Result};
 
 pub const CYNDA_VERSION: u16 = 1;
 
+pub const CYNDA_VERSION_V2: u16 = 2;
+
+pub const CYNDA_VERSION_MAX: u16 = CYNDA_VERSION_V2;
+
 pub const MAX_PAYLOAD_SIZE: usize = 1024;
 
 pub const ACK_TIMEOUT_MS: u64 = 100;
@@ -16,3 +77,9 @@ pub const ACK_TIMEOUT_MS: u64 = 100;
 pub const MAX_RETRANSMIT_ATTEMPTS: u32 = 3;
 
 pub const BACKOFF_MULTIPLIER: u64 = 2;
+
+// A panic on one thread must not poison state the gateway's shards share;
+// everything guarded this way stays consistent between statements.
+pub(crate) fn lock<T>(mutex: &std::sync::Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+}
@@ -0,0 +1,144 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::contracts::Heartbeat;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LivenessEvent {
+    CameOnline { device_id: u32 },
+    
+    WentOffline { device_id: u32, last_seen_ms: u64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceLiveness {
+    pub last_seen_ms: u64,
+    
+    pub battery_level_percent: Option<u8>,
+    
+    pub online: bool,
+}
+
+// Any datagram from a device counts as a sign of life; heartbeats only make
+// sure a quiet sensor still shows up within the offline threshold.
+pub struct LivenessTracker {
+    devices: HashMap<u32, DeviceLiveness>,
+    events: VecDeque<LivenessEvent>,
+    offline_threshold_ms: u64,
+}
+
+impl LivenessTracker {
+    pub fn new(offline_threshold_ms: u64) -> Self {
+        Self {
+            devices: HashMap::new(),
+            events: VecDeque::new(),
+            offline_threshold_ms,
+        }
+    }
+    
+    pub fn offline_threshold_ms(&self) -> u64 {
+        self.offline_threshold_ms
+    }
+    
+    pub fn record_activity(&mut self, device_id: u32, current_time_ms: u64) {
+        self.update(device_id, current_time_ms, None);
+    }
+    
+    pub fn record_heartbeat(&mut self, heartbeat: &Heartbeat, current_time_ms: u64) {
+        self.update(
+            heartbeat.device_unique_id,
+            current_time_ms,
+            Some(heartbeat.battery_level_percent),
+        );
+    }
+    
+    fn update(&mut self, device_id: u32, current_time_ms: u64, battery: Option<u8>) {
+        let entry = self.devices.entry(device_id).or_insert(DeviceLiveness {
+            last_seen_ms: current_time_ms,
+            battery_level_percent: None,
+            online: false,
+        });
+        
+        if !entry.online {
+            entry.online = true;
+            self.events.push_back(LivenessEvent::CameOnline { device_id });
+        }
+        
+        entry.last_seen_ms = entry.last_seen_ms.max(current_time_ms);
+        if battery.is_some() {
+            entry.battery_level_percent = battery;
+        }
+    }
+    
+    // Marks devices silent for longer than the threshold as offline and
+    // queues a WentOffline event for each; returns how many went offline.
+    pub fn sweep(&mut self, current_time_ms: u64) -> usize {
+        let mut count = 0;
+        
+        for (&device_id, entry) in self.devices.iter_mut() {
+            if entry.online && current_time_ms.saturating_sub(entry.last_seen_ms) > self.offline_threshold_ms {
+                entry.online = false;
+                self.events.push_back(LivenessEvent::WentOffline {
+                    device_id,
+                    last_seen_ms: entry.last_seen_ms,
+                });
+                count += 1;
+            }
+        }
+        
+        count
+    }
+    
+    pub fn is_online(&self, device_id: u32, current_time_ms: u64) -> bool {
+        self.devices.get(&device_id)
+            .map(|entry| current_time_ms.saturating_sub(entry.last_seen_ms) <= self.offline_threshold_ms)
+            .unwrap_or(false)
+    }
+    
+    pub fn device(&self, device_id: u32) -> Option<DeviceLiveness> {
+        self.devices.get(&device_id).copied()
+    }
+    
+    pub fn online_devices(&self, current_time_ms: u64) -> Vec<u32> {
+        self.devices.keys()
+            .copied()
+            .filter(|&device_id| self.is_online(device_id, current_time_ms))
+            .collect()
+    }
+    
+    pub fn forget(&mut self, device_id: u32) -> bool {
+        self.devices.remove(&device_id).is_some()
+    }
+    
+    pub fn poll_event(&mut self) -> Option<LivenessEvent> {
+        self.events.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_device_goes_offline_and_returns() {
+        let mut tracker = LivenessTracker::new(1_000);
+        
+        tracker.record_heartbeat(&Heartbeat::new(1, 0, 80), 0);
+        tracker.record_activity(2, 500);
+        assert_eq!(tracker.poll_event(), Some(LivenessEvent::CameOnline { device_id: 1 }));
+        assert_eq!(tracker.poll_event(), Some(LivenessEvent::CameOnline { device_id: 2 }));
+        assert_eq!(tracker.device(1).unwrap().battery_level_percent, Some(80));
+        
+        assert_eq!(tracker.sweep(1_200), 1);
+        assert!(!tracker.is_online(1, 1_200));
+        assert!(tracker.is_online(2, 1_200));
+        assert_eq!(
+            tracker.poll_event(),
+            Some(LivenessEvent::WentOffline { device_id: 1, last_seen_ms: 0 })
+        );
+        assert_eq!(tracker.sweep(1_300), 0);
+        
+        tracker.record_heartbeat(&Heartbeat::new(1, 1_400, 79), 1_400);
+        assert_eq!(tracker.poll_event(), Some(LivenessEvent::CameOnline { device_id: 1 }));
+        assert_eq!(tracker.online_devices(1_400).len(), 2);
+    }
+}
@@ -0,0 +1,476 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use ed25519_dalek::SigningKey;
+
+use crate::contracts::{compute_payload_hash, DLTTransactionRecord};
+use crate::dlt::{record_id, AnchorReceipt, DltSubmitter};
+use crate::errors::{CyDnAError, Result};
+
+pub const DEFAULT_MERKLE_INTERVAL_MS: u64 = 60_000;
+
+pub const DEFAULT_MERKLE_MAX_RECORDS: usize = 4096;
+
+// Anchored batches whose records can still be proven.
+pub const DEFAULT_MERKLE_HISTORY: usize = 1024;
+
+// Domain separation keeps a leaf from ever being passed off as an inner node.
+const LEAF_PREFIX: u8 = 0x00;
+
+const NODE_PREFIX: u8 = 0x01;
+
+pub fn leaf_hash(record_id: &[u8; 32]) -> [u8; 32] {
+    let mut bytes = [0u8; 33];
+    bytes[0] = LEAF_PREFIX;
+    bytes[1..].copy_from_slice(record_id);
+    compute_payload_hash(&bytes)
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut bytes = [0u8; 65];
+    bytes[0] = NODE_PREFIX;
+    bytes[1..33].copy_from_slice(left);
+    bytes[33..].copy_from_slice(right);
+    compute_payload_hash(&bytes)
+}
+
+// Pairs are hashed level by level; an odd node out is carried up unchanged
+// rather than paired with itself, so `[a, b, c]` and `[a, b, c, c]` get
+// different roots.
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level.chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+// `None` for an empty list.
+pub fn merkle_root(record_ids: &[[u8; 32]]) -> Option<[u8; 32]> {
+    let mut level: Vec<[u8; 32]> = record_ids.iter().map(leaf_hash).collect();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level.first().copied()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProofStep {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::hex_bytes"))]
+    pub sibling: [u8; 32],
+    
+    pub sibling_on_left: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InclusionProof {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::hex_bytes"))]
+    pub record_id: [u8; 32],
+    
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::hex_bytes"))]
+    pub root: [u8; 32],
+    
+    pub leaf_index: usize,
+    
+    pub leaf_count: usize,
+    
+    pub path: Vec<ProofStep>,
+}
+
+impl InclusionProof {
+    pub fn build(record_ids: &[[u8; 32]], leaf_index: usize) -> Option<Self> {
+        let record_id = *record_ids.get(leaf_index)?;
+        
+        let mut level: Vec<[u8; 32]> = record_ids.iter().map(leaf_hash).collect();
+        let mut index = leaf_index;
+        let mut path = Vec::new();
+        
+        while level.len() > 1 {
+            let sibling = index ^ 1;
+            if sibling < level.len() {
+                path.push(ProofStep { sibling: level[sibling], sibling_on_left: sibling < index });
+            }
+            level = next_level(&level);
+            index /= 2;
+        }
+        
+        Some(Self {
+            record_id,
+            root: level[0],
+            leaf_index,
+            leaf_count: record_ids.len(),
+            path,
+        })
+    }
+    
+    // Checks the path only; that `root` was anchored is shown by the signed
+    // root record and its receipt.
+    pub fn verify(&self) -> bool {
+        let computed = self.path.iter().fold(leaf_hash(&self.record_id), |hash, step| {
+            if step.sibling_on_left {
+                node_hash(&step.sibling, &hash)
+            } else {
+                node_hash(&hash, &step.sibling)
+            }
+        });
+        
+        computed == self.root
+    }
+    
+    pub fn proves(&self, record: &DLTTransactionRecord) -> bool {
+        self.record_id == record_id(record) && self.verify()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnchoredBatch {
+    pub batch_sequence: u64,
+    
+    pub root: [u8; 32],
+    
+    // Signed by the gateway with the root as its `source_payload_hash`.
+    pub root_record: DLTTransactionRecord,
+    
+    pub receipt: AnchorReceipt,
+    
+    pub record_ids: Vec<[u8; 32]>,
+    
+    pub opened_at_ms: u64,
+    
+    pub sealed_at_ms: u64,
+}
+
+impl AnchoredBatch {
+    pub fn proof(&self, record_id: &[u8; 32]) -> Option<InclusionProof> {
+        let leaf_index = self.record_ids.iter().position(|id| id == record_id)?;
+        InclusionProof::build(&self.record_ids, leaf_index)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MerkleMetrics {
+    pub records: u64,
+    
+    pub batches_anchored: u64,
+    
+    pub anchor_failures: u64,
+    
+    pub duplicates_skipped: u64,
+}
+
+struct OpenBatch {
+    records: Vec<DLTTransactionRecord>,
+    ids: HashSet<[u8; 32]>,
+    opened_at_ms: u64,
+}
+
+struct SealedBatch {
+    root: [u8; 32],
+    root_record: DLTTransactionRecord,
+    record_ids: Vec<[u8; 32]>,
+    opened_at_ms: u64,
+    sealed_at_ms: u64,
+}
+
+// Collects records for an interval (or until `max_records`), then anchors a
+// single signed root record in their place. Individual records are proven
+// afterwards with `proof`.
+pub struct MerkleBatcher<S: DltSubmitter> {
+    submitter: S,
+    signing_key: SigningKey,
+    gateway_id: u32,
+    interval_ms: u64,
+    max_records: usize,
+    open: Option<OpenBatch>,
+    sealed: Option<SealedBatch>,
+    history: VecDeque<AnchoredBatch>,
+    history_limit: usize,
+    index: HashMap<[u8; 32], u64>,
+    next_batch_sequence: u64,
+    metrics: MerkleMetrics,
+}
+
+impl<S: DltSubmitter> MerkleBatcher<S> {
+    pub fn new(submitter: S, signing_key: SigningKey, gateway_id: u32) -> Result<Self> {
+        if gateway_id == 0 {
+            return Err(CyDnAError::InvalidGatewayId(gateway_id));
+        }
+        
+        Ok(Self {
+            submitter,
+            signing_key,
+            gateway_id,
+            interval_ms: DEFAULT_MERKLE_INTERVAL_MS,
+            max_records: DEFAULT_MERKLE_MAX_RECORDS,
+            open: None,
+            sealed: None,
+            history: VecDeque::new(),
+            history_limit: DEFAULT_MERKLE_HISTORY,
+            index: HashMap::new(),
+            next_batch_sequence: 0,
+            metrics: MerkleMetrics::default(),
+        })
+    }
+    
+    pub fn with_interval_ms(mut self, interval_ms: u64) -> Self {
+        self.interval_ms = interval_ms;
+        self
+    }
+    
+    pub fn with_max_records(mut self, max_records: usize) -> Self {
+        self.max_records = max_records.max(1);
+        self
+    }
+    
+    pub fn with_history_limit(mut self, batches: usize) -> Self {
+        self.history_limit = batches.max(1);
+        self
+    }
+    
+    pub fn submitter(&self) -> &S {
+        &self.submitter
+    }
+    
+    pub fn metrics(&self) -> MerkleMetrics {
+        self.metrics
+    }
+    
+    pub fn pending_count(&self) -> usize {
+        let open = self.open.as_ref().map_or(0, |batch| batch.records.len());
+        let sealed = self.sealed.as_ref().map_or(0, |batch| batch.record_ids.len());
+        open + sealed
+    }
+    
+    // Adds the record to the open batch and anchors it if the batch is now
+    // due. A record that is already pending or anchored is ignored.
+    pub fn push(&mut self, record: DLTTransactionRecord, current_time_ms: u64) -> Result<Option<AnchoredBatch>> {
+        let id = record_id(&record);
+        let pending = self.open.as_ref().is_some_and(|batch| batch.ids.contains(&id))
+            || self.sealed.as_ref().is_some_and(|batch| batch.record_ids.contains(&id));
+        
+        if pending || self.index.contains_key(&id) {
+            self.metrics.duplicates_skipped += 1;
+            return Ok(None);
+        }
+        
+        self.metrics.records += 1;
+        let open = self.open.get_or_insert_with(|| OpenBatch {
+            records: Vec::new(),
+            ids: HashSet::new(),
+            opened_at_ms: current_time_ms,
+        });
+        open.ids.insert(id);
+        open.records.push(record);
+        
+        self.poll(current_time_ms)
+    }
+    
+    // Anchors the open batch once its interval has elapsed or it is full.
+    // Call periodically so a quiet gateway still anchors what it has.
+    pub fn poll(&mut self, current_time_ms: u64) -> Result<Option<AnchoredBatch>> {
+        let due = self.sealed.is_some() || self.open.as_ref().is_some_and(|batch| {
+            batch.records.len() >= self.max_records
+                || current_time_ms.saturating_sub(batch.opened_at_ms) >= self.interval_ms
+        });
+        
+        if !due {
+            return Ok(None);
+        }
+        
+        self.flush(current_time_ms)
+    }
+    
+    // Seals and anchors now, regardless of the interval. A batch whose anchor
+    // failed is retried with the same root before a new one is sealed.
+    pub fn flush(&mut self, current_time_ms: u64) -> Result<Option<AnchoredBatch>> {
+        if self.sealed.is_none() {
+            let Some(open) = self.open.take() else {
+                return Ok(None);
+            };
+            self.sealed = Some(self.seal(open, current_time_ms)?);
+        }
+        
+        let Some(sealed) = self.sealed.as_ref() else {
+            return Ok(None);
+        };
+        
+        let receipt = match self.submitter.submit(std::slice::from_ref(&sealed.root_record)) {
+            Ok(mut receipts) if receipts.len() == 1 && receipts[0].record_id == record_id(&sealed.root_record) => {
+                receipts.remove(0)
+            }
+            Ok(_) => {
+                self.metrics.anchor_failures += 1;
+                return Err(CyDnAError::DeserializationError("Anchor receipts do not match the batch"));
+            }
+            Err(e) => {
+                self.metrics.anchor_failures += 1;
+                return Err(e);
+            }
+        };
+        
+        let Some(sealed) = self.sealed.take() else {
+            return Ok(None);
+        };
+        
+        let batch = AnchoredBatch {
+            batch_sequence: self.next_batch_sequence,
+            root: sealed.root,
+            root_record: sealed.root_record,
+            receipt,
+            record_ids: sealed.record_ids,
+            opened_at_ms: sealed.opened_at_ms,
+            sealed_at_ms: sealed.sealed_at_ms,
+        };
+        self.next_batch_sequence += 1;
+        self.metrics.batches_anchored += 1;
+        self.remember(batch.clone());
+        
+        Ok(Some(batch))
+    }
+    
+    // The root record carries the batch's worst case: the highest anomaly
+    // score and whether any record was a critical alert.
+    fn seal(&self, open: OpenBatch, current_time_ms: u64) -> Result<SealedBatch> {
+        let record_ids: Vec<[u8; 32]> = open.records.iter().map(record_id).collect();
+        let root = merkle_root(&record_ids).ok_or(CyDnAError::SerializationError("Empty Merkle batch"))?;
+        
+        let score = open.records.iter().map(|r| r.final_anomaly_score).fold(0.0f32, f32::max);
+        let critical = open.records.iter().any(|r| r.is_critical_alert);
+        let consensus_mode = open.records.iter().map(|r| r.consensus_mode_used).max().unwrap_or(0);
+        
+        let mut root_record = DLTTransactionRecord::new(self.gateway_id, score, critical, consensus_mode, root, [0u8; 64])?;
+        root_record.sign(&self.signing_key);
+        
+        Ok(SealedBatch {
+            root,
+            root_record,
+            record_ids,
+            opened_at_ms: open.opened_at_ms,
+            sealed_at_ms: current_time_ms,
+        })
+    }
+    
+    fn remember(&mut self, batch: AnchoredBatch) {
+        if self.history.len() == self.history_limit {
+            if let Some(oldest) = self.history.pop_front() {
+                for id in &oldest.record_ids {
+                    self.index.remove(id);
+                }
+            }
+        }
+        
+        for id in &batch.record_ids {
+            self.index.insert(*id, batch.batch_sequence);
+        }
+        self.history.push_back(batch);
+    }
+    
+    pub fn batch(&self, batch_sequence: u64) -> Option<&AnchoredBatch> {
+        let first = self.history.front()?.batch_sequence;
+        self.history.get(batch_sequence.checked_sub(first)? as usize)
+    }
+    
+    // The proof together with the batch that anchored its root; `None` while
+    // the record is still pending or once its batch left the history.
+    pub fn proof(&self, record_id: &[u8; 32]) -> Option<(InclusionProof, &AnchoredBatch)> {
+        let batch = self.batch(*self.index.get(record_id)?)?;
+        Some((batch.proof(record_id)?, batch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    struct RecordingLedger {
+        anchored: Vec<DLTTransactionRecord>,
+        fail_next: bool,
+    }
+    
+    impl DltSubmitter for RecordingLedger {
+        fn submit(&mut self, batch: &[DLTTransactionRecord]) -> Result<Vec<AnchorReceipt>> {
+            if std::mem::take(&mut self.fail_next) {
+                return Err(CyDnAError::AnchorRejected(503));
+            }
+            
+            self.anchored.extend_from_slice(batch);
+            Ok(batch.iter().map(|record| AnchorReceipt {
+                record_id: record_id(record),
+                anchor_id: format!("root-{}", self.anchored.len()),
+                anchored_at_ms: 0,
+            }).collect())
+        }
+    }
+    
+    fn record(index: u32, critical: bool) -> DLTTransactionRecord {
+        DLTTransactionRecord::builder()
+            .with_payload_bytes(&index.to_le_bytes())
+            .with_gateway_id(9)
+            .with_anomaly_score(index as f32 / 100.0)
+            .with_critical_alert(critical)
+            .build(&SigningKey::from_bytes(&[3u8; 32]))
+            .unwrap()
+    }
+    
+    #[test]
+    fn test_proofs_for_every_leaf_count() {
+        for count in 1..=9usize {
+            let ids: Vec<[u8; 32]> = (0..count).map(|i| record_id(&record(i as u32, false))).collect();
+            let root = merkle_root(&ids).unwrap();
+            
+            for leaf_index in 0..count {
+                let proof = InclusionProof::build(&ids, leaf_index).unwrap();
+                assert_eq!(proof.root, root);
+                assert!(proof.verify(), "count {} leaf {}", count, leaf_index);
+                
+                if count > 1 {
+                    let mut forged = proof.clone();
+                    forged.record_id = ids[(leaf_index + 1) % count];
+                    assert!(!forged.verify());
+                }
+            }
+        }
+        
+        assert_eq!(merkle_root(&[]), None);
+    }
+    
+    #[test]
+    fn test_batcher_anchors_signed_root() {
+        let signing_key = SigningKey::from_bytes(&[4u8; 32]);
+        let ledger = RecordingLedger { anchored: Vec::new(), fail_next: false };
+        let mut batcher = MerkleBatcher::new(ledger, signing_key.clone(), 9).unwrap()
+            .with_interval_ms(1_000);
+        
+        let records: Vec<_> = (1..=5).map(|i| record(i, i == 3)).collect();
+        for (offset, record) in records.iter().enumerate() {
+            assert!(batcher.push(record.clone(), offset as u64 * 10).unwrap().is_none());
+        }
+        assert!(batcher.push(records[0].clone(), 60).unwrap().is_none());
+        assert_eq!(batcher.pending_count(), 5);
+        
+        // The failed root is retried as-is; the new record opens the next batch.
+        batcher.submitter.fail_next = true;
+        assert!(batcher.poll(1_000).is_err());
+        let batch = batcher.push(record(6, false), 1_001).unwrap().unwrap();
+        
+        assert_eq!(batch.record_ids.len(), 5);
+        assert_eq!(batcher.pending_count(), 1);
+        assert_eq!(batcher.submitter().anchored.len(), 1);
+        assert_eq!(batch.root_record.source_payload_hash, batch.root);
+        assert!(batch.root_record.is_critical_alert);
+        assert!((batch.root_record.final_anomaly_score - 0.05).abs() < 1e-6);
+        batch.root_record.verify(&signing_key.verifying_key()).unwrap();
+        
+        let (proof, anchored) = batcher.proof(&record_id(&records[2])).unwrap();
+        assert!(proof.proves(&records[2]));
+        assert_eq!(proof.root, anchored.root);
+        assert!(batcher.proof(&record_id(&record(6, false))).is_none());
+        
+        let metrics = batcher.metrics();
+        assert_eq!((metrics.records, metrics.batches_anchored, metrics.anchor_failures), (6, 1, 1));
+        assert_eq!(metrics.duplicates_skipped, 1);
+    }
+}
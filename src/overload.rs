@@ -0,0 +1,381 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::errors::{CyDnAError, Result};
+use crate::framing::Priority;
+use crate::lock;
+use crate::rate_limit::{RateLimit, RateLimiter};
+
+pub const DEFAULT_OVERLOAD_QUEUE_DEPTH: usize = 1_024;
+
+pub const DEFAULT_OVERLOAD_LATENCY: Duration = Duration::from_millis(5);
+
+// Critical payloads admitted while shedding, per device and across all
+// devices. The priority flag is set by the sender and not authenticated, so
+// it buys a bounded allowance rather than a way around shedding.
+pub const DEFAULT_CRITICAL_PER_DEVICE: RateLimit = RateLimit { packets_per_second: 1.0, burst: 5 };
+
+pub const DEFAULT_CRITICAL_TOTAL: RateLimit = RateLimit { packets_per_second: 100.0, burst: 200 };
+
+// Handed to the callback on every switch into or out of shedding, with the
+// readings that caused it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OverloadSignal {
+    pub shedding: bool,
+    
+    pub queue_depth: usize,
+    
+    // Smoothed over recent payloads.
+    pub validation_latency: Duration,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SheddingMetrics {
+    pub shedding: bool,
+    
+    pub activations: u64,
+    
+    pub shed: u64,
+    
+    pub critical_admitted: u64,
+    
+    // Critical payloads shed because their allowance was used up.
+    pub critical_shed: u64,
+    
+    pub peak_queue_depth: usize,
+}
+
+type SheddingCallback = dyn Fn(&OverloadSignal) + Send + Sync;
+
+struct DetectorState {
+    latency: Option<Duration>,
+    queue_depth: usize,
+    critical_per_device: RateLimiter,
+    // A single bucket, keyed by device 0, shared by every device.
+    critical_total: RateLimiter,
+    metrics: SheddingMetrics,
+}
+
+// Watches the receive queue depth and validation latency and switches into
+// shedding when either crosses its threshold. It switches back only once
+// both are under half their threshold, so a gateway hovering at the limit
+// does not flap between modes on every payload.
+pub struct OverloadDetector {
+    queue_threshold: usize,
+    latency_threshold: Duration,
+    state: Mutex<DetectorState>,
+    callback: Option<Arc<SheddingCallback>>,
+}
+
+impl OverloadDetector {
+    pub fn new(queue_threshold: usize, latency_threshold: Duration) -> Self {
+        Self {
+            queue_threshold: queue_threshold.max(1),
+            latency_threshold,
+            state: Mutex::new(DetectorState {
+                latency: None,
+                queue_depth: 0,
+                critical_per_device: Self::limiter(DEFAULT_CRITICAL_PER_DEVICE),
+                critical_total: Self::limiter(DEFAULT_CRITICAL_TOTAL).with_max_devices(1),
+                metrics: SheddingMetrics::default(),
+            }),
+            callback: None,
+        }
+    }
+    
+    fn limiter(limit: RateLimit) -> RateLimiter {
+        RateLimiter::new(limit.packets_per_second, limit.burst)
+    }
+    
+    // Replaces the allowance for Critical payloads while shedding.
+    pub fn with_critical_budget(self, per_device: RateLimit, total: RateLimit) -> Self {
+        {
+            let mut state = lock(&self.state);
+            state.critical_per_device = Self::limiter(per_device);
+            state.critical_total = Self::limiter(total).with_max_devices(1);
+        }
+        self
+    }
+    
+    // Runs on the receive thread that saw the change, after the switch has
+    // taken effect; keep it short.
+    pub fn with_callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&OverloadSignal) + Send + Sync + 'static,
+    {
+        self.callback = Some(Arc::new(callback));
+        self
+    }
+    
+    pub fn queue_threshold(&self) -> usize {
+        self.queue_threshold
+    }
+    
+    pub fn latency_threshold(&self) -> Duration {
+        self.latency_threshold
+    }
+    
+    // Feeds one reading; returns whether the gateway is shedding afterwards.
+    pub fn observe(&self, queue_depth: usize, validation_latency: Duration) -> bool {
+        let mut state = lock(&self.state);
+        let latency = match state.latency {
+            Some(smoothed) => smoothed.mul_f64(0.875) + validation_latency.mul_f64(0.125),
+            None => validation_latency,
+        };
+        state.latency = Some(latency);
+        state.queue_depth = queue_depth;
+        state.metrics.peak_queue_depth = state.metrics.peak_queue_depth.max(queue_depth);
+        
+        let overloaded = queue_depth >= self.queue_threshold || latency >= self.latency_threshold;
+        let recovered = queue_depth < self.queue_threshold / 2 && latency < self.latency_threshold / 2;
+        
+        let shedding = match state.metrics.shedding {
+            false => overloaded,
+            true => !recovered,
+        };
+        if shedding == state.metrics.shedding {
+            return shedding;
+        }
+        
+        state.metrics.shedding = shedding;
+        if shedding {
+            state.metrics.activations += 1;
+        }
+        drop(state);
+        
+        if let Some(callback) = &self.callback {
+            callback(&OverloadSignal { shedding, queue_depth, validation_latency: latency });
+        }
+        shedding
+    }
+    
+    // While shedding, everything is refused as rate-limited so sensors back
+    // off and retransmit later, except Critical payloads within the critical
+    // budget; a flood of frames claiming to be Critical is shed like the rest.
+    pub fn admit(&self, device_id: u32, priority: Priority, current_time_ms: u64) -> Result<()> {
+        let mut state = lock(&self.state);
+        if !state.metrics.shedding {
+            return Ok(());
+        }
+        
+        let within_budget = priority == Priority::Critical
+            && state.critical_per_device.check(device_id, current_time_ms).is_ok()
+            && state.critical_total.check(0, current_time_ms).is_ok();
+        
+        match (priority, within_budget) {
+            (_, true) => {
+                state.metrics.critical_admitted += 1;
+                Ok(())
+            }
+            (Priority::Critical, false) => {
+                state.metrics.critical_shed += 1;
+                state.metrics.shed += 1;
+                Err(CyDnAError::RateLimited(device_id))
+            }
+            _ => {
+                state.metrics.shed += 1;
+                Err(CyDnAError::RateLimited(device_id))
+            }
+        }
+    }
+    
+    pub fn is_shedding(&self) -> bool {
+        lock(&self.state).metrics.shedding
+    }
+    
+    pub fn validation_latency(&self) -> Option<Duration> {
+        lock(&self.state).latency
+    }
+    
+    pub fn queue_depth(&self) -> usize {
+        lock(&self.state).queue_depth
+    }
+    
+    pub fn metrics(&self) -> SheddingMetrics {
+        lock(&self.state).metrics
+    }
+}
+
+impl Default for OverloadDetector {
+    fn default() -> Self {
+        Self::new(DEFAULT_OVERLOAD_QUEUE_DEPTH, DEFAULT_OVERLOAD_LATENCY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    
+    #[test]
+    fn test_detector_sheds_with_hysteresis() {
+        let switches = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&switches);
+        let detector = OverloadDetector::new(100, Duration::from_millis(10))
+            .with_callback(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            });
+        
+        assert!(!detector.observe(10, Duration::from_millis(1)));
+        assert!(detector.admit(1, Priority::Normal, 0).is_ok());
+        
+        assert!(detector.observe(150, Duration::from_millis(1)));
+        assert!(matches!(detector.admit(1, Priority::Normal, 0), Err(CyDnAError::RateLimited(1))));
+        assert!(matches!(detector.admit(1, Priority::Bulk, 0), Err(CyDnAError::RateLimited(1))));
+        assert!(detector.admit(1, Priority::Critical, 0).is_ok());
+        
+        // Back under the threshold but not under half of it: still shedding.
+        assert!(detector.observe(80, Duration::from_millis(1)));
+        assert!(!detector.observe(20, Duration::from_millis(1)));
+        
+        let metrics = detector.metrics();
+        assert_eq!((metrics.activations, metrics.shed, metrics.critical_admitted), (1, 2, 1));
+        assert_eq!(metrics.peak_queue_depth, 150);
+        assert_eq!(switches.load(Ordering::SeqCst), 2);
+    }
+    
+    #[test]
+    fn test_critical_flag_only_buys_a_bounded_allowance() {
+        let detector = OverloadDetector::new(10, Duration::from_secs(60))
+            .with_critical_budget(RateLimit::new(1.0, 2), RateLimit::new(0.5, 3));
+        assert!(detector.observe(10, Duration::ZERO));
+        
+        // Per device: two in a burst, then one a second.
+        assert!(detector.admit(1, Priority::Critical, 0).is_ok());
+        assert!(detector.admit(1, Priority::Critical, 0).is_ok());
+        assert!(detector.admit(1, Priority::Critical, 0).is_err());
+        assert!(detector.admit(1, Priority::Critical, 1_000).is_ok());
+        
+        // Spoofing other device ids runs into the shared budget.
+        assert!(detector.admit(2, Priority::Critical, 1_000).is_err());
+        assert!(detector.admit(3, Priority::Critical, 2_000).is_ok());
+        
+        let metrics = detector.metrics();
+        assert_eq!((metrics.critical_admitted, metrics.critical_shed, metrics.shed), (4, 2, 2));
+    }
+    
+    #[test]
+    fn test_latency_is_smoothed() {
+        let detector = OverloadDetector::new(100, Duration::from_millis(10));
+        
+        // One slow payload does not trip it; a sustained slowdown does.
+        assert!(!detector.observe(0, Duration::from_millis(1)));
+        assert!(!detector.observe(0, Duration::from_millis(40)));
+        while !detector.observe(0, Duration::from_millis(40)) {}
+        assert!(detector.validation_latency().unwrap() >= Duration::from_millis(10));
+    }
+    
+    #[test]
+    fn test_poisoned_state_keeps_working() {
+        let detector = OverloadDetector::new(4, Duration::from_secs(60));
+        let _ = std::thread::scope(|scope| scope.spawn(|| {
+            let _state = detector.state.lock().unwrap();
+            panic!("poison the detector");
+        }).join());
+        assert!(detector.state.is_poisoned());
+        
+        assert!(detector.observe(4, Duration::ZERO));
+        assert!(detector.admit(1, Priority::Normal, 0).is_err());
+        assert_eq!(detector.metrics().shed, 1);
+    }
+    
+    #[test]
+    fn test_gateway_sheds_all_but_critical() {
+        use crate::ack_manager::{AckManager, AckMessage};
+        use crate::contracts::{AckPacket, NackReason, SensorPayload, ANOMALY_VECTOR_SIZE};
+        use crate::server::GatewayServer;
+        use crate::stream::OverflowPolicy;
+        use crate::transmitter::Transmitter;
+        use std::net::UdpSocket;
+        use std::time::{SystemTime, UNIX_EPOCH};
+        
+        let transitions = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&transitions);
+        let detector = OverloadDetector::new(2, Duration::from_secs(60))
+            .with_callback(move |signal| recorded.lock().unwrap().push(signal.shedding));
+        
+        let (server, stream) = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_overload_detector(detector)
+            .with_poll_interval_ms(20)
+            .into_stream(64, OverflowPolicy::Nack);
+        let gateway = server.spawn().unwrap();
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        
+        let exchange = |device_id: u32, priority: Priority| -> AckPacket {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+            let payload = SensorPayload::new(device_id, now, 1, 80, 5_000, 0, [0.0; ANOMALY_VECTOR_SIZE]).unwrap();
+            let frame = Transmitter::frame_payload_with_priority(&payload, priority).unwrap();
+            sensor.send_to(&frame, gateway.local_address()).unwrap();
+            
+            let mut buffer = [0u8; 64];
+            let (len, _) = sensor.recv_from(&mut buffer).unwrap();
+            match AckManager::parse_ack_message(&buffer[..len]).unwrap() {
+                Some(AckMessage::Single(ack)) => ack,
+                _ => panic!("expected a single ACK"),
+            }
+        };
+        
+        // The stream backlog reaches the threshold on the third payload.
+        assert!(exchange(1, Priority::Normal).is_ack());
+        assert!(exchange(2, Priority::Normal).is_ack());
+        assert_eq!(exchange(3, Priority::Normal).reason(), NackReason::RateLimited);
+        assert_eq!(exchange(4, Priority::Bulk).reason(), NackReason::RateLimited);
+        assert!(exchange(5, Priority::Critical).is_ack());
+        assert!(gateway.is_shedding());
+        
+        while stream.try_recv().is_some() {}
+        assert!(exchange(3, Priority::Normal).is_ack());
+        assert!(!gateway.is_shedding());
+        
+        let metrics = gateway.shedding_metrics().unwrap();
+        assert_eq!((metrics.activations, metrics.shed, metrics.critical_admitted), (1, 2, 1));
+        assert_eq!(*transitions.lock().unwrap(), [true, false]);
+        gateway.shutdown();
+    }
+    
+    #[test]
+    fn test_socket_backlog_trips_shedding_without_a_stream() {
+        use crate::ack_manager::{AckManager, AckMessage};
+        use crate::contracts::{NackReason, SensorPayload, ANOMALY_VECTOR_SIZE};
+        use crate::server::GatewayServer;
+        use crate::transmitter::Transmitter;
+        use std::net::UdpSocket;
+        use std::time::{SystemTime, UNIX_EPOCH};
+        
+        // A slow first payload lets the rest pile up on the socket.
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_overload_detector(OverloadDetector::new(8, Duration::from_secs(60)))
+            .with_handler(|packet| {
+                if packet.device_unique_id == 1 {
+                    std::thread::sleep(Duration::from_millis(200));
+                }
+            })
+            .with_poll_interval_ms(20)
+            .spawn()
+            .unwrap();
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        for device_id in 1..=24 {
+            let payload = SensorPayload::new(device_id, now, 1, 80, 5_000, 0, [0.0; ANOMALY_VECTOR_SIZE]).unwrap();
+            Transmitter::send(&sensor, &payload, gateway.local_address()).unwrap();
+        }
+        
+        let mut rate_limited = 0;
+        let mut buffer = [0u8; 64];
+        for _ in 1..=24 {
+            let (len, _) = sensor.recv_from(&mut buffer).unwrap();
+            if let Some(AckMessage::Single(ack)) = AckManager::parse_ack_message(&buffer[..len]).unwrap() {
+                rate_limited += usize::from(!ack.is_ack() && ack.reason() == NackReason::RateLimited);
+            }
+        }
+        
+        let metrics = gateway.shedding_metrics().unwrap();
+        assert!(rate_limited > 0);
+        assert!(metrics.activations >= 1);
+        assert!(metrics.peak_queue_depth >= 8);
+        gateway.shutdown();
+    }
+}
@@ -0,0 +1,245 @@
+use std::time::{Duration, Instant};
+
+use crate::errors::{CyDnAError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PacingConfig {
+    pub initial_rate_pps: f64,
+    
+    pub min_rate_pps: f64,
+    
+    pub max_rate_pps: f64,
+    
+    pub additive_increase_pps: f64,
+    
+    pub multiplicative_decrease: f64,
+    
+    // RTT samples above `min_rtt * delay_threshold` count as queue build-up.
+    pub delay_threshold: f64,
+}
+
+impl PacingConfig {
+    // Rates must be finite and positive with min <= max, so the send
+    // interval is always defined, and the decrease factor lies in (0, 1].
+    pub fn validate(&self) -> Result<()> {
+        let values = [
+            self.initial_rate_pps,
+            self.min_rate_pps,
+            self.max_rate_pps,
+            self.additive_increase_pps,
+            self.multiplicative_decrease,
+            self.delay_threshold,
+        ];
+        if !values.iter().all(|value| value.is_finite()) {
+            return Err(CyDnAError::InvalidConfig("Pacing values must be finite"));
+        }
+        if self.min_rate_pps <= 0.0 || self.min_rate_pps > self.max_rate_pps {
+            return Err(CyDnAError::InvalidConfig("Pacing needs 0 < min_rate_pps <= max_rate_pps"));
+        }
+        if self.additive_increase_pps < 0.0 {
+            return Err(CyDnAError::InvalidConfig("Pacing additive increase must not be negative"));
+        }
+        if self.multiplicative_decrease <= 0.0 || self.multiplicative_decrease > 1.0 {
+            return Err(CyDnAError::InvalidConfig("Pacing multiplicative decrease must be in (0, 1]"));
+        }
+        if self.delay_threshold < 1.0 {
+            return Err(CyDnAError::InvalidConfig("Pacing delay threshold must be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+impl Default for PacingConfig {
+    fn default() -> Self {
+        Self {
+            initial_rate_pps: 50.0,
+            min_rate_pps: 1.0,
+            max_rate_pps: 1_000.0,
+            additive_increase_pps: 5.0,
+            multiplicative_decrease: 0.5,
+            delay_threshold: 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PacingMetrics {
+    pub acks: u64,
+    
+    pub losses: u64,
+    
+    pub decreases: u64,
+}
+
+// AIMD on the send rate: every ACK adds roughly `additive_increase_pps` per
+// second worth of traffic, while a loss or a delay spike cuts the rate
+// multiplicatively at most once per smoothed RTT, so one burst of losses
+// from a single congestion event only backs off once.
+pub struct Pacer {
+    config: PacingConfig,
+    rate_pps: f64,
+    next_send: Option<Instant>,
+    srtt: Option<Duration>,
+    min_rtt: Option<Duration>,
+    last_decrease: Option<Instant>,
+    metrics: PacingMetrics,
+}
+
+impl Pacer {
+    pub fn new(config: PacingConfig) -> Result<Self> {
+        config.validate()?;
+        let rate_pps = config.initial_rate_pps.clamp(config.min_rate_pps, config.max_rate_pps);
+        
+        Ok(Self {
+            config,
+            rate_pps,
+            next_send: None,
+            srtt: None,
+            min_rtt: None,
+            last_decrease: None,
+            metrics: PacingMetrics::default(),
+        })
+    }
+    
+    pub fn rate_pps(&self) -> f64 {
+        self.rate_pps
+    }
+    
+    pub fn smoothed_rtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+    
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.rate_pps)
+    }
+    
+    pub fn time_until_send(&self, now: Instant) -> Duration {
+        self.next_send
+            .map(|next| next.saturating_duration_since(now))
+            .unwrap_or(Duration::ZERO)
+    }
+    
+    pub fn can_send(&self, now: Instant) -> bool {
+        self.time_until_send(now).is_zero()
+    }
+    
+    // Idle time is not banked: after a quiet period the next send is
+    // scheduled from `now`, not from the stale slot.
+    pub fn on_send(&mut self, now: Instant) {
+        let start = self.next_send.map(|next| next.max(now)).unwrap_or(now);
+        self.next_send = Some(start + self.interval());
+    }
+    
+    pub fn on_ack(&mut self, rtt: Option<Duration>, now: Instant) {
+        self.metrics.acks += 1;
+        
+        if let Some(sample) = rtt {
+            self.min_rtt = Some(self.min_rtt.map(|min| min.min(sample)).unwrap_or(sample));
+            self.srtt = Some(match self.srtt {
+                Some(srtt) => srtt.mul_f64(0.875) + sample.mul_f64(0.125),
+                None => sample,
+            });
+            
+            let min_rtt = self.min_rtt.unwrap_or(sample);
+            if sample > min_rtt.mul_f64(self.config.delay_threshold) {
+                self.decrease(now);
+                return;
+            }
+        }
+        
+        let increase = self.config.additive_increase_pps / self.rate_pps.max(1.0);
+        self.rate_pps = (self.rate_pps + increase).min(self.config.max_rate_pps);
+    }
+    
+    pub fn on_loss(&mut self, now: Instant) {
+        self.metrics.losses += 1;
+        self.decrease(now);
+    }
+    
+    fn decrease(&mut self, now: Instant) {
+        let epoch = self.srtt.unwrap_or(self.interval());
+        if let Some(last) = self.last_decrease {
+            if now.saturating_duration_since(last) < epoch {
+                return;
+            }
+        }
+        
+        self.rate_pps = (self.rate_pps * self.config.multiplicative_decrease)
+            .max(self.config.min_rate_pps);
+        self.last_decrease = Some(now);
+        self.metrics.decreases += 1;
+    }
+    
+    pub fn metrics(&self) -> PacingMetrics {
+        self.metrics
+    }
+}
+
+impl Default for Pacer {
+    fn default() -> Self {
+        Self::new(PacingConfig::default()).expect("default pacing config is valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_pacing_interval() {
+        let mut pacer = Pacer::new(PacingConfig { initial_rate_pps: 10.0, ..Default::default() }).unwrap();
+        let start = Instant::now();
+        
+        assert!(pacer.can_send(start));
+        pacer.on_send(start);
+        pacer.on_send(start);
+        assert_eq!(pacer.time_until_send(start), Duration::from_millis(200));
+        assert!(pacer.can_send(start + Duration::from_millis(200)));
+        
+        let later = start + Duration::from_secs(5);
+        pacer.on_send(later);
+        assert_eq!(pacer.time_until_send(later), Duration::from_millis(100));
+    }
+    
+    #[test]
+    fn test_invalid_config_is_rejected() {
+        let invalid = [
+            PacingConfig { min_rate_pps: 0.0, ..Default::default() },
+            PacingConfig { min_rate_pps: 10.0, max_rate_pps: 5.0, ..Default::default() },
+            PacingConfig { initial_rate_pps: f64::NAN, ..Default::default() },
+            PacingConfig { multiplicative_decrease: 0.0, ..Default::default() },
+        ];
+        for config in invalid {
+            assert!(matches!(Pacer::new(config), Err(CyDnAError::InvalidConfig(_))));
+        }
+        
+        let pacer = Pacer::new(PacingConfig { initial_rate_pps: 1e9, ..Default::default() }).unwrap();
+        assert_eq!(pacer.rate_pps(), PacingConfig::default().max_rate_pps);
+    }
+    
+    #[test]
+    fn test_aimd_reacts_to_loss_and_delay() {
+        let mut pacer = Pacer::new(PacingConfig { initial_rate_pps: 100.0, ..Default::default() }).unwrap();
+        let start = Instant::now();
+        let rtt = Duration::from_millis(20);
+        
+        for _ in 0..10 {
+            pacer.on_ack(Some(rtt), start);
+        }
+        assert!(pacer.rate_pps() > 100.0);
+        
+        let before = pacer.rate_pps();
+        pacer.on_loss(start);
+        pacer.on_loss(start + Duration::from_millis(5));
+        assert!((pacer.rate_pps() - before / 2.0).abs() < 1e-9);
+        assert_eq!(pacer.metrics().decreases, 1);
+        
+        pacer.on_ack(Some(Duration::from_millis(80)), start + Duration::from_millis(100));
+        assert!((pacer.rate_pps() - before / 4.0).abs() < 1e-9);
+        
+        for _ in 0..100 {
+            pacer.on_loss(start + Duration::from_secs(60));
+        }
+        assert!(pacer.rate_pps() >= PacingConfig::default().min_rate_pps);
+    }
+}
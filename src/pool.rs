@@ -0,0 +1,336 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+use rkyv::AlignedVec;
+
+use crate::contracts::{ArchivedSensorPayload, SensorPayload};
+use crate::errors::{CyDnAError, Result};
+use crate::framing::{FrameHeader, Priority, FRAME_HEADER_SIZE};
+use crate::receiver::Receiver;
+
+pub const DEFAULT_POOL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolMetrics {
+    pub acquired: u64,
+    
+    pub allocated: u64,
+    
+    pub recycled: u64,
+    
+    pub discarded: u64,
+}
+
+struct PacketSlot {
+    data: AlignedVec,
+    len: usize,
+    sender: SocketAddr,
+}
+
+struct PoolShared {
+    free: Mutex<Vec<Arc<PacketSlot>>>,
+    metrics: Mutex<PoolMetrics>,
+    buffer_size: usize,
+    capacity: usize,
+}
+
+// Fixed-size receive buffers recycled through handles: once the last clone of
+// a `PooledPacket` is dropped its buffer (and the Arc around it) goes back on
+// the free list, so steady-state receiving allocates nothing.
+#[derive(Clone)]
+pub struct PacketPool {
+    shared: Arc<PoolShared>,
+}
+
+impl PacketPool {
+    pub fn new(buffer_size: usize, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let pool = Self {
+            shared: Arc::new(PoolShared {
+                free: Mutex::new(Vec::with_capacity(capacity)),
+                metrics: Mutex::new(PoolMetrics::default()),
+                buffer_size,
+                capacity,
+            }),
+        };
+        
+        let slots: Vec<_> = (0..capacity).map(|_| pool.allocate()).collect();
+        pool.shared.free.lock().unwrap().extend(slots);
+        pool
+    }
+    
+    pub fn buffer_size(&self) -> usize {
+        self.shared.buffer_size
+    }
+    
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+    
+    pub fn available(&self) -> usize {
+        self.shared.free.lock().unwrap().len()
+    }
+    
+    pub fn metrics(&self) -> PoolMetrics {
+        *self.shared.metrics.lock().unwrap()
+    }
+    
+    fn allocate(&self) -> Arc<PacketSlot> {
+        let mut data = AlignedVec::with_capacity(self.shared.buffer_size);
+        data.resize(self.shared.buffer_size, 0);
+        self.shared.metrics.lock().unwrap().allocated += 1;
+        
+        Arc::new(PacketSlot {
+            data,
+            len: 0,
+            sender: SocketAddr::from(([0, 0, 0, 0], 0)),
+        })
+    }
+    
+    // Falls back to a fresh allocation when every buffer is checked out.
+    fn acquire(&self) -> Arc<PacketSlot> {
+        self.shared.metrics.lock().unwrap().acquired += 1;
+        
+        let recycled = self.shared.free.lock().unwrap().pop();
+        recycled.unwrap_or_else(|| self.allocate())
+    }
+    
+    fn release(&self, slot: Arc<PacketSlot>) {
+        let mut free = self.shared.free.lock().unwrap();
+        let mut metrics = self.shared.metrics.lock().unwrap();
+        
+        if free.len() < self.shared.capacity {
+            free.push(slot);
+            metrics.recycled += 1;
+        } else {
+            metrics.discarded += 1;
+        }
+    }
+    
+    // Receives one sensor payload frame into a pooled buffer and validates it
+    // once; the returned handle derefs to the archived payload in place.
+    pub fn receive(&self, socket: &UdpSocket) -> Result<PooledPacket> {
+        self.fill_slot(|packet| {
+            socket.recv_from(&mut packet.data)
+                .map_err(|e| CyDnAError::IoError(e.kind()))
+        })
+    }
+    
+    // Like `receive`, but `open` first unwraps an envelope in place: it gets
+    // the buffer and datagram length and returns the length of the frame it
+    // left at the start of the buffer.
+    pub fn receive_with<F>(&self, socket: &UdpSocket, open: F) -> Result<PooledPacket>
+    where
+        F: FnOnce(&mut [u8], usize) -> Result<usize>,
+    {
+        self.fill_slot(|packet| {
+            let (len, sender) = socket.recv_from(&mut packet.data)
+                .map_err(|e| CyDnAError::IoError(e.kind()))?;
+            Ok((open(&mut packet.data, len)?, sender))
+        })
+    }
+    
+    // Copies a frame that arrived some other way (e.g. over TCP) into a
+    // pooled buffer, with the same validation as `receive`.
+    pub fn load(&self, frame: &[u8], sender: SocketAddr) -> Result<PooledPacket> {
+        self.load_with(frame, sender, |_, len| Ok(len))
+    }
+    
+    pub fn load_with<F>(&self, frame: &[u8], sender: SocketAddr, open: F) -> Result<PooledPacket>
+    where
+        F: FnOnce(&mut [u8], usize) -> Result<usize>,
+    {
+        self.fill_slot(|packet| {
+            if frame.len() > packet.data.len() {
+                return Err(CyDnAError::BufferTooSmall {
+                    required: frame.len(),
+                    available: packet.data.len(),
+                });
+            }
+            
+            packet.data[..frame.len()].copy_from_slice(frame);
+            Ok((open(&mut packet.data, frame.len())?, sender))
+        })
+    }
+    
+    fn fill_slot<F>(&self, read: F) -> Result<PooledPacket>
+    where
+        F: FnOnce(&mut PacketSlot) -> Result<(usize, SocketAddr)>,
+    {
+        let mut slot = self.acquire();
+        if Arc::get_mut(&mut slot).is_none() {
+            slot = self.allocate();
+        }
+        
+        let result = match Arc::get_mut(&mut slot) {
+            Some(packet) => read(packet).and_then(|(len, sender)| Self::finish(packet, len, sender)),
+            None => unreachable!("a freshly allocated slot has no other handles"),
+        };
+        
+        match result {
+            Ok(()) => Ok(PooledPacket { slot: Some(slot), pool: self.clone() }),
+            Err(e) => {
+                self.release(slot);
+                Err(e)
+            }
+        }
+    }
+    
+    fn finish(packet: &mut PacketSlot, bytes_received: usize, sender: SocketAddr) -> Result<()> {
+        let frame_len = crate::compression::inflate_in_place(&mut packet.data, bytes_received)?;
+        Receiver::archive_frame(&packet.data[..frame_len])?;
+        
+        packet.len = frame_len;
+        packet.sender = sender;
+        Ok(())
+    }
+    
+    // Appends up to `count` packets to `batch`; a caller reusing the same Vec
+    // across calls keeps the whole loop allocation-free.
+    pub fn receive_batch_into(
+        &self,
+        socket: &UdpSocket,
+        count: usize,
+        batch: &mut Vec<PooledPacket>,
+    ) -> Result<usize> {
+        for received in 0..count {
+            match self.receive(socket) {
+                Ok(packet) => batch.push(packet),
+                Err(CyDnAError::IoError(kind)) if received > 0
+                    && matches!(kind, std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {
+                    return Ok(received);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        
+        Ok(count)
+    }
+}
+
+impl Default for PacketPool {
+    fn default() -> Self {
+        Self::new(crate::MAX_PAYLOAD_SIZE, DEFAULT_POOL_CAPACITY)
+    }
+}
+
+// Cheap to clone (reference count only); derefs to the validated archived
+// payload without copying it out of the receive buffer.
+#[derive(Clone)]
+pub struct PooledPacket {
+    slot: Option<Arc<PacketSlot>>,
+    pool: PacketPool,
+}
+
+impl PooledPacket {
+    fn slot(&self) -> &PacketSlot {
+        self.slot.as_ref().expect("slot is only taken on drop")
+    }
+    
+    pub fn sender(&self) -> SocketAddr {
+        self.slot().sender
+    }
+    
+    pub fn frame(&self) -> &[u8] {
+        let slot = self.slot();
+        &slot.data[..slot.len]
+    }
+    
+    // Frames that fail to parse never get a handle, so the header is sound.
+    pub fn priority(&self) -> Priority {
+        FrameHeader::decode(self.frame())
+            .map(|header| header.priority())
+            .unwrap_or_default()
+    }
+    
+    pub fn payload(&self) -> &ArchivedSensorPayload {
+        // SAFETY: the frame was validated by `Receiver::archive_frame` before
+        // the handle was created, and the buffer cannot be written again until
+        // the last handle is dropped and the slot returns to the pool.
+        unsafe { rkyv::archived_root::<SensorPayload>(&self.frame()[FRAME_HEADER_SIZE..]) }
+    }
+}
+
+impl Deref for PooledPacket {
+    type Target = ArchivedSensorPayload;
+    
+    fn deref(&self) -> &Self::Target {
+        self.payload()
+    }
+}
+
+impl Drop for PooledPacket {
+    fn drop(&mut self) {
+        // Only the last handle recycles the slot. Two clones racing to drop
+        // may both see a count of two, in which case the slot is freed
+        // instead of recycled and the pool allocates a replacement later.
+        if let Some(slot) = self.slot.take() {
+            if Arc::strong_count(&slot) == 1 {
+                self.pool.release(slot);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::ANOMALY_VECTOR_SIZE;
+    use crate::transmitter::Transmitter;
+    use std::time::Duration;
+    
+    #[test]
+    fn test_pool_recycles_buffers() {
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        gateway.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let pool = PacketPool::new(crate::MAX_PAYLOAD_SIZE, 2);
+        let mut batch = Vec::with_capacity(2);
+        
+        for round in 0..3u32 {
+            for id in 1..=2 {
+                let payload = SensorPayload::new(id + round * 10, 1000, 1, 50, 1000, id, [0.5; ANOMALY_VECTOR_SIZE])
+                    .unwrap();
+                Transmitter::send(&sensor, &payload, &gateway_addr).unwrap();
+            }
+            
+            assert_eq!(pool.receive_batch_into(&gateway, 2, &mut batch).unwrap(), 2);
+            assert_eq!(batch[0].device_unique_id, 1 + round * 10);
+            assert_eq!(batch[1].sender(), sensor.local_addr().unwrap());
+            assert_eq!(pool.available(), 0);
+            
+            let kept = batch[0].clone();
+            batch.clear();
+            assert_eq!(pool.available(), 1);
+            assert_eq!(kept.battery_level_percent, 50);
+            drop(kept);
+            assert_eq!(pool.available(), 2);
+        }
+        
+        let metrics = pool.metrics();
+        assert_eq!(metrics.allocated, 2);
+        assert_eq!(metrics.acquired, 6);
+        assert_eq!(metrics.recycled, 6);
+    }
+    
+    #[test]
+    fn test_pool_returns_buffer_on_invalid_frame() {
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        gateway.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        
+        let pool = PacketPool::new(crate::MAX_PAYLOAD_SIZE, 1);
+        sensor.send_to(b"not a frame", gateway.local_addr().unwrap()).unwrap();
+        
+        assert!(pool.receive(&gateway).is_err());
+        assert_eq!(pool.available(), 1);
+        
+        let timed_out = pool.receive(&gateway);
+        assert!(matches!(timed_out, Err(CyDnAError::IoError(_))));
+        assert_eq!(pool.available(), 1);
+        assert_eq!(pool.metrics().allocated, 1);
+    }
+}
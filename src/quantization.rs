@@ -0,0 +1,239 @@
+use half::f16;
+
+use crate::errors::{CyDnAError, Result};
+
+// Worst-case reconstruction error per component, for a vector `x`:
+//
+//   F32        exact.
+//   F16        relative error <= 2^-11 (~0.05%) for 6.1e-5 <= |x| <= 65504;
+//              below that absolute error <= 2^-25, above it values saturate
+//              to +/-65504.
+//   I8Scaled   absolute error <= max|x| / 254 (half a quantisation step,
+//              step = max|x| / 127). All-zero vectors round-trip exactly.
+//   TopK(k)    the k largest-magnitude components are exact; every other
+//              component decodes as 0, so its error is its own magnitude,
+//              bounded by the (k+1)-th largest |x|.
+//
+// Wire size for the 32-dim vector: F32 128 B, F16 64 B, I8Scaled 36 B,
+// TopK(k) 5k B (40 B at k = 8).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VectorEncoding {
+    #[default]
+    F32,
+    F16,
+    I8Scaled,
+    TopK(u8),
+}
+
+impl VectorEncoding {
+    pub fn to_wire(&self) -> (u8, u8) {
+        match self {
+            Self::F32 => (0, 0),
+            Self::F16 => (1, 0),
+            Self::I8Scaled => (2, 0),
+            Self::TopK(k) => (3, *k),
+        }
+    }
+    
+    pub fn from_wire(kind: u8, param: u8) -> Result<Self> {
+        match kind {
+            0 => Ok(Self::F32),
+            1 => Ok(Self::F16),
+            2 => Ok(Self::I8Scaled),
+            3 => Ok(Self::TopK(param)),
+            other => Err(CyDnAError::UnknownVectorEncoding(other)),
+        }
+    }
+    
+    pub fn encoded_len(&self, dims: usize) -> usize {
+        match self {
+            Self::F32 => dims * 4,
+            Self::F16 => dims * 2,
+            Self::I8Scaled => 4 + dims,
+            Self::TopK(k) => (*k as usize).min(dims) * 5,
+        }
+    }
+    
+    // The bound from the table above, evaluated for a concrete vector.
+    pub fn max_abs_error(&self, vector: &[f32]) -> f32 {
+        let max_abs = vector.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+        
+        match self {
+            Self::F32 => 0.0,
+            Self::F16 => {
+                if max_abs > f16::MAX.to_f32() {
+                    max_abs - f16::MAX.to_f32()
+                } else {
+                    (max_abs * f32::powi(2.0, -11)).max(f32::powi(2.0, -25))
+                }
+            }
+            Self::I8Scaled => max_abs / 254.0,
+            Self::TopK(k) => {
+                let mut magnitudes: Vec<f32> = vector.iter().map(|x| x.abs()).collect();
+                magnitudes.sort_by(|a, b| b.total_cmp(a));
+                magnitudes.get(*k as usize).copied().unwrap_or(0.0)
+            }
+        }
+    }
+}
+
+fn require_finite(vector: &[f32]) -> Result<()> {
+    if vector.iter().all(|x| x.is_finite()) {
+        Ok(())
+    } else {
+        Err(CyDnAError::SerializationError(
+            "Anomaly vector contains non-finite values"
+        ))
+    }
+}
+
+pub fn quantize(vector: &[f32], encoding: VectorEncoding) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(encoding.encoded_len(vector.len()));
+    
+    match encoding {
+        VectorEncoding::F32 => {
+            for value in vector {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        VectorEncoding::F16 => {
+            for value in vector {
+                let clamped = value.clamp(f16::MIN.to_f32(), f16::MAX.to_f32());
+                bytes.extend_from_slice(&f16::from_f32(clamped).to_le_bytes());
+            }
+        }
+        VectorEncoding::I8Scaled => {
+            require_finite(vector)?;
+            let max_abs = vector.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+            let scale = if max_abs > 0.0 { max_abs / 127.0 } else { 0.0 };
+            
+            bytes.extend_from_slice(&scale.to_le_bytes());
+            for value in vector {
+                let level = if scale > 0.0 { (value / scale).round().clamp(-127.0, 127.0) } else { 0.0 };
+                bytes.push(level as i8 as u8);
+            }
+        }
+        VectorEncoding::TopK(k) => {
+            require_finite(vector)?;
+            if vector.len() > u8::MAX as usize + 1 {
+                return Err(CyDnAError::SerializationError(
+                    "TopK encoding supports at most 256 dimensions"
+                ));
+            }
+            
+            let mut indices: Vec<usize> = (0..vector.len()).collect();
+            indices.sort_by(|&a, &b| vector[b].abs().total_cmp(&vector[a].abs()).then(a.cmp(&b)));
+            indices.truncate(k as usize);
+            indices.sort_unstable();
+            
+            for index in indices {
+                bytes.push(index as u8);
+                bytes.extend_from_slice(&vector[index].to_le_bytes());
+            }
+        }
+    }
+    
+    Ok(bytes)
+}
+
+pub fn dequantize(bytes: &[u8], encoding: VectorEncoding, dims: usize) -> Result<Vec<f32>> {
+    let malformed = || CyDnAError::InvalidVectorLength(bytes.len());
+    
+    match encoding {
+        VectorEncoding::F32 => {
+            if bytes.len() != dims * 4 {
+                return Err(malformed());
+            }
+            
+            Ok(bytes.chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                .collect())
+        }
+        VectorEncoding::F16 => {
+            if bytes.len() != dims * 2 {
+                return Err(malformed());
+            }
+            
+            Ok(bytes.chunks_exact(2)
+                .map(|chunk| f16::from_le_bytes([chunk[0], chunk[1]]).to_f32())
+                .collect())
+        }
+        VectorEncoding::I8Scaled => {
+            if bytes.len() != 4 + dims {
+                return Err(malformed());
+            }
+            
+            let scale = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            Ok(bytes[4..].iter().map(|&level| level as i8 as f32 * scale).collect())
+        }
+        VectorEncoding::TopK(_) => {
+            if !bytes.len().is_multiple_of(5) {
+                return Err(malformed());
+            }
+            
+            let mut vector = vec![0.0f32; dims];
+            for entry in bytes.chunks_exact(5) {
+                let slot = vector.get_mut(entry[0] as usize).ok_or_else(malformed)?;
+                *slot = f32::from_le_bytes([entry[1], entry[2], entry[3], entry[4]]);
+            }
+            
+            Ok(vector)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn sample_vector() -> Vec<f32> {
+        (0..32).map(|i| ((i as f32) * 0.37).sin() * (1.0 + i as f32 / 8.0)).collect()
+    }
+    
+    #[test]
+    fn test_roundtrip_within_documented_bounds() {
+        let vector = sample_vector();
+        
+        for encoding in [
+            VectorEncoding::F32,
+            VectorEncoding::F16,
+            VectorEncoding::I8Scaled,
+            VectorEncoding::TopK(8),
+        ] {
+            let bytes = quantize(&vector, encoding).unwrap();
+            assert_eq!(bytes.len(), encoding.encoded_len(vector.len()));
+            
+            let decoded = dequantize(&bytes, encoding, vector.len()).unwrap();
+            let bound = encoding.max_abs_error(&vector);
+            for (original, restored) in vector.iter().zip(&decoded) {
+                assert!(
+                    (original - restored).abs() <= bound + f32::EPSILON,
+                    "{:?}: {} vs {} exceeds {}", encoding, original, restored, bound,
+                );
+            }
+        }
+        
+        assert_eq!(VectorEncoding::F16.encoded_len(32), 64);
+        assert_eq!(VectorEncoding::I8Scaled.encoded_len(32), 36);
+    }
+    
+    #[test]
+    fn test_edge_cases() {
+        let zeros = [0.0f32; 32];
+        let bytes = quantize(&zeros, VectorEncoding::I8Scaled).unwrap();
+        assert_eq!(dequantize(&bytes, VectorEncoding::I8Scaled, 32).unwrap(), zeros.to_vec());
+        
+        let mut with_nan = zeros;
+        with_nan[3] = f32::NAN;
+        assert!(quantize(&with_nan, VectorEncoding::I8Scaled).is_err());
+        
+        let huge = [1.0e6f32; 4];
+        let decoded = dequantize(&quantize(&huge, VectorEncoding::F16).unwrap(), VectorEncoding::F16, 4)
+            .unwrap();
+        assert_eq!(decoded[0], 65504.0);
+        
+        assert!(dequantize(&[40, 0, 0, 0, 0], VectorEncoding::TopK(1), 32).is_err());
+        assert_eq!(VectorEncoding::from_wire(3, 8).unwrap(), VectorEncoding::TopK(8));
+        assert_eq!(VectorEncoding::from_wire(9, 0), Err(CyDnAError::UnknownVectorEncoding(9)));
+    }
+}
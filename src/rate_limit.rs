@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+
+use crate::errors::{CyDnAError, Result};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimitMetrics {
+    pub allowed: u64,
+    
+    pub limited: u64,
+    
+    pub evictions: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub packets_per_second: f64,
+    
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(packets_per_second: f64, burst: u32) -> Self {
+        Self {
+            packets_per_second: packets_per_second.max(0.0),
+            burst: burst.max(1),
+        }
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill_ms: u64,
+}
+
+impl TokenBucket {
+    fn refill(&mut self, limit: &RateLimit, current_time_ms: u64) {
+        let elapsed_ms = current_time_ms.saturating_sub(self.last_refill_ms);
+        self.tokens = (self.tokens + elapsed_ms as f64 * limit.packets_per_second / 1000.0)
+            .min(limit.burst as f64);
+        self.last_refill_ms = self.last_refill_ms.max(current_time_ms);
+    }
+}
+
+pub struct RateLimiter {
+    buckets: HashMap<u32, TokenBucket>,
+    overrides: HashMap<u32, RateLimit>,
+    default_limit: RateLimit,
+    max_devices: usize,
+    metrics: RateLimitMetrics,
+}
+
+impl RateLimiter {
+    pub fn new(packets_per_second: f64, burst: u32) -> Self {
+        Self {
+            buckets: HashMap::new(),
+            overrides: HashMap::new(),
+            default_limit: RateLimit::new(packets_per_second, burst),
+            max_devices: 65_536,
+            metrics: RateLimitMetrics::default(),
+        }
+    }
+    
+    pub fn with_max_devices(mut self, max_devices: usize) -> Self {
+        self.max_devices = max_devices.max(1);
+        self
+    }
+    
+    pub fn with_device_limit(mut self, device_id: u32, limit: RateLimit) -> Self {
+        self.set_device_limit(device_id, limit);
+        self
+    }
+    
+    pub fn set_device_limit(&mut self, device_id: u32, limit: RateLimit) {
+        self.overrides.insert(device_id, limit);
+        self.buckets.remove(&device_id);
+    }
+    
+    pub fn clear_device_limit(&mut self, device_id: u32) -> bool {
+        self.buckets.remove(&device_id);
+        self.overrides.remove(&device_id).is_some()
+    }
+    
+    pub fn limit_for(&self, device_id: u32) -> RateLimit {
+        self.overrides.get(&device_id).copied().unwrap_or(self.default_limit)
+    }
+    
+    // Consumes one token from the device's bucket. New devices start with a
+    // full bucket, so a freshly booted sensor can flush its burst immediately.
+    pub fn check(&mut self, device_id: u32, current_time_ms: u64) -> Result<()> {
+        let limit = self.limit_for(device_id);
+        
+        if !self.buckets.contains_key(&device_id) && self.buckets.len() >= self.max_devices {
+            self.evict_idle(current_time_ms);
+            if self.buckets.len() >= self.max_devices {
+                self.evict_least_recent();
+            }
+        }
+        
+        let bucket = self.buckets.entry(device_id).or_insert(TokenBucket {
+            tokens: limit.burst as f64,
+            last_refill_ms: current_time_ms,
+        });
+        bucket.refill(&limit, current_time_ms);
+        
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            self.metrics.allowed += 1;
+            Ok(())
+        } else {
+            self.metrics.limited += 1;
+            Err(CyDnAError::RateLimited(device_id))
+        }
+    }
+    
+    // A bucket that has refilled completely carries no state worth keeping.
+    pub fn evict_idle(&mut self, current_time_ms: u64) -> usize {
+        let before = self.buckets.len();
+        let default_limit = self.default_limit;
+        let overrides = &self.overrides;
+        
+        self.buckets.retain(|device_id, bucket| {
+            let limit = overrides.get(device_id).copied().unwrap_or(default_limit);
+            bucket.refill(&limit, current_time_ms);
+            bucket.tokens < limit.burst as f64
+        });
+        
+        let evicted = before - self.buckets.len();
+        self.metrics.evictions += evicted as u64;
+        evicted
+    }
+    
+    fn evict_least_recent(&mut self) {
+        let oldest = self.buckets.iter()
+            .min_by_key(|(_, bucket)| bucket.last_refill_ms)
+            .map(|(&device_id, _)| device_id);
+        
+        if let Some(device_id) = oldest {
+            self.buckets.remove(&device_id);
+            self.metrics.evictions += 1;
+        }
+    }
+    
+    pub fn tracked_devices(&self) -> usize {
+        self.buckets.len()
+    }
+    
+    pub fn metrics(&self) -> RateLimitMetrics {
+        self.metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_burst_then_refill() {
+        let mut limiter = RateLimiter::new(10.0, 3);
+        
+        for _ in 0..3 {
+            assert!(limiter.check(1, 1_000).is_ok());
+        }
+        assert!(matches!(limiter.check(1, 1_000), Err(CyDnAError::RateLimited(1))));
+        assert!(limiter.check(2, 1_000).is_ok());
+        
+        assert!(limiter.check(1, 1_050).is_err());
+        assert!(limiter.check(1, 1_100).is_ok());
+        assert!(limiter.check(1, 1_100).is_err());
+        
+        let metrics = limiter.metrics();
+        assert_eq!(metrics.allowed, 5);
+        assert_eq!(metrics.limited, 3);
+    }
+    
+    #[test]
+    fn test_device_override_and_eviction() {
+        let mut limiter = RateLimiter::new(1.0, 1)
+            .with_device_limit(7, RateLimit::new(1000.0, 5))
+            .with_max_devices(2);
+        
+        for _ in 0..5 {
+            assert!(limiter.check(7, 0).is_ok());
+        }
+        assert!(limiter.check(1, 0).is_ok());
+        assert!(limiter.check(1, 0).is_err());
+        
+        assert!(limiter.check(2, 10).is_ok());
+        assert_eq!(limiter.tracked_devices(), 2);
+        assert_eq!(limiter.metrics().evictions, 1);
+        
+        assert_eq!(limiter.evict_idle(10_000), 2);
+        assert_eq!(limiter.tracked_devices(), 0);
+    }
+}
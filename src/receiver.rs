@@ -3,34 +3,141 @@ use std::time::Instant;
 
 use rkyv::check_archived_root;
 
-use crate::contracts::SensorPayload;
+use crate::clock::Clock;
+use crate::contracts::{
+    ArchivedSensorPayload, ArchivedSensorPayloadV2, Heartbeat, QuantizedSensorPayload, SensorPayload,
+    SensorPayloadV2,
+};
 use crate::errors::{CyDnAError, Result};
+use crate::framing::{decode_frame, packed_stride, FrameHeader, MessageType, PACKED_HEADER_SIZE};
+use crate::sequence::{SequenceStatus, SequenceTracker};
+use crate::validation::{ValidationContext, ValidationPipeline};
 
 pub struct Receiver;
 
+// Common read-only view over both payload versions; fields that V1 has no
+// room for read as None.
+#[derive(Clone, Copy)]
+pub enum VersionedPayload<'a> {
+    V1(&'a ArchivedSensorPayload),
+    V2(&'a ArchivedSensorPayloadV2),
+}
+
+impl<'a> VersionedPayload<'a> {
+    pub fn version(&self) -> u16 {
+        match self {
+            Self::V1(_) => crate::CYNDA_VERSION,
+            Self::V2(_) => crate::CYNDA_VERSION_V2,
+        }
+    }
+    
+    pub fn device_unique_id(&self) -> u32 {
+        match self {
+            Self::V1(payload) => payload.device_unique_id,
+            Self::V2(payload) => payload.device_unique_id,
+        }
+    }
+    
+    pub fn timestamp_ms_utc(&self) -> u64 {
+        match self {
+            Self::V1(payload) => payload.timestamp_ms_utc,
+            Self::V2(payload) => payload.timestamp_ms_utc,
+        }
+    }
+    
+    pub fn sensor_model_version(&self) -> u16 {
+        match self {
+            Self::V1(payload) => payload.sensor_model_version,
+            Self::V2(payload) => payload.sensor_model_version,
+        }
+    }
+    
+    pub fn battery_level_percent(&self) -> u8 {
+        match self {
+            Self::V1(payload) => payload.battery_level_percent,
+            Self::V2(payload) => payload.battery_level_percent,
+        }
+    }
+    
+    pub fn time_to_live_ms(&self) -> u16 {
+        match self {
+            Self::V1(payload) => payload.time_to_live_ms,
+            Self::V2(payload) => payload.time_to_live_ms,
+        }
+    }
+    
+    pub fn raw_data_hash_crc(&self) -> u32 {
+        match self {
+            Self::V1(payload) => payload.raw_data_hash_crc,
+            Self::V2(payload) => payload.raw_data_hash_crc,
+        }
+    }
+    
+    pub fn sequence_number(&self) -> u32 {
+        match self {
+            Self::V1(payload) => payload.sequence_number,
+            Self::V2(payload) => payload.sequence_number,
+        }
+    }
+    
+    pub fn anomaly_vector(&self) -> &'a [f32] {
+        match self {
+            Self::V1(payload) => &payload.anomaly_ai_vector,
+            Self::V2(payload) => payload.anomaly_ai_vector.as_slice(),
+        }
+    }
+    
+    pub fn temperature_centi_celsius(&self) -> Option<i16> {
+        match self {
+            Self::V1(_) => None,
+            Self::V2(payload) => payload.temperature_centi_celsius.as_ref().copied(),
+        }
+    }
+    
+    pub fn to_v2(&self) -> SensorPayloadV2 {
+        SensorPayloadV2 {
+            device_unique_id: self.device_unique_id(),
+            timestamp_ms_utc: self.timestamp_ms_utc(),
+            sensor_model_version: self.sensor_model_version(),
+            battery_level_percent: self.battery_level_percent(),
+            time_to_live_ms: self.time_to_live_ms(),
+            raw_data_hash_crc: self.raw_data_hash_crc(),
+            sequence_number: self.sequence_number(),
+            anomaly_ai_vector: self.anomaly_vector().to_vec(),
+            temperature_centi_celsius: self.temperature_centi_celsius(),
+        }
+    }
+}
+
 impl Receiver {
     pub fn receive<'a>(
         socket: &UdpSocket,
         buffer: &'a mut [u8],
     ) -> Result<(&'a crate::contracts::ArchivedSensorPayload, usize, std::net::SocketAddr)> {
         let (bytes_received, sender_addr) = socket.recv_from(buffer)
-            .map_err(|e| CyDnAError::IoError(e.to_string()))?;
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
         
-        if bytes_received < std::mem::size_of::<SensorPayload>() {
-            return Err(CyDnAError::InvalidPacketLength {
-                expected: std::mem::size_of::<SensorPayload>(),
-                received: bytes_received,
-            });
-        }
-        
-        let archived = check_archived_root::<SensorPayload>(&buffer[..bytes_received])
-            .map_err(|_| CyDnAError::DeserializationError(
-                "Failed to validate archived payload structure".to_string()
-            ))?;
+        let frame_len = crate::compression::inflate_in_place(buffer, bytes_received)?;
+        let archived = Self::archive_frame(&buffer[..frame_len])?;
         
         Ok((archived, bytes_received, sender_addr))
     }
     
+    // Owned decode for frames in any compiled-in wire format; rkyv frames
+    // should keep using `receive` to stay zero-copy.
+    pub fn receive_decoded(
+        socket: &UdpSocket,
+        buffer: &mut [u8],
+    ) -> Result<(SensorPayload, usize, std::net::SocketAddr)> {
+        let (bytes_received, sender_addr) = socket.recv_from(buffer)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let frame_len = crate::compression::inflate_in_place(buffer, bytes_received)?;
+        let payload = crate::codec::decode_payload_frame(&buffer[..frame_len])?;
+        
+        Ok((payload, bytes_received, sender_addr))
+    }
+    
     pub fn receive_with_ttl_check<'a>(
         socket: &UdpSocket,
         buffer: &'a mut [u8],
@@ -38,32 +145,404 @@ impl Receiver {
     ) -> Result<(&'a crate::contracts::ArchivedSensorPayload, usize, std::net::SocketAddr)> {
         let (archived, bytes_received, sender_addr) = Self::receive(socket, buffer)?;
         
-        let timestamp_ms = archived.timestamp_ms_utc;
-        let ttl_ms = archived.time_to_live_ms as u64;
+        Self::check_ttl(archived, current_time_ms)?;
         
-        if current_time_ms > timestamp_ms.saturating_add(ttl_ms) {
+        Ok((archived, bytes_received, sender_addr))
+    }
+    
+    // Runs the standard validation pipeline: fields, then TTL.
+    pub fn receive_validated<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+        current_time_ms: u64,
+    ) -> Result<(&'a crate::contracts::ArchivedSensorPayload, usize, std::net::SocketAddr)> {
+        Self::receive_with_pipeline(
+            socket,
+            buffer,
+            &ValidationContext::new(current_time_ms),
+            crate::validation::standard_pipeline(),
+        )
+    }
+    
+    pub fn receive_with_pipeline<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+        context: &ValidationContext<'_>,
+        pipeline: &ValidationPipeline,
+    ) -> Result<(&'a crate::contracts::ArchivedSensorPayload, usize, std::net::SocketAddr)> {
+        let (archived, bytes_received, sender_addr) = Self::receive(socket, buffer)?;
+        
+        pipeline.validate(archived, context)?;
+        
+        Ok((archived, bytes_received, sender_addr))
+    }
+    
+    // `receive_validated` with TTLs judged against `clock`; a MonotonicClock
+    // keeps verdicts stable across wall-clock steps.
+    pub fn receive_with_clock<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+        clock: &dyn Clock,
+    ) -> Result<(&'a crate::contracts::ArchivedSensorPayload, usize, std::net::SocketAddr)> {
+        Self::receive_validated(socket, buffer, clock.now_ms())
+    }
+    
+    pub fn receive_tracked<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+        current_time_ms: u64,
+        tracker: &mut SequenceTracker,
+    ) -> Result<(&'a crate::contracts::ArchivedSensorPayload, SequenceStatus, std::net::SocketAddr)> {
+        let (archived, _, sender_addr) = Self::receive_validated(socket, buffer, current_time_ms)?;
+        
+        let status = tracker.observe(archived.device_unique_id, archived.sequence_number);
+        
+        Ok((archived, status, sender_addr))
+    }
+    
+    pub fn receive_guarded<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+        current_time_ms: u64,
+        guard: &mut crate::replay::ReplayGuard,
+    ) -> Result<(&'a crate::contracts::ArchivedSensorPayload, usize, std::net::SocketAddr)> {
+        let (archived, bytes_received, sender_addr) = Self::receive_validated(
+            socket,
+            buffer,
+            current_time_ms,
+        )?;
+        
+        guard.check(
+            archived.device_unique_id,
+            archived.timestamp_ms_utc,
+            archived.sequence_number,
+            current_time_ms,
+        )?;
+        
+        Ok((archived, bytes_received, sender_addr))
+    }
+    
+    // TTL evaluated against the gateway clock translated onto the sender's
+    // clock, so a sensor whose clock lags is not rejected as expired.
+    pub fn receive_skew_corrected<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+        current_time_ms: u64,
+        skew: &crate::time_sync::ClockSkewTracker,
+    ) -> Result<(&'a crate::contracts::ArchivedSensorPayload, usize, std::net::SocketAddr)> {
+        let (archived, bytes_received, sender_addr) = Self::receive(socket, buffer)?;
+        
+        Self::check_ttl(archived, skew.device_time_ms(archived.device_unique_id, current_time_ms))?;
+        Self::check_fields(archived)?;
+        
+        Ok((archived, bytes_received, sender_addr))
+    }
+    
+    pub fn receive_authorized<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+        current_time_ms: u64,
+        access: &mut crate::access::DeviceAccessList,
+    ) -> Result<(&'a crate::contracts::ArchivedSensorPayload, usize, std::net::SocketAddr)> {
+        let (archived, bytes_received, sender_addr) = Self::receive(socket, buffer)?;
+        
+        access.check(archived.device_unique_id)?;
+        Self::check_ttl(archived, current_time_ms)?;
+        Self::check_fields(archived)?;
+        
+        Ok((archived, bytes_received, sender_addr))
+    }
+    
+    // Accepts both the fixed-vector (version 1) and length-prefixed
+    // (version 2) payload, picked by the frame header version.
+    pub fn receive_versioned<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+        current_time_ms: u64,
+    ) -> Result<(VersionedPayload<'a>, usize, std::net::SocketAddr)> {
+        let (bytes_received, sender_addr) = socket.recv_from(buffer)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let frame_len = crate::compression::inflate_in_place(buffer, bytes_received)?;
+        let payload = Self::archive_versioned(&buffer[..frame_len])?;
+        
+        Self::check_versioned(&payload, current_time_ms)?;
+        
+        Ok((payload, bytes_received, sender_addr))
+    }
+    
+    pub(crate) fn archive_versioned(datagram: &[u8]) -> Result<VersionedPayload<'_>> {
+        let header = FrameHeader::decode(datagram)?;
+        header.expect_type(MessageType::SensorPayload)?;
+        
+        if header.version == crate::CYNDA_VERSION {
+            return Ok(VersionedPayload::V1(Self::archive(&datagram[header.body_range()])?));
+        }
+        
+        let archived = check_archived_root::<SensorPayloadV2>(&datagram[header.body_range()])
+            .map_err(|_| CyDnAError::DeserializationError(
+                "Failed to validate archived V2 payload structure"
+            ))?;
+        
+        Ok(VersionedPayload::V2(archived))
+    }
+    
+    pub(crate) fn check_versioned(payload: &VersionedPayload, current_time_ms: u64) -> Result<()> {
+        let timestamp_ms = payload.timestamp_ms_utc();
+        let ttl_ms = payload.time_to_live_ms();
+        
+        if current_time_ms > timestamp_ms.saturating_add(ttl_ms as u64) {
+            return Err(CyDnAError::PayloadExpired { timestamp_ms, ttl_ms });
+        }
+        
+        if payload.device_unique_id() == 0 {
+            return Err(CyDnAError::InvalidDeviceId(0));
+        }
+        
+        if payload.battery_level_percent() > 100 {
+            return Err(CyDnAError::InvalidBatteryLevel(payload.battery_level_percent()));
+        }
+        
+        let vector_len = payload.anomaly_vector().len();
+        if vector_len > crate::contracts::MAX_VARIABLE_VECTOR_SIZE {
+            return Err(CyDnAError::InvalidVectorLength(vector_len));
+        }
+        
+        Ok(())
+    }
+    
+    // Quantized vectors cannot be viewed in place, so this path hands back an
+    // owned, dequantized payload after the usual TTL and field checks.
+    pub fn receive_quantized(
+        socket: &UdpSocket,
+        buffer: &mut [u8],
+        current_time_ms: u64,
+    ) -> Result<(SensorPayload, usize, std::net::SocketAddr)> {
+        let (bytes_received, sender_addr) = socket.recv_from(buffer)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let frame_len = crate::compression::inflate_in_place(buffer, bytes_received)?;
+        let payload = Self::parse_quantized(&buffer[..frame_len])?;
+        
+        let expiry = payload.timestamp_ms_utc.saturating_add(payload.time_to_live_ms as u64);
+        if current_time_ms > expiry {
             return Err(CyDnAError::PayloadExpired {
-                timestamp_ms,
-                ttl_ms: ttl_ms as u16,
+                timestamp_ms: payload.timestamp_ms_utc,
+                ttl_ms: payload.time_to_live_ms,
             });
         }
         
+        Ok((payload, bytes_received, sender_addr))
+    }
+    
+    pub fn parse_quantized(datagram: &[u8]) -> Result<SensorPayload> {
+        let body = decode_frame(datagram, MessageType::QuantizedPayload)?;
+        
+        let archived = check_archived_root::<QuantizedSensorPayload>(body)
+            .map_err(|_| CyDnAError::DeserializationError(
+                "Failed to validate QuantizedSensorPayload"
+            ))?;
+        let quantized: QuantizedSensorPayload = rkyv::Deserialize::deserialize(archived, &mut rkyv::Infallible)
+            .map_err(|_| CyDnAError::DeserializationError(
+                "Failed to deserialize QuantizedSensorPayload"
+            ))?;
+        
+        quantized.to_payload()
+    }
+    
+    pub fn receive_heartbeat(
+        socket: &UdpSocket,
+        buffer: &mut [u8],
+        current_time_ms: u64,
+        liveness: &mut crate::liveness::LivenessTracker,
+    ) -> Result<(Heartbeat, std::net::SocketAddr)> {
+        let (bytes_received, sender_addr) = socket.recv_from(buffer)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let heartbeat = Self::parse_heartbeat(&buffer[..bytes_received])?;
+        if heartbeat.device_unique_id == 0 {
+            return Err(CyDnAError::InvalidDeviceId(0));
+        }
+        
+        liveness.record_heartbeat(&heartbeat, current_time_ms);
+        
+        Ok((heartbeat, sender_addr))
+    }
+    
+    pub fn parse_heartbeat(datagram: &[u8]) -> Result<Heartbeat> {
+        let body = decode_frame(datagram, MessageType::Heartbeat)?;
+        
+        let archived = check_archived_root::<Heartbeat>(body)
+            .map_err(|_| CyDnAError::DeserializationError(
+                "Failed to validate Heartbeat"
+            ))?;
+        
+        Ok(Heartbeat::new(
+            archived.device_unique_id,
+            archived.timestamp_ms_utc,
+            archived.battery_level_percent,
+        ))
+    }
+    
+    // Rate limiting runs before the TTL and field checks so that a flooding
+    // device is turned away as cheaply as possible.
+    pub fn receive_rate_limited<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+        current_time_ms: u64,
+        limiter: &mut crate::rate_limit::RateLimiter,
+    ) -> Result<(&'a crate::contracts::ArchivedSensorPayload, usize, std::net::SocketAddr)> {
+        let (archived, bytes_received, sender_addr) = Self::receive(socket, buffer)?;
+        
+        limiter.check(archived.device_unique_id, current_time_ms)?;
+        Self::check_ttl(archived, current_time_ms)?;
+        Self::check_fields(archived)?;
+        
         Ok((archived, bytes_received, sender_addr))
     }
     
-    pub fn receive_validated<'a>(
+    pub fn receive_packed<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+    ) -> Result<(Vec<&'a crate::contracts::ArchivedSensorPayload>, usize, std::net::SocketAddr)> {
+        let (bytes_received, sender_addr) = socket.recv_from(buffer)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let frame_len = crate::compression::inflate_in_place(buffer, bytes_received)?;
+        let payloads = Self::archive_packed(&buffer[..frame_len])?;
+        
+        Ok((payloads, bytes_received, sender_addr))
+    }
+    
+    pub fn receive_verified<'a>(
         socket: &UdpSocket,
         buffer: &'a mut [u8],
         current_time_ms: u64,
+        raw_data: &[u8],
     ) -> Result<(&'a crate::contracts::ArchivedSensorPayload, usize, std::net::SocketAddr)> {
-        let (archived, bytes_received, sender_addr) = Self::receive_with_ttl_check(
+        let (archived, bytes_received, sender_addr) = Self::receive_validated(
             socket,
             buffer,
             current_time_ms,
         )?;
         
-        let _crc = archived.raw_data_hash_crc;
+        archived.verify_raw_data(raw_data)?;
+        
+        Ok((archived, bytes_received, sender_addr))
+    }
+    
+    #[cfg(feature = "authentication")]
+    pub fn receive_authenticated<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+        authenticator: &crate::authentication::DatagramAuthenticator,
+    ) -> Result<(&'a crate::contracts::ArchivedSensorPayload, usize, std::net::SocketAddr)> {
+        let (bytes_received, sender_addr) = socket.recv_from(buffer)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let (device_id, inner_frame) = authenticator.open(&buffer[..bytes_received])?;
+        let archived = Self::archive_frame(inner_frame)?;
+        
+        if archived.device_unique_id != device_id {
+            return Err(CyDnAError::AuthenticationFailed(archived.device_unique_id));
+        }
+        
+        Ok((archived, bytes_received, sender_addr))
+    }
+    
+    #[cfg(feature = "encryption")]
+    pub fn receive_encrypted<'a>(
+        socket: &UdpSocket,
+        buffer: &'a mut [u8],
+        cipher: &crate::encryption::PayloadCipher,
+    ) -> Result<(&'a crate::contracts::ArchivedSensorPayload, usize, std::net::SocketAddr)> {
+        let (bytes_received, sender_addr) = socket.recv_from(buffer)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let header = crate::framing::FrameHeader::decode(&buffer[..bytes_received])?;
+        header.expect_type(MessageType::EncryptedPayload)?;
+        
+        let (device_id, plaintext) = cipher.open(&mut buffer[header.body_range()])?;
+        let archived = Self::archive(plaintext)?;
+        
+        if archived.device_unique_id != device_id {
+            return Err(CyDnAError::InvalidDeviceId(archived.device_unique_id));
+        }
+        
+        Ok((archived, bytes_received, sender_addr))
+    }
+    
+    pub(crate) fn archive_frame(datagram: &[u8]) -> Result<&crate::contracts::ArchivedSensorPayload> {
+        let body = decode_frame(datagram, MessageType::SensorPayload)?;
+        FrameHeader::decode(datagram)?.expect_format(crate::framing::WireFormat::Rkyv)?;
+        
+        Self::archive(body)
+    }
+    
+    pub(crate) fn archive_packed(
+        datagram: &[u8],
+    ) -> Result<Vec<&crate::contracts::ArchivedSensorPayload>> {
+        let body = decode_frame(datagram, MessageType::PackedPayloads)?;
+        
+        if body.len() < PACKED_HEADER_SIZE {
+            return Err(CyDnAError::InvalidPacketLength {
+                expected: PACKED_HEADER_SIZE,
+                received: body.len(),
+            });
+        }
+        
+        let count = u16::from_le_bytes([body[0], body[1]]) as usize;
+        let entry_len = u16::from_le_bytes([body[2], body[3]]) as usize;
+        let stride = packed_stride(entry_len);
+        
+        let expected = PACKED_HEADER_SIZE + count * stride;
+        if body.len() != expected {
+            return Err(CyDnAError::InvalidPacketLength {
+                expected,
+                received: body.len(),
+            });
+        }
+        
+        (0..count)
+            .map(|idx| {
+                let offset = PACKED_HEADER_SIZE + idx * stride;
+                Self::archive(&body[offset..offset + entry_len])
+            })
+            .collect()
+    }
+    
+    pub(crate) fn archive(bytes: &[u8]) -> Result<&crate::contracts::ArchivedSensorPayload> {
+        if bytes.len() < std::mem::size_of::<SensorPayload>() {
+            return Err(CyDnAError::InvalidPacketLength {
+                expected: std::mem::size_of::<SensorPayload>(),
+                received: bytes.len(),
+            });
+        }
+        
+        check_archived_root::<SensorPayload>(bytes)
+            .map_err(|_| CyDnAError::DeserializationError(
+                "Failed to validate archived payload structure"
+            ))
+    }
+    
+    pub(crate) fn check_ttl(
+        archived: &crate::contracts::ArchivedSensorPayload,
+        current_time_ms: u64,
+    ) -> Result<()> {
+        let timestamp_ms = archived.timestamp_ms_utc;
+        let ttl_ms = archived.time_to_live_ms as u64;
+        
+        if current_time_ms > timestamp_ms.saturating_add(ttl_ms) {
+            return Err(CyDnAError::PayloadExpired {
+                timestamp_ms,
+                ttl_ms: ttl_ms as u16,
+            });
+        }
         
+        Ok(())
+    }
+    
+    pub(crate) fn check_fields(archived: &crate::contracts::ArchivedSensorPayload) -> Result<()> {
         if archived.device_unique_id == 0 {
             return Err(CyDnAError::InvalidDeviceId(0));
         }
@@ -72,9 +551,18 @@ impl Receiver {
             return Err(CyDnAError::InvalidBatteryLevel(archived.battery_level_percent));
         }
         
-        Ok((archived, bytes_received, sender_addr))
+        Ok(())
     }
     
+    pub fn receive_pooled(
+        socket: &UdpSocket,
+        pool: &crate::pool::PacketPool,
+    ) -> Result<crate::pool::PooledPacket> {
+        pool.receive(socket)
+    }
+    
+    // Copies every datagram into its own Vec; prefer
+    // `PacketPool::receive_batch_into` on hot paths.
     pub fn receive_batch(
         socket: &UdpSocket,
         count: usize,
@@ -85,7 +573,7 @@ impl Receiver {
         
         for _ in 0..count {
             let (bytes_received, _) = socket.recv_from(&mut recv_buffer)
-                .map_err(|e| CyDnAError::IoError(e.to_string()))?;
+                .map_err(|e| CyDnAError::IoError(e.kind()))?;
             
             batch.push(recv_buffer[..bytes_received].to_vec());
         }
@@ -135,6 +623,18 @@ impl ReceiverBuilder {
     pub fn is_ttl_check_enabled(&self) -> bool {
         self.enable_ttl_check
     }
+    
+    // The standard pipeline minus whichever checks this builder disables.
+    pub fn validation_pipeline(&self) -> ValidationPipeline {
+        let mut pipeline = ValidationPipeline::standard();
+        if !self.enable_crc_check {
+            pipeline = pipeline.without_stage(crate::validation::ValidationStage::Crc);
+        }
+        if !self.enable_ttl_check {
+            pipeline = pipeline.without_stage(crate::validation::ValidationStage::Ttl);
+        }
+        pipeline
+    }
 }
 
 impl Default for ReceiverBuilder {
@@ -162,21 +662,11 @@ pub fn receive_with_metrics<'a>(
     
     let receive_start = Instant::now();
     let (bytes_received, _sender_addr) = socket.recv_from(buffer)
-        .map_err(|e| CyDnAError::IoError(e.to_string()))?;
+        .map_err(|e| CyDnAError::IoError(e.kind()))?;
     let receive_us = receive_start.elapsed().as_micros() as u64;
     
-    if bytes_received < std::mem::size_of::<SensorPayload>() {
-        return Err(CyDnAError::InvalidPacketLength {
-            expected: std::mem::size_of::<SensorPayload>(),
-            received: bytes_received,
-        });
-    }
-    
     let validation_start = Instant::now();
-    let archived = check_archived_root::<SensorPayload>(&buffer[..bytes_received])
-        .map_err(|_| CyDnAError::DeserializationError(
-            "Failed to validate archived payload structure".to_string()
-        ))?;
+    let archived = Receiver::archive_frame(&buffer[..bytes_received])?;
     let validation_us = validation_start.elapsed().as_micros() as u64;
     
     let total_us = start.elapsed().as_micros() as u64;
@@ -205,4 +695,315 @@ mod tests {
         assert!(!builder.is_crc_check_enabled());
         assert!(builder.is_ttl_check_enabled());
     }
+    
+    #[test]
+    fn test_receive_tracked_flags_retransmission() {
+        use crate::transmitter::Transmitter;
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let payload = SensorPayload::new(
+            2, 1000, 1, 50, 1000, 0x12345678,
+            [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap().with_sequence_number(41);
+        
+        let mut tracker = SequenceTracker::new();
+        let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+        
+        Transmitter::send(&sensor, &payload, &gateway_addr).unwrap();
+        let (archived, status, _) = Receiver::receive_tracked(&gateway, &mut buffer, 1100, &mut tracker)
+            .unwrap();
+        assert_eq!(archived.sequence_number, 41);
+        assert_eq!(status, SequenceStatus::First);
+        
+        Transmitter::send(&sensor, &payload, &gateway_addr).unwrap();
+        let (_, status, _) = Receiver::receive_tracked(&gateway, &mut buffer, 1100, &mut tracker)
+            .unwrap();
+        assert_eq!(status, SequenceStatus::Duplicate);
+    }
+    
+    #[test]
+    fn test_receive_rate_limited() {
+        use crate::rate_limit::RateLimiter;
+        use crate::transmitter::Transmitter;
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let payload = SensorPayload::new(
+            4, 1000, 1, 50, 1000, 0x12345678,
+            [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap();
+        
+        let mut limiter = RateLimiter::new(1.0, 1);
+        let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+        
+        Transmitter::send(&sensor, &payload, &gateway_addr).unwrap();
+        Transmitter::send(&sensor, &payload, &gateway_addr).unwrap();
+        assert!(Receiver::receive_rate_limited(&gateway, &mut buffer, 1100, &mut limiter).is_ok());
+        assert!(matches!(
+            Receiver::receive_rate_limited(&gateway, &mut buffer, 1100, &mut limiter),
+            Err(CyDnAError::RateLimited(4))
+        ));
+    }
+    
+    #[test]
+    fn test_receive_authorized_rejects_unknown_device() {
+        use crate::access::DeviceAccessList;
+        use crate::transmitter::Transmitter;
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let payload = |id: u32| SensorPayload::new(
+            id, 1000, 1, 50, 1000, 0x12345678,
+            [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap();
+        
+        let mut access = DeviceAccessList::new().with_range(1..=10);
+        let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+        
+        Transmitter::send(&sensor, &payload(5), &gateway_addr).unwrap();
+        Transmitter::send(&sensor, &payload(11), &gateway_addr).unwrap();
+        assert!(Receiver::receive_authorized(&gateway, &mut buffer, 1100, &mut access).is_ok());
+        assert!(matches!(
+            Receiver::receive_authorized(&gateway, &mut buffer, 1100, &mut access),
+            Err(CyDnAError::DeviceNotAllowed(11))
+        ));
+        assert_eq!(access.metrics().rejected, 1);
+    }
+    
+    #[test]
+    fn test_heartbeat_updates_liveness() {
+        use crate::liveness::LivenessTracker;
+        use crate::transmitter::Transmitter;
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let mut liveness = LivenessTracker::new(30_000);
+        let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+        
+        Transmitter::send_heartbeat(&sensor, &Heartbeat::new(3, 5_000, 64), &gateway_addr).unwrap();
+        let (heartbeat, _) = Receiver::receive_heartbeat(&gateway, &mut buffer, 5_010, &mut liveness)
+            .unwrap();
+        assert_eq!(heartbeat, Heartbeat::new(3, 5_000, 64));
+        assert!(liveness.is_online(3, 6_000));
+        assert!(!liveness.is_online(3, 40_000));
+        
+        let payload = SensorPayload::new(
+            3, 1000, 1, 50, 1000, 0x12345678,
+            [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap();
+        Transmitter::send(&sensor, &payload, &gateway_addr).unwrap();
+        assert!(matches!(
+            Receiver::receive_heartbeat(&gateway, &mut buffer, 5_010, &mut liveness),
+            Err(CyDnAError::UnexpectedMessageType { .. })
+        ));
+    }
+    
+    #[cfg(feature = "compression-lz4")]
+    #[test]
+    fn test_receive_compressed_packed() {
+        use crate::framing::Compression;
+        use crate::transmitter::Transmitter;
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let payloads: Vec<SensorPayload> = (1..=4)
+            .map(|id| SensorPayload::new(
+                id, 1000, 1, 50, 1000, id,
+                [0.25; crate::contracts::ANOMALY_VECTOR_SIZE],
+            ).unwrap())
+            .collect();
+        
+        let plain_len = Transmitter::frame_packed(&payloads).unwrap().len();
+        let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+        
+        // A gateway without LZ4 support gets the plain frame.
+        let sent = Transmitter::send_packed_compressed(&sensor, &payloads, Compression::Lz4, 0, &gateway_addr)
+            .unwrap();
+        assert_eq!(sent, plain_len);
+        Receiver::receive_packed(&gateway, &mut buffer).unwrap();
+        
+        let sent = Transmitter::send_packed_compressed(
+            &sensor, &payloads, Compression::Lz4, crate::framing::local_compression_mask(), &gateway_addr,
+        ).unwrap();
+        assert!(sent < plain_len);
+        
+        let (archived, received, _) = Receiver::receive_packed(&gateway, &mut buffer).unwrap();
+        assert_eq!(received, sent);
+        assert_eq!(archived.len(), 4);
+        assert_eq!(archived[3].device_unique_id, 4);
+    }
+    
+    #[test]
+    fn test_receive_quantized() {
+        use crate::quantization::VectorEncoding;
+        use crate::transmitter::Transmitter;
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let mut vector = [0.0f32; crate::contracts::ANOMALY_VECTOR_SIZE];
+        vector[4] = 0.75;
+        vector[9] = -0.5;
+        let payload = SensorPayload::new(6, 1000, 2, 40, 1000, 0xabcd, vector)
+            .unwrap()
+            .with_sequence_number(12);
+        
+        let plain = Transmitter::send(&sensor, &payload, &gateway_addr).unwrap();
+        let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+        Receiver::receive(&gateway, &mut buffer).unwrap();
+        
+        let sent = Transmitter::send_quantized(&sensor, &payload, VectorEncoding::I8Scaled, &gateway_addr)
+            .unwrap();
+        assert!(sent < plain);
+        
+        let (received, _, _) = Receiver::receive_quantized(&gateway, &mut buffer, 1100).unwrap();
+        assert_eq!(received.device_unique_id, 6);
+        assert_eq!(received.sequence_number, 12);
+        assert_eq!(received.anomaly_ai_vector[4], 0.75);
+        assert!((received.anomaly_ai_vector[9] + 0.5).abs() <= 0.75 / 254.0);
+    }
+    
+    #[test]
+    fn test_receive_versioned_accepts_v1_and_v2() {
+        use crate::transmitter::Transmitter;
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let v1 = SensorPayload::new(
+            7, 1000, 1, 50, 1000, 0x1,
+            [0.5; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap();
+        let v2 = SensorPayloadV2::new(8, 1000, 3, 60, 1000, 0x2, vec![0.25; 100]).unwrap()
+            .with_temperature_centi_celsius(-1250);
+        
+        Transmitter::send(&sensor, &v1, &gateway_addr).unwrap();
+        Transmitter::send_v2(&sensor, &v2, &gateway_addr).unwrap();
+        Transmitter::send_v2(&sensor, &v2, &gateway_addr).unwrap();
+        
+        let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+        let (payload, _, _) = Receiver::receive_versioned(&gateway, &mut buffer, 1100).unwrap();
+        assert_eq!(payload.version(), crate::CYNDA_VERSION);
+        assert_eq!(payload.anomaly_vector().len(), crate::contracts::ANOMALY_VECTOR_SIZE);
+        assert_eq!(payload.device_unique_id(), 7);
+        assert_eq!(payload.temperature_centi_celsius(), None);
+        assert_eq!(payload.to_v2(), SensorPayloadV2::from(&v1));
+        
+        let (payload, _, _) = Receiver::receive_versioned(&gateway, &mut buffer, 1100).unwrap();
+        assert_eq!(payload.version(), crate::CYNDA_VERSION_V2);
+        assert_eq!(payload.anomaly_vector(), &[0.25; 100][..]);
+        assert_eq!(payload.temperature_centi_celsius(), Some(-1250));
+        assert_eq!(payload.to_v2(), v2);
+        
+        assert!(matches!(
+            Receiver::receive(&gateway, &mut buffer),
+            Err(CyDnAError::UnsupportedVersion { expected: 1, received: 2 })
+        ));
+        
+        assert!(matches!(
+            SensorPayloadV2::new(8, 0, 1, 50, 0, 0, vec![0.0; 241]),
+            Err(CyDnAError::InvalidVectorLength(241))
+        ));
+        assert!(Transmitter::frame_payload_v2(&SensorPayloadV2::new(
+            8, 0, 1, 50, 0, 0, vec![0.0; crate::contracts::MAX_VARIABLE_VECTOR_SIZE],
+        ).unwrap()).is_ok());
+    }
+    
+    #[test]
+    fn test_packed_roundtrip() {
+        use crate::transmitter::Transmitter;
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let payloads: Vec<_> = (1..=4)
+            .map(|id| SensorPayload::new(
+                id, 1000 + id as u64, 1, 50, 1000, id,
+                [id as f32; crate::contracts::ANOMALY_VECTOR_SIZE],
+            ).unwrap())
+            .collect();
+        
+        Transmitter::send_packed(&sensor, &payloads, &gateway_addr).unwrap();
+        
+        let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+        let (archived, _, _) = Receiver::receive_packed(&gateway, &mut buffer).unwrap();
+        
+        assert_eq!(archived.len(), 4);
+        for (idx, payload) in archived.iter().enumerate() {
+            assert_eq!(payload.device_unique_id, idx as u32 + 1);
+            assert_eq!(payload.anomaly_ai_vector[0], (idx + 1) as f32);
+        }
+        
+        assert!(Transmitter::frame_packed(&[]).is_err());
+        assert!(Transmitter::frame_packed(&vec![payloads[0]; 16]).is_err());
+    }
+    
+    #[cfg(feature = "authentication")]
+    #[test]
+    fn test_receive_authenticated() {
+        use crate::authentication::DatagramAuthenticator;
+        use crate::transmitter::Transmitter;
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let mut sensor_auth = DatagramAuthenticator::new();
+        sensor_auth.provision_key(6, b"device-6-secret-key").unwrap();
+        let mut gateway_auth = DatagramAuthenticator::new();
+        gateway_auth.provision_key(6, b"device-6-secret-key").unwrap();
+        
+        let payload = SensorPayload::new(
+            6, 1000, 1, 50, 1000, 0x12345678,
+            [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap();
+        let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+        
+        Transmitter::send_authenticated(&sensor, &payload, &sensor_auth, &gateway_addr).unwrap();
+        let (archived, _, _) = Receiver::receive_authenticated(&gateway, &mut buffer, &gateway_auth)
+            .unwrap();
+        assert_eq!(archived.device_unique_id, 6);
+        
+        Transmitter::send(&sensor, &payload, &gateway_addr).unwrap();
+        let result = Receiver::receive_authenticated(&gateway, &mut buffer, &gateway_auth);
+        assert!(matches!(result, Err(CyDnAError::UnexpectedMessageType { .. })));
+    }
+    
+    #[test]
+    fn test_receive_verified_crc_mismatch() {
+        use crate::transmitter::Transmitter;
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let raw = b"vibration block";
+        let payload = SensorPayload::with_raw_data(
+            1, 1000, 1, 50, 1000, raw,
+            [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap();
+        
+        let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+        
+        Transmitter::send(&sensor, &payload, &gateway_addr).unwrap();
+        assert!(Receiver::receive_verified(&gateway, &mut buffer, 1500, raw).is_ok());
+        
+        Transmitter::send(&sensor, &payload, &gateway_addr).unwrap();
+        let result = Receiver::receive_verified(&gateway, &mut buffer, 1500, b"other block");
+        assert!(matches!(result, Err(CyDnAError::IntegrityCheckFailed { .. })));
+    }
 }
@@ -0,0 +1,267 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::errors::{CyDnAError, Result};
+use crate::sequence::{SequenceStatus, SequenceTracker};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayMetrics {
+    pub accepted: u64,
+    
+    pub replays_rejected: u64,
+    
+    pub too_old_rejected: u64,
+    
+    pub evictions: u64,
+    
+    pub capacity_rejected: u64,
+    
+    pub restarts: u64,
+    
+    // Accepted payloads handed back with `release`.
+    pub released: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DeviceWindow {
+    last_seen_ms: u64,
+    newest_timestamp_ms: u64,
+    epoch_start_ms: u64,
+}
+
+// A datagram older than `max_age_ms` is rejected outright, and a device's
+// window is only ever evicted once it has been silent for longer than that,
+// so anything young enough to pass the age check was seen while its window
+// lived. When every window is younger, a new device is refused as
+// rate-limited instead of pushing one out.
+//
+// A sequence number the window has already passed, but carrying a timestamp
+// newer than anything accepted from the device, is a new epoch (a rebooted
+// sensor counting from 0 again) rather than a replay: a recorded datagram can
+// be no newer than the newest one accepted. Datagrams from before the epoch
+// are rejected by their timestamp.
+pub struct ReplayGuard {
+    tracker: SequenceTracker,
+    devices: HashMap<u32, DeviceWindow>,
+    by_last_seen: BTreeSet<(u64, u32)>,
+    max_devices: usize,
+    entry_ttl_ms: u64,
+    max_age_ms: u64,
+    metrics: ReplayMetrics,
+}
+
+impl ReplayGuard {
+    pub fn new(max_devices: usize, max_age_ms: u64) -> Self {
+        Self {
+            tracker: SequenceTracker::new(),
+            devices: HashMap::new(),
+            by_last_seen: BTreeSet::new(),
+            max_devices: max_devices.max(1),
+            entry_ttl_ms: max_age_ms,
+            max_age_ms,
+            metrics: ReplayMetrics::default(),
+        }
+    }
+    
+    pub fn with_entry_ttl_ms(mut self, entry_ttl_ms: u64) -> Self {
+        self.entry_ttl_ms = entry_ttl_ms.max(self.max_age_ms);
+        self
+    }
+    
+    pub fn check(
+        &mut self,
+        device_id: u32,
+        timestamp_ms: u64,
+        sequence_number: u32,
+        current_time_ms: u64,
+    ) -> Result<()> {
+        // Too old to tell apart from a replay, but possibly never seen: the
+        // sender is told it was dropped rather than that it was a duplicate.
+        if current_time_ms.saturating_sub(timestamp_ms) > self.max_age_ms {
+            self.metrics.too_old_rejected += 1;
+            return Err(CyDnAError::PayloadTooOld { timestamp_ms, max_age_ms: self.max_age_ms });
+        }
+        
+        self.evict_expired(current_time_ms);
+        
+        let window = match self.devices.get(&device_id) {
+            Some(window) => *window,
+            None => {
+                if self.devices.len() >= self.max_devices && !self.evict_least_recent(current_time_ms) {
+                    self.metrics.capacity_rejected += 1;
+                    return Err(CyDnAError::RateLimited(device_id));
+                }
+                DeviceWindow { last_seen_ms: current_time_ms, newest_timestamp_ms: 0, epoch_start_ms: 0 }
+            }
+        };
+        
+        if timestamp_ms < window.epoch_start_ms {
+            self.metrics.replays_rejected += 1;
+            return Err(CyDnAError::ReplayDetected { device_id, sequence_number });
+        }
+        
+        let mut epoch_start_ms = window.epoch_start_ms;
+        match self.tracker.observe(device_id, sequence_number) {
+            SequenceStatus::Duplicate | SequenceStatus::Stale if timestamp_ms > window.newest_timestamp_ms => {
+                self.tracker.restart(device_id, sequence_number);
+                self.metrics.restarts += 1;
+                epoch_start_ms = timestamp_ms;
+            }
+            SequenceStatus::Duplicate | SequenceStatus::Stale => {
+                self.metrics.replays_rejected += 1;
+                return Err(CyDnAError::ReplayDetected { device_id, sequence_number });
+            }
+            _ => {}
+        }
+        
+        self.by_last_seen.remove(&(window.last_seen_ms, device_id));
+        self.by_last_seen.insert((current_time_ms, device_id));
+        self.devices.insert(device_id, DeviceWindow {
+            last_seen_ms: current_time_ms,
+            newest_timestamp_ms: window.newest_timestamp_ms.max(timestamp_ms),
+            epoch_start_ms,
+        });
+        self.metrics.accepted += 1;
+        Ok(())
+    }
+    
+    // Undoes a successful `check` for a payload that was never handled, so
+    // its retransmission is accepted instead of re-ACKed as a duplicate.
+    pub fn release(&mut self, device_id: u32, sequence_number: u32) -> bool {
+        let released = self.tracker.unobserve(device_id, sequence_number);
+        if released {
+            self.metrics.released += 1;
+        }
+        released
+    }
+    
+    pub fn evict_expired(&mut self, current_time_ms: u64) -> usize {
+        let mut evicted = 0;
+        while let Some(&(seen, device_id)) = self.by_last_seen.first() {
+            if current_time_ms.saturating_sub(seen) <= self.entry_ttl_ms {
+                break;
+            }
+            
+            self.forget(device_id);
+            evicted += 1;
+        }
+        
+        evicted
+    }
+    
+    // Only a window past `max_age_ms` may go; see the struct comment.
+    fn evict_least_recent(&mut self, current_time_ms: u64) -> bool {
+        match self.by_last_seen.first() {
+            Some(&(seen, device_id)) if current_time_ms.saturating_sub(seen) > self.max_age_ms => {
+                self.forget(device_id);
+                true
+            }
+            _ => false,
+        }
+    }
+    
+    fn forget(&mut self, device_id: u32) {
+        if let Some(window) = self.devices.remove(&device_id) {
+            self.by_last_seen.remove(&(window.last_seen_ms, device_id));
+        }
+        self.tracker.reset(device_id);
+        self.metrics.evictions += 1;
+    }
+    
+    pub fn tracked_devices(&self) -> usize {
+        self.devices.len()
+    }
+    
+    pub fn metrics(&self) -> ReplayMetrics {
+        self.metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_replay_rejected() {
+        let mut guard = ReplayGuard::new(16, 5_000);
+        
+        assert!(guard.check(1, 1_000, 7, 1_100).is_ok());
+        assert!(guard.check(1, 1_010, 8, 1_100).is_ok());
+        assert!(matches!(
+            guard.check(1, 1_000, 7, 1_200),
+            Err(CyDnAError::ReplayDetected { device_id: 1, sequence_number: 7 })
+        ));
+        assert!(matches!(
+            guard.check(1, 1_000, 9, 10_000),
+            Err(CyDnAError::PayloadTooOld { timestamp_ms: 1_000, max_age_ms: 5_000 })
+        ));
+        
+        let metrics = guard.metrics();
+        assert_eq!(metrics.accepted, 2);
+        assert_eq!(metrics.replays_rejected, 1);
+        assert_eq!(metrics.too_old_rejected, 1);
+    }
+    
+    #[test]
+    fn test_memory_bounded() {
+        let mut guard = ReplayGuard::new(4, 1_000);
+        
+        for device_id in 1..=4 {
+            guard.check(device_id, 100, 0, 100 + device_id as u64).unwrap();
+        }
+        assert_eq!(guard.tracked_devices(), 4);
+        
+        // Every window is still inside the age limit, so none can go.
+        assert!(matches!(guard.check(5, 200, 0, 200), Err(CyDnAError::RateLimited(5))));
+        assert_eq!(guard.tracked_devices(), 4);
+        assert_eq!(guard.metrics().capacity_rejected, 1);
+        
+        guard.check(4, 1_000, 1, 1_000).unwrap();
+        guard.check(5, 1_100, 0, 1_150).unwrap();
+        assert_eq!(guard.tracked_devices(), 2);
+        assert_eq!(guard.metrics().evictions, 3);
+    }
+    
+    #[test]
+    fn test_flood_cannot_evict_a_live_window() {
+        let mut guard = ReplayGuard::new(8, 5_000);
+        guard.check(1, 1_000, 42, 1_000).unwrap();
+        
+        for spoofed in 100..200 {
+            let _ = guard.check(spoofed, 1_100, 0, 1_100);
+        }
+        assert!(guard.check(1, 1_000, 42, 1_200).is_err());
+    }
+    
+    #[test]
+    fn test_released_payload_is_accepted_again() {
+        let mut guard = ReplayGuard::new(8, 5_000);
+        guard.check(1, 1_000, 3, 1_000).unwrap();
+        guard.check(1, 1_010, 4, 1_010).unwrap();
+        
+        assert!(guard.release(1, 4));
+        assert!(!guard.release(1, 4));
+        guard.check(1, 1_010, 4, 1_020).unwrap();
+        assert!(guard.check(1, 1_010, 4, 1_030).is_err());
+        assert!(guard.check(1, 1_000, 3, 1_030).is_err());
+        assert_eq!(guard.metrics().released, 1);
+    }
+    
+    #[test]
+    fn test_rebooted_sensor_starts_new_epoch() {
+        let mut guard = ReplayGuard::new(16, 60_000);
+        for sequence in 0..100 {
+            guard.check(1, 1_000 + sequence as u64, sequence, 1_100).unwrap();
+        }
+        
+        // Counting from 0 again with fresh timestamps after a reboot.
+        guard.check(1, 5_000, 0, 5_000).unwrap();
+        guard.check(1, 5_010, 1, 5_010).unwrap();
+        assert_eq!(guard.metrics().restarts, 1);
+        
+        // Recordings from either epoch stay rejected.
+        assert!(guard.check(1, 5_000, 0, 5_100).is_err());
+        assert!(guard.check(1, 1_099, 99, 5_100).is_err());
+        assert!(guard.check(1, 1_050, 50, 5_100).is_err());
+        assert_eq!(guard.metrics().restarts, 1);
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+
+use crate::contracts::ExtendedAckPacket;
+
+pub const SEQUENCE_WINDOW: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceStatus {
+    First,
+    
+    InOrder,
+    
+    Gap { missing: u32 },
+    
+    Late,
+    
+    Duplicate,
+    
+    Stale,
+    
+    // The sender started a new sequence epoch, e.g. after a reboot.
+    Restarted,
+}
+
+impl SequenceStatus {
+    pub fn is_new(&self) -> bool {
+        !matches!(self, Self::Duplicate | Self::Stale)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SequenceStats {
+    pub received: u64,
+    
+    pub duplicates: u64,
+    
+    pub missing: u64,
+    
+    pub late: u64,
+    
+    pub stale: u64,
+    
+    pub restarts: u64,
+}
+
+impl SequenceStats {
+    pub fn loss_ratio(&self) -> f64 {
+        let expected = self.received + self.missing;
+        if expected == 0 {
+            return 0.0;
+        }
+        
+        self.missing as f64 / expected as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DeviceSequence {
+    first: u32,
+    highest: u32,
+    seen_window: u64,
+    stats: SequenceStats,
+}
+
+pub struct SequenceTracker {
+    devices: HashMap<u32, DeviceSequence>,
+}
+
+impl SequenceTracker {
+    pub fn new() -> Self {
+        Self {
+            devices: HashMap::new(),
+        }
+    }
+    
+    pub fn observe(&mut self, device_id: u32, sequence_number: u32) -> SequenceStatus {
+        let state = match self.devices.get_mut(&device_id) {
+            Some(state) => state,
+            None => {
+                self.devices.insert(device_id, DeviceSequence {
+                    first: sequence_number,
+                    highest: sequence_number,
+                    seen_window: 1,
+                    stats: SequenceStats {
+                        received: 1,
+                        ..SequenceStats::default()
+                    },
+                });
+                return SequenceStatus::First;
+            }
+        };
+        
+        // Signed distance so the tracker survives u32 wraparound.
+        let distance = sequence_number.wrapping_sub(state.highest) as i32;
+        
+        if distance > 0 {
+            let advance = distance as u32;
+            state.seen_window = if advance >= SEQUENCE_WINDOW {
+                1
+            } else {
+                (state.seen_window << advance) | 1
+            };
+            state.highest = sequence_number;
+            state.stats.received += 1;
+            
+            if advance == 1 {
+                return SequenceStatus::InOrder;
+            }
+            
+            state.stats.missing += (advance - 1) as u64;
+            return SequenceStatus::Gap { missing: advance - 1 };
+        }
+        
+        let behind = distance.unsigned_abs();
+        if behind >= SEQUENCE_WINDOW {
+            state.stats.stale += 1;
+            return SequenceStatus::Stale;
+        }
+        
+        let bit = 1u64 << behind;
+        if state.seen_window & bit != 0 {
+            state.stats.duplicates += 1;
+            return SequenceStatus::Duplicate;
+        }
+        
+        state.seen_window |= bit;
+        state.stats.received += 1;
+        state.stats.late += 1;
+        state.stats.missing = state.stats.missing.saturating_sub(1);
+        SequenceStatus::Late
+    }
+    
+    // Starts a new epoch at `sequence_number`, keeping the device's counters.
+    // `observe` alone cannot tell a rebooted sender from a replayed old
+    // datagram, so the caller decides, e.g. from the payload timestamp.
+    pub fn restart(&mut self, device_id: u32, sequence_number: u32) -> SequenceStatus {
+        let mut stats = self.stats(device_id).unwrap_or_default();
+        stats.received += 1;
+        stats.restarts += 1;
+        
+        self.devices.insert(device_id, DeviceSequence {
+            first: sequence_number,
+            highest: sequence_number,
+            seen_window: 1,
+            stats,
+        });
+        SequenceStatus::Restarted
+    }
+    
+    // Un-sees a sequence number still inside the window, so its next arrival
+    // is taken as late rather than a duplicate. Returns false when it is no
+    // longer tracked.
+    pub fn unobserve(&mut self, device_id: u32, sequence_number: u32) -> bool {
+        let Some(state) = self.devices.get_mut(&device_id) else {
+            return false;
+        };
+        
+        let behind = state.highest.wrapping_sub(sequence_number);
+        let bit = match behind < SEQUENCE_WINDOW {
+            true => 1u64 << behind,
+            false => return false,
+        };
+        if state.seen_window & bit == 0 {
+            return false;
+        }
+        
+        state.seen_window &= !bit;
+        state.stats.received = state.stats.received.saturating_sub(1);
+        true
+    }
+    
+    pub fn stats(&self, device_id: u32) -> Option<SequenceStats> {
+        self.devices.get(&device_id).map(|state| state.stats)
+    }
+    
+    pub fn highest_sequence(&self, device_id: u32) -> Option<u32> {
+        self.devices.get(&device_id).map(|state| state.highest)
+    }
+    
+    pub fn extended_ack(&self, device_id: u32) -> Option<ExtendedAckPacket> {
+        let state = self.devices.get(&device_id)?;
+        
+        // Only sequences observed since tracking began count as missing.
+        let known = state.highest.wrapping_sub(state.first).min(SEQUENCE_WINDOW - 1);
+        let is_seen = |behind: u32| state.seen_window & (1u64 << behind) != 0;
+        
+        let oldest_missing = (0..=known).rev().find(|&behind| !is_seen(behind));
+        let cumulative = match oldest_missing {
+            Some(behind) => state.highest.wrapping_sub(behind).wrapping_sub(1),
+            None => state.highest,
+        };
+        
+        let mut bitmap = 0u64;
+        let ahead = state.highest.wrapping_sub(cumulative);
+        for offset in 1..=ahead.min(64) {
+            let behind = ahead - offset;
+            if is_seen(behind) {
+                bitmap |= 1u64 << (offset - 1);
+            }
+        }
+        
+        Some(ExtendedAckPacket::new(device_id, cumulative, bitmap))
+    }
+    
+    pub fn reset(&mut self, device_id: u32) {
+        self.devices.remove(&device_id);
+    }
+    
+    pub fn device_count(&self) -> usize {
+        self.devices.len()
+    }
+}
+
+impl Default for SequenceTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct SequenceCounter {
+    next: u32,
+}
+
+impl SequenceCounter {
+    pub fn new() -> Self {
+        Self { next: 0 }
+    }
+    
+    pub fn starting_at(next: u32) -> Self {
+        Self { next }
+    }
+    
+    pub fn next_sequence(&mut self) -> u32 {
+        let current = self.next;
+        self.next = self.next.wrapping_add(1);
+        current
+    }
+}
+
+impl Default for SequenceCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_duplicates_and_gaps() {
+        let mut tracker = SequenceTracker::new();
+        
+        assert_eq!(tracker.observe(1, 100), SequenceStatus::First);
+        assert_eq!(tracker.observe(1, 101), SequenceStatus::InOrder);
+        assert_eq!(tracker.observe(1, 101), SequenceStatus::Duplicate);
+        assert_eq!(tracker.observe(1, 105), SequenceStatus::Gap { missing: 3 });
+        assert_eq!(tracker.observe(1, 103), SequenceStatus::Late);
+        assert_eq!(tracker.observe(1, 103), SequenceStatus::Duplicate);
+        assert_eq!(tracker.observe(1, 105 - SEQUENCE_WINDOW), SequenceStatus::Stale);
+        
+        let stats = tracker.stats(1).unwrap();
+        assert_eq!(stats.received, 4);
+        assert_eq!(stats.duplicates, 2);
+        assert_eq!(stats.missing, 2);
+        assert_eq!(stats.late, 1);
+        assert_eq!(stats.stale, 1);
+        assert!((stats.loss_ratio() - 2.0 / 6.0).abs() < 1e-9);
+        
+        assert_eq!(tracker.observe(2, 0), SequenceStatus::First);
+        assert_eq!(tracker.device_count(), 2);
+    }
+    
+    #[test]
+    fn test_extended_ack_from_tracker() {
+        let mut tracker = SequenceTracker::new();
+        assert!(tracker.extended_ack(1).is_none());
+        
+        for sequence in [10, 11, 12, 14, 16, 17] {
+            tracker.observe(1, sequence);
+        }
+        
+        let ack = tracker.extended_ack(1).unwrap();
+        assert_eq!(ack.cumulative_sequence, 12);
+        assert_eq!(ack.selective_bitmap, 0b11010);
+        for sequence in [10, 11, 12, 14, 16, 17] {
+            assert!(ack.acknowledges(sequence));
+        }
+        assert!(!ack.acknowledges(13));
+        assert!(!ack.acknowledges(15));
+        
+        tracker.observe(1, 13);
+        tracker.observe(1, 15);
+        let ack = tracker.extended_ack(1).unwrap();
+        assert_eq!(ack.cumulative_sequence, 17);
+        assert_eq!(ack.selective_bitmap, 0);
+    }
+    
+    #[test]
+    fn test_restart_starts_new_epoch() {
+        let mut tracker = SequenceTracker::new();
+        for sequence in 0..200 {
+            tracker.observe(3, sequence);
+        }
+        assert_eq!(tracker.observe(3, 0), SequenceStatus::Stale);
+        
+        assert_eq!(tracker.restart(3, 0), SequenceStatus::Restarted);
+        assert_eq!(tracker.observe(3, 1), SequenceStatus::InOrder);
+        assert_eq!(tracker.observe(3, 0), SequenceStatus::Duplicate);
+        
+        let stats = tracker.stats(3).unwrap();
+        assert_eq!((stats.received, stats.restarts), (202, 1));
+        assert_eq!(tracker.extended_ack(3).unwrap().cumulative_sequence, 1);
+    }
+    
+    #[test]
+    fn test_sequence_wraparound() {
+        let mut tracker = SequenceTracker::new();
+        let mut counter = SequenceCounter::starting_at(u32::MAX - 1);
+        
+        for _ in 0..4 {
+            assert!(tracker.observe(7, counter.next_sequence()).is_new());
+        }
+        
+        assert_eq!(tracker.highest_sequence(7), Some(1));
+        assert_eq!(tracker.observe(7, u32::MAX), SequenceStatus::Duplicate);
+        assert_eq!(tracker.stats(7).unwrap().missing, 0);
+    }
+}
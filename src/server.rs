@@ -0,0 +1,1323 @@
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs, UdpSocket};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::ack_manager::AckManager;
+use crate::clock::{SharedClock, SystemClock};
+use crate::contracts::{AckPacket, NackReason};
+use crate::errors::{CyDnAError, Result};
+use crate::framing::{FrameHeader, MessageType};
+use crate::journal::{DedupJournal, JournalMetrics};
+use crate::lock;
+use crate::overload::{OverloadDetector, SheddingMetrics};
+use crate::pool::{PacketPool, PooledPacket, DEFAULT_POOL_CAPACITY};
+use crate::receiver::Receiver;
+use crate::replay::ReplayGuard;
+use crate::shutdown::ShutdownToken;
+use crate::socket::SocketBuilder;
+use crate::stream::{OverflowPolicy, PayloadQueue, PayloadStream};
+use crate::time_sync::ClockSkewTracker;
+use crate::transport::{FrameTransport, TcpTransport};
+use crate::validation::{ValidationContext, ValidationPipeline, Validator};
+
+pub const DEFAULT_SHUTDOWN_POLL_MS: u64 = 100;
+
+pub const DEFAULT_REPLAY_MAX_DEVICES: usize = 65_536;
+
+pub const DEFAULT_REPLAY_MAX_AGE_MS: u64 = 60_000;
+
+type PayloadHandler = dyn Fn(&PooledPacket) + Send + Sync;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShardMetrics {
+    pub received: u64,
+    
+    pub accepted: u64,
+    
+    pub duplicates: u64,
+    
+    pub rejected: u64,
+    
+    pub malformed: u64,
+    
+    // Frames dropped for a missing, forged or undecryptable envelope.
+    pub unauthenticated: u64,
+    
+    // Payloads whose handler panicked; each was NACKed.
+    pub handler_panics: u64,
+    
+    // Receive loops restarted after a panic outside the handler.
+    pub restarts: u64,
+}
+
+impl ShardMetrics {
+    fn merge(&mut self, other: &ShardMetrics) {
+        self.received += other.received;
+        self.accepted += other.accepted;
+        self.duplicates += other.duplicates;
+        self.rejected += other.rejected;
+        self.malformed += other.malformed;
+        self.unauthenticated += other.unauthenticated;
+        self.handler_panics += other.handler_panics;
+        self.restarts += other.restarts;
+    }
+}
+
+// Receives, validates, de-duplicates and ACKs sensor payloads, handing each
+// accepted one to the handler. Shards share one ReplayGuard so a
+// retransmission landing on a different socket is still recognised.
+pub struct GatewayServer {
+    socket: SocketBuilder,
+    replay: Arc<Mutex<ReplayGuard>>,
+    journal: Option<Arc<Mutex<DedupJournal>>>,
+    handler: Arc<PayloadHandler>,
+    stream: Option<Arc<PayloadQueue>>,
+    skew: Option<ClockSkewTracker>,
+    clock: SharedClock,
+    validation: Arc<ValidationPipeline>,
+    overload: Option<Arc<OverloadDetector>>,
+    envelopes: Envelopes,
+    backlog: Arc<AtomicUsize>,
+    pool_capacity: usize,
+    poll_interval: Duration,
+    tcp_fallback: bool,
+    shutdown: ShutdownToken,
+    #[cfg(feature = "grpc")]
+    grpc_address: Option<SocketAddr>,
+}
+
+impl GatewayServer {
+    pub fn new(bind_address: impl ToSocketAddrs) -> Result<Self> {
+        Ok(Self {
+            socket: SocketBuilder::new(bind_address)?,
+            replay: Arc::new(Mutex::new(
+                ReplayGuard::new(DEFAULT_REPLAY_MAX_DEVICES, DEFAULT_REPLAY_MAX_AGE_MS)
+            )),
+            journal: None,
+            handler: Arc::new(|_: &PooledPacket| {}),
+            stream: None,
+            skew: None,
+            clock: Arc::new(SystemClock),
+            validation: Arc::new(ValidationPipeline::standard()),
+            overload: None,
+            envelopes: Envelopes::default(),
+            backlog: Arc::new(AtomicUsize::new(0)),
+            pool_capacity: DEFAULT_POOL_CAPACITY,
+            poll_interval: Duration::from_millis(DEFAULT_SHUTDOWN_POLL_MS),
+            tcp_fallback: false,
+            shutdown: ShutdownToken::new(),
+            #[cfg(feature = "grpc")]
+            grpc_address: None,
+        })
+    }
+    
+    pub fn with_handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&PooledPacket) + Send + Sync + 'static,
+    {
+        self.handler = Arc::new(handler);
+        self
+    }
+    
+    // Buffer sizes, DSCP and the bind address come from `builder`; reuse-port,
+    // blocking mode and the read timeout are always set by the server itself.
+    pub fn with_socket_builder(mut self, builder: SocketBuilder) -> Self {
+        self.socket = builder;
+        self
+    }
+    
+    pub fn with_replay_guard(mut self, guard: ReplayGuard) -> Self {
+        self.replay = Arc::new(Mutex::new(guard));
+        self
+    }
+    
+    // Also queues every accepted payload on a bounded stream for a consumer
+    // that may fall behind, e.g. one writing to a database. When the stream
+    // is full, `overflow` decides between evicting the oldest payload and
+    // NACKing the new one as rate-limited. The stream closes on shutdown.
+    pub fn into_stream(mut self, capacity: usize, overflow: OverflowPolicy) -> (Self, PayloadStream) {
+        let queue = PayloadQueue::new(capacity, overflow);
+        self.stream = Some(Arc::clone(&queue));
+        (self, PayloadStream::new(queue))
+    }
+    
+    // Evaluates TTL and replay age on each device's own clock, as estimated
+    // by the time-sync exchanges run through `tracker` (or a clone of it).
+    pub fn with_clock_skew(mut self, tracker: ClockSkewTracker) -> Self {
+        self.skew = Some(tracker);
+        self
+    }
+    
+    // Replaces the standard checks (fields, TTL) run on every payload before
+    // dedup. A payload failing validation is NACKed with the reason mapped
+    // from the validator's error.
+    pub fn with_validation_pipeline(mut self, pipeline: ValidationPipeline) -> Self {
+        self.validation = Arc::new(pipeline);
+        self
+    }
+    
+    // Adds a domain check to the current pipeline, after the built-in ones.
+    pub fn with_validator(mut self, validator: impl Validator + 'static) -> Self {
+        let pipeline = ValidationPipeline::clone(&self.validation);
+        self.validation = Arc::new(pipeline.with_validator(validator));
+        self
+    }
+    
+    // Sheds load once `detector` reports an overload: payloads that pass
+    // validation are NACKed as rate-limited, apart from a bounded allowance
+    // for those flagged Critical. Queue depth is the `into_stream` backlog
+    // plus the datagrams the shards have read off their sockets and not yet
+    // answered; each shard drains up to the detector's queue threshold at a
+    // time, so a socket backlog deep enough to trip it is seen.
+    pub fn with_overload_detector(mut self, detector: OverloadDetector) -> Self {
+        self.overload = Some(Arc::new(detector));
+        self
+    }
+    
+    // Accepts only frames sealed under a key `authenticator` holds for the
+    // payload's device; see `Envelopes`.
+    #[cfg(feature = "authentication")]
+    pub fn with_authenticator(self, authenticator: crate::authentication::DatagramAuthenticator) -> Self {
+        self.with_shared_authenticator(Arc::new(Mutex::new(authenticator)))
+    }
+    
+    // As `with_authenticator`, keeping a handle for `KeyRotation` to push
+    // new device keys through while the gateway runs.
+    #[cfg(feature = "authentication")]
+    pub fn with_shared_authenticator(mut self, authenticator: Arc<Mutex<crate::authentication::DatagramAuthenticator>>) -> Self {
+        self.envelopes.authenticator = Some(authenticator);
+        self
+    }
+    
+    // Accepts only payloads encrypted under a key `cipher` holds for their
+    // device; combined with `with_authenticator`, either envelope is enough.
+    #[cfg(feature = "encryption")]
+    pub fn with_cipher(self, cipher: crate::encryption::PayloadCipher) -> Self {
+        self.with_shared_cipher(Arc::new(Mutex::new(cipher)))
+    }
+    
+    #[cfg(feature = "encryption")]
+    pub fn with_shared_cipher(mut self, cipher: Arc<Mutex<crate::encryption::PayloadCipher>>) -> Self {
+        self.envelopes.cipher = Some(cipher);
+        self
+    }
+    
+    // TTL, replay age and journal retention are judged against `clock`; a
+    // MonotonicClock keeps them steady when the host's wall clock is stepped.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+    
+    // Records every accepted payload on disk, so a retransmission of one
+    // handled before a restart is re-ACKed instead of handled again.
+    pub fn with_dedup_journal(mut self, journal: DedupJournal) -> Self {
+        self.journal = Some(Arc::new(Mutex::new(journal)));
+        self
+    }
+    
+    pub fn with_pool_capacity(mut self, capacity: usize) -> Self {
+        self.pool_capacity = capacity;
+        self
+    }
+    
+    // Upper bound on how long a shard takes to notice `shutdown`.
+    pub fn with_poll_interval_ms(mut self, interval_ms: u64) -> Self {
+        self.poll_interval = Duration::from_millis(interval_ms.max(1));
+        self
+    }
+    
+    // Lets a signal handler or admin command stop the gateway without
+    // owning the `ShardedGateway`; `ShardedGateway::wait` then joins it.
+    pub fn with_shutdown_token(mut self, token: ShutdownToken) -> Self {
+        self.shutdown = token;
+        self
+    }
+    
+    // Also listens for length-prefixed TCP on the same port, for sensors
+    // behind firewalls that drop UDP (see `SensorClient::with_tcp_fallback`).
+    pub fn with_tcp_fallback(mut self, enabled: bool) -> Self {
+        self.tcp_fallback = enabled;
+        self
+    }
+    
+    // Also serves the `cynda.v1.Ingestion` gRPC service on `address`, with
+    // the same validation, de-duplication and handler (`grpc` feature).
+    #[cfg(feature = "grpc")]
+    pub fn with_grpc(mut self, address: SocketAddr) -> Self {
+        self.grpc_address = Some(address);
+        self
+    }
+    
+    pub fn spawn(self) -> Result<ShardedGateway> {
+        self.spawn_sharded(1)
+    }
+    
+    // Binds `shards` sockets to the same address with SO_REUSEPORT, so the
+    // kernel spreads senders across them, and runs one receive loop per
+    // socket. Without SO_REUSEPORT (non-Unix) only a single shard can bind.
+    pub fn spawn_sharded(self, shards: usize) -> Result<ShardedGateway> {
+        let shards = shards.max(1);
+        let builder = self.socket.clone()
+            .with_reuse_port(true)
+            .with_nonblocking(false)
+            .with_read_timeout(self.poll_interval);
+        let first = builder.bind()?;
+        let local_address = first.local_addr()
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let mut sockets = vec![first];
+        for _ in 1..shards {
+            sockets.push(builder.bind_to(local_address)?);
+        }
+        
+        let shutdown = self.shutdown.clone();
+        let mut metrics = Vec::with_capacity(shards);
+        let mut threads = Vec::with_capacity(shards);
+        
+        for (index, socket) in sockets.into_iter().enumerate() {
+            let shard = Shard {
+                socket,
+                pool: PacketPool::new(crate::MAX_PAYLOAD_SIZE, self.pool_capacity),
+                pipeline: self.pipeline(),
+                shutdown: shutdown.clone(),
+            };
+            metrics.push(Arc::clone(&shard.pipeline.metrics));
+            
+            let thread = std::thread::Builder::new()
+                .name(format!("cynda-shard-{}", index))
+                .spawn(move || shard.supervise())
+                .map_err(|e| CyDnAError::IoError(e.kind()))?;
+            threads.push(thread);
+        }
+        
+        let mut tcp_metrics = None;
+        if self.tcp_fallback {
+            let listener = TcpListener::bind(local_address)
+                .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+                .map_err(|e| CyDnAError::IoError(e.kind()))?;
+            
+            let acceptor = TcpAcceptor {
+                listener,
+                pool: PacketPool::new(crate::MAX_PAYLOAD_SIZE, self.pool_capacity),
+                pipeline: self.pipeline(),
+                poll_interval: self.poll_interval,
+                shutdown: shutdown.clone(),
+            };
+            tcp_metrics = Some(Arc::clone(&acceptor.pipeline.metrics));
+            
+            let thread = std::thread::Builder::new()
+                .name("cynda-tcp-accept".to_string())
+                .spawn(move || acceptor.run())
+                .map_err(|e| CyDnAError::IoError(e.kind()))?;
+            threads.push(thread);
+        }
+        
+        #[cfg(feature = "grpc")]
+        let grpc = self.spawn_grpc(&shutdown, &mut threads)?;
+        #[cfg(not(feature = "grpc"))]
+        let grpc = None;
+        
+        Ok(ShardedGateway {
+            local_address,
+            replay: self.replay,
+            journal: self.journal,
+            stream: self.stream,
+            overload: self.overload,
+            metrics,
+            tcp_metrics,
+            grpc,
+            threads,
+            shutdown,
+        })
+    }
+    
+    #[cfg(feature = "grpc")]
+    fn spawn_grpc(
+        &self,
+        shutdown: &ShutdownToken,
+        threads: &mut Vec<JoinHandle<()>>,
+    ) -> Result<Option<(SocketAddr, Arc<Mutex<ShardMetrics>>)>> {
+        let Some(address) = self.grpc_address else {
+            return Ok(None);
+        };
+        
+        let endpoint = crate::grpc::GrpcEndpoint::bind(
+            address,
+            PacketPool::new(crate::MAX_PAYLOAD_SIZE, self.pool_capacity),
+            self.pipeline(),
+            self.poll_interval,
+            shutdown.clone(),
+        )?;
+        let grpc = (endpoint.local_address(), Arc::clone(&endpoint.pipeline().metrics));
+        
+        let thread = std::thread::Builder::new()
+            .name("cynda-grpc".to_string())
+            .spawn(move || endpoint.run())
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        threads.push(thread);
+        
+        Ok(Some(grpc))
+    }
+    
+    fn pipeline(&self) -> Pipeline {
+        Pipeline {
+            replay: Arc::clone(&self.replay),
+            journal: self.journal.clone(),
+            handler: Arc::clone(&self.handler),
+            stream: self.stream.clone(),
+            skew: self.skew.clone(),
+            clock: Arc::clone(&self.clock),
+            validation: Arc::clone(&self.validation),
+            overload: self.overload.clone(),
+            envelopes: self.envelopes.clone(),
+            backlog: Arc::clone(&self.backlog),
+            metrics: Arc::new(Mutex::new(ShardMetrics::default())),
+        }
+    }
+}
+
+// Envelopes the gateway requires around every sensor frame. Once any is
+// configured a bare frame is refused like a forged one, and neither gets a
+// reply: answering an unauthenticated source address would turn the gateway
+// into a reflector.
+#[derive(Clone, Default)]
+struct Envelopes {
+    #[cfg(feature = "authentication")]
+    authenticator: Option<Arc<Mutex<crate::authentication::DatagramAuthenticator>>>,
+    #[cfg(feature = "encryption")]
+    cipher: Option<Arc<Mutex<crate::encryption::PayloadCipher>>>,
+}
+
+impl Envelopes {
+    fn is_required(&self) -> bool {
+        [
+            #[cfg(feature = "authentication")]
+            self.authenticator.is_some(),
+            #[cfg(feature = "encryption")]
+            self.cipher.is_some(),
+        ].into_iter().any(|required| required)
+    }
+    
+    // Rewrites `buffer` to start with the sensor frame the envelope carried
+    // and returns that frame's length.
+    fn open(&self, buffer: &mut [u8], len: usize) -> Result<usize> {
+        let header = FrameHeader::decode(&buffer[..len])?;
+        
+        #[cfg(feature = "authentication")]
+        if let (MessageType::Authenticated, Some(authenticator)) = (header.message_type, &self.authenticator) {
+            let (device_id, inner_frame) = lock(authenticator).open(&buffer[..len])?;
+            if Receiver::archive_frame(inner_frame)?.device_unique_id != device_id {
+                return Err(CyDnAError::AuthenticationFailed(device_id));
+            }
+            
+            let start = crate::framing::FRAME_HEADER_SIZE + crate::authentication::AUTH_PREFIX_SIZE;
+            let inner_len = inner_frame.len();
+            buffer.copy_within(start..start + inner_len, 0);
+            return Ok(inner_len);
+        }
+        
+        #[cfg(feature = "encryption")]
+        if let (MessageType::EncryptedPayload, Some(cipher)) = (header.message_type, &self.cipher) {
+            let body = header.body_range();
+            let (device_id, plaintext) = lock(cipher).open(&mut buffer[body.clone()])?;
+            if Receiver::archive(plaintext)?.device_unique_id != device_id {
+                return Err(CyDnAError::InvalidDeviceId(device_id));
+            }
+            
+            // The plaintext is a bare payload; give it back its own header.
+            let start = body.start + crate::encryption::ENVELOPE_HEADER_SIZE;
+            let plaintext_len = plaintext.len();
+            let inner = FrameHeader {
+                message_type: MessageType::SensorPayload,
+                payload_len: plaintext_len as u16,
+                ..header
+            };
+            let header_len = crate::framing::FRAME_HEADER_SIZE;
+            buffer.copy_within(start..start + plaintext_len, header_len);
+            buffer[..header_len].copy_from_slice(&inner.encode());
+            return Ok(header_len + plaintext_len);
+        }
+        
+        header.expect_type(MessageType::SensorPayload)?;
+        let device_id = Receiver::archive_frame(&buffer[..len]).map_or(0, |payload| payload.device_unique_id);
+        Err(CyDnAError::AuthenticationFailed(device_id))
+    }
+}
+
+// Validation, dedup, handler and reply shared by the UDP shards, the TCP
+// fallback sessions and the gRPC endpoint.
+#[derive(Clone)]
+pub(crate) struct Pipeline {
+    replay: Arc<Mutex<ReplayGuard>>,
+    journal: Option<Arc<Mutex<DedupJournal>>>,
+    handler: Arc<PayloadHandler>,
+    stream: Option<Arc<PayloadQueue>>,
+    skew: Option<ClockSkewTracker>,
+    clock: SharedClock,
+    validation: Arc<ValidationPipeline>,
+    overload: Option<Arc<OverloadDetector>>,
+    envelopes: Envelopes,
+    // Datagrams read off the shards' sockets and not yet decided, across
+    // every shard.
+    backlog: Arc<AtomicUsize>,
+    pub(crate) metrics: Arc<Mutex<ShardMetrics>>,
+}
+
+impl Pipeline {
+    pub(crate) fn receive(&self, pool: &PacketPool, socket: &UdpSocket) -> Result<PooledPacket> {
+        match self.envelopes.is_required() {
+            true => pool.receive_with(socket, |buffer, len| self.envelopes.open(buffer, len)),
+            false => pool.receive(socket),
+        }
+    }
+    
+    pub(crate) fn load(&self, pool: &PacketPool, frame: &[u8], sender: SocketAddr) -> Result<PooledPacket> {
+        match self.envelopes.is_required() {
+            true => pool.load_with(frame, sender, |buffer, len| self.envelopes.open(buffer, len)),
+            false => pool.load(frame, sender),
+        }
+    }
+    
+    // Counts a frame that never became a packet, by why it did not.
+    pub(crate) fn record_unreadable(&self, error: &CyDnAError) {
+        let mut metrics = lock(&self.metrics);
+        metrics.received += 1;
+        match error {
+            CyDnAError::AuthenticationFailed(_)
+            | CyDnAError::DecryptionFailed(_)
+            | CyDnAError::UnknownDeviceKey(_)
+            | CyDnAError::InvalidDeviceId(_) => metrics.unauthenticated += 1,
+            _ => metrics.malformed += 1,
+        }
+    }
+    
+    // Returns the ACK or NACK frame for the sender.
+    fn process(&self, packet: &PooledPacket) -> Result<Vec<u8>> {
+        AckManager::encode_ack(&self.decide(packet))
+    }
+    
+    pub(crate) fn decide(&self, packet: &PooledPacket) -> AckPacket {
+        let now_ms = self.clock.now_ms();
+        
+        // Sensor timestamps are only comparable with the gateway clock once
+        // translated; devices without an estimate are taken as in sync.
+        let device_now_ms = match &self.skew {
+            Some(skew) => skew.device_time_ms(packet.device_unique_id, now_ms),
+            None => now_ms,
+        };
+        
+        let started = self.clock.now();
+        let validated = self.validation.validate(packet, &ValidationContext::new(device_now_ms));
+        if let Some(overload) = &self.overload {
+            let queue_depth = self.backlog.load(Ordering::Relaxed) + self.stream.as_ref().map_or(0, |stream| stream.len());
+            overload.observe(queue_depth, self.clock.now().saturating_duration_since(started));
+        }
+        
+        // Shedding comes after validation so the latency estimate keeps
+        // updating while shedding; what it saves is the handler, stream and
+        // journal work. It comes before the replay guard for the same reason
+        // the stream check does.
+        let validated = validated
+            .and_then(|_| self.check_journal(packet))
+            .and_then(|_| match &self.overload {
+                Some(overload) => overload.admit(packet.device_unique_id, packet.priority(), now_ms),
+                None => Ok(()),
+            })
+            .and_then(|_| match &self.stream {
+                Some(stream) => stream.check_capacity(packet.device_unique_id),
+                None => Ok(()),
+            })
+            .and_then(|_| lock(&self.replay).check(
+                packet.device_unique_id,
+                packet.timestamp_ms_utc,
+                packet.sequence_number,
+                device_now_ms,
+            ));
+        
+        let mut metrics = lock(&self.metrics);
+        metrics.received += 1;
+        
+        // Duplicates are re-ACKed: the sensor only retransmits because the
+        // first ACK was lost.
+        match validated {
+            Ok(()) => {
+                drop(metrics);
+                
+                // The payload was never handled, so the replay guard forgets
+                // it and the sensor's retransmission gets another attempt.
+                if std::panic::catch_unwind(AssertUnwindSafe(|| (self.handler)(packet))).is_err() {
+                    lock(&self.replay).release(packet.device_unique_id, packet.sequence_number);
+                    let mut metrics = lock(&self.metrics);
+                    metrics.handler_panics += 1;
+                    metrics.rejected += 1;
+                    return AckPacket::nack_with_reason(
+                        packet.device_unique_id,
+                        packet.timestamp_ms_utc,
+                        NackReason::Unspecified,
+                    );
+                }
+                lock(&self.metrics).accepted += 1;
+                
+                if let Some(stream) = &self.stream {
+                    stream.push(packet.clone());
+                }
+                
+                // Recorded after the handler: a crash in between means a
+                // possible second handling after restart, never a lost one.
+                // A failed append has the same consequence and no other.
+                if let Some(journal) = &self.journal {
+                    let _ = lock(journal).record(
+                        packet.device_unique_id,
+                        packet.sequence_number,
+                        packet.timestamp_ms_utc,
+                        now_ms,
+                    );
+                }
+                AckPacket::ack(packet.device_unique_id, packet.timestamp_ms_utc)
+            }
+            Err(CyDnAError::ReplayDetected { .. }) => {
+                metrics.duplicates += 1;
+                AckPacket::ack(packet.device_unique_id, packet.timestamp_ms_utc)
+            }
+            Err(e) => {
+                metrics.rejected += 1;
+                AckPacket::nack_with_reason(
+                    packet.device_unique_id,
+                    packet.timestamp_ms_utc,
+                    NackReason::from_error(&e),
+                )
+            }
+        }
+    }
+    
+    // A journal hit is a retransmission of a payload handled before the
+    // last restart.
+    fn check_journal(&self, packet: &PooledPacket) -> Result<()> {
+        let journaled = self.journal.as_ref().is_some_and(|journal| lock(journal).contains(
+            packet.device_unique_id,
+            packet.sequence_number,
+            packet.timestamp_ms_utc,
+        ));
+        
+        match journaled {
+            true => Err(CyDnAError::ReplayDetected {
+                device_id: packet.device_unique_id,
+                sequence_number: packet.sequence_number,
+            }),
+            false => Ok(()),
+        }
+    }
+}
+
+struct Shard {
+    socket: UdpSocket,
+    pool: PacketPool,
+    pipeline: Pipeline,
+    shutdown: ShutdownToken,
+}
+
+impl Shard {
+    // A panic outside the handler restarts the receive loop instead of
+    // leaving the socket unread for the rest of the process's life.
+    fn supervise(self) {
+        while std::panic::catch_unwind(AssertUnwindSafe(|| self.run())).is_err() {
+            lock(&self.pipeline.metrics).restarts += 1;
+            let _ = self.socket.set_nonblocking(self.shutdown.is_triggered());
+        }
+    }
+    
+    fn run(&self) {
+        let mut batch = Vec::new();
+        while !self.shutdown.is_triggered() {
+            self.receive_batch(&mut batch, true);
+        }
+        
+        // Datagrams already queued on the socket were sent before the
+        // shutdown; ACK them so their senders do not retransmit into a
+        // gateway that is gone.
+        if self.socket.set_nonblocking(true).is_ok() {
+            while self.receive_batch(&mut batch, false) {}
+        }
+    }
+    
+    // Waits for one datagram (unless the socket is already non-blocking),
+    // then, with an overload detector attached, reads whatever is queued
+    // behind it, so the detector sees the socket's backlog. The packet being
+    // decided is not part of its own backlog, hence one past the threshold.
+    // Returns false once the socket has nothing more to read.
+    fn receive_batch(&self, batch: &mut Vec<PooledPacket>, blocking: bool) -> bool {
+        if !self.receive_into(batch) {
+            return false;
+        }
+        
+        if let Some(overload) = &self.pipeline.overload {
+            if !blocking || self.socket.set_nonblocking(true).is_ok() {
+                let limit = overload.queue_threshold().saturating_add(1);
+                while batch.len() < limit && self.receive_into(batch) {}
+                if blocking {
+                    let _ = self.socket.set_nonblocking(false);
+                }
+            }
+        }
+        
+        let backlog = &self.pipeline.backlog;
+        backlog.fetch_add(batch.len(), Ordering::Relaxed);
+        for packet in batch.drain(..) {
+            backlog.fetch_sub(1, Ordering::Relaxed);
+            if let Ok(reply) = self.pipeline.process(&packet) {
+                let _ = self.socket.send_to(&reply, packet.sender());
+            }
+        }
+        true
+    }
+    
+    // Returns false once the socket has nothing more to read; a malformed
+    // or unauthenticated datagram is counted and skipped.
+    fn receive_into(&self, batch: &mut Vec<PooledPacket>) -> bool {
+        match self.pipeline.receive(&self.pool, &self.socket) {
+            Ok(packet) => {
+                batch.push(packet);
+                true
+            }
+            Err(CyDnAError::IoError(_)) => false,
+            Err(e) => {
+                self.pipeline.record_unreadable(&e);
+                true
+            }
+        }
+    }
+}
+
+// Accepts sensors that fell back to TCP on the gateway's port and serves
+// each connection on its own thread with the same pipeline as the shards.
+struct TcpAcceptor {
+    listener: TcpListener,
+    pool: PacketPool,
+    pipeline: Pipeline,
+    poll_interval: Duration,
+    shutdown: ShutdownToken,
+}
+
+impl TcpAcceptor {
+    fn run(self) {
+        let mut sessions = Vec::new();
+        
+        while !self.shutdown.is_triggered() {
+            match TcpTransport::accept(&self.listener) {
+                Ok((transport, peer)) => {
+                    let session = TcpSession {
+                        transport,
+                        peer,
+                        pool: self.pool.clone(),
+                        pipeline: self.pipeline.clone(),
+                        poll_interval: self.poll_interval,
+                        shutdown: self.shutdown.clone(),
+                    };
+                    
+                    if let Ok(thread) = std::thread::Builder::new()
+                        .name(format!("cynda-tcp-{}", peer))
+                        .spawn(move || session.run())
+                    {
+                        sessions.push(thread);
+                    }
+                }
+                Err(_) => std::thread::sleep(self.poll_interval),
+            }
+            
+            sessions.retain(|session: &JoinHandle<()>| !session.is_finished());
+        }
+        
+        for session in sessions {
+            let _ = session.join();
+        }
+    }
+}
+
+struct TcpSession {
+    transport: TcpTransport,
+    peer: SocketAddr,
+    pool: PacketPool,
+    pipeline: Pipeline,
+    poll_interval: Duration,
+    shutdown: ShutdownToken,
+}
+
+impl TcpSession {
+    // Ends when the sensor disconnects, the stream is corrupt or the
+    // gateway shuts down.
+    fn run(mut self) {
+        let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+        
+        while !self.shutdown.is_triggered() {
+            match self.transport.recv_frame(&mut buffer, Some(self.poll_interval)) {
+                Ok(Some(frame_len)) => {
+                    if !self.serve(&buffer[..frame_len]) {
+                        return;
+                    }
+                }
+                Ok(None) => {}
+                Err(_) => return,
+            }
+        }
+        
+        // As on the shards, answer what the sensor already sent.
+        while let Ok(Some(frame_len)) = self.transport.recv_frame(&mut buffer, None) {
+            if !self.serve(&buffer[..frame_len]) {
+                return;
+            }
+        }
+    }
+    
+    // Returns false once the connection is unusable.
+    fn serve(&mut self, frame: &[u8]) -> bool {
+        match self.pipeline.load(&self.pool, frame, self.peer) {
+            Ok(packet) => {
+                let sent = self.pipeline.process(&packet)
+                    .and_then(|reply| self.transport.send_frame(&reply));
+                !matches!(sent, Err(CyDnAError::IoError(_)))
+            }
+            Err(e) => {
+                self.pipeline.record_unreadable(&e);
+                true
+            }
+        }
+    }
+}
+
+pub struct ShardedGateway {
+    local_address: SocketAddr,
+    replay: Arc<Mutex<ReplayGuard>>,
+    journal: Option<Arc<Mutex<DedupJournal>>>,
+    stream: Option<Arc<PayloadQueue>>,
+    overload: Option<Arc<OverloadDetector>>,
+    metrics: Vec<Arc<Mutex<ShardMetrics>>>,
+    tcp_metrics: Option<Arc<Mutex<ShardMetrics>>>,
+    grpc: Option<(SocketAddr, Arc<Mutex<ShardMetrics>>)>,
+    threads: Vec<JoinHandle<()>>,
+    shutdown: ShutdownToken,
+}
+
+impl ShardedGateway {
+    pub fn local_address(&self) -> SocketAddr {
+        self.local_address
+    }
+    
+    pub fn shard_count(&self) -> usize {
+        self.metrics.len()
+    }
+    
+    // Receive loops that exited before a shutdown was requested; any at all
+    // means part of the port's traffic is going unanswered.
+    pub fn dead_shards(&self) -> usize {
+        if self.shutdown.is_triggered() {
+            return 0;
+        }
+        self.threads[..self.metrics.len()].iter().filter(|thread| thread.is_finished()).count()
+    }
+    
+    pub fn shard_metrics(&self, shard: usize) -> Option<ShardMetrics> {
+        self.metrics.get(shard).map(|metrics| *lock(metrics))
+    }
+    
+    // Frames received over the TCP fallback, if enabled.
+    pub fn tcp_metrics(&self) -> Option<ShardMetrics> {
+        self.tcp_metrics.as_ref().map(|metrics| *lock(metrics))
+    }
+    
+    // Where the gRPC endpoint listens, if enabled; differs from the
+    // configured address when that used port 0.
+    pub fn grpc_address(&self) -> Option<SocketAddr> {
+        self.grpc.as_ref().map(|(address, _)| *address)
+    }
+    
+    pub fn grpc_metrics(&self) -> Option<ShardMetrics> {
+        self.grpc.as_ref().map(|(_, metrics)| *lock(metrics))
+    }
+    
+    pub fn metrics(&self) -> ShardMetrics {
+        let mut total = ShardMetrics::default();
+        let grpc_metrics = self.grpc.as_ref().map(|(_, metrics)| metrics);
+        for metrics in self.metrics.iter().chain(&self.tcp_metrics).chain(grpc_metrics) {
+            total.merge(&lock(metrics));
+        }
+        total
+    }
+    
+    pub fn replay_metrics(&self) -> crate::replay::ReplayMetrics {
+        lock(&self.replay).metrics()
+    }
+    
+    pub fn journal_metrics(&self) -> Option<JournalMetrics> {
+        self.journal.as_ref().map(|journal| lock(journal).metrics())
+    }
+    
+    pub fn shedding_metrics(&self) -> Option<SheddingMetrics> {
+        self.overload.as_ref().map(|overload| overload.metrics())
+    }
+    
+    pub fn is_shedding(&self) -> bool {
+        self.overload.as_ref().is_some_and(|overload| overload.is_shedding())
+    }
+    
+    pub fn shutdown_token(&self) -> ShutdownToken {
+        self.shutdown.clone()
+    }
+    
+    // Signals every shard and waits for their receive loops to exit. Frames
+    // that had already arrived are still validated, handed to the handler
+    // and ACKed before the threads finish.
+    pub fn shutdown(self) {
+        self.shutdown.trigger();
+        self.wait();
+    }
+    
+    // Blocks until the shutdown token is triggered elsewhere and every
+    // worker thread has drained and exited.
+    pub fn wait(self) {
+        for thread in self.threads {
+            let _ = thread.join();
+        }
+        
+        if let Some(journal) = &self.journal {
+            let _ = lock(journal).sync();
+        }
+        if let Some(stream) = &self.stream {
+            stream.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::{SensorPayload, ANOMALY_VECTOR_SIZE};
+    use crate::transmitter::Transmitter;
+    use std::time::{Instant, SystemTime, UNIX_EPOCH};
+    
+    fn payload(device_id: u32, sequence_number: u32) -> SensorPayload {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        SensorPayload::new(device_id, now, 1, 80, 5000, device_id, [0.0; ANOMALY_VECTOR_SIZE])
+            .unwrap()
+            .with_sequence_number(sequence_number)
+    }
+    
+    #[test]
+    fn test_sharded_gateway_dedups_across_shards() {
+        let handled = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&handled);
+        
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_handler(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .with_pool_capacity(8)
+            .with_poll_interval_ms(20)
+            .spawn_sharded(4)
+            .unwrap();
+        assert_eq!(gateway.shard_count(), 4);
+        let gateway_addr = gateway.local_address().to_string();
+        
+        let sensors: Vec<UdpSocket> = (0..8)
+            .map(|_| UdpSocket::bind("127.0.0.1:0").unwrap())
+            .collect();
+        for (index, sensor) in sensors.iter().enumerate() {
+            sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+            let message = payload(index as u32 + 1, 0);
+            Transmitter::send(sensor, &message, &gateway_addr).unwrap();
+            Transmitter::send(sensor, &message, &gateway_addr).unwrap();
+        }
+        
+        let mut buffer = [0u8; 64];
+        for sensor in &sensors {
+            for _ in 0..2 {
+                let (len, _) = sensor.recv_from(&mut buffer).unwrap();
+                assert!(matches!(
+                    AckManager::parse_ack_message(&buffer[..len]),
+                    Ok(Some(crate::ack_manager::AckMessage::Single(ack))) if ack.is_ack()
+                ));
+            }
+        }
+        
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while gateway.metrics().received < 16 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        
+        let metrics = gateway.metrics();
+        assert_eq!(metrics.accepted, 8);
+        assert_eq!(metrics.duplicates, 8);
+        assert_eq!(handled.load(Ordering::SeqCst), 8);
+        assert_eq!(gateway.replay_metrics().accepted, 8);
+        
+        let per_shard: u64 = (0..4).map(|shard| gateway.shard_metrics(shard).unwrap().received).sum();
+        assert_eq!(per_shard, 16);
+        
+        gateway.shutdown();
+    }
+    
+    #[test]
+    fn test_gateway_nacks_expired_and_counts_malformed() {
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_poll_interval_ms(20)
+            .spawn()
+            .unwrap();
+        let gateway_addr = gateway.local_address();
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        
+        sensor.send_to(b"garbage", gateway_addr).unwrap();
+        let expired = SensorPayload::new(3, 1000, 1, 80, 10, 0, [0.0; ANOMALY_VECTOR_SIZE]).unwrap();
+        Transmitter::send(&sensor, &expired, gateway_addr).unwrap();
+        
+        let mut buffer = [0u8; 64];
+        let (len, _) = sensor.recv_from(&mut buffer).unwrap();
+        let Some(crate::ack_manager::AckMessage::Single(nack)) =
+            AckManager::parse_ack_message(&buffer[..len]).unwrap() else {
+            panic!("expected a single NACK");
+        };
+        assert!(!nack.is_ack());
+        assert_eq!(nack.reason(), NackReason::ExpiredTtl);
+        
+        let metrics = gateway.metrics();
+        assert_eq!(metrics.malformed, 1);
+        assert_eq!(metrics.rejected, 1);
+        gateway.shutdown();
+    }
+    
+    #[test]
+    fn test_payload_older_than_replay_window_is_nacked_not_acked() {
+        let handled = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&handled);
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_replay_guard(ReplayGuard::new(16, 1_000))
+            .with_handler(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .with_poll_interval_ms(20)
+            .spawn()
+            .unwrap();
+        
+        // Still inside its own TTL, e.g. drained from store-and-forward.
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        let stale = SensorPayload::new(9, now - 3_000, 1, 80, 30_000, 9, [0.0; ANOMALY_VECTOR_SIZE]).unwrap();
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        Transmitter::send(&sensor, &stale, gateway.local_address()).unwrap();
+        
+        let mut buffer = [0u8; 64];
+        let (len, _) = sensor.recv_from(&mut buffer).unwrap();
+        let Some(crate::ack_manager::AckMessage::Single(nack)) =
+            AckManager::parse_ack_message(&buffer[..len]).unwrap() else {
+            panic!("expected a single NACK");
+        };
+        assert!(!nack.is_ack());
+        assert_eq!(nack.reason(), NackReason::ExpiredTtl);
+        assert!(!nack.reason().should_retransmit());
+        
+        let metrics = gateway.metrics();
+        assert_eq!((metrics.rejected, metrics.duplicates), (1, 0));
+        assert_eq!(handled.load(Ordering::SeqCst), 0);
+        gateway.shutdown();
+    }
+    
+    #[test]
+    fn test_retransmission_after_handler_panic_is_handled() {
+        use std::sync::atomic::AtomicBool;
+        
+        let handled = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&handled);
+        let panicked = AtomicBool::new(false);
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_handler(move |_| {
+                assert!(panicked.swap(true, Ordering::SeqCst), "first attempt fails");
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .with_poll_interval_ms(20)
+            .spawn()
+            .unwrap();
+        let gateway_addr = gateway.local_address();
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let message = payload(4, 0);
+        
+        let mut buffer = [0u8; 64];
+        for expect_ack in [false, true] {
+            Transmitter::send(&sensor, &message, gateway_addr).unwrap();
+            let (len, _) = sensor.recv_from(&mut buffer).unwrap();
+            let Some(crate::ack_manager::AckMessage::Single(reply)) =
+                AckManager::parse_ack_message(&buffer[..len]).unwrap() else {
+                panic!("expected a single ACK or NACK");
+            };
+            assert_eq!(reply.is_ack(), expect_ack);
+        }
+        
+        assert_eq!(handled.load(Ordering::SeqCst), 1);
+        let metrics = gateway.metrics();
+        assert_eq!(metrics.handler_panics, 1);
+        assert_eq!(metrics.accepted, 1);
+        assert_eq!(metrics.duplicates, 0);
+        gateway.shutdown();
+    }
+    
+    #[test]
+    fn test_handler_panic_is_nacked_and_the_shard_keeps_serving() {
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_handler(|packet| assert_ne!(packet.device_unique_id, 1, "handler bug"))
+            .with_poll_interval_ms(20)
+            .spawn()
+            .unwrap();
+        let gateway_addr = gateway.local_address();
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        
+        let mut buffer = [0u8; 64];
+        for (device_id, expect_ack) in [(1, false), (2, true)] {
+            Transmitter::send(&sensor, &payload(device_id, 0), gateway_addr).unwrap();
+            let (len, _) = sensor.recv_from(&mut buffer).unwrap();
+            let Some(crate::ack_manager::AckMessage::Single(reply)) =
+                AckManager::parse_ack_message(&buffer[..len]).unwrap() else {
+                panic!("expected a single ACK or NACK");
+            };
+            assert_eq!(reply.is_ack(), expect_ack);
+        }
+        
+        let metrics = gateway.metrics();
+        assert_eq!(metrics.handler_panics, 1);
+        assert_eq!(metrics.accepted, 1);
+        assert_eq!(metrics.rejected, 1);
+        assert_eq!(gateway.dead_shards(), 0);
+        gateway.shutdown();
+    }
+    
+    #[cfg(feature = "authentication")]
+    #[test]
+    fn test_authenticated_gateway_drops_bare_and_forged_frames() {
+        use crate::authentication::DatagramAuthenticator;
+        
+        let mut authenticator = DatagramAuthenticator::new();
+        authenticator.provision_key(7, &[7u8; 32]).unwrap();
+        authenticator.provision_key(8, &[8u8; 32]).unwrap();
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_authenticator(authenticator)
+            .with_poll_interval_ms(20)
+            .spawn()
+            .unwrap();
+        let gateway_addr = gateway.local_address();
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_millis(300))).unwrap();
+        let mut buffer = [0u8; 64];
+        
+        // Device 8 holds a valid key of its own but claims to be device 7.
+        let mut forger = DatagramAuthenticator::new();
+        forger.provision_key(8, &[8u8; 32]).unwrap();
+        let forged = forger.seal(8, &Transmitter::frame_payload(&payload(7, 0)).unwrap()).unwrap();
+        sensor.send_to(&forged, gateway_addr).unwrap();
+        Transmitter::send(&sensor, &payload(7, 1), gateway_addr).unwrap();
+        assert!(sensor.recv_from(&mut buffer).is_err());
+        
+        let mut device = DatagramAuthenticator::new();
+        device.provision_key(7, &[7u8; 32]).unwrap();
+        Transmitter::send_authenticated(&sensor, &payload(7, 2), &device, gateway_addr).unwrap();
+        let (len, _) = sensor.recv_from(&mut buffer).unwrap();
+        assert!(matches!(
+            AckManager::parse_ack_message(&buffer[..len]),
+            Ok(Some(crate::ack_manager::AckMessage::Single(ack))) if ack.is_ack()
+        ));
+        
+        let metrics = gateway.metrics();
+        assert_eq!(metrics.unauthenticated, 2);
+        assert_eq!(metrics.accepted, 1);
+        gateway.shutdown();
+    }
+    
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encrypted_gateway_handles_the_decrypted_payload() {
+        use crate::encryption::PayloadCipher;
+        
+        let handled = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&handled);
+        let mut cipher = PayloadCipher::new();
+        cipher.add_device_key(5, [5u8; 32]);
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_cipher(cipher)
+            .with_handler(move |packet| {
+                assert_eq!(packet.device_unique_id, 5);
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .with_poll_interval_ms(20)
+            .spawn()
+            .unwrap();
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let mut device = PayloadCipher::new();
+        device.add_device_key(5, [5u8; 32]);
+        Transmitter::send_encrypted(&sensor, &payload(5, 0), &mut device, gateway.local_address()).unwrap();
+        
+        let mut buffer = [0u8; 64];
+        let (len, _) = sensor.recv_from(&mut buffer).unwrap();
+        assert!(matches!(
+            AckManager::parse_ack_message(&buffer[..len]),
+            Ok(Some(crate::ack_manager::AckMessage::Single(ack))) if ack.is_ack()
+        ));
+        assert_eq!(handled.load(Ordering::SeqCst), 1);
+        gateway.shutdown();
+    }
+    
+    #[test]
+    fn test_gateway_serves_tcp_fallback() {
+        use crate::transport::{FrameTransport, TcpTransport};
+        
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_tcp_fallback(true)
+            .with_poll_interval_ms(20)
+            .spawn()
+            .unwrap();
+        
+        let mut sensor = TcpTransport::connect(gateway.local_address(), Duration::from_secs(1)).unwrap();
+        let message = payload(9, 0);
+        for _ in 0..2 {
+            sensor.send_frame(&Transmitter::frame_payload(&message).unwrap()).unwrap();
+        }
+        sensor.send_frame(b"garbage").unwrap();
+        
+        let mut buffer = [0u8; 64];
+        for _ in 0..2 {
+            let len = sensor.recv_frame(&mut buffer, Some(Duration::from_secs(2))).unwrap().unwrap();
+            assert!(matches!(
+                AckManager::parse_ack_message(&buffer[..len]),
+                Ok(Some(crate::ack_manager::AckMessage::Single(ack))) if ack.is_ack()
+            ));
+        }
+        
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while gateway.metrics().received < 3 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        
+        let tcp = gateway.tcp_metrics().unwrap();
+        assert_eq!((tcp.accepted, tcp.duplicates, tcp.malformed), (1, 1, 1));
+        assert_eq!(gateway.metrics().received, 3);
+        gateway.shutdown();
+    }
+    
+    #[test]
+    fn test_shutdown_drains_queued_frames() {
+        let token = ShutdownToken::new();
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_handler(|packet| {
+                if packet.device_unique_id == 1 {
+                    std::thread::sleep(Duration::from_millis(150));
+                }
+            })
+            .with_poll_interval_ms(20)
+            .with_shutdown_token(token.clone())
+            .spawn()
+            .unwrap();
+        let gateway_addr = gateway.local_address();
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        for device_id in 1..=3 {
+            Transmitter::send(&sensor, &payload(device_id, 0), gateway_addr).unwrap();
+        }
+        
+        // The shard is still inside the first handler call when the token
+        // fires; the other two frames are waiting on the socket.
+        std::thread::sleep(Duration::from_millis(50));
+        token.trigger();
+        assert!(gateway.shutdown_token().is_triggered());
+        gateway.wait();
+        
+        let mut buffer = [0u8; 64];
+        for _ in 0..3 {
+            let (len, _) = sensor.recv_from(&mut buffer).unwrap();
+            assert!(matches!(
+                AckManager::parse_ack_message(&buffer[..len]),
+                Ok(Some(crate::ack_manager::AckMessage::Single(ack))) if ack.is_ack()
+            ));
+        }
+    }
+    
+    #[test]
+    fn test_dedup_journal_survives_restart() {
+        let path = std::env::temp_dir().join(format!("cynda-gateway-journal-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let message = payload(4, 0);
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let mut buffer = [0u8; 64];
+        
+        for run in 0..2 {
+            let handled = Arc::new(AtomicUsize::new(0));
+            let counter = Arc::clone(&handled);
+            let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+                .with_handler(move |_| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                })
+                .with_dedup_journal(DedupJournal::open(&path, 60_000).unwrap())
+                .with_poll_interval_ms(20)
+                .spawn()
+                .unwrap();
+            
+            Transmitter::send(&sensor, &message, gateway.local_address()).unwrap();
+            let (len, _) = sensor.recv_from(&mut buffer).unwrap();
+            assert!(matches!(
+                AckManager::parse_ack_message(&buffer[..len]),
+                Ok(Some(crate::ack_manager::AckMessage::Single(ack))) if ack.is_ack()
+            ));
+            
+            // The restarted gateway re-ACKs the retransmission without
+            // handing it to the handler a second time.
+            let metrics = gateway.metrics();
+            assert_eq!((metrics.accepted, metrics.duplicates), if run == 0 { (1, 0) } else { (0, 1) });
+            assert_eq!(handled.load(Ordering::SeqCst), 1 - run);
+            assert_eq!(gateway.journal_metrics().unwrap().restored, run as u64);
+            gateway.shutdown();
+        }
+        
+        std::fs::remove_file(&path).unwrap();
+    }
+    
+    #[test]
+    fn test_ttl_follows_gateway_clock() {
+        use crate::clock::MockClock;
+        
+        let clock = MockClock::new(1_000_000);
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_clock(Arc::new(clock.clone()))
+            .with_poll_interval_ms(20)
+            .spawn()
+            .unwrap();
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let mut buffer = [0u8; 64];
+        let mut exchange = |device_id: u32| {
+            let message = SensorPayload::new(device_id, 1_000_000, 1, 80, 5000, 0, [0.0; ANOMALY_VECTOR_SIZE]).unwrap();
+            Transmitter::send(&sensor, &message, gateway.local_address()).unwrap();
+            let (len, _) = sensor.recv_from(&mut buffer).unwrap();
+            match AckManager::parse_ack_message(&buffer[..len]) {
+                Ok(Some(crate::ack_manager::AckMessage::Single(ack))) => ack,
+                other => panic!("expected a single ACK, got {:?}", other),
+            }
+        };
+        
+        assert!(exchange(1).is_ack());
+        clock.advance_ms(5_001);
+        assert_eq!(exchange(2).reason(), NackReason::ExpiredTtl);
+        
+        gateway.shutdown();
+    }
+}
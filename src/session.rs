@@ -0,0 +1,452 @@
+use std::collections::HashMap;
+
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::authentication::DatagramAuthenticator;
+use crate::encryption::PayloadCipher;
+use crate::errors::{CyDnAError, Result};
+use crate::framing::{decode_frame, encode_frame, MessageType};
+
+pub const HANDSHAKE_BODY_SIZE: usize = 40;
+
+pub const DEFAULT_REKEY_INTERVAL_MS: u64 = 3_600_000;
+
+const SESSION_KDF_SALT: &[u8] = b"CyDnA-session-v1";
+
+pub struct StaticKeypair {
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl StaticKeypair {
+    pub fn generate() -> Self {
+        Self::from_secret(StaticSecret::random_from_rng(OsRng))
+    }
+    
+    pub fn from_bytes(secret: [u8; 32]) -> Self {
+        Self::from_secret(StaticSecret::from(secret))
+    }
+    
+    fn from_secret(secret: StaticSecret) -> Self {
+        let public = PublicKey::from(&secret);
+        Self { secret, public }
+    }
+    
+    pub fn public_bytes(&self) -> [u8; 32] {
+        self.public.to_bytes()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandshakeMessage {
+    pub device_unique_id: u32,
+    
+    pub ephemeral_public: [u8; 32],
+}
+
+impl HandshakeMessage {
+    // Body: device_id (u32 LE) | reserved (4) | ephemeral X25519 public key.
+    pub fn encode(&self, message_type: MessageType) -> Result<Vec<u8>> {
+        let mut body = [0u8; HANDSHAKE_BODY_SIZE];
+        body[0..4].copy_from_slice(&self.device_unique_id.to_le_bytes());
+        body[8..40].copy_from_slice(&self.ephemeral_public);
+        
+        encode_frame(message_type, &body)
+    }
+    
+    pub fn decode(datagram: &[u8], message_type: MessageType) -> Result<Self> {
+        let body = decode_frame(datagram, message_type)?;
+        
+        if body.len() != HANDSHAKE_BODY_SIZE {
+            return Err(CyDnAError::InvalidPacketLength {
+                expected: HANDSHAKE_BODY_SIZE,
+                received: body.len(),
+            });
+        }
+        
+        let mut ephemeral_public = [0u8; 32];
+        ephemeral_public.copy_from_slice(&body[8..40]);
+        
+        Ok(Self {
+            device_unique_id: u32::from_le_bytes([body[0], body[1], body[2], body[3]]),
+            ephemeral_public,
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct SessionKeys {
+    pub encryption_key: [u8; 32],
+    
+    pub authentication_key: [u8; 32],
+}
+
+impl std::fmt::Debug for SessionKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SessionKeys { .. }")
+    }
+}
+
+impl SessionKeys {
+    pub fn install(
+        &self,
+        device_id: u32,
+        cipher: &mut PayloadCipher,
+        authenticator: &mut DatagramAuthenticator,
+    ) -> Result<()> {
+        cipher.add_device_key(device_id, self.encryption_key);
+        authenticator.provision_key(device_id, &self.authentication_key)
+    }
+}
+
+fn shared_secret(secret: &StaticSecret, public: &PublicKey) -> Result<[u8; 32]> {
+    let shared = secret.diffie_hellman(public);
+    
+    if !shared.was_contributory() {
+        return Err(CyDnAError::HandshakeFailed("Non-contributory X25519 public key"));
+    }
+    
+    Ok(shared.to_bytes())
+}
+
+// ikm = DH(e_d, e_g) || DH(s_d, e_g) || DH(e_d, s_g): both static keys are
+// bound in, so each side proves possession of its static secret.
+fn derive_session_keys(
+    device_id: u32,
+    ikm: &[u8; 96],
+    device_ephemeral: &[u8; 32],
+    gateway_ephemeral: &[u8; 32],
+) -> Result<SessionKeys> {
+    let mut info = Vec::with_capacity(68);
+    info.extend_from_slice(&device_id.to_le_bytes());
+    info.extend_from_slice(device_ephemeral);
+    info.extend_from_slice(gateway_ephemeral);
+    
+    let mut okm = [0u8; 64];
+    Hkdf::<Sha256>::new(Some(SESSION_KDF_SALT), ikm)
+        .expand(&info, &mut okm)
+        .map_err(|_| CyDnAError::HandshakeFailed("Session key derivation failed"))?;
+    
+    let mut keys = SessionKeys {
+        encryption_key: [0u8; 32],
+        authentication_key: [0u8; 32],
+    };
+    keys.encryption_key.copy_from_slice(&okm[..32]);
+    keys.authentication_key.copy_from_slice(&okm[32..]);
+    Ok(keys)
+}
+
+pub struct DeviceHandshake {
+    device_id: u32,
+    ephemeral: StaticSecret,
+    ephemeral_public: PublicKey,
+}
+
+impl DeviceHandshake {
+    pub fn start(device_id: u32) -> (Self, HandshakeMessage) {
+        let ephemeral = StaticSecret::random_from_rng(OsRng);
+        let ephemeral_public = PublicKey::from(&ephemeral);
+        
+        let init = HandshakeMessage {
+            device_unique_id: device_id,
+            ephemeral_public: ephemeral_public.to_bytes(),
+        };
+        
+        (Self { device_id, ephemeral, ephemeral_public }, init)
+    }
+    
+    pub fn finish(
+        self,
+        device_static: &StaticKeypair,
+        gateway_public: &[u8; 32],
+        response: &HandshakeMessage,
+    ) -> Result<SessionKeys> {
+        if response.device_unique_id != self.device_id {
+            return Err(CyDnAError::InvalidDeviceId(response.device_unique_id));
+        }
+        
+        let gateway_static = PublicKey::from(*gateway_public);
+        let gateway_ephemeral = PublicKey::from(response.ephemeral_public);
+        
+        let mut ikm = [0u8; 96];
+        ikm[..32].copy_from_slice(&shared_secret(&self.ephemeral, &gateway_ephemeral)?);
+        ikm[32..64].copy_from_slice(&shared_secret(&device_static.secret, &gateway_ephemeral)?);
+        ikm[64..].copy_from_slice(&shared_secret(&self.ephemeral, &gateway_static)?);
+        
+        derive_session_keys(
+            self.device_id,
+            &ikm,
+            &self.ephemeral_public.to_bytes(),
+            &response.ephemeral_public,
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub keys: SessionKeys,
+    
+    pub established_ms: u64,
+    
+    pub generation: u32,
+}
+
+impl Session {
+    pub fn age_ms(&self, current_time_ms: u64) -> u64 {
+        current_time_ms.saturating_sub(self.established_ms)
+    }
+}
+
+// A handshake only stages a session: the init is unauthenticated, so the
+// device's live session stays in place until a datagram authenticated under
+// the new keys proves the device itself completed it (`confirm`).
+pub struct SessionManager {
+    gateway_static: StaticKeypair,
+    device_keys: HashMap<u32, PublicKey>,
+    sessions: HashMap<u32, Session>,
+    pending: HashMap<u32, Session>,
+    rekey_interval_ms: u64,
+}
+
+impl SessionManager {
+    pub fn new(gateway_static: StaticKeypair) -> Self {
+        Self {
+            gateway_static,
+            device_keys: HashMap::new(),
+            sessions: HashMap::new(),
+            pending: HashMap::new(),
+            rekey_interval_ms: DEFAULT_REKEY_INTERVAL_MS,
+        }
+    }
+    
+    pub fn with_rekey_interval_ms(mut self, interval_ms: u64) -> Self {
+        self.rekey_interval_ms = interval_ms;
+        self
+    }
+    
+    pub fn gateway_public(&self) -> [u8; 32] {
+        self.gateway_static.public_bytes()
+    }
+    
+    pub fn register_device(&mut self, device_id: u32, static_public: [u8; 32]) {
+        self.device_keys.insert(device_id, PublicKey::from(static_public));
+    }
+    
+    pub fn unregister_device(&mut self, device_id: u32) -> bool {
+        self.sessions.remove(&device_id);
+        self.pending.remove(&device_id);
+        self.device_keys.remove(&device_id).is_some()
+    }
+    
+    // Returns the response and the staged session; a later handshake for the
+    // same device replaces a staged one but never the live one.
+    pub fn accept(
+        &mut self,
+        init: &HandshakeMessage,
+        current_time_ms: u64,
+    ) -> Result<(HandshakeMessage, &Session)> {
+        let device_id = init.device_unique_id;
+        let device_static = *self.device_keys.get(&device_id)
+            .ok_or(CyDnAError::UnknownDeviceKey(device_id))?;
+        let device_ephemeral = PublicKey::from(init.ephemeral_public);
+        
+        let ephemeral = StaticSecret::random_from_rng(OsRng);
+        let ephemeral_public = PublicKey::from(&ephemeral);
+        
+        let mut ikm = [0u8; 96];
+        ikm[..32].copy_from_slice(&shared_secret(&ephemeral, &device_ephemeral)?);
+        ikm[32..64].copy_from_slice(&shared_secret(&ephemeral, &device_static)?);
+        ikm[64..].copy_from_slice(&shared_secret(&self.gateway_static.secret, &device_ephemeral)?);
+        
+        let keys = derive_session_keys(
+            device_id,
+            &ikm,
+            &init.ephemeral_public,
+            &ephemeral_public.to_bytes(),
+        )?;
+        
+        let generation = self.sessions.get(&device_id)
+            .map(|session| session.generation.wrapping_add(1))
+            .unwrap_or(0);
+        self.pending.insert(device_id, Session {
+            keys,
+            established_ms: current_time_ms,
+            generation,
+        });
+        
+        let response = HandshakeMessage {
+            device_unique_id: device_id,
+            ephemeral_public: ephemeral_public.to_bytes(),
+        };
+        
+        Ok((response, &self.pending[&device_id]))
+    }
+    
+    // Promotes the staged session once `datagram` (sealed by
+    // `DatagramAuthenticator`) verifies under its authentication key. Until
+    // then the old keys stay installed and the old session stays live.
+    pub fn confirm(&mut self, device_id: u32, datagram: &[u8]) -> Result<&Session> {
+        let staged = self.pending.get(&device_id)
+            .ok_or(CyDnAError::UnknownDeviceKey(device_id))?;
+        
+        let mut authenticator = DatagramAuthenticator::new();
+        authenticator.provision_key(device_id, &staged.keys.authentication_key)?;
+        let (sender, _) = authenticator.open(datagram)?;
+        if sender != device_id {
+            return Err(CyDnAError::AuthenticationFailed(sender));
+        }
+        
+        if let Some(session) = self.pending.remove(&device_id) {
+            self.sessions.insert(device_id, session);
+        }
+        Ok(&self.sessions[&device_id])
+    }
+    
+    pub fn pending_session(&self, device_id: u32) -> Option<&Session> {
+        self.pending.get(&device_id)
+    }
+    
+    pub fn session(&self, device_id: u32) -> Option<&Session> {
+        self.sessions.get(&device_id)
+    }
+    
+    pub fn needs_rekey(&self, device_id: u32, current_time_ms: u64) -> bool {
+        match self.sessions.get(&device_id) {
+            Some(session) => session.age_ms(current_time_ms) >= self.rekey_interval_ms,
+            None => true,
+        }
+    }
+    
+    pub fn devices_due_for_rekey(&self, current_time_ms: u64) -> Vec<u32> {
+        self.device_keys.keys()
+            .copied()
+            .filter(|&device_id| self.needs_rekey(device_id, current_time_ms))
+            .collect()
+    }
+    
+    pub fn drop_session(&mut self, device_id: u32) -> bool {
+        self.pending.remove(&device_id);
+        self.sessions.remove(&device_id).is_some()
+    }
+    
+    pub fn active_sessions(&self) -> usize {
+        self.sessions.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn setup() -> (StaticKeypair, SessionManager) {
+        let device = StaticKeypair::from_bytes([11u8; 32]);
+        let mut manager = SessionManager::new(StaticKeypair::from_bytes([22u8; 32]))
+            .with_rekey_interval_ms(1_000);
+        manager.register_device(1, device.public_bytes());
+        (device, manager)
+    }
+    
+    #[test]
+    fn test_handshake_derives_matching_keys() {
+        let (device, mut manager) = setup();
+        let gateway_public = manager.gateway_public();
+        
+        let (handshake, init) = DeviceHandshake::start(1);
+        let init_frame = init.encode(MessageType::HandshakeInit).unwrap();
+        let init = HandshakeMessage::decode(&init_frame, MessageType::HandshakeInit).unwrap();
+        
+        let (response, session) = manager.accept(&init, 5_000).unwrap();
+        let gateway_keys = session.keys.clone();
+        assert_eq!(session.generation, 0);
+        assert!(manager.session(1).is_none());
+        
+        let response_frame = response.encode(MessageType::HandshakeResponse).unwrap();
+        let response = HandshakeMessage::decode(&response_frame, MessageType::HandshakeResponse)
+            .unwrap();
+        let device_keys = handshake.finish(&device, &gateway_public, &response).unwrap();
+        
+        assert_eq!(device_keys, gateway_keys);
+        assert_ne!(device_keys.encryption_key, device_keys.authentication_key);
+        
+        let mut cipher = PayloadCipher::new();
+        let mut authenticator = DatagramAuthenticator::new();
+        device_keys.install(1, &mut cipher, &mut authenticator).unwrap();
+        assert!(cipher.has_device_key(1));
+        assert!(authenticator.has_key(1));
+        
+        let first = authenticator.seal(1, &init_frame).unwrap();
+        assert_eq!(manager.confirm(1, &first).unwrap().generation, 0);
+        assert!(manager.pending_session(1).is_none());
+    }
+    
+    #[test]
+    fn test_wrong_static_key_yields_different_keys() {
+        let (_, mut manager) = setup();
+        let impostor = StaticKeypair::from_bytes([33u8; 32]);
+        
+        let (handshake, init) = DeviceHandshake::start(1);
+        let (response, session) = manager.accept(&init, 0).unwrap();
+        let gateway_keys = session.keys.clone();
+        
+        let impostor_keys = handshake
+            .finish(&impostor, &manager.gateway_public(), &response)
+            .unwrap();
+        assert_ne!(impostor_keys, gateway_keys);
+        
+        let (_, unknown) = DeviceHandshake::start(2);
+        assert!(matches!(manager.accept(&unknown, 0), Err(CyDnAError::UnknownDeviceKey(2))));
+    }
+    
+    // Runs a full handshake for device 1 and confirms it with a datagram
+    // sealed under the new keys.
+    fn establish(device: &StaticKeypair, manager: &mut SessionManager, current_time_ms: u64) -> u32 {
+        let (handshake, init) = DeviceHandshake::start(1);
+        let (response, _) = manager.accept(&init, current_time_ms).unwrap();
+        let keys = handshake.finish(device, &manager.gateway_public(), &response).unwrap();
+        
+        let mut authenticator = DatagramAuthenticator::new();
+        authenticator.provision_key(1, &keys.authentication_key).unwrap();
+        let datagram = authenticator.seal(1, &init.encode(MessageType::HandshakeInit).unwrap()).unwrap();
+        manager.confirm(1, &datagram).unwrap().generation
+    }
+    
+    #[test]
+    fn test_rekey_interval() {
+        let (device, mut manager) = setup();
+        assert!(manager.needs_rekey(1, 0));
+        
+        establish(&device, &mut manager, 10_000);
+        assert!(!manager.needs_rekey(1, 10_500));
+        assert!(manager.needs_rekey(1, 11_000));
+        assert_eq!(manager.devices_due_for_rekey(11_000), vec![1]);
+        
+        assert_eq!(establish(&device, &mut manager, 11_000), 1);
+        assert_eq!(manager.active_sessions(), 1);
+    }
+    
+    #[test]
+    fn test_spoofed_init_keeps_live_session() {
+        let (device, mut manager) = setup();
+        establish(&device, &mut manager, 0);
+        let live = manager.session(1).unwrap().keys.clone();
+        
+        // Anyone can send an init for a known device id, but without the
+        // device's static key they cannot confirm the staged session.
+        let (spoofer, init) = DeviceHandshake::start(1);
+        let (response, _) = manager.accept(&init, 100).unwrap();
+        let impostor = StaticKeypair::from_bytes([44u8; 32]);
+        let keys = spoofer.finish(&impostor, &manager.gateway_public(), &response).unwrap();
+        
+        let mut authenticator = DatagramAuthenticator::new();
+        authenticator.provision_key(1, &keys.authentication_key).unwrap();
+        let datagram = authenticator.seal(1, &init.encode(MessageType::HandshakeInit).unwrap()).unwrap();
+        assert!(matches!(manager.confirm(1, &datagram), Err(CyDnAError::AuthenticationFailed(1))));
+        
+        let session = manager.session(1).unwrap();
+        assert_eq!((session.generation, &session.keys), (0, &live));
+        assert_eq!(establish(&device, &mut manager, 200), 1);
+    }
+}
@@ -0,0 +1,24 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// A cloneable stop signal shared between whoever decides to stop (a signal
+// handler, an admin command) and the loops that have to wind down. Once
+// triggered it stays triggered.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownToken {
+    triggered: Arc<AtomicBool>,
+}
+
+impl ShutdownToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    pub fn trigger(&self) {
+        self.triggered.store(true, Ordering::Relaxed);
+    }
+    
+    pub fn is_triggered(&self) -> bool {
+        self.triggered.load(Ordering::Relaxed)
+    }
+}
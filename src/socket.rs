@@ -0,0 +1,258 @@
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+
+use crate::errors::{CyDnAError, Result};
+use crate::framing::Priority;
+
+// Differentiated Services code points (RFC 4594). The TOS / traffic class
+// byte carries the code point in its upper six bits.
+pub const DSCP_DEFAULT: u8 = 0;
+
+pub const DSCP_LOW_PRIORITY: u8 = 8;
+
+pub const DSCP_EXPEDITED_FORWARDING: u8 = 46;
+
+pub const DSCP_MAX: u8 = 63;
+
+// Resolves once so loops sending to the same peer skip the per-send lookup a
+// `&str` destination would cost. IPv6 link-local peers need a numeric scope
+// id, e.g. `[fe80::1%2]:8080`.
+pub fn resolve(address: impl ToSocketAddrs) -> Result<SocketAddr> {
+    address.to_socket_addrs()
+        .map_err(|e| CyDnAError::IoError(e.kind()))?
+        .next()
+        .ok_or(CyDnAError::IoError(std::io::ErrorKind::InvalidInput))
+}
+
+// Marks every datagram subsequently sent on `socket`. Usable on sockets that
+// were not created through `SocketBuilder`.
+pub fn set_dscp(socket: &UdpSocket, dscp: u8) -> Result<()> {
+    if dscp > DSCP_MAX {
+        return Err(CyDnAError::IoError(std::io::ErrorKind::InvalidInput));
+    }
+    
+    let socket = SockRef::from(socket);
+    let tos = u32::from(dscp) << 2;
+    
+    match socket.local_addr().map_err(|e| CyDnAError::IoError(e.kind()))?.as_socket() {
+        Some(SocketAddr::V4(_)) => socket.set_tos_v4(tos),
+        #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "freebsd"))]
+        Some(SocketAddr::V6(_)) => socket.set_tclass_v6(tos),
+        _ => Err(std::io::ErrorKind::Unsupported.into()),
+    }
+    .map_err(|e| CyDnAError::IoError(e.kind()))
+}
+
+// One place for the socket options every sender and gateway needs; unset
+// options keep the OS defaults.
+#[derive(Debug, Clone)]
+pub struct SocketBuilder {
+    bind_address: SocketAddr,
+    dscp: Option<u8>,
+    recv_buffer_size: Option<usize>,
+    send_buffer_size: Option<usize>,
+    nonblocking: bool,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    reuse_address: bool,
+    reuse_port: bool,
+    only_v6: Option<bool>,
+}
+
+impl SocketBuilder {
+    pub fn new(bind_address: impl ToSocketAddrs) -> Result<Self> {
+        Ok(Self::from_address(resolve(bind_address)?))
+    }
+    
+    pub fn from_address(bind_address: SocketAddr) -> Self {
+        Self {
+            bind_address,
+            dscp: None,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+            nonblocking: false,
+            read_timeout: None,
+            write_timeout: None,
+            reuse_address: false,
+            reuse_port: false,
+            only_v6: None,
+        }
+    }
+    
+    pub fn with_dscp(mut self, dscp: u8) -> Self {
+        self.dscp = Some(dscp);
+        self
+    }
+    
+    pub fn with_priority(self, priority: Priority) -> Self {
+        self.with_dscp(priority.dscp())
+    }
+    
+    // The kernel may round these (Linux doubles them for bookkeeping).
+    pub fn with_recv_buffer_size(mut self, bytes: usize) -> Self {
+        self.recv_buffer_size = Some(bytes);
+        self
+    }
+    
+    pub fn with_send_buffer_size(mut self, bytes: usize) -> Self {
+        self.send_buffer_size = Some(bytes);
+        self
+    }
+    
+    pub fn with_nonblocking(mut self, nonblocking: bool) -> Self {
+        self.nonblocking = nonblocking;
+        self
+    }
+    
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+    
+    pub fn with_write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+    
+    pub fn with_reuse_address(mut self, reuse: bool) -> Self {
+        self.reuse_address = reuse;
+        self
+    }
+    
+    // Ignored on platforms without SO_REUSEPORT.
+    pub fn with_reuse_port(mut self, reuse: bool) -> Self {
+        self.reuse_port = reuse;
+        self
+    }
+    
+    // IPv6 sockets only: `false` also accepts IPv4-mapped peers on `[::]`.
+    pub fn with_only_v6(mut self, only_v6: bool) -> Self {
+        self.only_v6 = Some(only_v6);
+        self
+    }
+    
+    pub fn bind_address(&self) -> SocketAddr {
+        self.bind_address
+    }
+    
+    pub fn bind(&self) -> Result<UdpSocket> {
+        self.bind_to(self.bind_address)
+    }
+    
+    // Same options on a different address, e.g. the resolved port of a
+    // first shard bound to port 0.
+    pub fn bind_to(&self, address: SocketAddr) -> Result<UdpSocket> {
+        let socket = Socket::new(Domain::for_address(address), Type::DGRAM, Some(Protocol::UDP))
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        self.configure(&socket, address.is_ipv6())
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        socket.bind(&address.into())
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let socket: UdpSocket = socket.into();
+        if let Some(dscp) = self.dscp {
+            set_dscp(&socket, dscp)?;
+        }
+        
+        Ok(socket)
+    }
+    
+    fn configure(&self, socket: &Socket, is_ipv6: bool) -> std::io::Result<()> {
+        if let (Some(only_v6), true) = (self.only_v6, is_ipv6) {
+            socket.set_only_v6(only_v6)?;
+        }
+        
+        if self.reuse_address {
+            socket.set_reuse_address(true)?;
+        }
+        
+        #[cfg(unix)]
+        if self.reuse_port {
+            socket.set_reuse_port(true)?;
+        }
+        
+        if let Some(bytes) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(bytes)?;
+        }
+        
+        if let Some(bytes) = self.send_buffer_size {
+            socket.set_send_buffer_size(bytes)?;
+        }
+        
+        socket.set_nonblocking(self.nonblocking)?;
+        socket.set_read_timeout(self.read_timeout)?;
+        socket.set_write_timeout(self.write_timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_builder_applies_options() {
+        let socket = SocketBuilder::new("127.0.0.1:0").unwrap()
+            .with_priority(Priority::Critical)
+            .with_recv_buffer_size(256 * 1024)
+            .with_send_buffer_size(64 * 1024)
+            .with_read_timeout(Duration::from_millis(50))
+            .bind()
+            .unwrap();
+        
+        let options = SockRef::from(&socket);
+        assert_eq!(options.tos_v4().unwrap(), u32::from(DSCP_EXPEDITED_FORWARDING) << 2);
+        assert!(options.recv_buffer_size().unwrap() >= 256 * 1024);
+        assert!(options.send_buffer_size().unwrap() >= 64 * 1024);
+        assert!(socket.read_timeout().unwrap().is_some());
+        
+        let mut buf = [0u8; 8];
+        let err = socket.recv(&mut buf).unwrap_err();
+        assert!(matches!(err.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut));
+    }
+    
+    #[test]
+    fn test_nonblocking_and_invalid_dscp() {
+        let socket = SocketBuilder::new("127.0.0.1:0").unwrap()
+            .with_nonblocking(true)
+            .bind()
+            .unwrap();
+        
+        let mut buf = [0u8; 8];
+        assert_eq!(socket.recv(&mut buf).unwrap_err().kind(), std::io::ErrorKind::WouldBlock);
+        
+        let result = SocketBuilder::new("127.0.0.1:0").unwrap().with_dscp(64).bind();
+        assert!(matches!(result, Err(CyDnAError::IoError(std::io::ErrorKind::InvalidInput))));
+        
+        set_dscp(&socket, Priority::Bulk.dscp()).unwrap();
+        assert_eq!(SockRef::from(&socket).tos_v4().unwrap(), u32::from(DSCP_LOW_PRIORITY) << 2);
+    }
+    
+    #[test]
+    fn test_resolve_ipv6_link_local() {
+        let resolved = resolve("[fe80::1%3]:8080").unwrap();
+        match resolved {
+            SocketAddr::V6(address) => {
+                assert_eq!(address.scope_id(), 3);
+                assert_eq!(address.port(), 8080);
+            }
+            other => panic!("expected IPv6, got {}", other),
+        }
+        
+        assert_eq!(resolve(resolved).unwrap(), resolved);
+        assert!(resolve("not an address").is_err());
+    }
+    
+    #[test]
+    fn test_builder_binds_ipv6() {
+        let socket = SocketBuilder::new("[::1]:0").unwrap()
+            .with_only_v6(true)
+            .with_priority(Priority::Critical)
+            .bind()
+            .unwrap();
+        
+        assert!(socket.local_addr().unwrap().is_ipv6());
+        assert!(SockRef::from(&socket).only_v6().unwrap());
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use crate::contracts::Heartbeat;
+use crate::sequence::{SequenceStatus, SequenceTracker};
+
+// Per-device sample windows; percentiles and the battery trend describe the
+// recent past, not the device's whole lifetime.
+pub const RTT_SAMPLE_WINDOW: usize = 256;
+
+pub const BATTERY_SAMPLE_WINDOW: usize = 32;
+
+pub const DEFAULT_SNAPSHOT_INTERVAL_MS: u64 = 60_000;
+
+const MS_PER_HOUR: f64 = 3_600_000.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RttSummary {
+    pub samples: usize,
+    
+    pub p50_us: u64,
+    
+    pub p90_us: u64,
+    
+    pub p99_us: u64,
+    
+    pub max_us: u64,
+}
+
+impl RttSummary {
+    // Nearest-rank percentiles over the sample window.
+    fn from_samples(samples: &VecDeque<Duration>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        
+        let mut sorted: Vec<u64> = samples.iter().map(|rtt| rtt.as_micros() as u64).collect();
+        sorted.sort_unstable();
+        
+        let percentile = |p: f64| sorted[((p * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len()) - 1];
+        
+        Some(Self {
+            samples: sorted.len(),
+            p50_us: percentile(0.50),
+            p90_us: percentile(0.90),
+            p99_us: percentile(0.99),
+            max_us: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceStats {
+    pub device_id: u32,
+    
+    pub packets: u64,
+    
+    pub bytes: u64,
+    
+    pub duplicates: u64,
+    
+    // Sequence numbers skipped and not (yet) filled in by a late arrival.
+    pub missing: u64,
+    
+    pub loss_ratio: f64,
+    
+    pub rtt: Option<RttSummary>,
+    
+    pub battery_level_percent: Option<u8>,
+    
+    // Least-squares slope over the battery window; negative while draining.
+    pub battery_trend_percent_per_hour: Option<f64>,
+    
+    pub first_seen_ms: u64,
+    
+    pub last_seen_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StatsSnapshot {
+    pub taken_at_ms: u64,
+    
+    // Ordered by device id.
+    pub devices: Vec<DeviceStats>,
+}
+
+struct DeviceRecord {
+    packets: u64,
+    bytes: u64,
+    rtt_samples: VecDeque<Duration>,
+    battery_samples: VecDeque<(u64, u8)>,
+    first_seen_ms: u64,
+    last_seen_ms: u64,
+}
+
+impl DeviceRecord {
+    fn new(current_time_ms: u64) -> Self {
+        Self {
+            packets: 0,
+            bytes: 0,
+            rtt_samples: VecDeque::new(),
+            battery_samples: VecDeque::new(),
+            first_seen_ms: current_time_ms,
+            last_seen_ms: current_time_ms,
+        }
+    }
+    
+    fn record_battery(&mut self, battery_level_percent: u8, current_time_ms: u64) {
+        if self.battery_samples.len() == BATTERY_SAMPLE_WINDOW {
+            self.battery_samples.pop_front();
+        }
+        self.battery_samples.push_back((current_time_ms, battery_level_percent));
+    }
+    
+    fn battery_trend(&self) -> Option<f64> {
+        let count = self.battery_samples.len() as f64;
+        let (first_ms, _) = *self.battery_samples.front()?;
+        
+        // Hours since the first sample keep the sums well inside f64 precision.
+        let points = self.battery_samples.iter()
+            .map(|&(time_ms, level)| (time_ms.saturating_sub(first_ms) as f64 / MS_PER_HOUR, f64::from(level)));
+        let (sum_t, sum_level) = points.clone().fold((0.0, 0.0), |(t, l), (time, level)| (t + time, l + level));
+        let (mean_t, mean_level) = (sum_t / count, sum_level / count);
+        
+        let (covariance, variance) = points.fold((0.0, 0.0), |(cov, var), (time, level)| {
+            (cov + (time - mean_t) * (level - mean_level), var + (time - mean_t).powi(2))
+        });
+        
+        (variance > 0.0).then(|| covariance / variance)
+    }
+}
+
+// Fleet-wide, per-device counters for dashboards. Fed from the receive path
+// (`record_packet`, `record_heartbeat`) and, where the caller measures it,
+// with round-trip times (`record_rtt`).
+pub struct StatsCollector {
+    devices: HashMap<u32, DeviceRecord>,
+    sequences: SequenceTracker,
+    snapshot_interval_ms: u64,
+    next_snapshot_ms: u64,
+}
+
+impl StatsCollector {
+    pub fn new() -> Self {
+        Self {
+            devices: HashMap::new(),
+            sequences: SequenceTracker::new(),
+            snapshot_interval_ms: DEFAULT_SNAPSHOT_INTERVAL_MS,
+            next_snapshot_ms: 0,
+        }
+    }
+    
+    pub fn with_snapshot_interval_ms(mut self, interval_ms: u64) -> Self {
+        self.snapshot_interval_ms = interval_ms.max(1);
+        self
+    }
+    
+    // `bytes` is the datagram size as received, header included.
+    pub fn record_packet(
+        &mut self,
+        device_id: u32,
+        sequence_number: u32,
+        bytes: usize,
+        battery_level_percent: u8,
+        current_time_ms: u64,
+    ) -> SequenceStatus {
+        let record = self.touch(device_id, current_time_ms);
+        record.packets += 1;
+        record.bytes += bytes as u64;
+        record.record_battery(battery_level_percent, current_time_ms);
+        
+        self.sequences.observe(device_id, sequence_number)
+    }
+    
+    pub fn record_heartbeat(&mut self, heartbeat: &Heartbeat, current_time_ms: u64) {
+        self.touch(heartbeat.device_unique_id, current_time_ms)
+            .record_battery(heartbeat.battery_level_percent, current_time_ms);
+    }
+    
+    pub fn record_rtt(&mut self, device_id: u32, rtt: Duration) {
+        let Some(record) = self.devices.get_mut(&device_id) else {
+            return;
+        };
+        
+        if record.rtt_samples.len() == RTT_SAMPLE_WINDOW {
+            record.rtt_samples.pop_front();
+        }
+        record.rtt_samples.push_back(rtt);
+    }
+    
+    fn touch(&mut self, device_id: u32, current_time_ms: u64) -> &mut DeviceRecord {
+        let record = self.devices.entry(device_id).or_insert_with(|| DeviceRecord::new(current_time_ms));
+        record.last_seen_ms = record.last_seen_ms.max(current_time_ms);
+        record
+    }
+    
+    pub fn device(&self, device_id: u32) -> Option<DeviceStats> {
+        let record = self.devices.get(&device_id)?;
+        let sequence = self.sequences.stats(device_id).unwrap_or_default();
+        
+        Some(DeviceStats {
+            device_id,
+            packets: record.packets,
+            bytes: record.bytes,
+            duplicates: sequence.duplicates,
+            missing: sequence.missing,
+            loss_ratio: sequence.loss_ratio(),
+            rtt: RttSummary::from_samples(&record.rtt_samples),
+            battery_level_percent: record.battery_samples.back().map(|&(_, level)| level),
+            battery_trend_percent_per_hour: record.battery_trend(),
+            first_seen_ms: record.first_seen_ms,
+            last_seen_ms: record.last_seen_ms,
+        })
+    }
+    
+    pub fn device_ids(&self) -> Vec<u32> {
+        let mut ids: Vec<u32> = self.devices.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+    
+    pub fn device_count(&self) -> usize {
+        self.devices.len()
+    }
+    
+    // Devices matching `filter`, ordered by device id, e.g. everything
+    // losing more than 5% or silent for the last ten minutes.
+    pub fn query(&self, filter: impl Fn(&DeviceStats) -> bool) -> Vec<DeviceStats> {
+        self.device_ids()
+            .into_iter()
+            .filter_map(|device_id| self.device(device_id))
+            .filter(|stats| filter(stats))
+            .collect()
+    }
+    
+    pub fn remove(&mut self, device_id: u32) -> Option<DeviceStats> {
+        let stats = self.device(device_id);
+        self.devices.remove(&device_id);
+        self.sequences.reset(device_id);
+        stats
+    }
+    
+    pub fn snapshot(&self, current_time_ms: u64) -> StatsSnapshot {
+        StatsSnapshot {
+            taken_at_ms: current_time_ms,
+            devices: self.query(|_| true),
+        }
+    }
+    
+    // For periodic export: a snapshot on the first call and then once per
+    // interval, `None` in between.
+    pub fn poll_snapshot(&mut self, current_time_ms: u64) -> Option<StatsSnapshot> {
+        if current_time_ms < self.next_snapshot_ms {
+            return None;
+        }
+        
+        self.next_snapshot_ms = current_time_ms.saturating_add(self.snapshot_interval_ms);
+        Some(self.snapshot(current_time_ms))
+    }
+}
+
+impl Default for StatsCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_counts_loss_and_battery_trend() {
+        let mut collector = StatsCollector::new();
+        
+        // One reading a minute, draining 1% per reading, with sequence 3 lost.
+        for (minute, sequence) in [0u32, 1, 2, 4, 5].into_iter().enumerate() {
+            let now_ms = minute as u64 * 60_000;
+            collector.record_packet(7, sequence, 220, 90 - minute as u8, now_ms);
+        }
+        assert_eq!(collector.record_packet(7, 5, 220, 85, 300_000), SequenceStatus::Duplicate);
+        
+        let stats = collector.device(7).unwrap();
+        assert_eq!(stats.packets, 6);
+        assert_eq!(stats.bytes, 6 * 220);
+        assert_eq!(stats.missing, 1);
+        assert_eq!(stats.duplicates, 1);
+        assert!((stats.loss_ratio - 1.0 / 6.0).abs() < 1e-9);
+        assert_eq!(stats.battery_level_percent, Some(85));
+        assert!(stats.battery_trend_percent_per_hour.unwrap() < -50.0);
+        assert_eq!((stats.first_seen_ms, stats.last_seen_ms), (0, 300_000));
+        assert!(stats.rtt.is_none());
+        
+        collector.record_heartbeat(&Heartbeat::new(8, 0, 100), 1_000);
+        assert_eq!(collector.device(8).unwrap().battery_trend_percent_per_hour, None);
+        assert_eq!(collector.device_ids(), vec![7, 8]);
+        assert_eq!(collector.query(|stats| stats.missing > 0).len(), 1);
+    }
+    
+    #[test]
+    fn test_rtt_percentiles_and_snapshots() {
+        let mut collector = StatsCollector::new().with_snapshot_interval_ms(1_000);
+        collector.record_rtt(1, Duration::from_millis(5));
+        assert!(collector.device(1).is_none());
+        
+        collector.record_packet(1, 0, 100, 50, 0);
+        for ms in 1..=100 {
+            collector.record_rtt(1, Duration::from_millis(ms));
+        }
+        
+        let rtt = collector.device(1).unwrap().rtt.unwrap();
+        assert_eq!(rtt.samples, 100);
+        assert_eq!((rtt.p50_us, rtt.p90_us, rtt.p99_us, rtt.max_us), (50_000, 90_000, 99_000, 100_000));
+        
+        assert_eq!(collector.poll_snapshot(10).unwrap().devices.len(), 1);
+        assert!(collector.poll_snapshot(500).is_none());
+        assert_eq!(collector.poll_snapshot(1_010).unwrap().taken_at_ms, 1_010);
+        
+        assert!(collector.remove(1).is_some());
+        assert_eq!(collector.device_count(), 0);
+    }
+    
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_json_export() {
+        let mut collector = StatsCollector::new();
+        collector.record_packet(3, 0, 120, 77, 5_000);
+        collector.record_rtt(3, Duration::from_micros(1_500));
+        
+        let snapshot = collector.snapshot(6_000);
+        let json = snapshot.to_json().unwrap();
+        assert!(json.contains("\"battery_level_percent\":77"));
+        assert_eq!(StatsSnapshot::from_json(&json).unwrap(), snapshot);
+    }
+}
@@ -0,0 +1,422 @@
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use rkyv::{AlignedVec, Deserialize};
+
+use crate::contracts::SensorPayload;
+use crate::errors::{CyDnAError, Result};
+use crate::receiver::Receiver;
+use crate::transmitter::Transmitter;
+
+pub const STORE_FILE_MAGIC: [u8; 4] = *b"CYSF";
+
+pub const STORE_FILE_HEADER_SIZE: usize = 16;
+
+pub const STORE_SLOT_SIZE: usize = 256;
+
+const SLOT_HEADER_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StoreMetrics {
+    pub enqueued: u64,
+    
+    pub forwarded: u64,
+    
+    pub expired: u64,
+    
+    pub evicted: u64,
+    
+    pub dropped: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StoredPayload {
+    pub payload: SensorPayload,
+    
+    pub critical: bool,
+}
+
+struct Entry {
+    stored: StoredPayload,
+    order: u64,
+    slot: usize,
+}
+
+// Slot layout: used u8 | critical u8 | reserved (2) | body len u32 LE |
+// enqueue order u64 LE | archived payload. Slots are reused in place, so
+// removing an entry from the middle of the queue is a single byte write.
+struct FileRing {
+    file: File,
+}
+
+impl FileRing {
+    fn open(path: &Path, capacity: usize) -> Result<(Self, Vec<Entry>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let expected_len = (STORE_FILE_HEADER_SIZE + capacity * STORE_SLOT_SIZE) as u64;
+        let file_len = file.metadata()
+            .map_err(|e| CyDnAError::IoError(e.kind()))?
+            .len();
+        
+        let mut header = [0u8; STORE_FILE_HEADER_SIZE];
+        if file_len > 0 {
+            file.read_exact(&mut header)
+                .map_err(|e| CyDnAError::IoError(e.kind()))?;
+            
+            // Never truncate a ring that may still hold payloads: a file that
+            // is not ours, or was sized for another capacity, is an error.
+            if header[0..4] != STORE_FILE_MAGIC {
+                return Err(CyDnAError::DeserializationError("Not a store-and-forward ring file"));
+            }
+            let stored_capacity = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+            if stored_capacity != capacity || file_len != expected_len {
+                return Err(CyDnAError::DeserializationError("Store ring file capacity does not match the queue"));
+            }
+        }
+        
+        let mut ring = Self { file };
+        
+        if file_len == 0 {
+            header[0..4].copy_from_slice(&STORE_FILE_MAGIC);
+            header[4..8].copy_from_slice(&(capacity as u32).to_le_bytes());
+            ring.file.set_len(expected_len)
+                .map_err(|e| CyDnAError::IoError(e.kind()))?;
+            ring.write_at(0, &header)?;
+            return Ok((ring, Vec::new()));
+        }
+        
+        let mut entries = Vec::new();
+        let mut slot_bytes = [0u8; STORE_SLOT_SIZE];
+        for slot in 0..capacity {
+            ring.read_at(Self::slot_offset(slot), &mut slot_bytes)?;
+            if let Some((stored, order)) = Self::decode_slot(&slot_bytes) {
+                entries.push(Entry { stored, order, slot });
+            }
+        }
+        entries.sort_by_key(|entry| entry.order);
+        
+        Ok((ring, entries))
+    }
+    
+    fn slot_offset(slot: usize) -> u64 {
+        (STORE_FILE_HEADER_SIZE + slot * STORE_SLOT_SIZE) as u64
+    }
+    
+    fn decode_slot(slot_bytes: &[u8]) -> Option<(StoredPayload, u64)> {
+        if slot_bytes[0] != 1 {
+            return None;
+        }
+        
+        let body_len = u32::from_le_bytes([slot_bytes[4], slot_bytes[5], slot_bytes[6], slot_bytes[7]]) as usize;
+        if body_len > STORE_SLOT_SIZE - SLOT_HEADER_SIZE {
+            return None;
+        }
+        
+        let mut order = [0u8; 8];
+        order.copy_from_slice(&slot_bytes[8..16]);
+        
+        // Copy into an aligned buffer so rkyv can validate in place.
+        let mut body = AlignedVec::with_capacity(body_len);
+        body.extend_from_slice(&slot_bytes[SLOT_HEADER_SIZE..SLOT_HEADER_SIZE + body_len]);
+        let archived = Receiver::archive(&body).ok()?;
+        let payload: SensorPayload = archived.deserialize(&mut rkyv::Infallible).ok()?;
+        
+        Some((StoredPayload { payload, critical: slot_bytes[1] == 1 }, u64::from_le_bytes(order)))
+    }
+    
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        let body = Transmitter::serialize_payload(&entry.stored.payload)?;
+        if body.len() > STORE_SLOT_SIZE - SLOT_HEADER_SIZE {
+            return Err(CyDnAError::BufferTooSmall {
+                required: body.len(),
+                available: STORE_SLOT_SIZE - SLOT_HEADER_SIZE,
+            });
+        }
+        
+        let mut slot_bytes = [0u8; STORE_SLOT_SIZE];
+        slot_bytes[0] = 1;
+        slot_bytes[1] = entry.stored.critical as u8;
+        slot_bytes[4..8].copy_from_slice(&(body.len() as u32).to_le_bytes());
+        slot_bytes[8..16].copy_from_slice(&entry.order.to_le_bytes());
+        slot_bytes[SLOT_HEADER_SIZE..SLOT_HEADER_SIZE + body.len()].copy_from_slice(&body);
+        
+        self.write_at(Self::slot_offset(entry.slot), &slot_bytes)
+    }
+    
+    fn clear_slot(&mut self, slot: usize) -> Result<()> {
+        self.write_at(Self::slot_offset(slot), &[0u8])
+    }
+    
+    fn read_at(&mut self, offset: u64, bytes: &mut [u8]) -> Result<()> {
+        self.file.seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.read_exact(bytes))
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    fn write_at(&mut self, offset: u64, bytes: &[u8]) -> Result<()> {
+        self.file.seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.write_all(bytes))
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    fn sync(&mut self) -> Result<()> {
+        self.file.sync_data()
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+}
+
+pub struct StoreAndForwardQueue {
+    entries: VecDeque<Entry>,
+    free_slots: Vec<usize>,
+    next_order: u64,
+    ring: Option<FileRing>,
+    metrics: StoreMetrics,
+}
+
+impl StoreAndForwardQueue {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            free_slots: (0..capacity).rev().collect(),
+            next_order: 0,
+            ring: None,
+            metrics: StoreMetrics::default(),
+        }
+    }
+    
+    // Creates the ring file, or reopens a previous one and restores the queued
+    // payloads in their original order. Fails rather than discard them if the
+    // existing file was created with a different capacity.
+    pub fn with_file(path: impl AsRef<Path>, capacity: usize) -> Result<Self> {
+        let mut queue = Self::new(capacity);
+        let (ring, restored) = FileRing::open(path.as_ref(), queue.capacity())?;
+        
+        queue.free_slots.retain(|slot| !restored.iter().any(|entry| entry.slot == *slot));
+        queue.next_order = restored.last().map(|entry| entry.order + 1).unwrap_or(0);
+        queue.entries.extend(restored);
+        queue.ring = Some(ring);
+        
+        Ok(queue)
+    }
+    
+    pub fn capacity(&self) -> usize {
+        self.entries.len() + self.free_slots.len()
+    }
+    
+    // When full, the oldest routine payload is evicted first; critical alerts
+    // are only evicted by newer critical alerts. Returns false if the new
+    // payload itself was dropped.
+    pub fn push(&mut self, payload: SensorPayload, critical: bool) -> Result<bool> {
+        if self.free_slots.is_empty() {
+            let victim = self.entries.iter()
+                .position(|entry| !entry.stored.critical)
+                .or(if critical { Some(0) } else { None });
+            
+            match victim {
+                Some(index) => {
+                    self.remove_at(index)?;
+                    self.metrics.evicted += 1;
+                }
+                None => {
+                    self.metrics.dropped += 1;
+                    return Ok(false);
+                }
+            }
+        }
+        
+        let slot = self.free_slots.pop().ok_or(CyDnAError::BufferTooSmall { required: 1, available: 0 })?;
+        let entry = Entry {
+            stored: StoredPayload { payload, critical },
+            order: self.next_order,
+            slot,
+        };
+        self.next_order += 1;
+        
+        if let Some(ring) = self.ring.as_mut() {
+            if let Err(e) = ring.write_entry(&entry) {
+                self.free_slots.push(slot);
+                return Err(e);
+            }
+        }
+        
+        self.entries.push_back(entry);
+        self.metrics.enqueued += 1;
+        Ok(true)
+    }
+    
+    fn remove_at(&mut self, index: usize) -> Result<Option<StoredPayload>> {
+        let Some(entry) = self.entries.remove(index) else {
+            return Ok(None);
+        };
+        
+        self.free_slots.push(entry.slot);
+        if let Some(ring) = self.ring.as_mut() {
+            ring.clear_slot(entry.slot)?;
+        }
+        
+        Ok(Some(entry.stored))
+    }
+    
+    // Pops the oldest payload still within its TTL, critical alerts first;
+    // expired payloads met along the way are discarded.
+    pub fn pop_live(&mut self, current_time_ms: u64) -> Result<Option<StoredPayload>> {
+        self.discard_expired(current_time_ms)?;
+        
+        let index = self.entries.iter()
+            .position(|entry| entry.stored.critical)
+            .unwrap_or(0);
+        
+        let stored = self.remove_at(index)?;
+        if stored.is_some() {
+            self.metrics.forwarded += 1;
+        }
+        
+        Ok(stored)
+    }
+    
+    pub fn drain_live(&mut self, current_time_ms: u64) -> Result<Vec<StoredPayload>> {
+        let mut drained = Vec::with_capacity(self.entries.len());
+        while let Some(stored) = self.pop_live(current_time_ms)? {
+            drained.push(stored);
+        }
+        
+        Ok(drained)
+    }
+    
+    pub fn discard_expired(&mut self, current_time_ms: u64) -> Result<usize> {
+        let mut discarded = 0;
+        let mut index = 0;
+        
+        while index < self.entries.len() {
+            let payload = &self.entries[index].stored.payload;
+            let expiry = payload.timestamp_ms_utc.saturating_add(payload.time_to_live_ms as u64);
+            
+            if current_time_ms > expiry {
+                self.remove_at(index)?;
+                discarded += 1;
+            } else {
+                index += 1;
+            }
+        }
+        
+        self.metrics.expired += discarded as u64;
+        Ok(discarded)
+    }
+    
+    pub fn sync(&mut self) -> Result<()> {
+        match self.ring.as_mut() {
+            Some(ring) => ring.sync(),
+            None => Ok(()),
+        }
+    }
+    
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    
+    pub fn metrics(&self) -> StoreMetrics {
+        self.metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn payload(id: u32, timestamp_ms: u64) -> SensorPayload {
+        SensorPayload::new(
+            id, timestamp_ms, 1, 50, 1000, id,
+            [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap()
+    }
+    
+    #[test]
+    fn test_eviction_prefers_routine_payloads() {
+        let mut queue = StoreAndForwardQueue::new(3);
+        
+        assert!(queue.push(payload(1, 0), false).unwrap());
+        assert!(queue.push(payload(2, 0), true).unwrap());
+        assert!(queue.push(payload(3, 0), false).unwrap());
+        assert!(queue.push(payload(4, 0), true).unwrap());
+        assert!(queue.push(payload(5, 0), true).unwrap());
+        assert!(!queue.push(payload(6, 0), false).unwrap());
+        assert!(queue.push(payload(7, 0), true).unwrap());
+        
+        let ids: Vec<u32> = queue.drain_live(500).unwrap()
+            .iter()
+            .map(|stored| stored.payload.device_unique_id)
+            .collect();
+        assert_eq!(ids, vec![4, 5, 7]);
+        
+        let metrics = queue.metrics();
+        assert_eq!(metrics.evicted, 3);
+        assert_eq!(metrics.dropped, 1);
+        assert_eq!(metrics.forwarded, 3);
+    }
+    
+    #[test]
+    fn test_drain_respects_ttl_and_priority() {
+        let mut queue = StoreAndForwardQueue::new(8);
+        
+        queue.push(payload(1, 0), false).unwrap();
+        queue.push(payload(2, 5_000), false).unwrap();
+        queue.push(payload(3, 5_000), true).unwrap();
+        
+        let drained = queue.drain_live(5_500).unwrap();
+        assert_eq!(drained.len(), 2);
+        assert_eq!(drained[0].payload.device_unique_id, 3);
+        assert!(drained[0].critical);
+        assert_eq!(drained[1].payload.device_unique_id, 2);
+        assert_eq!(queue.metrics().expired, 1);
+        assert!(queue.is_empty());
+    }
+    
+    #[test]
+    fn test_file_ring_survives_reopen() {
+        let path = std::env::temp_dir()
+            .join(format!("cynda_store_{}.ring", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        
+        {
+            let mut queue = StoreAndForwardQueue::with_file(&path, 4).unwrap();
+            queue.push(payload(1, 100), false).unwrap();
+            queue.push(payload(2, 100), true).unwrap();
+            queue.push(payload(3, 100), false).unwrap();
+            assert_eq!(queue.pop_live(100).unwrap().unwrap().payload.device_unique_id, 2);
+            queue.sync().unwrap();
+        }
+        
+        // A capacity mismatch is refused and leaves the stored payloads alone.
+        assert!(matches!(
+            StoreAndForwardQueue::with_file(&path, 8),
+            Err(CyDnAError::DeserializationError(_))
+        ));
+        
+        let mut queue = StoreAndForwardQueue::with_file(&path, 4).unwrap();
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.capacity(), 4);
+        queue.push(payload(4, 100), false).unwrap();
+        
+        let ids: Vec<u32> = queue.drain_live(100).unwrap()
+            .iter()
+            .map(|stored| stored.payload.device_unique_id)
+            .collect();
+        assert_eq!(ids, vec![1, 3, 4]);
+        
+        std::fs::remove_file(&path).unwrap();
+    }
+}
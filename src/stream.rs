@@ -0,0 +1,313 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::errors::{CyDnAError, Result};
+use crate::pool::PooledPacket;
+
+// What the receive loop does with a validated payload while the stream is
+// full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    // ACK it and evict the oldest queued payload: the freshest readings win,
+    // at the cost of losing acknowledged ones.
+    DropOldest,
+    
+    // NACK it as rate-limited, so the sensor backs off and retransmits once
+    // the consumer has caught up. Nothing acknowledged is ever lost.
+    #[default]
+    Nack,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamMetrics {
+    pub enqueued: u64,
+    
+    pub delivered: u64,
+    
+    pub dropped_oldest: u64,
+    
+    pub nacked: u64,
+}
+
+struct QueueState {
+    packets: VecDeque<PooledPacket>,
+    closed: bool,
+    metrics: StreamMetrics,
+}
+
+pub(crate) struct PayloadQueue {
+    state: Mutex<QueueState>,
+    available: Condvar,
+    #[cfg(feature = "tokio")]
+    notify: tokio::sync::Notify,
+    capacity: usize,
+    overflow: OverflowPolicy,
+}
+
+impl PayloadQueue {
+    pub(crate) fn new(capacity: usize, overflow: OverflowPolicy) -> Arc<Self> {
+        let capacity = capacity.max(1);
+        
+        Arc::new(Self {
+            state: Mutex::new(QueueState {
+                packets: VecDeque::with_capacity(capacity),
+                closed: false,
+                metrics: StreamMetrics::default(),
+            }),
+            available: Condvar::new(),
+            #[cfg(feature = "tokio")]
+            notify: tokio::sync::Notify::new(),
+            capacity,
+            overflow,
+        })
+    }
+    
+    // Checked before the replay guard sees the payload, so a NACKed frame is
+    // not remembered as processed and its retransmission is accepted later.
+    pub(crate) fn check_capacity(&self, device_id: u32) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if self.overflow == OverflowPolicy::Nack && state.packets.len() >= self.capacity {
+            state.metrics.nacked += 1;
+            return Err(CyDnAError::RateLimited(device_id));
+        }
+        
+        Ok(())
+    }
+    
+    // Shards race between `check_capacity` and `push`, so under the NACK
+    // policy the queue may briefly exceed its capacity by one per shard.
+    pub(crate) fn push(&self, packet: PooledPacket) {
+        let mut state = self.state.lock().unwrap();
+        if self.overflow == OverflowPolicy::DropOldest && state.packets.len() >= self.capacity {
+            state.packets.pop_front();
+            state.metrics.dropped_oldest += 1;
+        }
+        
+        state.packets.push_back(packet);
+        state.metrics.enqueued += 1;
+        drop(state);
+        
+        self.available.notify_one();
+        #[cfg(feature = "tokio")]
+        self.notify.notify_one();
+    }
+    
+    pub(crate) fn len(&self) -> usize {
+        self.state.lock().unwrap().packets.len()
+    }
+    
+    pub(crate) fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        
+        self.available.notify_all();
+        #[cfg(feature = "tokio")]
+        self.notify.notify_waiters();
+    }
+}
+
+// The consuming end of `GatewayServer::into_stream`. Payloads come out in
+// arrival order; once the gateway has shut down and the queue is empty every
+// receive returns `None`.
+#[derive(Clone)]
+pub struct PayloadStream {
+    queue: Arc<PayloadQueue>,
+}
+
+impl PayloadStream {
+    pub(crate) fn new(queue: Arc<PayloadQueue>) -> Self {
+        Self { queue }
+    }
+    
+    pub fn try_recv(&self) -> Option<PooledPacket> {
+        let mut state = self.queue.state.lock().unwrap();
+        let packet = state.packets.pop_front()?;
+        state.metrics.delivered += 1;
+        Some(packet)
+    }
+    
+    pub fn recv(&self) -> Option<PooledPacket> {
+        let mut state = self.queue.state.lock().unwrap();
+        
+        loop {
+            if let Some(packet) = state.packets.pop_front() {
+                state.metrics.delivered += 1;
+                return Some(packet);
+            }
+            if state.closed {
+                return None;
+            }
+            
+            state = self.queue.available.wait(state).unwrap();
+        }
+    }
+    
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<PooledPacket> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.queue.state.lock().unwrap();
+        
+        loop {
+            if let Some(packet) = state.packets.pop_front() {
+                state.metrics.delivered += 1;
+                return Some(packet);
+            }
+            
+            let now = Instant::now();
+            if state.closed || now >= deadline {
+                return None;
+            }
+            
+            state = self.queue.available.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+    
+    #[cfg(feature = "tokio")]
+    pub async fn recv_async(&self) -> Option<PooledPacket> {
+        loop {
+            // Registered before checking, so a push in between still wakes us.
+            let notified = self.queue.notify.notified();
+            
+            {
+                let mut state = self.queue.state.lock().unwrap();
+                if let Some(packet) = state.packets.pop_front() {
+                    state.metrics.delivered += 1;
+                    return Some(packet);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            
+            notified.await;
+        }
+    }
+    
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity
+    }
+    
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.queue.overflow
+    }
+    
+    pub fn is_closed(&self) -> bool {
+        self.queue.state.lock().unwrap().closed
+    }
+    
+    pub fn metrics(&self) -> StreamMetrics {
+        self.queue.state.lock().unwrap().metrics
+    }
+}
+
+impl Iterator for PayloadStream {
+    type Item = PooledPacket;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ack_manager::{AckManager, AckMessage};
+    use crate::contracts::{AckPacket, NackReason, SensorPayload, ANOMALY_VECTOR_SIZE};
+    use crate::server::GatewayServer;
+    use crate::transmitter::Transmitter;
+    use std::net::UdpSocket;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    
+    fn exchange(sensor: &UdpSocket, gateway: std::net::SocketAddr, device_id: u32) -> AckPacket {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        let payload = SensorPayload::new(device_id, now, 1, 80, 5_000, 0, [0.0; ANOMALY_VECTOR_SIZE]).unwrap();
+        Transmitter::send(sensor, &payload, gateway).unwrap();
+        
+        let mut buffer = [0u8; 64];
+        let (len, _) = sensor.recv_from(&mut buffer).unwrap();
+        match AckManager::parse_ack_message(&buffer[..len]).unwrap() {
+            Some(AckMessage::Single(ack)) => ack,
+            _ => panic!("expected a single ACK"),
+        }
+    }
+    
+    fn sensor() -> UdpSocket {
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        sensor
+    }
+    
+    #[test]
+    fn test_full_stream_nacks_until_drained() {
+        let (server, stream) = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_poll_interval_ms(20)
+            .into_stream(1, OverflowPolicy::Nack);
+        let gateway = server.spawn().unwrap();
+        let sensor = sensor();
+        
+        assert!(exchange(&sensor, gateway.local_address(), 1).is_ack());
+        let refused = exchange(&sensor, gateway.local_address(), 2);
+        assert_eq!(refused.reason(), NackReason::RateLimited);
+        
+        assert_eq!(stream.recv().unwrap().device_unique_id, 1);
+        assert!(exchange(&sensor, gateway.local_address(), 2).is_ack());
+        assert_eq!(stream.recv_timeout(Duration::from_secs(1)).unwrap().device_unique_id, 2);
+        
+        let metrics = stream.metrics();
+        assert_eq!((metrics.enqueued, metrics.delivered, metrics.nacked), (2, 2, 1));
+        assert_eq!(gateway.metrics().rejected, 1);
+        
+        gateway.shutdown();
+        assert!(stream.is_closed());
+        assert!(stream.recv().is_none());
+    }
+    
+    #[test]
+    fn test_drop_oldest_keeps_freshest() {
+        let (server, stream) = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_poll_interval_ms(20)
+            .into_stream(2, OverflowPolicy::DropOldest);
+        let gateway = server.spawn().unwrap();
+        let sensor = sensor();
+        
+        for device_id in 1..=3 {
+            assert!(exchange(&sensor, gateway.local_address(), device_id).is_ack());
+        }
+        gateway.shutdown();
+        
+        // The stream keeps what was queued before the shutdown.
+        let devices: Vec<u32> = stream.clone().map(|packet| packet.device_unique_id).collect();
+        assert_eq!(devices, [2, 3]);
+        assert_eq!(stream.metrics().dropped_oldest, 1);
+    }
+    
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_recv_async_wakes_on_push_and_close() {
+        let (server, stream) = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_poll_interval_ms(20)
+            .into_stream(4, OverflowPolicy::Nack);
+        let gateway = server.spawn().unwrap();
+        let address = gateway.local_address();
+        
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let sender = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            exchange(&sensor(), address, 9);
+            gateway.shutdown();
+        });
+        
+        runtime.block_on(async {
+            assert_eq!(stream.recv_async().await.unwrap().device_unique_id, 9);
+            assert!(stream.recv_async().await.is_none());
+        });
+        sender.join().unwrap();
+    }
+}
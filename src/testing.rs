@@ -0,0 +1,371 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::ack_manager::AckManager;
+use crate::contracts::AckPacket;
+use crate::errors::{CyDnAError, Result};
+use crate::transmitter::Transmitter;
+use crate::transport::{FrameTransport, TransportKind};
+use crate::MAX_PAYLOAD_SIZE;
+
+// How often the ACK responder checks whether it has been stopped.
+const RESPONDER_POLL_MS: u64 = 5;
+
+// What one direction of an in-process link does to the frames sent over it.
+// Every random decision comes from a generator seeded with `seed`, so a test
+// sees the same drops, duplicates and delays on every run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Impairment {
+    seed: u64,
+    drop_probability: f64,
+    duplicate_probability: f64,
+    reorder_probability: f64,
+    reorder_delay: Duration,
+    latency: Duration,
+    jitter: Duration,
+}
+
+impl Impairment {
+    // A perfect link; add impairments with the `with_*` methods.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            drop_probability: 0.0,
+            duplicate_probability: 0.0,
+            reorder_probability: 0.0,
+            reorder_delay: Duration::ZERO,
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+        }
+    }
+    
+    pub fn with_drop(mut self, probability: f64) -> Self {
+        self.drop_probability = probability.clamp(0.0, 1.0);
+        self
+    }
+    
+    pub fn with_duplication(mut self, probability: f64) -> Self {
+        self.duplicate_probability = probability.clamp(0.0, 1.0);
+        self
+    }
+    
+    // A reordered frame is held back by `delay`, so frames sent after it
+    // within that window overtake it.
+    pub fn with_reorder(mut self, probability: f64, delay: Duration) -> Self {
+        self.reorder_probability = probability.clamp(0.0, 1.0);
+        self.reorder_delay = delay;
+        self
+    }
+    
+    // Each frame is delayed by `latency` plus a uniform share of `jitter`.
+    pub fn with_latency(mut self, latency: Duration, jitter: Duration) -> Self {
+        self.latency = latency;
+        self.jitter = jitter;
+        self
+    }
+    
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    pub sent: u64,
+    
+    pub dropped: u64,
+    
+    pub duplicated: u64,
+    
+    pub reordered: u64,
+    
+    pub delivered: u64,
+}
+
+struct InFlight {
+    deliver_at: Instant,
+    order: u64,
+    frame: Vec<u8>,
+}
+
+struct LinkState {
+    impairment: Impairment,
+    rng: StdRng,
+    in_flight: Vec<InFlight>,
+    next_order: u64,
+    stats: LinkStats,
+}
+
+// Delivery times are real `Instant`s rather than readings of a `Clock`: a
+// receiver parks on the condvar until the next frame is due, and a mock clock
+// would never wake it. Latency, jitter and reorder delays are therefore
+// always real time, even when the endpoints run on a `MockClock`.
+struct Link {
+    state: Mutex<LinkState>,
+    arrived: Condvar,
+}
+
+impl Link {
+    fn new(impairment: Impairment) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(LinkState {
+                impairment,
+                rng: StdRng::seed_from_u64(impairment.seed),
+                in_flight: Vec::new(),
+                next_order: 0,
+                stats: LinkStats::default(),
+            }),
+            arrived: Condvar::new(),
+        })
+    }
+    
+    fn send(&self, frame: &[u8]) -> Result<usize> {
+        Transmitter::check_datagram_size(frame.len())?;
+        
+        let mut state = self.state.lock().unwrap();
+        let impairment = state.impairment;
+        state.stats.sent += 1;
+        
+        if state.rng.gen_bool(impairment.drop_probability) {
+            state.stats.dropped += 1;
+            return Ok(frame.len());
+        }
+        
+        let copies = if state.rng.gen_bool(impairment.duplicate_probability) {
+            state.stats.duplicated += 1;
+            2
+        } else {
+            1
+        };
+        
+        let now = Instant::now();
+        for _ in 0..copies {
+            let mut delay = impairment.latency + impairment.jitter.mul_f64(state.rng.gen::<f64>());
+            if state.rng.gen_bool(impairment.reorder_probability) {
+                state.stats.reordered += 1;
+                delay += impairment.reorder_delay;
+            }
+            
+            let order = state.next_order;
+            state.next_order += 1;
+            state.in_flight.push(InFlight { deliver_at: now + delay, order, frame: frame.to_vec() });
+        }
+        
+        self.arrived.notify_all();
+        Ok(frame.len())
+    }
+    
+    fn recv(&self, buffer: &mut [u8], timeout: Option<Duration>) -> Result<Option<usize>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.state.lock().unwrap();
+        
+        loop {
+            let now = Instant::now();
+            let due = state.in_flight.iter()
+                .enumerate()
+                .filter(|(_, in_flight)| in_flight.deliver_at <= now)
+                .min_by_key(|(_, in_flight)| (in_flight.deliver_at, in_flight.order))
+                .map(|(index, _)| index);
+            
+            if let Some(index) = due {
+                let frame_len = state.in_flight[index].frame.len();
+                if buffer.len() < frame_len {
+                    return Err(CyDnAError::BufferTooSmall {
+                        required: frame_len,
+                        available: buffer.len(),
+                    });
+                }
+                
+                let in_flight = state.in_flight.remove(index);
+                buffer[..frame_len].copy_from_slice(&in_flight.frame);
+                state.stats.delivered += 1;
+                return Ok(Some(frame_len));
+            }
+            
+            let Some(deadline) = deadline.filter(|deadline| *deadline > now) else {
+                return Ok(None);
+            };
+            
+            let next_arrival = state.in_flight.iter().map(|in_flight| in_flight.deliver_at).min();
+            let wakeup = next_arrival.map_or(deadline, |arrival| arrival.min(deadline));
+            state = self.arrived.wait_timeout(state, wakeup - now).unwrap().0;
+        }
+    }
+    
+    fn stats(&self) -> LinkStats {
+        self.state.lock().unwrap().stats
+    }
+}
+
+// One end of an in-process datagram link. Behaves like a connected UDP
+// socket: sends never fail because of the impairments, frames simply go
+// missing, arrive twice or arrive late.
+pub struct LossyTransport {
+    outgoing: Arc<Link>,
+    incoming: Arc<Link>,
+}
+
+impl LossyTransport {
+    // Both directions impaired alike; the return direction draws from
+    // `seed + 1` so it does not mirror the forward one.
+    pub fn pair(impairment: Impairment) -> (Self, Self) {
+        let downlink = Impairment { seed: impairment.seed.wrapping_add(1), ..impairment };
+        
+        Self::pair_with(impairment, downlink)
+    }
+    
+    // `uplink` applies to frames sent by the first endpoint, `downlink` to
+    // frames sent by the second.
+    pub fn pair_with(uplink: Impairment, downlink: Impairment) -> (Self, Self) {
+        let uplink = Link::new(uplink);
+        let downlink = Link::new(downlink);
+        
+        (
+            Self { outgoing: Arc::clone(&uplink), incoming: Arc::clone(&downlink) },
+            Self { outgoing: downlink, incoming: uplink },
+        )
+    }
+    
+    pub fn outgoing_stats(&self) -> LinkStats {
+        self.outgoing.stats()
+    }
+    
+    pub fn incoming_stats(&self) -> LinkStats {
+        self.incoming.stats()
+    }
+}
+
+impl FrameTransport for LossyTransport {
+    fn kind(&self) -> TransportKind {
+        TransportKind::Udp
+    }
+    
+    fn send_frame(&mut self, frame: &[u8]) -> Result<usize> {
+        self.outgoing.send(frame)
+    }
+    
+    fn recv_frame(&mut self, buffer: &mut [u8], timeout: Option<Duration>) -> Result<Option<usize>> {
+        self.incoming.recv(buffer, timeout)
+    }
+}
+
+// A stand-in gateway on the far end of a link: ACKs every sensor payload
+// frame it receives and ignores everything else.
+pub struct AckResponder {
+    running: Arc<AtomicBool>,
+    payloads: Arc<AtomicU64>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl AckResponder {
+    pub fn spawn(mut transport: impl FrameTransport + Send + 'static) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+        let payloads = Arc::new(AtomicU64::new(0));
+        
+        let thread_running = Arc::clone(&running);
+        let thread_payloads = Arc::clone(&payloads);
+        let handle = std::thread::spawn(move || {
+            let mut buffer = vec![0u8; MAX_PAYLOAD_SIZE];
+            let poll = Some(Duration::from_millis(RESPONDER_POLL_MS));
+            
+            while thread_running.load(Ordering::Relaxed) {
+                let Ok(Some(frame_len)) = transport.recv_frame(&mut buffer, poll) else {
+                    continue;
+                };
+                
+                let Ok(payload) = crate::codec::decode_payload_frame(&buffer[..frame_len]) else {
+                    continue;
+                };
+                thread_payloads.fetch_add(1, Ordering::Relaxed);
+                
+                let ack = AckPacket::ack(payload.device_unique_id, payload.timestamp_ms_utc);
+                if let Ok(frame) = AckManager::encode_ack(&ack) {
+                    let _ = transport.send_frame(&frame);
+                }
+            }
+        });
+        
+        Self { running, payloads, handle: Some(handle) }
+    }
+    
+    // Payload frames received so far, duplicates included.
+    pub fn payloads_received(&self) -> u64 {
+        self.payloads.load(Ordering::Relaxed)
+    }
+    
+    pub fn stop(mut self) -> u64 {
+        self.shutdown();
+        self.payloads_received()
+    }
+    
+    fn shutdown(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for AckResponder {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn send_burst(impairment: Impairment, frames: u8) -> (Vec<u8>, LinkStats) {
+        let (mut sensor, mut gateway) = LossyTransport::pair(impairment);
+        for index in 0..frames {
+            sensor.send_frame(&[index]).unwrap();
+        }
+        
+        let mut received = Vec::new();
+        let mut buffer = [0u8; 8];
+        while let Some(len) = gateway.recv_frame(&mut buffer, Some(Duration::from_millis(30))).unwrap() {
+            assert_eq!(len, 1);
+            received.push(buffer[0]);
+        }
+        
+        (received, sensor.outgoing_stats())
+    }
+    
+    #[test]
+    fn test_impairments_are_deterministic() {
+        let impairment = Impairment::new(42).with_drop(0.3).with_duplication(0.2);
+        let (first, stats) = send_burst(impairment, 200);
+        let (second, _) = send_burst(impairment, 200);
+        
+        assert_eq!(first, second);
+        assert_eq!(stats.sent, 200);
+        assert!(stats.dropped > 20 && stats.duplicated > 10);
+        assert_eq!(stats.delivered, stats.sent - stats.dropped + stats.duplicated);
+        assert_eq!(first.len() as u64, stats.delivered);
+    }
+    
+    #[test]
+    fn test_reorder_and_latency() {
+        let (received, stats) = send_burst(Impairment::new(7).with_reorder(0.25, Duration::from_millis(10)), 40);
+        assert_eq!(received.len(), 40);
+        assert!(stats.reordered > 0);
+        assert!(received.windows(2).any(|pair| pair[0] > pair[1]));
+        
+        let (mut sensor, mut gateway) = LossyTransport::pair(
+            Impairment::new(1).with_latency(Duration::from_millis(20), Duration::ZERO),
+        );
+        sensor.send_frame(&[1]).unwrap();
+        
+        let mut buffer = [0u8; 8];
+        assert_eq!(gateway.recv_frame(&mut buffer, None).unwrap(), None);
+        let start = Instant::now();
+        assert_eq!(gateway.recv_frame(&mut buffer, Some(Duration::from_secs(1))).unwrap(), Some(1));
+        assert!(start.elapsed() >= Duration::from_millis(15));
+    }
+}
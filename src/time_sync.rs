@@ -0,0 +1,358 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+
+use rkyv::{check_archived_root, to_bytes, Deserialize};
+
+use crate::contracts::{TimeSyncRequest, TimeSyncResponse};
+use crate::errors::{CyDnAError, Result};
+use crate::framing::{decode_frame, encode_frame, MessageType};
+
+// Samples kept per device; the estimate comes from the one with the
+// shortest round trip, which has the least asymmetric queueing in it.
+pub const DEFAULT_SKEW_SAMPLE_WINDOW: usize = 8;
+
+// A request unanswered for this long is taken as lost; a round trip that
+// slow would not give a usable estimate anyway.
+pub const DEFAULT_TIME_SYNC_TIMEOUT_MS: u64 = 10_000;
+
+// Outstanding requests kept per device; sending another drops the oldest.
+pub const MAX_PENDING_TIME_SYNC: usize = 4;
+
+pub fn encode_time_sync_request(request: &TimeSyncRequest) -> Result<Vec<u8>> {
+    let bytes = to_bytes::<_, 32>(request)
+        .map_err(|_| CyDnAError::SerializationError(
+            "Failed to serialize TimeSyncRequest"
+        ))?;
+    
+    encode_frame(MessageType::TimeSyncRequest, &bytes)
+}
+
+pub fn parse_time_sync_request(datagram: &[u8]) -> Result<TimeSyncRequest> {
+    let body = decode_frame(datagram, MessageType::TimeSyncRequest)?;
+    
+    let archived = check_archived_root::<TimeSyncRequest>(body)
+        .map_err(|_| CyDnAError::DeserializationError(
+            "Failed to validate TimeSyncRequest"
+        ))?;
+    
+    archived.deserialize(&mut rkyv::Infallible)
+        .map_err(|_| CyDnAError::DeserializationError(
+            "Failed to deserialize TimeSyncRequest"
+        ))
+}
+
+pub fn encode_time_sync_response(response: &TimeSyncResponse) -> Result<Vec<u8>> {
+    let bytes = to_bytes::<_, 64>(response)
+        .map_err(|_| CyDnAError::SerializationError(
+            "Failed to serialize TimeSyncResponse"
+        ))?;
+    
+    encode_frame(MessageType::TimeSyncResponse, &bytes)
+}
+
+pub fn parse_time_sync_response(datagram: &[u8]) -> Result<TimeSyncResponse> {
+    let body = decode_frame(datagram, MessageType::TimeSyncResponse)?;
+    
+    let archived = check_archived_root::<TimeSyncResponse>(body)
+        .map_err(|_| CyDnAError::DeserializationError(
+            "Failed to validate TimeSyncResponse"
+        ))?;
+    
+    archived.deserialize(&mut rkyv::Infallible)
+        .map_err(|_| CyDnAError::DeserializationError(
+            "Failed to deserialize TimeSyncResponse"
+        ))
+}
+
+// Sensor side: the reply frame for `request`, stamped with the sensor clock
+// when the request arrived and when the reply leaves.
+pub fn respond(request: &TimeSyncRequest, receive_ms: u64, transmit_ms: u64) -> Result<Vec<u8>> {
+    encode_time_sync_response(&TimeSyncResponse {
+        device_unique_id: request.device_unique_id,
+        request_id: request.request_id,
+        origin_ms: request.origin_ms,
+        receive_ms,
+        transmit_ms,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSyncSample {
+    // Sensor clock minus gateway clock; positive when the sensor runs ahead.
+    pub offset_ms: i64,
+    
+    pub round_trip_ms: u64,
+}
+
+impl TimeSyncSample {
+    // NTP's four-timestamp estimate, assuming the path is equally slow in
+    // both directions; `destination_ms` is the gateway clock on arrival.
+    pub fn from_exchange(response: &TimeSyncResponse, destination_ms: u64) -> Self {
+        let (t0, t1, t2, t3) = (
+            response.origin_ms as i64,
+            response.receive_ms as i64,
+            response.transmit_ms as i64,
+            destination_ms as i64,
+        );
+        
+        Self {
+            offset_ms: ((t1 - t0) + (t2 - t3)) / 2,
+            round_trip_ms: ((t3 - t0) - (t2 - t1)).max(0) as u64,
+        }
+    }
+}
+
+#[derive(Default)]
+struct SkewState {
+    pending: HashMap<(u32, u32), u64>,
+    samples: HashMap<u32, VecDeque<TimeSyncSample>>,
+    next_request_id: u32,
+}
+
+// Gateway side: runs time-sync exchanges with sensors and keeps a clock
+// offset estimate per device. Cheap to clone; a clone handed to
+// `GatewayServer::with_clock_skew` sees every estimate made through another.
+#[derive(Clone)]
+pub struct ClockSkewTracker {
+    state: Arc<Mutex<SkewState>>,
+    sample_window: usize,
+    request_timeout_ms: u64,
+}
+
+impl ClockSkewTracker {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(SkewState {
+                next_request_id: rand::random(),
+                ..Default::default()
+            })),
+            sample_window: DEFAULT_SKEW_SAMPLE_WINDOW,
+            request_timeout_ms: DEFAULT_TIME_SYNC_TIMEOUT_MS,
+        }
+    }
+    
+    pub fn with_sample_window(mut self, samples: usize) -> Self {
+        self.sample_window = samples.max(1);
+        self
+    }
+    
+    pub fn with_request_timeout_ms(mut self, timeout_ms: u64) -> Self {
+        self.request_timeout_ms = timeout_ms;
+        self
+    }
+    
+    // `current_time_ms` is the gateway clock, the same one later passed to
+    // `handle_response`.
+    pub fn send_request(
+        &self,
+        socket: &UdpSocket,
+        destination: impl ToSocketAddrs,
+        device_id: u32,
+        current_time_ms: u64,
+    ) -> Result<u32> {
+        let destination = crate::socket::resolve(destination)?;
+        let mut state = self.state.lock().unwrap();
+        let request_id = state.next_request_id;
+        
+        let request = TimeSyncRequest { device_unique_id: device_id, request_id, origin_ms: current_time_ms };
+        socket.send_to(&encode_time_sync_request(&request)?, destination)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        state.next_request_id = request_id.wrapping_add(1);
+        
+        // Lost probes and offline sensors would otherwise grow the map for
+        // good.
+        let timeout_ms = self.request_timeout_ms;
+        state.pending.retain(|_, sent_ms| current_time_ms.saturating_sub(*sent_ms) <= timeout_ms);
+        let mut outstanding: Vec<(u32, u64)> = state.pending.iter()
+            .filter(|((pending_device, _), _)| *pending_device == device_id)
+            .map(|(&(_, pending_id), &sent_ms)| (pending_id, sent_ms))
+            .collect();
+        if outstanding.len() >= MAX_PENDING_TIME_SYNC {
+            outstanding.sort_unstable_by_key(|&(_, sent_ms)| sent_ms);
+            for &(pending_id, _) in &outstanding[..=outstanding.len() - MAX_PENDING_TIME_SYNC] {
+                state.pending.remove(&(device_id, pending_id));
+            }
+        }
+        
+        state.pending.insert((device_id, request_id), current_time_ms);
+        Ok(request_id)
+    }
+    
+    // Only answers to outstanding requests count, and only if they echo the
+    // origin that was sent, so a stale or forged response cannot move the
+    // estimate.
+    pub fn handle_response(&self, response: &TimeSyncResponse, current_time_ms: u64) -> Option<TimeSyncSample> {
+        let mut state = self.state.lock().unwrap();
+        let key = (response.device_unique_id, response.request_id);
+        if state.pending.get(&key) != Some(&response.origin_ms) {
+            return None;
+        }
+        state.pending.remove(&key);
+        drop(state);
+        
+        let sample = TimeSyncSample::from_exchange(response, current_time_ms);
+        self.record_sample(response.device_unique_id, sample);
+        Some(sample)
+    }
+    
+    pub fn handle_datagram(&self, datagram: &[u8], current_time_ms: u64) -> Option<TimeSyncSample> {
+        let response = parse_time_sync_response(datagram).ok()?;
+        self.handle_response(&response, current_time_ms)
+    }
+    
+    // For offsets measured some other way, e.g. by the sensor itself.
+    pub fn record_sample(&self, device_id: u32, sample: TimeSyncSample) {
+        let mut state = self.state.lock().unwrap();
+        let samples = state.samples.entry(device_id).or_default();
+        
+        if samples.len() == self.sample_window {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+    
+    pub fn offset_ms(&self, device_id: u32) -> Option<i64> {
+        let state = self.state.lock().unwrap();
+        
+        state.samples.get(&device_id)?
+            .iter()
+            .min_by_key(|sample| sample.round_trip_ms)
+            .map(|sample| sample.offset_ms)
+    }
+    
+    // The gateway's `current_time_ms` read on the device's clock, for
+    // comparing against the timestamps it sends; unchanged for devices
+    // without an estimate.
+    pub fn device_time_ms(&self, device_id: u32, current_time_ms: u64) -> u64 {
+        let offset_ms = self.offset_ms(device_id).unwrap_or(0);
+        current_time_ms.saturating_add_signed(offset_ms)
+    }
+    
+    pub fn forget(&self, device_id: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.samples.remove(&device_id);
+        state.pending.retain(|(pending_device, _), _| *pending_device != device_id);
+    }
+    
+    pub fn pending_count(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+    
+    pub fn tracked_devices(&self) -> usize {
+        self.state.lock().unwrap().samples.len()
+    }
+}
+
+impl Default for ClockSkewTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn response(origin_ms: u64, receive_ms: u64, transmit_ms: u64) -> TimeSyncResponse {
+        TimeSyncResponse { device_unique_id: 1, request_id: 0, origin_ms, receive_ms, transmit_ms }
+    }
+    
+    #[test]
+    fn test_offset_from_exchange() {
+        // Sensor 5 s behind, 20 ms each way, 2 ms to answer.
+        let sample = TimeSyncSample::from_exchange(&response(10_000, 5_020, 5_022), 10_042);
+        assert_eq!(sample, TimeSyncSample { offset_ms: -5_000, round_trip_ms: 40 });
+        
+        let request = TimeSyncRequest { device_unique_id: 1, request_id: 9, origin_ms: 10_000 };
+        let frame = encode_time_sync_request(&request).unwrap();
+        assert_eq!(parse_time_sync_request(&frame).unwrap(), request);
+        
+        let reply = parse_time_sync_response(&respond(&request, 5_020, 5_022).unwrap()).unwrap();
+        assert_eq!(reply, TimeSyncResponse { request_id: 9, ..response(10_000, 5_020, 5_022) });
+    }
+    
+    #[test]
+    fn test_tracker_prefers_shortest_round_trip() {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let tracker = ClockSkewTracker::new().with_sample_window(4);
+        
+        let request_id = tracker.send_request(&gateway, sensor.local_addr().unwrap(), 1, 10_000).unwrap();
+        let mut buffer = [0u8; 64];
+        let len = sensor.recv(&mut buffer).unwrap();
+        let request = parse_time_sync_request(&buffer[..len]).unwrap();
+        assert_eq!(request.request_id, request_id);
+        
+        // A forged origin is ignored; the genuine answer is used once.
+        let forged = TimeSyncResponse { request_id, ..response(9_000, 12_000, 12_000) };
+        assert!(tracker.handle_response(&forged, 10_010).is_none());
+        let reply = respond(&request, 12_005, 12_005).unwrap();
+        assert_eq!(tracker.handle_datagram(&reply, 10_010).unwrap().offset_ms, 2_000);
+        assert!(tracker.handle_datagram(&reply, 10_010).is_none());
+        
+        // A slow exchange with a lopsided path skews its own estimate but
+        // does not displace the faster one.
+        tracker.record_sample(1, TimeSyncSample { offset_ms: 2_300, round_trip_ms: 600 });
+        assert_eq!(tracker.offset_ms(1), Some(2_000));
+        assert_eq!(tracker.device_time_ms(1, 50_000), 52_000);
+        assert_eq!(tracker.device_time_ms(2, 50_000), 50_000);
+        assert_eq!(tracker.pending_count(), 0);
+    }
+    
+    #[test]
+    fn test_unanswered_requests_are_bounded() {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let tracker = ClockSkewTracker::new().with_request_timeout_ms(1_000);
+        
+        for attempt in 0..10 {
+            tracker.send_request(&gateway, sensor, 1, attempt * 10).unwrap();
+        }
+        assert_eq!(tracker.pending_count(), MAX_PENDING_TIME_SYNC);
+        
+        tracker.send_request(&gateway, sensor, 2, 5_000).unwrap();
+        assert_eq!(tracker.pending_count(), 1);
+    }
+    
+    #[test]
+    fn test_gateway_corrects_lagging_sensor() {
+        use crate::ack_manager::{AckManager, AckMessage};
+        use crate::contracts::{NackReason, SensorPayload, ANOMALY_VECTOR_SIZE};
+        use crate::server::GatewayServer;
+        use crate::transmitter::Transmitter;
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+        
+        let tracker = ClockSkewTracker::new();
+        tracker.record_sample(5, TimeSyncSample { offset_ms: -10_000, round_trip_ms: 4 });
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_poll_interval_ms(20)
+            .with_clock_skew(tracker.clone())
+            .spawn()
+            .unwrap();
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        
+        // Both sensors stamp a reading 10 s in the gateway's past with a 5 s
+        // TTL; only device 5 is known to run that far behind.
+        let mut buffer = [0u8; 64];
+        for (device_id, accepted) in [(5, true), (6, false)] {
+            let payload = SensorPayload::new(device_id, now - 10_000, 1, 80, 5_000, 0, [0.0; ANOMALY_VECTOR_SIZE]).unwrap();
+            Transmitter::send(&sensor, &payload, gateway.local_address()).unwrap();
+            
+            let (len, _) = sensor.recv_from(&mut buffer).unwrap();
+            let Some(AckMessage::Single(ack)) = AckManager::parse_ack_message(&buffer[..len]).unwrap() else {
+                panic!("expected a single ACK");
+            };
+            assert_eq!(ack.is_ack(), accepted);
+            if !accepted {
+                assert_eq!(ack.reason(), NackReason::ExpiredTtl);
+            }
+        }
+        
+        gateway.shutdown();
+    }
+}
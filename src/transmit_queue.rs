@@ -0,0 +1,188 @@
+use std::collections::VecDeque;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::time::Instant;
+
+use crate::contracts::SensorPayload;
+use crate::errors::{CyDnAError, Result};
+use crate::framing::Priority;
+use crate::pacing::Pacer;
+use crate::transmitter::Transmitter;
+
+const PRIORITY_CLASSES: usize = 3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransmitQueueMetrics {
+    pub sent: [u64; PRIORITY_CLASSES],
+    
+    pub dropped: [u64; PRIORITY_CLASSES],
+}
+
+// Frames are queued already encoded, one FIFO per priority class. Every
+// dequeue re-checks the classes from the top, so a critical alert pushed in
+// the middle of a bulk transfer goes out on the very next send.
+pub struct TransmitQueue {
+    classes: [VecDeque<Vec<u8>>; PRIORITY_CLASSES],
+    capacity_per_class: usize,
+    metrics: TransmitQueueMetrics,
+}
+
+impl TransmitQueue {
+    pub fn new(capacity_per_class: usize) -> Self {
+        Self {
+            classes: Default::default(),
+            capacity_per_class: capacity_per_class.max(1),
+            metrics: TransmitQueueMetrics::default(),
+        }
+    }
+    
+    pub fn push(&mut self, payload: &SensorPayload, priority: Priority) -> Result<bool> {
+        let frame = Transmitter::frame_payload_with_priority(payload, priority)?;
+        
+        Ok(self.push_frame(frame, priority))
+    }
+    
+    // A full class drops the incoming frame rather than an older one, so
+    // callers find out immediately and can fall back to store-and-forward.
+    pub fn push_frame(&mut self, frame: Vec<u8>, priority: Priority) -> bool {
+        let class = &mut self.classes[priority.rank()];
+        
+        if class.len() >= self.capacity_per_class {
+            self.metrics.dropped[priority.rank()] += 1;
+            return false;
+        }
+        
+        class.push_back(frame);
+        true
+    }
+    
+    pub fn pop(&mut self) -> Option<(Priority, Vec<u8>)> {
+        [Priority::Critical, Priority::Normal, Priority::Bulk]
+            .into_iter()
+            .find_map(|priority| {
+                self.classes[priority.rank()].pop_front().map(|frame| (priority, frame))
+            })
+    }
+    
+    pub fn send_next(&mut self, socket: &UdpSocket, destination: impl ToSocketAddrs) -> Result<Option<Priority>> {
+        let Some((priority, frame)) = self.pop() else {
+            return Ok(None);
+        };
+        
+        if let Err(e) = socket.send_to(&frame, destination) {
+            self.classes[priority.rank()].push_front(frame);
+            return Err(CyDnAError::IoError(e.kind()));
+        }
+        
+        self.metrics.sent[priority.rank()] += 1;
+        Ok(Some(priority))
+    }
+    
+    // Sends at most `budget` frames; returns how many went out.
+    pub fn flush(&mut self, socket: &UdpSocket, destination: impl ToSocketAddrs, budget: usize) -> Result<usize> {
+        let destination = crate::socket::resolve(destination)?;
+        let mut sent = 0;
+        
+        while sent < budget && self.send_next(socket, destination)?.is_some() {
+            sent += 1;
+        }
+        
+        Ok(sent)
+    }
+    
+    // Sends only as many frames as the pacer currently allows; never blocks.
+    pub fn flush_paced(
+        &mut self,
+        socket: &UdpSocket,
+        destination: impl ToSocketAddrs,
+        pacer: &mut Pacer,
+    ) -> Result<usize> {
+        let destination = crate::socket::resolve(destination)?;
+        let mut sent = 0;
+        
+        while pacer.can_send(Instant::now()) && self.send_next(socket, destination)?.is_some() {
+            pacer.on_send(Instant::now());
+            sent += 1;
+        }
+        
+        Ok(sent)
+    }
+    
+    pub fn len_of(&self, priority: Priority) -> usize {
+        self.classes[priority.rank()].len()
+    }
+    
+    pub fn len(&self) -> usize {
+        self.classes.iter().map(VecDeque::len).sum()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.classes.iter().all(VecDeque::is_empty)
+    }
+    
+    pub fn metrics(&self) -> TransmitQueueMetrics {
+        self.metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing::FrameHeader;
+    
+    fn payload(id: u32) -> SensorPayload {
+        SensorPayload::new(
+            id, 1000, 1, 50, 1000, id,
+            [0.0; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap()
+    }
+    
+    #[test]
+    fn test_critical_preempts_bulk() {
+        let mut queue = TransmitQueue::new(2);
+        
+        assert!(queue.push(&payload(1), Priority::Bulk).unwrap());
+        assert!(queue.push(&payload(2), Priority::Bulk).unwrap());
+        assert!(!queue.push(&payload(3), Priority::Bulk).unwrap());
+        assert!(queue.push(&payload(4), Priority::Normal).unwrap());
+        
+        assert_eq!(queue.pop().unwrap().0, Priority::Normal);
+        assert_eq!(queue.pop().unwrap().0, Priority::Bulk);
+        
+        assert!(queue.push(&payload(5), Priority::Critical).unwrap());
+        let (priority, frame) = queue.pop().unwrap();
+        assert_eq!(priority, Priority::Critical);
+        assert_eq!(FrameHeader::decode(&frame).unwrap().priority(), Priority::Critical);
+        
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.len_of(Priority::Bulk), 1);
+        assert_eq!(queue.metrics().dropped, [0, 0, 1]);
+    }
+    
+    #[test]
+    fn test_flush_respects_budget() {
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let mut queue = TransmitQueue::new(8);
+        for id in 1..=3 {
+            queue.push(&payload(id), Priority::Bulk).unwrap();
+        }
+        queue.push(&payload(9), Priority::Critical).unwrap();
+        
+        assert_eq!(queue.flush(&sensor, &gateway_addr, 2).unwrap(), 2);
+        assert_eq!(queue.metrics().sent, [1, 0, 1]);
+        
+        let mut buffer = [0u8; crate::MAX_PAYLOAD_SIZE];
+        let (received, _) = gateway.recv_from(&mut buffer).unwrap();
+        assert_eq!(FrameHeader::decode(&buffer[..received]).unwrap().priority(), Priority::Critical);
+        assert_eq!(queue.len(), 2);
+        
+        let mut pacer = Pacer::new(crate::pacing::PacingConfig {
+            initial_rate_pps: 1.0,
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(queue.flush_paced(&sensor, &gateway_addr, &mut pacer).unwrap(), 1);
+        assert_eq!(queue.len(), 1);
+    }
+}
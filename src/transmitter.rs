@@ -1,10 +1,16 @@
-use std::net::UdpSocket;
+use std::net::{ToSocketAddrs, UdpSocket};
 use std::time::Instant;
 
 use rkyv::to_bytes;
 
-use crate::contracts::SensorPayload;
+use crate::compression::compress_frame;
+use crate::contracts::{Heartbeat, QuantizedSensorPayload, SensorPayload, SensorPayloadV2};
 use crate::errors::{CyDnAError, Result};
+use crate::quantization::VectorEncoding;
+use crate::framing::{
+    encode_frame, encode_frame_with_header, encode_frame_with_priority, negotiate_compression, packed_stride,
+    Compression, FrameHeader, MessageType, Priority, FRAME_HEADER_SIZE, PACKED_HEADER_SIZE,
+};
 
 pub struct Transmitter;
 
@@ -13,42 +19,218 @@ impl Transmitter {
         to_bytes::<_, 1024>(payload)
             .map(|aligned_vec| aligned_vec.to_vec())
             .map_err(|_| CyDnAError::SerializationError(
-                "Failed to serialize SensorPayload".to_string()
+                "Failed to serialize SensorPayload"
             ))
     }
     
+    pub fn frame_payload(payload: &SensorPayload) -> Result<Vec<u8>> {
+        Self::frame_payload_with_priority(payload, Priority::Normal)
+    }
+    
+    pub fn frame_payload_with_priority(payload: &SensorPayload, priority: Priority) -> Result<Vec<u8>> {
+        let bytes = Self::serialize_payload(payload)?;
+        
+        encode_frame_with_priority(MessageType::SensorPayload, priority, &bytes)
+    }
+    
     pub fn send(
         socket: &UdpSocket,
         payload: &SensorPayload,
-        destination: &str,
+        destination: impl ToSocketAddrs,
     ) -> Result<usize> {
-        let bytes = Self::serialize_payload(payload)?;
+        let frame = Self::frame_payload(payload)?;
         
-        if bytes.len() > crate::MAX_PAYLOAD_SIZE {
-            return Err(CyDnAError::BufferTooSmall {
-                required: bytes.len(),
-                available: crate::MAX_PAYLOAD_SIZE,
-            });
-        }
+        socket.send_to(&frame, destination)
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    pub fn send_with_codec<C: crate::codec::WireCodec>(
+        socket: &UdpSocket,
+        payload: &SensorPayload,
+        destination: impl ToSocketAddrs,
+    ) -> Result<usize> {
+        let frame = crate::codec::frame_payload::<C>(payload)?;
+        
+        socket.send_to(&frame, destination)
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    pub fn frame_payload_v2(payload: &SensorPayloadV2) -> Result<Vec<u8>> {
+        let bytes = to_bytes::<_, 1024>(payload)
+            .map_err(|_| CyDnAError::SerializationError(
+                "Failed to serialize SensorPayloadV2"
+            ))?;
+        let header = FrameHeader::new(MessageType::SensorPayload, 0)
+            .with_version(crate::CYNDA_VERSION_V2);
         
-        socket.send_to(&bytes, destination)
-            .map_err(|e| CyDnAError::IoError(e.to_string()))
+        encode_frame_with_header(header, &bytes)
+    }
+    
+    pub fn send_v2(
+        socket: &UdpSocket,
+        payload: &SensorPayloadV2,
+        destination: impl ToSocketAddrs,
+    ) -> Result<usize> {
+        let frame = Self::frame_payload_v2(payload)?;
+        
+        socket.send_to(&frame, destination)
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    pub fn frame_quantized(payload: &SensorPayload, encoding: VectorEncoding) -> Result<Vec<u8>> {
+        let quantized = QuantizedSensorPayload::from_payload(payload, encoding)?;
+        let bytes = to_bytes::<_, 256>(&quantized)
+            .map_err(|_| CyDnAError::SerializationError(
+                "Failed to serialize QuantizedSensorPayload"
+            ))?;
+        
+        encode_frame(MessageType::QuantizedPayload, &bytes)
+    }
+    
+    pub fn send_quantized(
+        socket: &UdpSocket,
+        payload: &SensorPayload,
+        encoding: VectorEncoding,
+        destination: impl ToSocketAddrs,
+    ) -> Result<usize> {
+        let frame = Self::frame_quantized(payload, encoding)?;
+        
+        socket.send_to(&frame, destination)
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    pub fn frame_heartbeat(heartbeat: &Heartbeat) -> Result<Vec<u8>> {
+        let bytes = to_bytes::<_, 64>(heartbeat)
+            .map_err(|_| CyDnAError::SerializationError(
+                "Failed to serialize Heartbeat"
+            ))?;
+        
+        encode_frame(MessageType::Heartbeat, &bytes)
+    }
+    
+    pub fn send_heartbeat(
+        socket: &UdpSocket,
+        heartbeat: &Heartbeat,
+        destination: impl ToSocketAddrs,
+    ) -> Result<usize> {
+        let frame = Self::frame_heartbeat(heartbeat)?;
+        
+        socket.send_to(&frame, destination)
+            .map_err(|e| CyDnAError::IoError(e.kind()))
     }
     
     pub fn send_raw(
         socket: &UdpSocket,
         bytes: &[u8],
-        destination: &str,
+        destination: impl ToSocketAddrs,
     ) -> Result<usize> {
-        if bytes.len() > crate::MAX_PAYLOAD_SIZE {
+        Self::check_datagram_size(bytes.len())?;
+        
+        socket.send_to(bytes, destination)
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    pub fn max_packed_payloads() -> usize {
+        let stride = packed_stride(std::mem::size_of::<crate::contracts::ArchivedSensorPayload>());
+        
+        (crate::MAX_PAYLOAD_SIZE - FRAME_HEADER_SIZE - PACKED_HEADER_SIZE) / stride
+    }
+    
+    // Packed body: count u16 LE | entry length u16 LE | reserved (4) | entries,
+    // each entry padded to an 8-byte stride so every archive stays aligned.
+    pub fn frame_packed(payloads: &[SensorPayload]) -> Result<Vec<u8>> {
+        if payloads.is_empty() {
+            return Err(CyDnAError::SerializationError(
+                "Cannot pack an empty payload batch"
+            ));
+        }
+        
+        let entries = Self::serialize_batch(payloads)?;
+        let entry_len = entries[0].len();
+        let stride = packed_stride(entry_len);
+        
+        let mut body = vec![0u8; PACKED_HEADER_SIZE + stride * entries.len()];
+        body[0..2].copy_from_slice(&(entries.len() as u16).to_le_bytes());
+        body[2..4].copy_from_slice(&(entry_len as u16).to_le_bytes());
+        
+        for (idx, entry) in entries.iter().enumerate() {
+            if entry.len() != entry_len {
+                return Err(CyDnAError::SerializationError(
+                    "Packed payload entries differ in length"
+                ));
+            }
+            
+            let offset = PACKED_HEADER_SIZE + idx * stride;
+            body[offset..offset + entry_len].copy_from_slice(entry);
+        }
+        
+        encode_frame(MessageType::PackedPayloads, &body)
+    }
+    
+    pub fn send_packed(
+        socket: &UdpSocket,
+        payloads: &[SensorPayload],
+        destination: impl ToSocketAddrs,
+    ) -> Result<usize> {
+        let frame = Self::frame_packed(payloads)?;
+        
+        socket.send_to(&frame, destination)
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    // Packed batches are where compression pays off. `peer_mask` is the
+    // gateway's advertised `compression_mask`: the frame goes out plain when
+    // the gateway cannot decode `compression`, or the codec would not shrink it.
+    pub fn send_packed_compressed(
+        socket: &UdpSocket,
+        payloads: &[SensorPayload],
+        compression: Compression,
+        peer_mask: u8,
+        destination: impl ToSocketAddrs,
+    ) -> Result<usize> {
+        let compression = negotiate_compression(compression, peer_mask);
+        let frame = compress_frame(&Self::frame_packed(payloads)?, compression)?;
+        
+        socket.send_to(&frame, destination)
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    #[cfg(feature = "authentication")]
+    pub fn send_authenticated(
+        socket: &UdpSocket,
+        payload: &SensorPayload,
+        authenticator: &crate::authentication::DatagramAuthenticator,
+        destination: impl ToSocketAddrs,
+    ) -> Result<usize> {
+        let frame = Self::frame_payload(payload)?;
+        let sealed = authenticator.seal(payload.device_unique_id, &frame)?;
+        
+        Self::send_raw(socket, &sealed, destination)
+    }
+    
+    #[cfg(feature = "encryption")]
+    pub fn send_encrypted(
+        socket: &UdpSocket,
+        payload: &SensorPayload,
+        cipher: &mut crate::encryption::PayloadCipher,
+        destination: impl ToSocketAddrs,
+    ) -> Result<usize> {
+        let bytes = Self::serialize_payload(payload)?;
+        let envelope = cipher.seal(payload.device_unique_id, &bytes)?;
+        let frame = encode_frame(MessageType::EncryptedPayload, &envelope)?;
+        
+        Self::send_raw(socket, &frame, destination)
+    }
+    
+    pub(crate) fn check_datagram_size(len: usize) -> Result<()> {
+        if len > crate::MAX_PAYLOAD_SIZE {
             return Err(CyDnAError::BufferTooSmall {
-                required: bytes.len(),
+                required: len,
                 available: crate::MAX_PAYLOAD_SIZE,
             });
         }
         
-        socket.send_to(bytes, destination)
-            .map_err(|e| CyDnAError::IoError(e.to_string()))
+        Ok(())
     }
     
     pub fn serialize_batch(payloads: &[SensorPayload]) -> Result<Vec<Vec<u8>>> {
@@ -111,24 +293,17 @@ pub struct TransmitMetrics {
 pub fn send_with_metrics(
     socket: &UdpSocket,
     payload: &SensorPayload,
-    destination: &str,
+    destination: impl ToSocketAddrs,
 ) -> Result<TransmitMetrics> {
     let start = Instant::now();
     
     let serialization_start = Instant::now();
-    let bytes = Transmitter::serialize_payload(payload)?;
+    let frame = Transmitter::frame_payload(payload)?;
     let serialization_us = serialization_start.elapsed().as_micros() as u64;
     
-    if bytes.len() > crate::MAX_PAYLOAD_SIZE {
-        return Err(CyDnAError::BufferTooSmall {
-            required: bytes.len(),
-            available: crate::MAX_PAYLOAD_SIZE,
-        });
-    }
-    
     let transmission_start = Instant::now();
-    let bytes_sent = socket.send_to(&bytes, destination)
-        .map_err(|e| CyDnAError::IoError(e.to_string()))? as u64;
+    let bytes_sent = socket.send_to(&frame, destination)
+        .map_err(|e| CyDnAError::IoError(e.kind()))? as u64;
     let transmission_us = transmission_start.elapsed().as_micros() as u64;
     
     let total_us = start.elapsed().as_micros() as u64;
@@ -192,4 +367,58 @@ mod tests {
         assert_eq!(builder.get_max_retries(), 5);
         assert_eq!(builder.get_socket_timeout_ms(), 200);
     }
+    
+    #[test]
+    fn test_ipv6_send_and_ack() {
+        use crate::ack_manager::AckManager;
+        use crate::receiver::Receiver;
+        use std::time::Duration;
+        
+        let sensor = UdpSocket::bind("[::1]:0").unwrap();
+        let gateway = UdpSocket::bind("[::1]:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        gateway.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let gateway_addr = gateway.local_addr().unwrap();
+        
+        let payload = SensorPayload::new(6, 1000, 1, 50, 1000, 6, [0.6; crate::contracts::ANOMALY_VECTOR_SIZE])
+            .unwrap();
+        Transmitter::send(&sensor, &payload, gateway_addr).unwrap();
+        
+        let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+        let (archived, _, sender) = Receiver::receive(&gateway, &mut buffer).unwrap();
+        assert_eq!(archived.device_unique_id, 6);
+        assert!(sender.is_ipv6());
+        AckManager::send_ack(&gateway, 6, 1000, sender).unwrap();
+        
+        let mut ack_buffer = vec![0u8; 256];
+        assert!(AckManager::wait_for_ack(&sensor, 6, 1000, &mut ack_buffer).unwrap());
+    }
+    
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_send_encrypted_roundtrip() {
+        use crate::encryption::PayloadCipher;
+        use crate::receiver::Receiver;
+        
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap().to_string();
+        
+        let mut sensor_cipher = PayloadCipher::new();
+        sensor_cipher.add_device_key(5, [3u8; 32]);
+        let mut gateway_cipher = PayloadCipher::new();
+        gateway_cipher.add_device_key(5, [3u8; 32]);
+        
+        let payload = SensorPayload::new(
+            5, 1000, 1, 50, 1000, 0x12345678,
+            [0.7; crate::contracts::ANOMALY_VECTOR_SIZE],
+        ).unwrap();
+        Transmitter::send_encrypted(&sensor, &payload, &mut sensor_cipher, &gateway_addr).unwrap();
+        
+        let mut buffer = vec![0u8; crate::MAX_PAYLOAD_SIZE];
+        let (archived, _, _) = Receiver::receive_encrypted(&gateway, &mut buffer, &gateway_cipher)
+            .unwrap();
+        assert_eq!(archived.device_unique_id, 5);
+        assert_eq!(archived.anomaly_ai_vector[0], 0.7);
+    }
 }
@@ -0,0 +1,457 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use crate::errors::{CyDnAError, Result};
+use crate::transmitter::Transmitter;
+use crate::MAX_PAYLOAD_SIZE;
+
+// TCP frames are the UDP datagrams prefixed with their length (u16 LE), so
+// both ends reuse the same framing, validation and ACK logic.
+pub const TCP_LENGTH_PREFIX_SIZE: usize = 2;
+
+pub const DEFAULT_FALLBACK_THRESHOLD: u32 = 6;
+
+pub const DEFAULT_TCP_CONNECT_TIMEOUT_MS: u64 = 2_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransportKind {
+    Udp,
+    Tcp,
+}
+
+// One frame per call in either direction. `recv_frame` with `None` never
+// blocks; `Ok(None)` means nothing arrived in time.
+pub trait FrameTransport {
+    fn kind(&self) -> TransportKind;
+    
+    fn send_frame(&mut self, frame: &[u8]) -> Result<usize>;
+    
+    fn recv_frame(&mut self, buffer: &mut [u8], timeout: Option<Duration>) -> Result<Option<usize>>;
+}
+
+fn is_timeout(kind: std::io::ErrorKind) -> bool {
+    matches!(kind, std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut)
+}
+
+pub struct UdpTransport {
+    socket: UdpSocket,
+}
+
+impl UdpTransport {
+    // The socket is connected so only the gateway's datagrams are received.
+    pub fn connect(socket: UdpSocket, gateway_address: impl ToSocketAddrs) -> Result<Self> {
+        socket.connect(gateway_address)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        Ok(Self { socket })
+    }
+    
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+    
+    pub fn peer_address(&self) -> Result<SocketAddr> {
+        self.socket.peer_addr()
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+}
+
+impl FrameTransport for UdpTransport {
+    fn kind(&self) -> TransportKind {
+        TransportKind::Udp
+    }
+    
+    fn send_frame(&mut self, frame: &[u8]) -> Result<usize> {
+        self.socket.send(frame)
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    // A refused connection (ICMP port unreachable) reads as "nothing yet",
+    // like a timeout: the retransmission logic decides what to do about it.
+    fn recv_frame(&mut self, buffer: &mut [u8], timeout: Option<Duration>) -> Result<Option<usize>> {
+        match timeout {
+            Some(timeout) => {
+                self.socket.set_nonblocking(false)
+                    .and_then(|_| self.socket.set_read_timeout(Some(timeout)))
+            }
+            None => self.socket.set_nonblocking(true),
+        }
+        .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        match self.socket.recv(buffer) {
+            Ok(bytes_received) => Ok(Some(bytes_received)),
+            Err(e) if is_timeout(e.kind()) || e.kind() == std::io::ErrorKind::ConnectionRefused => Ok(None),
+            Err(e) => Err(CyDnAError::IoError(e.kind())),
+        }
+    }
+}
+
+// An unconnected socket borrowed for one exchange with `peer`; replies are
+// read from any sender, like the socket-based helpers always have.
+pub(crate) struct UdpPeer<'a> {
+    socket: &'a UdpSocket,
+    peer: SocketAddr,
+}
+
+impl<'a> UdpPeer<'a> {
+    pub(crate) fn new(socket: &'a UdpSocket, peer: SocketAddr) -> Self {
+        Self { socket, peer }
+    }
+}
+
+impl FrameTransport for UdpPeer<'_> {
+    fn kind(&self) -> TransportKind {
+        TransportKind::Udp
+    }
+    
+    fn send_frame(&mut self, frame: &[u8]) -> Result<usize> {
+        self.socket.send_to(frame, self.peer)
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    fn recv_frame(&mut self, buffer: &mut [u8], timeout: Option<Duration>) -> Result<Option<usize>> {
+        match timeout {
+            Some(timeout) => self.socket.set_read_timeout(Some(timeout)),
+            None => self.socket.set_nonblocking(true),
+        }
+        .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        match self.socket.recv_from(buffer) {
+            Ok((bytes_received, _)) => Ok(Some(bytes_received)),
+            Err(e) if is_timeout(e.kind()) => Ok(None),
+            Err(e) => Err(CyDnAError::IoError(e.kind())),
+        }
+    }
+}
+
+// Partial reads are buffered, so a timeout in the middle of a frame never
+// desynchronises the stream.
+pub struct TcpTransport {
+    stream: TcpStream,
+    pending: Vec<u8>,
+}
+
+impl TcpTransport {
+    pub fn connect(address: SocketAddr, timeout: Duration) -> Result<Self> {
+        let stream = TcpStream::connect_timeout(&address, timeout)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        Self::from_stream(stream)
+    }
+    
+    // Nagle is disabled: frames are small and latency-critical.
+    pub fn from_stream(stream: TcpStream) -> Result<Self> {
+        stream.set_nodelay(true)
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        Ok(Self {
+            stream,
+            pending: Vec::with_capacity(TCP_LENGTH_PREFIX_SIZE + MAX_PAYLOAD_SIZE),
+        })
+    }
+    
+    pub fn accept(listener: &TcpListener) -> Result<(Self, SocketAddr)> {
+        let (stream, peer) = listener.accept()
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        Ok((Self::from_stream(stream)?, peer))
+    }
+    
+    pub fn peer_address(&self) -> Result<SocketAddr> {
+        self.stream.peer_addr()
+            .map_err(|e| CyDnAError::IoError(e.kind()))
+    }
+    
+    fn take_frame(&mut self, buffer: &mut [u8]) -> Result<Option<usize>> {
+        if self.pending.len() < TCP_LENGTH_PREFIX_SIZE {
+            return Ok(None);
+        }
+        
+        let frame_len = u16::from_le_bytes([self.pending[0], self.pending[1]]) as usize;
+        if frame_len > MAX_PAYLOAD_SIZE {
+            return Err(CyDnAError::InvalidPacketLength {
+                expected: MAX_PAYLOAD_SIZE,
+                received: frame_len,
+            });
+        }
+        
+        let end = TCP_LENGTH_PREFIX_SIZE + frame_len;
+        if self.pending.len() < end {
+            return Ok(None);
+        }
+        
+        if buffer.len() < frame_len {
+            return Err(CyDnAError::BufferTooSmall {
+                required: frame_len,
+                available: buffer.len(),
+            });
+        }
+        
+        buffer[..frame_len].copy_from_slice(&self.pending[TCP_LENGTH_PREFIX_SIZE..end]);
+        self.pending.drain(..end);
+        Ok(Some(frame_len))
+    }
+}
+
+impl FrameTransport for TcpTransport {
+    fn kind(&self) -> TransportKind {
+        TransportKind::Tcp
+    }
+    
+    fn send_frame(&mut self, frame: &[u8]) -> Result<usize> {
+        Transmitter::check_datagram_size(frame.len())?;
+        
+        let mut framed = Vec::with_capacity(TCP_LENGTH_PREFIX_SIZE + frame.len());
+        framed.extend_from_slice(&(frame.len() as u16).to_le_bytes());
+        framed.extend_from_slice(frame);
+        
+        // Writes block so a frame is never cut short by a full send buffer.
+        self.stream.set_nonblocking(false)
+            .and_then(|_| self.stream.write_all(&framed))
+            .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        Ok(frame.len())
+    }
+    
+    fn recv_frame(&mut self, buffer: &mut [u8], timeout: Option<Duration>) -> Result<Option<usize>> {
+        if let Some(frame_len) = self.take_frame(buffer)? {
+            return Ok(Some(frame_len));
+        }
+        
+        match timeout {
+            Some(timeout) => {
+                self.stream.set_nonblocking(false)
+                    .and_then(|_| self.stream.set_read_timeout(Some(timeout)))
+            }
+            None => self.stream.set_nonblocking(true),
+        }
+        .map_err(|e| CyDnAError::IoError(e.kind()))?;
+        
+        let mut chunk = [0u8; MAX_PAYLOAD_SIZE];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(CyDnAError::IoError(std::io::ErrorKind::UnexpectedEof)),
+                Ok(bytes_read) => {
+                    self.pending.extend_from_slice(&chunk[..bytes_read]);
+                    if let Some(frame_len) = self.take_frame(buffer)? {
+                        return Ok(Some(frame_len));
+                    }
+                }
+                Err(e) if is_timeout(e.kind()) => return Ok(None),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(CyDnAError::IoError(e.kind())),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FallbackMetrics {
+    pub udp_frames: u64,
+    
+    pub tcp_frames: u64,
+    
+    pub fallbacks: u64,
+    
+    pub connect_failures: u64,
+}
+
+// Sends over UDP until `failure_threshold` consecutive delivery failures are
+// reported, then switches to TCP and stays there. If the connection drops,
+// frames go over UDP again until the threshold is reached once more.
+pub struct FallbackTransport {
+    udp: UdpTransport,
+    tcp: Option<TcpTransport>,
+    tcp_address: Option<SocketAddr>,
+    failure_threshold: u32,
+    consecutive_failures: u32,
+    connect_timeout: Duration,
+    metrics: FallbackMetrics,
+}
+
+impl FallbackTransport {
+    // Without `with_tcp_fallback` this is plain UDP.
+    pub fn new(udp: UdpTransport) -> Self {
+        Self {
+            udp,
+            tcp: None,
+            tcp_address: None,
+            failure_threshold: DEFAULT_FALLBACK_THRESHOLD,
+            consecutive_failures: 0,
+            connect_timeout: Duration::from_millis(DEFAULT_TCP_CONNECT_TIMEOUT_MS),
+            metrics: FallbackMetrics::default(),
+        }
+    }
+    
+    pub fn with_tcp_fallback(mut self, tcp_address: SocketAddr, failure_threshold: u32) -> Self {
+        self.tcp_address = Some(tcp_address);
+        self.failure_threshold = failure_threshold.max(1);
+        self
+    }
+    
+    pub fn with_connect_timeout_ms(mut self, timeout_ms: u64) -> Self {
+        self.connect_timeout = Duration::from_millis(timeout_ms.max(1));
+        self
+    }
+    
+    pub fn udp(&self) -> &UdpTransport {
+        &self.udp
+    }
+    
+    pub fn metrics(&self) -> FallbackMetrics {
+        self.metrics
+    }
+    
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+    
+    pub fn on_delivery_success(&mut self) {
+        self.consecutive_failures = 0;
+    }
+    
+    // Connection errors are counted, not returned: UDP stays usable and the
+    // next failure retries the connect.
+    pub fn on_delivery_failure(&mut self) {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        
+        let Some(tcp_address) = self.tcp_address else {
+            return;
+        };
+        
+        if self.tcp.is_some() || self.consecutive_failures < self.failure_threshold {
+            return;
+        }
+        
+        match TcpTransport::connect(tcp_address, self.connect_timeout) {
+            Ok(tcp) => {
+                self.tcp = Some(tcp);
+                self.consecutive_failures = 0;
+                self.metrics.fallbacks += 1;
+            }
+            Err(_) => self.metrics.connect_failures += 1,
+        }
+    }
+    
+    fn is_broken_stream(result: &Result<impl Sized>) -> bool {
+        matches!(result, Err(CyDnAError::IoError(_)) | Err(CyDnAError::InvalidPacketLength { .. }))
+    }
+}
+
+impl FrameTransport for FallbackTransport {
+    fn kind(&self) -> TransportKind {
+        match self.tcp {
+            Some(_) => TransportKind::Tcp,
+            None => TransportKind::Udp,
+        }
+    }
+    
+    fn send_frame(&mut self, frame: &[u8]) -> Result<usize> {
+        match self.tcp.as_mut() {
+            Some(tcp) => {
+                let result = tcp.send_frame(frame);
+                if Self::is_broken_stream(&result) {
+                    self.tcp = None;
+                } else if result.is_ok() {
+                    self.metrics.tcp_frames += 1;
+                }
+                result
+            }
+            None => {
+                let result = self.udp.send_frame(frame);
+                if result.is_ok() {
+                    self.metrics.udp_frames += 1;
+                }
+                result
+            }
+        }
+    }
+    
+    // A closed or corrupt stream reads as "nothing yet", like a refused UDP
+    // port; the next delivery failure reconnects.
+    fn recv_frame(&mut self, buffer: &mut [u8], timeout: Option<Duration>) -> Result<Option<usize>> {
+        match self.tcp.as_mut() {
+            Some(tcp) => {
+                let result = tcp.recv_frame(buffer, timeout);
+                if Self::is_broken_stream(&result) {
+                    self.tcp = None;
+                    return Ok(None);
+                }
+                result
+            }
+            None => self.udp.recv_frame(buffer, timeout),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_tcp_frames_survive_split_writes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        
+        let mut client = TcpTransport::connect(address, Duration::from_secs(1)).unwrap();
+        let (mut server, _) = TcpTransport::accept(&listener).unwrap();
+        
+        client.send_frame(b"first").unwrap();
+        client.send_frame(b"second frame").unwrap();
+        
+        let mut buffer = [0u8; MAX_PAYLOAD_SIZE];
+        let len = server.recv_frame(&mut buffer, Some(Duration::from_secs(1))).unwrap().unwrap();
+        assert_eq!(&buffer[..len], b"first");
+        let len = server.recv_frame(&mut buffer, Some(Duration::from_secs(1))).unwrap().unwrap();
+        assert_eq!(&buffer[..len], b"second frame");
+        
+        // Half a frame, then the rest after the reader has already timed out.
+        let mut raw = TcpStream::connect(address).unwrap();
+        let (mut server, _) = TcpTransport::accept(&listener).unwrap();
+        raw.write_all(&[4, 0, b'a', b'b']).unwrap();
+        assert_eq!(server.recv_frame(&mut buffer, Some(Duration::from_millis(50))).unwrap(), None);
+        raw.write_all(b"cd").unwrap();
+        let len = server.recv_frame(&mut buffer, Some(Duration::from_secs(1))).unwrap().unwrap();
+        assert_eq!(&buffer[..len], b"abcd");
+        
+        raw.write_all(&[0xFF, 0xFF]).unwrap();
+        assert!(matches!(
+            server.recv_frame(&mut buffer, Some(Duration::from_secs(1))),
+            Err(CyDnAError::InvalidPacketLength { .. })
+        ));
+    }
+    
+    #[test]
+    fn test_fallback_switches_after_threshold() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        
+        let udp = UdpTransport::connect(UdpSocket::bind("127.0.0.1:0").unwrap(), silent.local_addr().unwrap())
+            .unwrap();
+        let mut transport = FallbackTransport::new(udp)
+            .with_tcp_fallback(listener.local_addr().unwrap(), 2);
+        
+        transport.send_frame(b"over udp").unwrap();
+        transport.on_delivery_failure();
+        transport.on_delivery_success();
+        transport.on_delivery_failure();
+        assert_eq!(transport.kind(), TransportKind::Udp);
+        
+        transport.on_delivery_failure();
+        assert_eq!(transport.kind(), TransportKind::Tcp);
+        
+        let (mut server, _) = TcpTransport::accept(&listener).unwrap();
+        transport.send_frame(b"over tcp").unwrap();
+        server.send_frame(b"ack").unwrap();
+        
+        let mut buffer = [0u8; MAX_PAYLOAD_SIZE];
+        let len = server.recv_frame(&mut buffer, Some(Duration::from_secs(1))).unwrap().unwrap();
+        assert_eq!(&buffer[..len], b"over tcp");
+        let len = transport.recv_frame(&mut buffer, Some(Duration::from_secs(1))).unwrap().unwrap();
+        assert_eq!(&buffer[..len], b"ack");
+        
+        let metrics = transport.metrics();
+        assert_eq!((metrics.udp_frames, metrics.tcp_frames, metrics.fallbacks), (1, 1, 1));
+    }
+}
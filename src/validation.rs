@@ -0,0 +1,340 @@
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::access::{AccessMetrics, DeviceAccessList};
+use crate::contracts::ArchivedSensorPayload;
+use crate::errors::{CyDnAError, Result};
+use crate::receiver::Receiver;
+
+// Where a validator runs in a pipeline. Cheap structural checks go first so a
+// garbage payload never reaches a costly or stateful one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ValidationStage {
+    Structure,
+    Crc,
+    Ttl,
+    Acl,
+    Custom,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ValidationContext<'a> {
+    // On the sensor's clock where a skew estimate exists.
+    pub current_time_ms: u64,
+    
+    // The block the payload's CRC refers to, when the caller has it.
+    pub raw_data: Option<&'a [u8]>,
+}
+
+impl<'a> ValidationContext<'a> {
+    pub fn new(current_time_ms: u64) -> Self {
+        Self { current_time_ms, raw_data: None }
+    }
+    
+    pub fn with_raw_data(mut self, raw_data: &'a [u8]) -> Self {
+        self.raw_data = Some(raw_data);
+        self
+    }
+}
+
+// One check on a received payload. Validators are shared between receive
+// threads, so any state they keep needs its own locking.
+pub trait Validator: Send + Sync {
+    fn stage(&self) -> ValidationStage {
+        ValidationStage::Custom
+    }
+    
+    fn validate(&self, payload: &ArchivedSensorPayload, context: &ValidationContext<'_>) -> Result<()>;
+}
+
+// Device id and battery level in range.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FieldValidator;
+
+impl Validator for FieldValidator {
+    fn stage(&self) -> ValidationStage {
+        ValidationStage::Structure
+    }
+    
+    fn validate(&self, payload: &ArchivedSensorPayload, _: &ValidationContext<'_>) -> Result<()> {
+        Receiver::check_fields(payload)
+    }
+}
+
+// Checks the payload's raw data CRC when the context carries the raw data;
+// passes otherwise.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CrcValidator;
+
+impl Validator for CrcValidator {
+    fn stage(&self) -> ValidationStage {
+        ValidationStage::Crc
+    }
+    
+    fn validate(&self, payload: &ArchivedSensorPayload, context: &ValidationContext<'_>) -> Result<()> {
+        match context.raw_data {
+            Some(raw_data) => payload.verify_raw_data(raw_data),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TtlValidator;
+
+impl Validator for TtlValidator {
+    fn stage(&self) -> ValidationStage {
+        ValidationStage::Ttl
+    }
+    
+    fn validate(&self, payload: &ArchivedSensorPayload, context: &ValidationContext<'_>) -> Result<()> {
+        Receiver::check_ttl(payload, context.current_time_ms)
+    }
+}
+
+pub struct AccessValidator {
+    access: Mutex<DeviceAccessList>,
+}
+
+impl AccessValidator {
+    pub fn new(access: DeviceAccessList) -> Self {
+        Self { access: Mutex::new(access) }
+    }
+    
+    pub fn allow_device(&self, device_id: u32) {
+        self.access.lock().unwrap().allow_device(device_id);
+    }
+    
+    pub fn deny_device(&self, device_id: u32) {
+        self.access.lock().unwrap().deny_device(device_id);
+    }
+    
+    pub fn metrics(&self) -> AccessMetrics {
+        self.access.lock().unwrap().metrics()
+    }
+}
+
+impl Validator for AccessValidator {
+    fn stage(&self) -> ValidationStage {
+        ValidationStage::Acl
+    }
+    
+    fn validate(&self, payload: &ArchivedSensorPayload, _: &ValidationContext<'_>) -> Result<()> {
+        self.access.lock().unwrap().check(payload.device_unique_id)
+    }
+}
+
+// Rejects payloads whose anomaly vector has a component outside
+// `min..=max` or one that is not a number.
+#[derive(Debug, Clone, Copy)]
+pub struct AnomalyRangeValidator {
+    min: f32,
+    max: f32,
+}
+
+impl AnomalyRangeValidator {
+    pub fn new(min: f32, max: f32) -> Self {
+        Self { min, max }
+    }
+}
+
+impl Validator for AnomalyRangeValidator {
+    fn validate(&self, payload: &ArchivedSensorPayload, _: &ValidationContext<'_>) -> Result<()> {
+        match payload.anomaly_ai_vector.iter().all(|value| (self.min..=self.max).contains(value)) {
+            true => Ok(()),
+            false => Err(CyDnAError::ValidationFailed("anomaly vector component out of range")),
+        }
+    }
+}
+
+impl<F> Validator for F
+where
+    F: Fn(&ArchivedSensorPayload, &ValidationContext<'_>) -> Result<()> + Send + Sync,
+{
+    fn validate(&self, payload: &ArchivedSensorPayload, context: &ValidationContext<'_>) -> Result<()> {
+        self(payload, context)
+    }
+}
+
+// An ordered list of validators run on each received payload, stopping at
+// the first failure. Validators run by stage, and within a stage in the order
+// they were added.
+#[derive(Clone, Default)]
+pub struct ValidationPipeline {
+    validators: Vec<Arc<dyn Validator>>,
+}
+
+impl ValidationPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    // The checks every receive path has always applied: fields, raw data CRC
+    // (when supplied) and TTL.
+    pub fn standard() -> Self {
+        Self::new()
+            .with_validator(FieldValidator)
+            .with_validator(CrcValidator)
+            .with_validator(TtlValidator)
+    }
+    
+    pub fn with_validator(self, validator: impl Validator + 'static) -> Self {
+        self.with_shared_validator(Arc::new(validator))
+    }
+    
+    // For validators the caller keeps a handle to, e.g. to update an access
+    // list at runtime.
+    pub fn with_shared_validator(mut self, validator: Arc<dyn Validator>) -> Self {
+        let stage = validator.stage();
+        let position = self.validators.partition_point(|existing| existing.stage() <= stage);
+        self.validators.insert(position, validator);
+        self
+    }
+    
+    pub fn with_access_list(self, access: DeviceAccessList) -> Self {
+        self.with_validator(AccessValidator::new(access))
+    }
+    
+    pub fn without_stage(mut self, stage: ValidationStage) -> Self {
+        self.validators.retain(|validator| validator.stage() != stage);
+        self
+    }
+    
+    pub fn validate(&self, payload: &ArchivedSensorPayload, context: &ValidationContext<'_>) -> Result<()> {
+        self.validators.iter().try_for_each(|validator| validator.validate(payload, context))
+    }
+    
+    // Archiving the frame is the structural check on the bytes themselves;
+    // the pipeline then runs on the archived payload.
+    pub fn validate_frame<'a>(
+        &self,
+        datagram: &'a [u8],
+        context: &ValidationContext<'_>,
+    ) -> Result<&'a ArchivedSensorPayload> {
+        let archived = Receiver::archive_frame(datagram)?;
+        self.validate(archived, context)?;
+        Ok(archived)
+    }
+    
+    pub fn stages(&self) -> Vec<ValidationStage> {
+        self.validators.iter().map(|validator| validator.stage()).collect()
+    }
+    
+    pub fn len(&self) -> usize {
+        self.validators.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.validators.is_empty()
+    }
+}
+
+pub(crate) fn standard_pipeline() -> &'static ValidationPipeline {
+    static STANDARD: OnceLock<ValidationPipeline> = OnceLock::new();
+    STANDARD.get_or_init(ValidationPipeline::standard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::{SensorPayload, ANOMALY_VECTOR_SIZE};
+    use crate::transmitter::Transmitter;
+    
+    fn frame(device_id: u32, vector: [f32; ANOMALY_VECTOR_SIZE]) -> Vec<u8> {
+        let payload = SensorPayload::new(device_id, 1_000, 1, 50, 1_000, 0, vector).unwrap();
+        Transmitter::frame_payload(&payload).unwrap()
+    }
+    
+    #[test]
+    fn test_pipeline_runs_stages_in_order() {
+        let pipeline = ValidationPipeline::new()
+            .with_validator(AnomalyRangeValidator::new(0.0, 1.0))
+            .with_access_list(DeviceAccessList::new().with_device(1))
+            .with_validator(TtlValidator)
+            .with_validator(FieldValidator);
+        assert_eq!(
+            pipeline.stages(),
+            [ValidationStage::Structure, ValidationStage::Ttl, ValidationStage::Acl, ValidationStage::Custom],
+        );
+        
+        let context = ValidationContext::new(1_500);
+        assert!(pipeline.validate_frame(&frame(1, [0.5; ANOMALY_VECTOR_SIZE]), &context).is_ok());
+        
+        // Each payload fails the earliest check it breaks.
+        let mut spiked = [0.5; ANOMALY_VECTOR_SIZE];
+        spiked[3] = f32::NAN;
+        let cases = [
+            (frame(1, spiked), ValidationContext::new(1_500)),
+            (frame(2, spiked), ValidationContext::new(1_500)),
+            (frame(2, spiked), ValidationContext::new(5_000)),
+        ];
+        let errors: Vec<u16> = cases.iter()
+            .map(|(datagram, context)| pipeline.validate_frame(datagram, context).err().unwrap().code())
+            .collect();
+        assert_eq!(errors, [
+            CyDnAError::ValidationFailed("").code(),
+            CyDnAError::DeviceNotAllowed(2).code(),
+            CyDnAError::PayloadExpired { timestamp_ms: 0, ttl_ms: 0 }.code(),
+        ]);
+    }
+    
+    #[test]
+    fn test_closure_validator_and_crc_stage() {
+        let raw = b"vibration block";
+        let payload = SensorPayload::with_raw_data(7, 1_000, 1, 50, 1_000, raw, [0.0; ANOMALY_VECTOR_SIZE]).unwrap();
+        let datagram = Transmitter::frame_payload(&payload).unwrap();
+        
+        let pipeline = ValidationPipeline::standard()
+            .with_validator(|payload: &ArchivedSensorPayload, _: &ValidationContext<'_>| {
+                match payload.sensor_model_version {
+                    1 => Ok(()),
+                    _ => Err(CyDnAError::ValidationFailed("unsupported sensor model")),
+                }
+            });
+        
+        let context = ValidationContext::new(1_500);
+        assert!(pipeline.validate_frame(&datagram, &context.with_raw_data(raw)).is_ok());
+        assert!(matches!(
+            pipeline.validate_frame(&datagram, &context.with_raw_data(b"other block")),
+            Err(CyDnAError::IntegrityCheckFailed { .. })
+        ));
+        assert!(pipeline.clone().without_stage(ValidationStage::Crc)
+            .validate_frame(&datagram, &context.with_raw_data(b"other block"))
+            .is_ok());
+    }
+    
+    #[test]
+    fn test_gateway_nacks_custom_validation_failure() {
+        use crate::ack_manager::{AckManager, AckMessage};
+        use crate::contracts::NackReason;
+        use crate::server::GatewayServer;
+        use std::net::UdpSocket;
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+        
+        let gateway = GatewayServer::new("127.0.0.1:0").unwrap()
+            .with_validator(AnomalyRangeValidator::new(0.0, 1.0))
+            .with_poll_interval_ms(20)
+            .spawn()
+            .unwrap();
+        let sensor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sensor.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        
+        let mut buffer = [0u8; 64];
+        for (component, accepted) in [(0.9, true), (2.0, false)] {
+            let payload = SensorPayload::new(3, now, 1, 80, 5_000, 0, [component; ANOMALY_VECTOR_SIZE]).unwrap();
+            Transmitter::send(&sensor, &payload, gateway.local_address()).unwrap();
+            
+            let (len, _) = sensor.recv_from(&mut buffer).unwrap();
+            let Some(AckMessage::Single(ack)) = AckManager::parse_ack_message(&buffer[..len]).unwrap() else {
+                panic!("expected a single ACK");
+            };
+            assert_eq!(ack.is_ack(), accepted);
+            if !accepted {
+                assert_eq!(ack.reason(), NackReason::Malformed);
+            }
+        }
+        
+        assert_eq!(gateway.metrics().accepted, 1);
+        gateway.shutdown();
+    }
+}